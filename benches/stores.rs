@@ -0,0 +1,205 @@
+//! Throughput of the stores and thread safe wrappers, run with `cargo bench`.
+//!
+//! Thread safe stores are also measured with several threads hammering them at once, to catch
+//! regressions in how they lock.
+
+use std::{hint::black_box, thread};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ezcache::{
+    prelude::*,
+    stores::{file_stores::ThreadSafeFileStore, MemoryStore, ThreadSafeMemoryStore},
+    thread_safe::dumb_wrappers::{DumbTryThreadSafeWrapper, EmptyDumbError},
+    TryCacheStoreErrorMap,
+};
+
+/// Keys used by every store, operations cycle over them.
+const KEYS: u64 = 256;
+/// Operations done by each thread on every iteration of the threaded benchmarks.
+const OPS_PER_THREAD: u64 = 256;
+const THREADS: &[u64] = &[1, 2, 4, 8, 16];
+
+fn dumb_wrapper() -> DumbTryThreadSafeWrapper<
+    'static,
+    u64,
+    u64,
+    EmptyDumbError,
+    impl TryCacheStore<Key = u64, Value = u64, Error = EmptyDumbError>,
+> {
+    let store: TryCacheStoreErrorMap<_, _, _, EmptyDumbError, _> = MemoryStore::default().into();
+    DumbTryThreadSafeWrapper::new(store)
+}
+
+fn file_store() -> (tempfile::TempDir, ThreadSafeFileStore<String, Vec<u8>>) {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ThreadSafeFileStore::new_on(dir.path().to_path_buf()).unwrap();
+    (dir, store)
+}
+
+fn single_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single_thread");
+
+    let mut store = MemoryStore::default();
+    group.bench_function("memory/set", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            store.set(i, i);
+        });
+    });
+    group.bench_function("memory/get", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            black_box(store.get(i))
+        });
+    });
+
+    let store = ThreadSafeMemoryStore::default();
+    group.bench_function("thread_safe_memory/set", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            store.ts_one_try_set(&i, &i).unwrap();
+        });
+    });
+    group.bench_function("thread_safe_memory/get", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            black_box(store.ts_one_try_get(&i).unwrap())
+        });
+    });
+
+    let store = dumb_wrapper();
+    group.bench_function("dumb_wrapper/set", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            store.ts_one_try_set(&i, &i).unwrap();
+        });
+    });
+    group.bench_function("dumb_wrapper/get", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            black_box(store.ts_one_try_get(&i).unwrap())
+        });
+    });
+
+    let (_dir, store) = file_store();
+    let value = vec![0; 1024];
+    group.bench_function("file/set", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            store.ts_one_try_set(&i.to_string(), &value).unwrap();
+        });
+    });
+    group.bench_function("file/get", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            black_box(store.ts_one_try_get(&i.to_string()).unwrap())
+        });
+    });
+
+    group.finish();
+}
+
+/// Runs `op` over [`OPS_PER_THREAD`] keys from each of `threads` threads at once.
+fn run_threads(threads: u64, op: impl Fn(u64) + Sync) {
+    thread::scope(|scope| {
+        let op = &op;
+        for t in 0..threads {
+            scope.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    op((t * OPS_PER_THREAD + i) % KEYS);
+                }
+            });
+        }
+    });
+}
+
+fn multi_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multi_thread");
+
+    let memory = ThreadSafeMemoryStore::default();
+    #[cfg(feature = "arc-swap")]
+    let read_mostly = ezcache::stores::read_mostly::ReadMostlyStore::new();
+    #[cfg(feature = "arc-swap")]
+    read_mostly.update_all(|map| map.extend((0..KEYS).map(|i| (i, i))));
+    let dumb = dumb_wrapper();
+    let (_dir, file) = file_store();
+    let value = vec![0; 1024];
+
+    for &threads in THREADS {
+        group.throughput(Throughput::Elements(threads * OPS_PER_THREAD));
+
+        group.bench_with_input(
+            BenchmarkId::new("thread_safe_memory", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    run_threads(threads, |i| {
+                        memory.ts_one_try_set(&i, &i).unwrap();
+                        black_box(memory.ts_one_try_get(&i).unwrap());
+                    });
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("thread_safe_memory/get", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    run_threads(threads, |i| {
+                        black_box(memory.ts_one_try_get(&i).unwrap());
+                    });
+                });
+            },
+        );
+        #[cfg(feature = "arc-swap")]
+        group.bench_with_input(
+            BenchmarkId::new("read_mostly/get", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    run_threads(threads, |i| {
+                        black_box(read_mostly.get(i));
+                    });
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("dumb_wrapper", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    run_threads(threads, |i| {
+                        dumb.ts_one_try_set(&i, &i).unwrap();
+                        black_box(dumb.ts_one_try_get(&i).unwrap());
+                    });
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("file", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    run_threads(threads, |i| {
+                        let key = i.to_string();
+                        file.ts_one_try_set(&key, &value).unwrap();
+                        black_box(file.ts_one_try_get(&key).unwrap());
+                    });
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, single_thread, multi_thread);
+criterion_main!(benches);
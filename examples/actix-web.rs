@@ -0,0 +1,45 @@
+//! Serves a slow endpoint behind a response cache.
+//!
+//! Run it and try `curl -i http://127.0.0.1:3000/slow?name=you` a few times: the first request
+//! takes a second and comes with `x-cache: MISS`, the following ones are instant and come with
+//! `x-cache: HIT` until the response expires 10 seconds later. Other names or languages, through
+//! `Accept-Language`, are cached apart.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use actix_web::{
+    http::header::{HeaderValue, ACCEPT_LANGUAGE},
+    web, App, HttpRequest, HttpServer,
+};
+use ezcache::{
+    actix_web::{CachedResponse, ResponseCache},
+    stores::ThreadSafeMemoryStore,
+};
+
+async fn slow(query: web::Query<HashMap<String, String>>, request: HttpRequest) -> String {
+    actix_web::rt::time::sleep(Duration::from_secs(1)).await;
+    let greeting = match request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .map(HeaderValue::as_bytes)
+    {
+        Some(b"es") => "Hola",
+        _ => "Hello",
+    };
+    let name = query.get("name").map_or("world", String::as_str);
+    format!("{greeting}, {name}!\n")
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let store = Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default());
+    let server = HttpServer::new(move || {
+        App::new().route("/slow", web::get().to(slow)).wrap(
+            ResponseCache::new(Arc::clone(&store), Duration::from_secs(10))
+                .with_vary(ACCEPT_LANGUAGE),
+        )
+    })
+    .bind("127.0.0.1:3000")?;
+    println!("listening on http://127.0.0.1:3000");
+    server.run().await
+}
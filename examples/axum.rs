@@ -0,0 +1,41 @@
+//! Serves a slow endpoint behind a response cache.
+//!
+//! Run it and try `curl -i http://127.0.0.1:3000/slow?name=you` a few times: the first request
+//! takes a second and comes with `x-cache: MISS`, the following ones are instant and come with
+//! `x-cache: HIT` until the response expires 10 seconds later. Other names or languages, through
+//! `Accept-Language`, are cached apart.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{
+    extract::Query,
+    http::{header::ACCEPT_LANGUAGE, HeaderMap, HeaderValue},
+    routing::get,
+    Router,
+};
+use ezcache::{
+    axum::{CachedResponse, ResponseCacheLayer},
+    stores::ThreadSafeMemoryStore,
+};
+
+async fn slow(Query(query): Query<HashMap<String, String>>, headers: HeaderMap) -> String {
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let greeting = match headers.get(ACCEPT_LANGUAGE).map(HeaderValue::as_bytes) {
+        Some(b"es") => "Hola",
+        _ => "Hello",
+    };
+    let name = query.get("name").map_or("world", String::as_str);
+    format!("{greeting}, {name}!\n")
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let store = Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default());
+    let app = Router::new()
+        .route("/slow", get(slow))
+        .layer(ResponseCacheLayer::new(store, Duration::from_secs(10)).with_vary(ACCEPT_LANGUAGE));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await
+}
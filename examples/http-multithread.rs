@@ -1,11 +1,12 @@
 #[path = "_common.rs"]
 pub mod common;
 
-use std::{io::Read, path::PathBuf, sync::Arc, time::Instant};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 
 use ezcache::{
+    http::{http_fetch, CachedResponse},
     prelude::*,
-    stores::file_stores::{ThreadSafeFileStore, ThreadSafeFileStoreError},
+    stores::file_stores::{ThreadSafeFileStoreError, ThreadSafeFileStoreSerializable},
 };
 use indicatif::{MultiProgress, ProgressBar};
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
@@ -23,7 +24,6 @@ pub enum Error {
     Io(#[from] std::io::Error),
 }
 
-const BS: usize = 2048;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // We get a cache dir path
     let args: Vec<_> = std::env::args().collect();
@@ -37,31 +37,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Aaand, we make the generative cache store
     let store: ThreadSafeGenTryCacheStoreWrapper<'_, _, _, Error, _, _, _, _, _> =
         ThreadSafeGenTryCacheStoreWrapper::new(
-            ThreadSafeFileStore::new_on(&dpath)?,
-            // With a fancy generator function
+            ThreadSafeFileStoreSerializable::new_on(&dpath)?,
+            // With the http generator, filling the bar once it's done
             |k: &&str,
              (client, pb): (&reqwest::blocking::Client, ProgressBar)|
-             -> Result<Vec<u8>, Error> {
-                let mut res = client.get(*k).send()?.error_for_status()?;
-
-                if let Some(len) = res.content_length() {
-                    pb.set_position(0);
-                    pb.set_length(len);
-
-                    #[allow(clippy::cast_possible_truncation)]
-                    let mut buf: Vec<u8> = vec![0; len as usize];
-                    buf.chunks_mut(BS).try_for_each(|ref mut chunk| {
-                        res.read_exact(chunk)?;
-                        pb.inc(chunk.len() as u64);
-                        Ok::<(), std::io::Error>(())
-                    })?;
-                    Ok(buf)
-                } else {
-                    let bytes = res.bytes()?.to_vec();
-                    pb.set_position(u64::MAX);
-
-                    Ok(bytes)
-                }
+             -> Result<CachedResponse, Error> {
+                let response = http_fetch(k, (client, None))?;
+                pb.set_position(u64::MAX);
+                Ok(response)
             },
         );
 
@@ -95,7 +78,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             // We call the store
             let a = Instant::now();
-            let value = store.ts_try_get_or_new(url, (&client,this_bar.clone()))?;
+            let value = store.ts_try_get_or_new(url, (&client,this_bar.clone()))?.body;
             let b = Instant::now();
 
             // More printing stuff
@@ -3,7 +3,7 @@ pub mod common;
 
 use std::time::Instant;
 
-use ezcache::{prelude::*, TryCacheStoreErrorMap};
+use ezcache::{http::http_fetch_generator, prelude::*, TryCacheStoreErrorMap};
 use rand::Rng;
 use sha2::{Digest, Sha256};
 use thiserror::Error;
@@ -30,12 +30,7 @@ fn main() {
 
     let store: TryCacheStoreErrorMap<_, _, _, Error, _> =
         TryCacheStoreErrorMap::from_store(MemoryStore::new());
-    let mut store = TryGenCacheStoreWrapper::new(
-        store,
-        |k: &&str, (client,): (&reqwest::blocking::Client,)| -> Result<_, reqwest::Error> {
-            Ok(client.get(*k).send()?.error_for_status()?.bytes()?.to_vec())
-        },
-    );
+    let mut store = TryGenCacheStoreWrapper::new(store, http_fetch_generator::<&str>());
 
     let client = reqwest::blocking::Client::new();
     let mut rng = rand::thread_rng();
@@ -49,8 +44,9 @@ fn main() {
 
         let a = Instant::now();
         let value = store
-            .try_get_or_new(url, (&client,))
-            .expect("unknown error downloading");
+            .try_get_or_new(url, (&client, None))
+            .expect("unknown error downloading")
+            .body;
         let b = Instant::now();
 
         let hash = Sha256::new()
@@ -32,7 +32,7 @@ set -x;
 
     # I'd run the http example if networking wasn't so unreliable...
     # shellcheck disable=2086
-    cargo clippy --example http -- $FLAGS
+    cargo clippy --example http --features reqwest -- $FLAGS
     # shellcheck disable=2086
-    cargo clippy --example http-multithread -- $FLAGS
+    cargo clippy --example http-multithread --features reqwest,serde,file-stores -- $FLAGS
 )
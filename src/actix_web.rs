@@ -0,0 +1,419 @@
+//! Caching the responses of actix-web handlers.
+//!
+//! [`ResponseCache`] is a middleware for actix-web apps, scopes and resources that keeps the
+//! responses of `GET` and `HEAD` requests in any thread safe store of [`CachedResponse`]s, keyed by
+//! the method, path and query of the request, and answers the same requests from there until they
+//! expire. What's cached and how is up to an [`HttpCache`], see the
+//! [`http_cache`][crate::http_cache] module docs.
+//!
+//! # Examples
+//! ```rust
+//! # use std::{sync::Arc, time::Duration};
+//! # use actix_web::{http::header::ACCEPT_LANGUAGE, web, App};
+//! # use ezcache::{actix_web::{CachedResponse, ResponseCache}, stores::ThreadSafeMemoryStore};
+//! #
+//! let store = Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default());
+//! let app = App::new()
+//!     .route("/report", web::get().to(|| async { "expensive report" }))
+//!     .wrap(ResponseCache::new(store, Duration::from_secs(60)).with_vary(ACCEPT_LANGUAGE));
+//! ```
+
+use core::{
+    future::{poll_fn, ready, Future, Ready},
+    pin::Pin,
+    time::Duration,
+};
+use std::{borrow::ToOwned, boxed::Box, rc::Rc, string::String, sync::Arc, vec::Vec};
+
+use ::actix_web::{
+    body::{BodySize, BodyStream, BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderName, HeaderValue, AUTHORIZATION},
+        StatusCode,
+    },
+    web::Bytes,
+    Error, HttpResponse,
+};
+use futures_util::{stream, StreamExt};
+
+pub use crate::http_cache::{CachedResponse, X_CACHE};
+use crate::{
+    clock::{Clock, SystemClock},
+    http_cache::HttpCache,
+    thread_safe::ThreadSafeTryCacheStore,
+};
+
+/// Turns a cached response back into a response, with an `X-Cache: HIT` header. Headers that
+/// aren't valid anymore are left out.
+fn hit(cached: CachedResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+    let mut response = HttpResponse::with_body(status, BoxBody::new(cached.body));
+    let headers = response.headers_mut();
+    for (name, value) in cached.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_bytes(&value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(
+        HeaderName::from_static(X_CACHE),
+        HeaderValue::from_static("HIT"),
+    );
+    response
+}
+
+/// Reads a whole body of up to `limit` bytes. If it's longer or fails, gives back a body with what
+/// was read followed by the rest of it, failure included.
+async fn buffer<B: MessageBody + 'static>(body: B, limit: usize) -> Result<Bytes, BoxBody> {
+    let mut rest = Box::pin(body);
+    let mut chunks: Vec<Result<Bytes, Box<dyn std::error::Error>>> = Vec::new();
+    let mut len = 0;
+    while let Some(chunk) = poll_fn(|cx| rest.as_mut().poll_next(cx)).await {
+        let chunk = chunk.map_err(Into::into);
+        let overflows = chunk.as_ref().map_or(true, |chunk| {
+            len += chunk.len();
+            len > limit
+        });
+        chunks.push(chunk);
+        if overflows {
+            let rest = stream::poll_fn(move |cx| {
+                let chunk = rest.as_mut().poll_next(cx);
+                chunk.map(|chunk| chunk.map(|chunk| chunk.map_err(Into::into)))
+            });
+            let body = BodyStream::new(stream::iter(chunks).chain(rest));
+            return Err(BoxBody::new(body));
+        }
+    }
+    let body: Vec<u8> = chunks.into_iter().flatten().flatten().collect();
+    Ok(body.into())
+}
+
+/// Middleware that caches the responses of the services it wraps, see the [module docs][self].
+///
+/// Generics:
+/// - `S`: [`ThreadSafeTryCacheStore`] the responses are kept in.
+/// - `C`: [`Clock`] used to expire responses, the system time by default.
+pub struct ResponseCache<S, C = SystemClock> {
+    cache: HttpCache<S, C>,
+}
+
+impl<S> ResponseCache<S> {
+    /// Make a new [`ResponseCache`] keeping responses in `store` for `ttl`, with bodies of up to
+    /// 1 MiB.
+    pub fn new(store: Arc<S>, ttl: Duration) -> Self {
+        Self {
+            cache: HttpCache::new(store, ttl),
+        }
+    }
+}
+
+impl<S, C> ResponseCache<S, C> {
+    /// Keeps a response apart for each value of the given request header.
+    #[must_use]
+    pub fn with_vary(mut self, header: HeaderName) -> Self {
+        self.cache = self.cache.with_vary(header);
+        self
+    }
+
+    /// Only caches responses whose body is up to `max_body` bytes long.
+    #[must_use]
+    pub fn with_max_body(mut self, max_body: usize) -> Self {
+        self.cache = self.cache.with_max_body(max_body);
+        self
+    }
+
+    /// Replaces the clock used to expire responses.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> ResponseCache<S, C2> {
+        ResponseCache {
+            cache: self.cache.with_clock(clock),
+        }
+    }
+}
+
+impl<S, C: Clone> Clone for ResponseCache<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<Svc, B, S, C> Transform<Svc, ServiceRequest> for ResponseCache<S, C>
+where
+    Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = String, Value = CachedResponse>
+        + Send
+        + Sync
+        + 'static,
+    C: Clock + Clone + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = ResponseCacheMiddleware<Svc, S, C>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, inner: Svc) -> Self::Future {
+        ready(Ok(ResponseCacheMiddleware {
+            inner: Rc::new(inner),
+            cache: Rc::new(self.cache.clone()),
+        }))
+    }
+}
+
+/// Service that caches the responses of another, made by a [`ResponseCache`].
+pub struct ResponseCacheMiddleware<Svc, S, C = SystemClock> {
+    inner: Rc<Svc>,
+    cache: Rc<HttpCache<S, C>>,
+}
+
+impl<Svc, B, S, C> Service<ServiceRequest> for ResponseCacheMiddleware<Svc, S, C>
+where
+    Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = String, Value = CachedResponse>
+        + Send
+        + Sync
+        + 'static,
+    C: Clock + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Error>>>>;
+
+    forward_ready!(inner);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let inner = Rc::clone(&self.inner);
+        let cache = Rc::clone(&self.cache);
+        Box::pin(async move {
+            let target = request
+                .uri()
+                .path_and_query()
+                .map_or("/", |target| target.as_str());
+            let Some(key) = cache.key(request.method().as_str(), target, |name| {
+                request.headers().get_all(name).map(HeaderValue::as_bytes)
+            }) else {
+                let mut response = inner.call(request).await?.map_into_boxed_body();
+                response.headers_mut().insert(
+                    HeaderName::from_static(X_CACHE),
+                    HeaderValue::from_static("MISS"),
+                );
+                return Ok(response);
+            };
+
+            if let Some(cached) = cache.lookup(&key).await {
+                let (request, _) = request.into_parts();
+                return Ok(ServiceResponse::new(request, hit(cached)));
+            }
+
+            let authorized = request.headers().contains_key(AUTHORIZATION);
+            let response = inner.call(request).await?;
+            let body_len = match response.response().body().size() {
+                BodySize::Sized(len) => Some(len),
+                BodySize::None => Some(0),
+                BodySize::Stream => None,
+            };
+            let cacheable = cache.is_cacheable(
+                response.status().as_u16(),
+                response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_bytes())),
+                body_len,
+                authorized,
+            );
+            let mut response = if cacheable {
+                let (request, response) = response.into_parts();
+                let (response, body) = response.into_parts();
+                let body = match buffer(body, cache.max_body()).await {
+                    Ok(body) => {
+                        let headers = response
+                            .headers()
+                            .iter()
+                            .map(|(name, value)| {
+                                (name.as_str().to_owned(), value.as_bytes().to_vec())
+                            })
+                            .collect();
+                        cache
+                            .keep(key, response.status().as_u16(), headers, body.to_vec())
+                            .await;
+                        BoxBody::new(body)
+                    }
+                    // Passed on as it is, without caching it
+                    Err(body) => body,
+                };
+                ServiceResponse::new(request, response.set_body(body))
+            } else {
+                response.map_into_boxed_body()
+            };
+            response.headers_mut().insert(
+                HeaderName::from_static(X_CACHE),
+                HeaderValue::from_static("MISS"),
+            );
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::ThreadSafeMemoryStore};
+    use ::actix_web::{
+        http::header::{ACCEPT_LANGUAGE, CACHE_CONTROL, COOKIE},
+        rt::System,
+        test::{call_service, init_service, read_body, TestRequest},
+        web, App,
+    };
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::string::ToString;
+
+    #[test]
+    fn caches_by_target_and_vary() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let clock = Arc::new(MockClock::default());
+        let handler_calls = Arc::clone(&calls);
+        let cache = ResponseCache::new(
+            Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default()),
+            Duration::from_mins(1),
+        )
+        .with_vary(ACCEPT_LANGUAGE)
+        .with_clock(Arc::clone(&clock));
+
+        System::new().block_on(async {
+            let app = init_service(
+                App::new()
+                    .route(
+                        "/",
+                        web::get().to(move || {
+                            let calls = Arc::clone(&handler_calls);
+                            async move { calls.fetch_add(1, Ordering::Relaxed).to_string() }
+                        }),
+                    )
+                    .wrap(cache),
+            )
+            .await;
+            let send = |uri: &'static str, language: &'static str| {
+                let request = TestRequest::get()
+                    .uri(uri)
+                    .insert_header((ACCEPT_LANGUAGE, language))
+                    .to_request();
+                let app = &app;
+                async move {
+                    let response = call_service(app, request).await;
+                    let hit = response
+                        .headers()
+                        .get(X_CACHE)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string();
+                    let body = read_body(response).await;
+                    (String::from_utf8(body.to_vec()).unwrap(), hit)
+                }
+            };
+
+            assert_eq!(send("/", "en").await, ("0".into(), "MISS".into()));
+            assert_eq!(send("/", "en").await, ("0".into(), "HIT".into()));
+            assert_eq!(send("/", "es").await, ("1".into(), "MISS".into()));
+            assert_eq!(send("/?q", "en").await, ("2".into(), "MISS".into()));
+
+            clock.advance(Duration::from_mins(1));
+            assert_eq!(send("/", "en").await, ("3".into(), "MISS".into()));
+        });
+    }
+
+    #[test]
+    fn skips_personal_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (private_calls, public_calls) = (Arc::clone(&calls), Arc::clone(&calls));
+        let cache = ResponseCache::new(
+            Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default()),
+            Duration::from_mins(1),
+        );
+
+        System::new().block_on(async {
+            let app = init_service(
+                App::new()
+                    .route(
+                        "/",
+                        web::get().to(move || {
+                            let calls = Arc::clone(&private_calls);
+                            async move { calls.fetch_add(1, Ordering::Relaxed).to_string() }
+                        }),
+                    )
+                    .route(
+                        "/public",
+                        web::get().to(move || {
+                            let calls = Arc::clone(&public_calls);
+                            async move {
+                                HttpResponse::Ok()
+                                    .insert_header((CACHE_CONTROL, "public"))
+                                    .body(calls.fetch_add(1, Ordering::Relaxed).to_string())
+                            }
+                        }),
+                    )
+                    .wrap(cache),
+            )
+            .await;
+
+            let send = |uri: &'static str, header: (HeaderName, &'static str)| {
+                let request = TestRequest::get()
+                    .uri(uri)
+                    .insert_header(header)
+                    .to_request();
+                let app = &app;
+                async move {
+                    let response = call_service(app, request).await;
+                    let hit = response
+                        .headers()
+                        .get(X_CACHE)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string();
+                    let body = read_body(response).await;
+                    (String::from_utf8(body.to_vec()).unwrap(), hit)
+                }
+            };
+
+            let alice = (AUTHORIZATION, "Bearer alice");
+            let bob = (AUTHORIZATION, "Bearer bob");
+            assert_eq!(send("/", alice.clone()).await, ("0".into(), "MISS".into()));
+            assert_eq!(send("/", bob.clone()).await, ("1".into(), "MISS".into()));
+            assert_eq!(
+                send("/", (COOKIE, "id=alice")).await,
+                ("2".into(), "MISS".into())
+            );
+            assert_eq!(
+                send("/", (COOKIE, "id=alice")).await,
+                ("3".into(), "MISS".into())
+            );
+
+            assert_eq!(send("/public", alice).await, ("4".into(), "MISS".into()));
+            assert_eq!(send("/public", bob).await, ("4".into(), "HIT".into()));
+        });
+    }
+
+    #[test]
+    fn passes_on_bodies_that_cant_be_buffered() {
+        System::new().block_on(async {
+            assert_eq!(buffer("hi", 2).await.unwrap(), "hi");
+
+            let long = buffer("hello", 2).await.unwrap_err();
+            assert_eq!(::actix_web::body::to_bytes(long).await.unwrap(), "hello");
+
+            let chunks: [Result<Bytes, std::io::Error>; 2] =
+                [Ok(Bytes::from("he")), Err(std::io::Error::other("broken"))];
+            let failed = buffer(BodyStream::new(stream::iter(chunks)), 8)
+                .await
+                .unwrap_err();
+            assert!(::actix_web::body::to_bytes(failed).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,265 @@
+//! Generative stores with async generators that run only once per key at a time.
+//!
+//! [`AsyncGenStore`] wraps a store and an async generator. When several tasks miss the same key
+//! at once, only the first one starts the generator, the rest await the very same future, so a
+//! burst of requests for one URL triggers a single fetch. Whoever gets the value first sets it on
+//! the store.
+//!
+//! The generator future is shared, so its output has to be [`Clone`], errors included (wrap them
+//! in an [`Arc`][std::sync::Arc] if they aren't). It keeps running as long as any task awaits it,
+//! even if the one that started it is dropped.
+//!
+//! # Cancellation safety
+//! [`try_get_or_new`][AsyncGenStore::try_get_or_new] can be dropped at any await point, like when
+//! it loses a `select!` or its request times out. No lock is held across awaits, and the store is
+//! only written synchronously once the value is ready, so a dropped future never leaves a
+//! half-written entry. When the last task awaiting a generation is dropped, the generation is
+//! dropped along with it and forgotten, so the next miss of the key starts a new one instead of
+//! waiting on an abandoned one.
+//!
+//! # Examples
+//! ```rust
+//! # use core::convert::Infallible;
+//! # use ezcache::{async_gen::AsyncGenStore, stores::MemoryStore};
+//! # use futures::{executor::block_on, future::join_all};
+//! #
+//! let store = AsyncGenStore::new(MemoryStore::<u32, u32>::new(), |&n: &u32, ()| async move {
+//!     // Some slow request
+//!     Ok::<_, Infallible>(n * 2)
+//! });
+//!
+//! let values = block_on(join_all((0..4).map(|_| store.try_get_or_new(&2, ()))));
+//! assert!(values.into_iter().all(|value| value == Ok(4)));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+
+/// Shared generation of a value.
+type Flight<V, E> = Shared<BoxFuture<'static, Result<V, E>>>;
+
+/// Generations running right now, by key, along with an id to tell them apart.
+type Flights<K, V, E> = HashMap<K, (u64, Flight<V, E>)>;
+
+/// Async generative wrapper around a [`TryCacheStore`] that deduplicates concurrent generations of
+/// the same key.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around, its errors must convert into `E`.
+/// - `F`: [`Fn<&K, A>`] returning the future that generates a value.
+/// - `E`: Error type of the generator, and of the wrapper.
+pub struct AsyncGenStore<S: TryCacheStore, F, E> {
+    store: Mutex<S>,
+    pub generator: F,
+    in_flight: Mutex<Flights<S::Key, S::Value, E>>,
+    next_flight: AtomicU64,
+}
+
+/// Locks a mutex, the data behind the ones of an [`AsyncGenStore`] is fine even if a thread
+/// panicked while holding them.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<S: TryCacheStore, F, E> AsyncGenStore<S, F, E> {
+    /// Make a new [`AsyncGenStore`] from a [`TryCacheStore`] and a generator function.
+    pub fn new(store: S, generator: F) -> Self {
+        Self {
+            store: Mutex::new(store),
+            generator,
+            in_flight: Mutex::new(HashMap::new()),
+            next_flight: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the inner store.
+    pub fn into_inner(self) -> S {
+        self.store
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S, F, E> AsyncGenStore<S, F, E>
+where
+    S: TryCacheStore,
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone + Send + Sync + 'static,
+    S::Error: Into<E>,
+    E: Clone + Send + Sync + 'static,
+{
+    /// Attempts to get the value from the store, without generating it.
+    ///
+    /// # Errors
+    /// Fails when the store does.
+    pub fn try_get(&self, key: &S::Key) -> Result<Option<S::Value>, E> {
+        lock(&self.store).try_get(key).map_err(Into::into)
+    }
+
+    /// Attempts to get the value from the store or generate it, adding it. If the key is already
+    /// being generated, awaits that generation instead, and `args` are ignored.
+    ///
+    /// # Errors
+    /// Fails when the store or the generator do, every task awaiting a failed generation gets its
+    /// error and the next miss generates again.
+    pub async fn try_get_or_new<A, Fut>(&self, key: &S::Key, args: A) -> Result<S::Value, E>
+    where
+        F: Fn(&S::Key, A) -> Fut,
+        Fut: Future<Output = Result<S::Value, E>> + Send + 'static,
+    {
+        let (id, flight) = {
+            // Looked up with the flights locked, so a finishing flight can't be missed both in the
+            // store and in the flights
+            let mut in_flight = lock(&self.in_flight);
+            if let Some((id, flight)) = in_flight.get(key) {
+                (*id, flight.clone())
+            } else {
+                if let Some(value) = self.try_get(key)? {
+                    return Ok(value);
+                }
+                let id = self.next_flight.fetch_add(1, Ordering::Relaxed);
+                let flight = (self.generator)(key, args).boxed().shared();
+                in_flight.insert(key.clone(), (id, flight.clone()));
+                (id, flight)
+            }
+        };
+
+        let result = Waiter {
+            in_flight: &self.in_flight,
+            key,
+            id,
+            flight,
+            done: false,
+        }
+        .await;
+
+        let mut in_flight = lock(&self.in_flight);
+        if in_flight
+            .get(key)
+            .is_some_and(|(current, _)| *current == id)
+        {
+            // First to see it done, set it before others can miss it
+            if let Ok(value) = &result {
+                lock(&self.store).try_set(key, value).map_err(Into::into)?;
+            }
+            in_flight.remove(key);
+        }
+        result
+    }
+}
+
+/// Awaits a flight, forgetting it if this was the last task awaiting it and it's dropped before
+/// the flight is done.
+struct Waiter<'a, K: Hash + Eq, V, E> {
+    in_flight: &'a Mutex<Flights<K, V, E>>,
+    key: &'a K,
+    id: u64,
+    flight: Flight<V, E>,
+    done: bool,
+}
+
+impl<K: Hash + Eq, V: Clone, E: Clone> Future for Waiter<'_, K, V, E> {
+    type Output = Result<V, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = Pin::new(&mut self.flight).poll(cx);
+        self.done = result.is_ready();
+        result
+    }
+}
+
+impl<K: Hash + Eq, V, E> Drop for Waiter<'_, K, V, E> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut in_flight = lock(self.in_flight);
+        // Clones are only made with the flights locked, so no other task can pick it up now
+        let abandoned = in_flight
+            .get(self.key)
+            .is_some_and(|(id, flight)| *id == self.id && flight.strong_count() == Some(2));
+        if abandoned {
+            in_flight.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use core::{convert::Infallible, sync::atomic::AtomicUsize};
+    use futures::{executor::block_on, future::join_all};
+    use std::{sync::Arc, vec::Vec};
+
+    /// Returns pending once, so other tasks get to poll meanwhile.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn burst_generates_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let generator_calls = Arc::clone(&calls);
+        let store = AsyncGenStore::new(MemoryStore::<u8, u8>::new(), move |&n: &u8, ()| {
+            generator_calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                YieldOnce(false).await;
+                Ok::<_, Infallible>(n + 1)
+            }
+        });
+
+        let values: Vec<_> = block_on(join_all((0..8).map(|_| store.try_get_or_new(&1, ()))));
+        assert!(values.into_iter().all(|value| value == Ok(2)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(store.try_get(&1), Ok(Some(2)));
+
+        // Already cached, not generated again
+        assert_eq!(block_on(store.try_get_or_new(&1, ())), Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn dropped_generation_is_forgotten() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let generator_calls = Arc::clone(&calls);
+        let store = AsyncGenStore::new(MemoryStore::<u8, u8>::new(), move |&n: &u8, ()| {
+            generator_calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                YieldOnce(false).await;
+                Ok::<_, Infallible>(n + 1)
+            }
+        });
+
+        // Polled once and dropped halfway through
+        assert!(store.try_get_or_new(&1, ()).now_or_never().is_none());
+        assert!(lock(&store.in_flight).is_empty());
+
+        assert_eq!(block_on(store.try_get_or_new(&1, ())), Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,402 @@
+//! Caching the responses of axum handlers.
+//!
+//! [`ResponseCacheLayer`] is a tower layer for axum routers that keeps the responses of `GET` and
+//! `HEAD` requests in any thread safe store of [`CachedResponse`]s, keyed by the method, path and
+//! query of the request, and answers the same requests from there until they expire. What's cached
+//! and how is up to an [`HttpCache`], see the [`http_cache`][crate::http_cache] module docs.
+//!
+//! # Examples
+//! ```rust
+//! # use std::{sync::Arc, time::Duration};
+//! # use axum::{http::header::ACCEPT_LANGUAGE, routing::get, Router};
+//! # use ezcache::{axum::{CachedResponse, ResponseCacheLayer}, stores::ThreadSafeMemoryStore};
+//! #
+//! let store = Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default());
+//! let app: Router = Router::new()
+//!     .route("/report", get(|| async { "expensive report" }))
+//!     .layer(ResponseCacheLayer::new(store, Duration::from_secs(60)).with_vary(ACCEPT_LANGUAGE));
+//! ```
+
+use core::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::{borrow::ToOwned, boxed::Box, string::String, sync::Arc, vec::Vec};
+
+use ::axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::Request,
+    http::{header::AUTHORIZATION, HeaderName, HeaderValue, StatusCode},
+    response::Response,
+};
+use futures_util::{stream, StreamExt};
+use tower_layer::Layer;
+use tower_service::Service;
+
+pub use crate::http_cache::{CachedResponse, X_CACHE};
+use crate::{
+    clock::{Clock, SystemClock},
+    http_cache::HttpCache,
+    thread_safe::ThreadSafeTryCacheStore,
+};
+
+/// Turns a cached response back into a response, with an `X-Cache: HIT` header. Headers that
+/// aren't valid anymore are left out.
+fn hit(cached: CachedResponse) -> Response {
+    let mut response = Response::new(Body::from(cached.body));
+    *response.status_mut() = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+    let headers = response.headers_mut();
+    for (name, value) in cached.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_bytes(&value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(X_CACHE, HeaderValue::from_static("HIT"));
+    response
+}
+
+/// Reads a whole body of up to `limit` bytes. If it's longer or fails, gives back a body with what
+/// was read followed by the rest of it, failure included.
+async fn buffer(body: Body, limit: usize) -> Result<Vec<u8>, Body> {
+    let mut rest = body.into_data_stream();
+    let mut chunks: Vec<Result<Bytes, ::axum::Error>> = Vec::new();
+    let mut len = 0;
+    while let Some(chunk) = rest.next().await {
+        let overflows = chunk.as_ref().map_or(true, |chunk| {
+            len += chunk.len();
+            len > limit
+        });
+        chunks.push(chunk);
+        if overflows {
+            return Err(Body::from_stream(stream::iter(chunks).chain(rest)));
+        }
+    }
+    Ok(chunks.into_iter().flatten().flatten().collect())
+}
+
+/// Tower layer that caches the responses of the services it wraps, see the
+/// [module docs][self].
+///
+/// Generics:
+/// - `S`: [`ThreadSafeTryCacheStore`] the responses are kept in.
+/// - `C`: [`Clock`] used to expire responses, the system time by default.
+pub struct ResponseCacheLayer<S, C = SystemClock> {
+    cache: HttpCache<S, C>,
+}
+
+impl<S> ResponseCacheLayer<S> {
+    /// Make a new [`ResponseCacheLayer`] keeping responses in `store` for `ttl`, with bodies of up
+    /// to 1 MiB.
+    pub fn new(store: Arc<S>, ttl: Duration) -> Self {
+        Self {
+            cache: HttpCache::new(store, ttl),
+        }
+    }
+}
+
+impl<S, C> ResponseCacheLayer<S, C> {
+    /// Keeps a response apart for each value of the given request header.
+    #[must_use]
+    pub fn with_vary(mut self, header: HeaderName) -> Self {
+        self.cache = self.cache.with_vary(header);
+        self
+    }
+
+    /// Only caches responses whose body is up to `max_body` bytes long.
+    #[must_use]
+    pub fn with_max_body(mut self, max_body: usize) -> Self {
+        self.cache = self.cache.with_max_body(max_body);
+        self
+    }
+
+    /// Replaces the clock used to expire responses.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> ResponseCacheLayer<S, C2> {
+        ResponseCacheLayer {
+            cache: self.cache.with_clock(clock),
+        }
+    }
+}
+
+impl<S, C: Clone> Clone for ResponseCacheLayer<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<Svc, S, C: Clone> Layer<Svc> for ResponseCacheLayer<S, C> {
+    type Service = ResponseCache<Svc, S, C>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        ResponseCache {
+            inner,
+            cache: Arc::new(self.cache.clone()),
+        }
+    }
+}
+
+/// Service that caches the responses of another, made by a [`ResponseCacheLayer`].
+pub struct ResponseCache<Svc, S, C = SystemClock> {
+    inner: Svc,
+    cache: Arc<HttpCache<S, C>>,
+}
+
+impl<Svc: Clone, S, C> Clone for ResponseCache<Svc, S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            cache: Arc::clone(&self.cache),
+        }
+    }
+}
+
+impl<Svc, S, C> Service<Request> for ResponseCache<Svc, S, C>
+where
+    Svc: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    Svc::Future: Send + 'static,
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = String, Value = CachedResponse>
+        + Send
+        + Sync
+        + 'static,
+    C: Clock + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The clone might not be ready, the one that was polled is taken instead
+        let clone = self.inner.clone();
+        let mut inner = core::mem::replace(&mut self.inner, clone);
+        let cache = Arc::clone(&self.cache);
+        Box::pin(async move {
+            let target = request
+                .uri()
+                .path_and_query()
+                .map_or("/", |target| target.as_str());
+            let Some(key) = cache.key(request.method().as_str(), target, |name| {
+                request
+                    .headers()
+                    .get_all(name)
+                    .iter()
+                    .map(HeaderValue::as_bytes)
+            }) else {
+                let mut response = inner.call(request).await?;
+                response
+                    .headers_mut()
+                    .insert(X_CACHE, HeaderValue::from_static("MISS"));
+                return Ok(response);
+            };
+
+            if let Some(cached) = cache.lookup(&key).await {
+                return Ok(hit(cached));
+            }
+
+            let authorized = request.headers().contains_key(AUTHORIZATION);
+            let mut response = inner.call(request).await?;
+            let cacheable = cache.is_cacheable(
+                response.status().as_u16(),
+                response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_bytes())),
+                response.body().size_hint().exact(),
+                authorized,
+            );
+            if cacheable {
+                let (mut parts, body) = response.into_parts();
+                let body = match buffer(body, cache.max_body()).await {
+                    Ok(body) => body,
+                    // Passed on as it is, without caching it
+                    Err(body) => {
+                        parts
+                            .headers
+                            .insert(X_CACHE, HeaderValue::from_static("MISS"));
+                        return Ok(Response::from_parts(parts, body));
+                    }
+                };
+                let headers = parts
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_vec()))
+                    .collect();
+                cache
+                    .keep(key, parts.status.as_u16(), headers, body.clone())
+                    .await;
+                parts
+                    .headers
+                    .insert(X_CACHE, HeaderValue::from_static("MISS"));
+                return Ok(Response::from_parts(parts, Body::from(body)));
+            }
+            response
+                .headers_mut()
+                .insert(X_CACHE, HeaderValue::from_static("MISS"));
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::ThreadSafeMemoryStore};
+    use ::axum::{
+        body::to_bytes,
+        http::header::{ACCEPT_LANGUAGE, CACHE_CONTROL, COOKIE},
+        routing::get,
+        Router,
+    };
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::string::ToString;
+
+    async fn send(app: &Router, uri: &str, header: (HeaderName, &str)) -> (String, String) {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header.0, header.1)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().call(request).await.unwrap();
+        let hit = response.headers()[X_CACHE].to_str().unwrap().to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), hit)
+    }
+
+    #[test]
+    fn caches_by_target_and_vary() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let clock = Arc::new(MockClock::default());
+        let handler_calls = Arc::clone(&calls);
+        let app =
+            Router::new()
+                .route(
+                    "/",
+                    get(move || async move {
+                        handler_calls.fetch_add(1, Ordering::Relaxed).to_string()
+                    }),
+                )
+                .layer(
+                    ResponseCacheLayer::new(
+                        Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default()),
+                        Duration::from_mins(1),
+                    )
+                    .with_vary(ACCEPT_LANGUAGE)
+                    .with_clock(Arc::clone(&clock)),
+                );
+
+        runtime.block_on(async {
+            assert_eq!(
+                send(&app, "/", (ACCEPT_LANGUAGE, "en")).await,
+                ("0".into(), "MISS".into())
+            );
+            assert_eq!(
+                send(&app, "/", (ACCEPT_LANGUAGE, "en")).await,
+                ("0".into(), "HIT".into())
+            );
+            assert_eq!(
+                send(&app, "/", (ACCEPT_LANGUAGE, "es")).await,
+                ("1".into(), "MISS".into())
+            );
+            assert_eq!(
+                send(&app, "/?q", (ACCEPT_LANGUAGE, "en")).await,
+                ("2".into(), "MISS".into())
+            );
+
+            clock.advance(Duration::from_mins(1));
+            assert_eq!(
+                send(&app, "/", (ACCEPT_LANGUAGE, "en")).await,
+                ("3".into(), "MISS".into())
+            );
+        });
+    }
+
+    #[test]
+    fn skips_personal_responses() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (private_calls, public_calls) = (Arc::clone(&calls), Arc::clone(&calls));
+        let app =
+            Router::new()
+                .route(
+                    "/",
+                    get(move || async move {
+                        private_calls.fetch_add(1, Ordering::Relaxed).to_string()
+                    }),
+                )
+                .route(
+                    "/public",
+                    get(move || async move {
+                        let count = public_calls.fetch_add(1, Ordering::Relaxed);
+                        ([(CACHE_CONTROL, "public")], count.to_string())
+                    }),
+                )
+                .layer(ResponseCacheLayer::new(
+                    Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default()),
+                    Duration::from_mins(1),
+                ));
+
+        runtime.block_on(async {
+            let alice = (AUTHORIZATION, "Bearer alice");
+            let bob = (AUTHORIZATION, "Bearer bob");
+            assert_eq!(
+                send(&app, "/", alice.clone()).await,
+                ("0".into(), "MISS".into())
+            );
+            assert_eq!(
+                send(&app, "/", bob.clone()).await,
+                ("1".into(), "MISS".into())
+            );
+            assert_eq!(
+                send(&app, "/", (COOKIE, "id=alice")).await,
+                ("2".into(), "MISS".into())
+            );
+            assert_eq!(
+                send(&app, "/", (COOKIE, "id=alice")).await,
+                ("3".into(), "MISS".into())
+            );
+
+            assert_eq!(
+                send(&app, "/public", alice).await,
+                ("4".into(), "MISS".into())
+            );
+            assert_eq!(send(&app, "/public", bob).await, ("4".into(), "HIT".into()));
+        });
+    }
+
+    #[test]
+    fn passes_on_bodies_that_cant_be_buffered() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert_eq!(buffer(Body::from("hi"), 2).await.unwrap(), b"hi");
+
+            let long = buffer(Body::from("hello"), 2).await.unwrap_err();
+            assert_eq!(to_bytes(long, usize::MAX).await.unwrap(), "hello");
+
+            let chunks: [Result<&str, std::io::Error>; 2] =
+                [Ok("he"), Err(std::io::Error::other("broken"))];
+            let failed = buffer(Body::from_stream(stream::iter(chunks)), 8)
+                .await
+                .unwrap_err();
+            let mut failed = failed.into_data_stream();
+            assert_eq!(failed.next().await.unwrap().unwrap(), "he");
+            assert!(failed.next().await.unwrap().is_err());
+        });
+    }
+}
@@ -0,0 +1,143 @@
+//! Maintenance of file store directories from the command line.
+//!
+//! Entries are stored in files named after the hash of their key, so they are listed by that name
+//! and keys given to other commands are hashed the same way to find them.
+
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use ezcache::stores::file_stores::CustomHash;
+use serde::Serialize;
+
+const USAGE: &str = "\
+usage: ez-inspect <dir> <command>
+
+commands:
+  list             list entries with their size in bytes and age in seconds
+  dump             same as list, as JSON
+  delete <key>...  delete the entries of the given keys
+  gc <seconds>     delete entries older than the given age
+";
+
+/// Entry file of a store.
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    age: Option<Duration>,
+}
+
+/// Entry as written by `dump`.
+#[derive(Serialize)]
+struct DumpedEntry<'a> {
+    name: Cow<'a, str>,
+    size: u64,
+    age: Option<u64>,
+}
+
+/// Returns every entry in the store directory.
+fn entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = vec![];
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        entries.push(Entry {
+            path: file.path(),
+            size: metadata.len(),
+            age: metadata.modified().ok().and_then(|at| at.elapsed().ok()),
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Removes a file, returning whether it existed.
+fn remove(path: &Path) -> io::Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn run(dir: &Path, command: &str, args: &[String]) -> io::Result<ExitCode> {
+    let mut stdout = io::stdout().lock();
+    match (command, args) {
+        ("list", []) => {
+            for entry in entries(dir)? {
+                let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+                let age = entry
+                    .age
+                    .map_or_else(|| String::from("-"), |age| age.as_secs().to_string());
+                writeln!(stdout, "{name}\t{}\t{age}", entry.size)?;
+            }
+        }
+        ("dump", []) => {
+            let entries = entries(dir)?;
+            let dumped: Vec<_> = entries
+                .iter()
+                .map(|entry| DumpedEntry {
+                    name: entry.path.file_name().unwrap_or_default().to_string_lossy(),
+                    size: entry.size,
+                    age: entry.age.map(|age| age.as_secs()),
+                })
+                .collect();
+            serde_json::to_writer(&mut stdout, &dumped)?;
+            writeln!(stdout)?;
+        }
+        ("delete", [_, ..]) => {
+            let mut missing = false;
+            for key in args {
+                if remove(&dir.join(CustomHash::hash(key)))? {
+                    writeln!(stdout, "deleted {key}")?;
+                } else {
+                    eprintln!("no entry for {key}");
+                    missing = true;
+                }
+            }
+            if missing {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        ("gc", [max_age]) => {
+            let Ok(max_age) = max_age.parse().map(Duration::from_secs) else {
+                eprint!("invalid age: {max_age}\n\n{USAGE}");
+                return Ok(ExitCode::from(2));
+            };
+            let (mut count, mut size) = (0, 0);
+            for entry in entries(dir)? {
+                if entry.age.is_some_and(|age| age > max_age) && remove(&entry.path)? {
+                    count += 1;
+                    size += entry.size;
+                }
+            }
+            writeln!(stdout, "deleted {count} entries, {size} bytes")?;
+        }
+        _ => {
+            eprint!("{USAGE}");
+            return Ok(ExitCode::from(2));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [dir, command, args @ ..] = args.as_slice() else {
+        eprint!("{USAGE}");
+        return ExitCode::from(2);
+    };
+
+    run(Path::new(dir), command, args).unwrap_or_else(|error| {
+        eprintln!("ez-inspect: {error}");
+        ExitCode::FAILURE
+    })
+}
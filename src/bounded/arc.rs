@@ -0,0 +1,315 @@
+//! [Adaptive Replacement Cache](https://en.wikipedia.org/wiki/Adaptive_replacement_cache) policy.
+//!
+//! ARC splits entries between those seen once recently and those seen at least twice, and also
+//! remembers the keys recently evicted from each side (ghosts). Hitting a ghost means that side
+//! deserved more room, so the target size of each side tunes itself to the workload instead of
+//! being fixed to recency (LRU) or frequency (LFU).
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{CacheStore, bounded::{LruMemoryStore, arc::ArcMemoryStore}};
+//! #
+//! let mut lru = LruMemoryStore::new(4);
+//! let mut arc = ArcMemoryStore::new(4);
+//!
+//! // A few hot keys mixed with a long scan of keys used only once
+//! for round in 0..50 {
+//!     for key in [0, 1, 2, 0, 1, 2, round + 100, round + 200, round + 300] {
+//!         if lru.get(key).is_none() {
+//!             lru.set(key, key);
+//!         }
+//!         if arc.get(key).is_none() {
+//!             arc.set(key, key);
+//!         }
+//!     }
+//! }
+//!
+//! // ARC keeps the hot keys around while the scan flushes them out of the LRU
+//! assert!(arc.stats().hit_ratio() > lru.stats().hit_ratio());
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{cell::RefCell, hash::Hash};
+use std::collections::HashMap;
+
+use super::{list::LruList, BoundedCacheStore, HitStats, ResizableCacheStore};
+
+struct ArcInner<K, V> {
+    values: HashMap<K, V>,
+    /// Resident keys seen once recently.
+    t1: LruList<K>,
+    /// Resident keys seen at least twice recently.
+    t2: LruList<K>,
+    /// Ghost keys recently evicted from `t1`.
+    b1: LruList<K>,
+    /// Ghost keys recently evicted from `t2`.
+    b2: LruList<K>,
+    /// Target size of `t1`.
+    target: usize,
+    stats: HitStats,
+}
+
+impl<K: Hash + Eq + Clone, V> ArcInner<K, V> {
+    /// Marks a resident key as used, promoting it to the frequent side.
+    fn promote(&mut self, key: &K) {
+        if self.t1.remove(key) {
+            self.t2.push_front(key.clone());
+        } else {
+            self.t2.touch(key);
+        }
+    }
+
+    /// Evicts an entry into its ghost list, from the side that's over its target.
+    fn replace(&mut self, capacity: usize, ghost_of_t2: bool) {
+        if self.t1.len() + self.t2.len() < capacity {
+            return;
+        }
+
+        let from_t1 = !self.t1.is_empty()
+            && (self.t1.len() > self.target || (ghost_of_t2 && self.t1.len() == self.target));
+        let (resident, ghosts) = if from_t1 || self.t2.is_empty() {
+            (&mut self.t1, &mut self.b1)
+        } else {
+            (&mut self.t2, &mut self.b2)
+        };
+        if let Some(key) = resident.pop_back() {
+            self.values.remove(&key);
+            ghosts.push_front(key);
+        }
+    }
+
+    /// Makes room for a key that's not resident, adapting the target size on ghost hits.
+    fn admit(&mut self, key: &K, capacity: usize) {
+        if self.b1.contains(key) {
+            let delta = (self.b2.len() / self.b1.len()).max(1);
+            self.target = (self.target + delta).min(capacity);
+            self.replace(capacity, false);
+            self.b1.remove(key);
+            self.t2.push_front(key.clone());
+        } else if self.b2.contains(key) {
+            let delta = (self.b1.len() / self.b2.len()).max(1);
+            self.target = self.target.saturating_sub(delta);
+            self.replace(capacity, true);
+            self.b2.remove(key);
+            self.t2.push_front(key.clone());
+        } else {
+            let recent = self.t1.len() + self.b1.len();
+            let total = recent + self.t2.len() + self.b2.len();
+            if recent >= capacity {
+                if self.t1.len() < capacity {
+                    self.b1.pop_back();
+                    self.replace(capacity, false);
+                } else if let Some(evicted) = self.t1.pop_back() {
+                    self.values.remove(&evicted);
+                }
+            } else if total >= capacity {
+                if total >= capacity * 2 {
+                    self.b2.pop_back();
+                }
+                self.replace(capacity, false);
+            }
+            self.t1.push_front(key.clone());
+        }
+    }
+}
+
+/// In memory store that holds up to a fixed amount of entries following the ARC policy.
+///
+/// Both [`get`][CacheStore::get] and [`set`][CacheStore::set] count as uses, while
+/// [`exists`][CacheStore::exists] doesn't. The [`victim`][BoundedCacheStore::victim] is only an
+/// estimate, as ARC decides what to evict depending on the key being inserted.
+pub struct ArcMemoryStore<K, V> {
+    capacity: usize,
+    inner: RefCell<ArcInner<K, V>>,
+}
+
+impl<K, V> ArcMemoryStore<K, V> {
+    /// Makes a new empty store that holds up to `capacity` entries.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: RefCell::new(ArcInner {
+                values: HashMap::new(),
+                t1: LruList::default(),
+                t2: LruList::default(),
+                b1: LruList::default(),
+                b2: LruList::default(),
+                target: 0,
+                stats: HitStats::default(),
+            }),
+        }
+    }
+
+    /// Hit and miss counters of all gets so far.
+    #[must_use]
+    pub fn stats(&self) -> HitStats {
+        self.inner.borrow().stats
+    }
+
+    /// Replaces the hit and miss counters, to carry on from the ones of a previous run.
+    pub fn restore_stats(&mut self, stats: HitStats) {
+        self.inner.get_mut().stats = stats;
+    }
+
+    /// Current target amount of entries seen only once, it grows on recency-heavy workloads and
+    /// shrinks on frequency-heavy ones.
+    #[must_use]
+    pub fn recency_target(&self) -> usize {
+        self.inner.borrow().target
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> CacheStore for ArcMemoryStore<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let mut inner = self.inner.borrow_mut();
+        let value = inner.values.get(key.borrow()).cloned();
+        inner.stats.record(value.is_some());
+        if value.is_some() {
+            inner.promote(key.borrow());
+        }
+        value
+    }
+
+    fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let inner = self.inner.get_mut();
+        let key = key.borrow();
+        if inner.values.contains_key(key) {
+            inner.promote(key);
+        } else {
+            inner.admit(key, self.capacity);
+        }
+        inner.values.insert(key.clone(), value.borrow().clone());
+    }
+
+    /// Removed entries don't become ghosts, they weren't evicted.
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let inner = self.inner.get_mut();
+        let value = inner.values.remove(key.borrow())?;
+        if !inner.t1.remove(key.borrow()) {
+            inner.t2.remove(key.borrow());
+        }
+        Some(value)
+    }
+
+    fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
+        self.inner.borrow().values.contains_key(key.borrow())
+    }
+}
+
+/// Entries go through [`set`][CacheStore::set], so they might evict each other.
+impl<K: Hash + Eq + Clone, V: Clone> Extend<(K, V)> for ArcMemoryStore<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.set(key, value);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> BoundedCacheStore for ArcMemoryStore<K, V> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.inner.borrow().values.len()
+    }
+
+    fn victim(&self) -> Option<Self::Key> {
+        let inner = self.inner.borrow();
+        if inner.values.len() < self.capacity {
+            return None;
+        }
+
+        if !inner.t1.is_empty() && (inner.t1.len() > inner.target || inner.t2.is_empty()) {
+            inner.t1.back().cloned()
+        } else {
+            inner.t2.back().cloned()
+        }
+    }
+}
+
+/// Shrinking keeps the ghosts of the evicted entries, up to the new capacity.
+impl<K: Hash + Eq + Clone, V: Clone> ResizableCacheStore for ArcMemoryStore<K, V> {
+    fn hit_stats(&self) -> HitStats {
+        self.stats()
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let inner = self.inner.get_mut();
+        inner.target = inner.target.min(capacity);
+        while inner.t1.len() + inner.t2.len() > capacity {
+            inner.replace(capacity, false);
+        }
+        while inner.b1.len() + inner.b2.len() > capacity {
+            if inner.b2.pop_back().is_none() {
+                inner.b1.pop_back();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_exceeds_capacity() {
+        let mut store = ArcMemoryStore::new(3);
+        for i in 0..100 {
+            store.set(i % 7, i);
+            store.get(i % 5);
+            assert!(store.len() <= 3);
+        }
+    }
+
+    #[test]
+    fn shrinking_evicts_down_to_capacity() {
+        let mut store = ArcMemoryStore::new(8);
+        for i in 0..8 {
+            store.set(i, i);
+        }
+        store.get(7);
+        store.set_capacity(3);
+        assert_eq!(store.len(), 3);
+        assert!(store.exists(7));
+
+        store.set(8, 8);
+        assert_eq!(store.len(), 3);
+    }
+
+    #[test]
+    fn frequent_keys_survive_scans() {
+        let mut store = ArcMemoryStore::new(3);
+        store.set(0, 0);
+        store.get(0);
+
+        for i in 1..50 {
+            store.set(i, i);
+        }
+        assert!(store.exists(0));
+    }
+
+    #[test]
+    fn ghost_hits_adapt_target() {
+        let mut store = ArcMemoryStore::new(2);
+        store.set(0, 0);
+        store.set(1, 1);
+        store.get(1);
+        store.set(2, 2);
+        assert!(!store.exists(0));
+
+        // 0 is a ghost of the recent side, so that side should get more room
+        store.set(0, 0);
+        assert_eq!(store.recency_target(), 1);
+    }
+}
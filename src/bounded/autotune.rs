@@ -0,0 +1,181 @@
+//! Sizing of bounded stores after their hit ratio.
+//!
+//! The right capacity for a cache depends on the workload of each deployment. A [`CapacityTuner`]
+//! samples the [`HitStats`] and size of a [`ResizableCacheStore`] every time it's
+//! [called][CapacityTuner::tune], and grows it while its hit ratio is below a target or shrinks it
+//! while it's comfortably above it, always between the configured bounds. With a
+//! [memory budget][CapacityTuner::with_max_bytes] it also shrinks the store when it goes over it,
+//! and never grows it past what would fit.
+//!
+//! Calls are meant to be spaced out, like from a periodic task, so each sample sees enough gets.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     CacheStore,
+//! #     bounded::{BoundedCacheStore, LruMemoryStore, autotune::CapacityTuner},
+//! # };
+//! #
+//! let mut store = LruMemoryStore::new(10);
+//! let mut tuner = CapacityTuner::new(10, 1000, 0.9);
+//!
+//! // A working set larger than the store keeps missing
+//! for round in 0..20 {
+//!     for key in 0..100 {
+//!         if store.get(key).is_none() {
+//!             store.set(key, key);
+//!         }
+//!     }
+//!     tuner.tune(&mut store);
+//! }
+//! assert!(store.capacity() >= 100);
+//! ```
+
+use super::{HitStats, ResizableCacheStore};
+use crate::size::SizedStore;
+
+/// Controller that grows or shrinks a [`ResizableCacheStore`], see the [module docs][self].
+#[derive(Debug, Clone)]
+pub struct CapacityTuner {
+    min: usize,
+    max: usize,
+    target_hit_ratio: f64,
+    /// Hit ratio over the target that still doesn't shrink the store, so it doesn't flap.
+    tolerance: f64,
+    /// Fraction of the capacity it changes by at once.
+    step: f64,
+    /// Least amount of gets a sample must have to act on it.
+    min_samples: u64,
+    max_bytes: Option<usize>,
+    /// Stats of the store at the end of the previous sample.
+    last: HitStats,
+}
+
+impl CapacityTuner {
+    /// Make a new [`CapacityTuner`] that keeps the capacity between `min` and `max` entries,
+    /// aiming for the given hit ratio. A `max` lower than `min` is raised to it.
+    #[must_use]
+    pub fn new(min: usize, max: usize, target_hit_ratio: f64) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            target_hit_ratio,
+            tolerance: 0.05,
+            step: 0.25,
+            min_samples: 100,
+            max_bytes: None,
+            last: HitStats::default(),
+        }
+    }
+
+    /// Sets the fraction of the current capacity it changes by at once, 25% by default.
+    #[must_use]
+    pub fn with_step(mut self, step: f64) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Sets how far over the target the hit ratio has to be for the store to shrink, 5% by
+    /// default.
+    #[must_use]
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Sets the least amount of gets since the previous call needed to act, 100 by default.
+    /// Calls with fewer gets than that keep accumulating them.
+    #[must_use]
+    pub fn with_min_samples(mut self, min_samples: u64) -> Self {
+        self.min_samples = min_samples;
+        self
+    }
+
+    /// Keeps the store under `max_bytes`, as told by its [`SizedStore`] implementation.
+    #[must_use]
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Samples the store and resizes it if needed, returns its new capacity.
+    ///
+    /// It only grows when the store is full, as misses can't be blamed on its capacity otherwise.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn tune<S: ResizableCacheStore + SizedStore>(&mut self, store: &mut S) -> usize {
+        let capacity = store.capacity();
+        let stats = store.hit_stats();
+        let sample = HitStats {
+            hits: stats.hits.saturating_sub(self.last.hits),
+            misses: stats.misses.saturating_sub(self.last.misses),
+        };
+        if sample.hits + sample.misses < self.min_samples {
+            return capacity;
+        }
+        self.last = stats;
+
+        let step = ((capacity as f64 * self.step) as usize).max(1);
+        let bytes = store.bytes_used();
+        // Largest capacity that fits in the budget at the current size per entry
+        let fits = match (self.max_bytes, store.len()) {
+            (Some(max_bytes), len) if len > 0 && bytes > 0 => max_bytes / bytes.div_ceil(len),
+            _ => usize::MAX,
+        };
+
+        let ratio = sample.hit_ratio();
+        let wanted = if self.max_bytes.is_some_and(|max_bytes| bytes > max_bytes) {
+            capacity.saturating_sub(step).min(fits)
+        } else if ratio < self.target_hit_ratio && store.len() >= capacity {
+            capacity.saturating_add(step).min(fits).max(capacity)
+        } else if ratio > self.target_hit_ratio + self.tolerance {
+            capacity.saturating_sub(step)
+        } else {
+            capacity
+        };
+
+        let wanted = wanted.clamp(self.min, self.max);
+        if wanted != capacity {
+            store.set_capacity(wanted);
+        }
+        wanted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bounded::{BoundedCacheStore, LruMemoryStore},
+        CacheStore,
+    };
+
+    #[test]
+    fn shrinks_over_budget_and_when_hitting() {
+        let mut store = LruMemoryStore::<u64, u64>::new(100);
+        for key in 0..100 {
+            store.set(key, key);
+        }
+        // 16 bytes per entry, room for 50 of them
+        let mut tuner = CapacityTuner::new(10, 100, 0.5)
+            .with_max_bytes(800)
+            .with_min_samples(10);
+        for key in 0..10 {
+            store.get(key);
+        }
+        assert_eq!(tuner.tune(&mut store), 50);
+        assert_eq!(store.len(), 50);
+
+        // Every get hits, more than needed
+        for key in 60..70 {
+            store.get(key);
+        }
+        assert_eq!(tuner.tune(&mut store), 38);
+        // Too few gets to tell
+        store.get(0);
+        assert_eq!(tuner.tune(&mut store), 38);
+    }
+}
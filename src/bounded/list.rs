@@ -0,0 +1,137 @@
+//! Recency ordered set of keys used as building block by the bounded stores.
+
+use core::hash::Hash;
+use std::{collections::HashMap, vec::Vec};
+
+struct Node<K> {
+    key: K,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Set of keys ordered from most to least recently used, all operations are O(1).
+///
+/// Nodes live in a slab indexed by the keys, so there are no allocations per operation once it's
+/// warmed up.
+pub(crate) struct LruList<K> {
+    index: HashMap<K, usize>,
+    nodes: Vec<Option<Node<K>>>,
+    free: Vec<usize>,
+    /// Most recently used.
+    head: Option<usize>,
+    /// Least recently used.
+    tail: Option<usize>,
+}
+
+impl<K> Default for LruList<K> {
+    fn default() -> Self {
+        Self {
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: None,
+            tail: None,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> LruList<K> {
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn node(&mut self, idx: usize) -> &mut Node<K> {
+        self.nodes[idx].as_mut().expect("linked node to be alive")
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = {
+            let node = self.node(idx);
+            (node.prev.take(), node.next.take())
+        };
+        match prev {
+            Some(prev) => self.node(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.node(next).prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn link_front(&mut self, idx: usize) {
+        let old_head = self.head.replace(idx);
+        self.node(idx).next = old_head;
+        match old_head {
+            Some(old_head) => self.node(old_head).prev = Some(idx),
+            None => self.tail = Some(idx),
+        }
+    }
+
+    /// Inserts the key as the most recently used one, or just marks it as so if already present.
+    pub fn push_front(&mut self, key: K) {
+        if self.touch(&key) {
+            return;
+        }
+
+        let node = Node {
+            key: key.clone(),
+            prev: None,
+            next: None,
+        };
+        let idx = if let Some(idx) = self.free.pop() {
+            self.nodes[idx] = Some(node);
+            idx
+        } else {
+            self.nodes.push(Some(node));
+            self.nodes.len() - 1
+        };
+        self.index.insert(key, idx);
+        self.link_front(idx);
+    }
+
+    /// Marks the key as the most recently used one, returns if it was present.
+    pub fn touch(&mut self, key: &K) -> bool {
+        let Some(&idx) = self.index.get(key) else {
+            return false;
+        };
+        if self.head != Some(idx) {
+            self.unlink(idx);
+            self.link_front(idx);
+        }
+        true
+    }
+
+    /// Removes a key, returns if it was present.
+    pub fn remove(&mut self, key: &K) -> bool {
+        let Some(idx) = self.index.remove(key) else {
+            return false;
+        };
+        self.unlink(idx);
+        self.nodes[idx] = None;
+        self.free.push(idx);
+        true
+    }
+
+    /// Least recently used key.
+    pub fn back(&self) -> Option<&K> {
+        self.tail
+            .and_then(|idx| self.nodes[idx].as_ref())
+            .map(|node| &node.key)
+    }
+
+    /// Removes and returns the least recently used key.
+    pub fn pop_back(&mut self) -> Option<K> {
+        let key = self.back()?.clone();
+        self.remove(&key);
+        Some(key)
+    }
+}
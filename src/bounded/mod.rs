@@ -0,0 +1,376 @@
+//! Cache stores with a limited capacity, which evict entries by themselves to make room for new
+//! ones.
+//!
+//! Traits:
+//! - [`BoundedCacheStore`]: Implemented by any store with a capacity, exposes which entry would be
+//!   evicted next.
+//! - [`PriorityCacheStore`]: Bounded store that takes a [`Priority`] per entry, always evicting
+//!   lower priority entries first.
+//! - [`ResizableCacheStore`]: Bounded store whose capacity can change while in use, so a
+//!   [`CapacityTuner`][autotune::CapacityTuner] can size it after its hit ratio.
+//!
+//! Stores:
+//! - [`LruMemoryStore`]: In memory store that evicts the least recently used entry.
+//! - [`ArcMemoryStore`][arc::ArcMemoryStore]: In memory store following the Adaptive Replacement
+//!   Cache policy, balances by itself between recency and frequency.
+//! - [`ClockMemoryStore`][clock::ClockMemoryStore]: Thread safe in memory store with CLOCK
+//!   eviction, reads only set an atomic flag instead of reordering entries under a lock. Requires
+//!   the `thread-safe` feature.
+//!
+//! Memory stores keep [`HitStats`] so different policies can be compared on the same workload.
+//!
+//! Wrappers:
+//! - [`TinyLfuStore`][tinylfu::TinyLfuStore]: Admission filter that only lets new entries in if
+//!   they are used more often than the entry they would evict.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{CacheStore, bounded::{BoundedCacheStore, LruMemoryStore}};
+//! #
+//! let mut store = LruMemoryStore::new(2);
+//!
+//! store.set(0, "zero");
+//! store.set(1, "one");
+//! // Using the first key makes the second one the least recently used
+//! assert_eq!(store.get(0), Some("zero"));
+//! assert_eq!(store.victim(), Some(1));
+//!
+//! // So it gets evicted to make room for a new one
+//! store.set(2, "two");
+//! assert_eq!(store.get(1), None);
+//! assert_eq!(store.len(), 2);
+//! ```
+
+pub mod arc;
+pub mod autotune;
+#[cfg(feature = "thread-safe")]
+pub mod clock;
+mod list;
+pub mod tinylfu;
+
+use crate::{
+    __internal_prelude::*,
+    size::{MemSize, SizedStore},
+};
+
+use core::{cell::RefCell, hash::Hash};
+use std::collections::HashMap;
+
+use list::LruList;
+
+/// Trait for a [`CacheStore`] with a limited capacity, that evicts entries by itself when it's
+/// full.
+pub trait BoundedCacheStore: CacheStore {
+    /// Maximum amount of entries the store can hold.
+    fn capacity(&self) -> usize;
+    /// Current amount of entries of the store.
+    fn len(&self) -> usize;
+    /// Checks if the store has no entries.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Key that would be evicted if a new entry was inserted right now, [`None`] if there's still
+    /// room for it.
+    fn victim(&self) -> Option<Self::Key>;
+}
+
+/// Eviction priority of an entry, entries are always evicted from the lowest priority that has
+/// any, regardless of how recently or frequently they were used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Best-effort data, the first to go.
+    Low,
+    #[default]
+    Normal,
+    /// Important data, only evicted if there's nothing else left.
+    High,
+}
+
+impl Priority {
+    /// All priorities, from lowest to highest.
+    pub const ALL: [Self; 3] = [Self::Low, Self::Normal, Self::High];
+}
+
+/// Trait for a [`BoundedCacheStore`] that can mix entries of different [`Priority`] in it.
+pub trait PriorityCacheStore: BoundedCacheStore {
+    /// Sets an entry with the given priority, replacing the priority it had if already present.
+    fn set_with_priority(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+        priority: Priority,
+    );
+}
+
+/// Trait for a [`BoundedCacheStore`] that keeps [`HitStats`] and whose capacity can be changed
+/// while in use.
+pub trait ResizableCacheStore: BoundedCacheStore {
+    /// Hit and miss counters of all gets so far.
+    fn hit_stats(&self) -> HitStats;
+    /// Changes the capacity, evicting entries right away if there are more than it.
+    fn set_capacity(&mut self, capacity: usize);
+}
+
+/// Hit and miss counters of a store, to compare how well eviction policies do on a workload.
+///
+/// With the "serde" feature they can be saved along with the rest of the application state and
+/// given back to a store after a restart, so hit ratios keep counting from where they were.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HitStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl HitStats {
+    /// Ratio of gets that were hits, `0.0` if there were no gets at all.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+}
+
+struct LruInner<K, V> {
+    values: HashMap<K, V>,
+    /// Recency order of the keys of each priority.
+    tiers: [LruList<K>; Priority::ALL.len()],
+    stats: HitStats,
+}
+
+impl<K: Hash + Eq + Clone, V> LruInner<K, V> {
+    fn touch(&mut self, key: &K) {
+        self.tiers.iter_mut().any(|tier| tier.touch(key));
+    }
+
+    /// Least recently used key of the lowest priority.
+    fn victim(&self) -> Option<&K> {
+        self.tiers.iter().find_map(LruList::back)
+    }
+}
+
+/// In memory store that holds up to a fixed amount of entries, evicting the least recently used
+/// one when full.
+///
+/// Both [`get`][CacheStore::get] and [`set`][CacheStore::set] count as uses, while
+/// [`exists`][CacheStore::exists] doesn't.
+///
+/// It also implements [`PriorityCacheStore`], keeping a recency order per priority. Plain
+/// [`set`][CacheStore::set]s use the default priority.
+pub struct LruMemoryStore<K, V> {
+    capacity: usize,
+    inner: RefCell<LruInner<K, V>>,
+}
+
+impl<K, V> LruMemoryStore<K, V> {
+    /// Makes a new empty store that holds up to `capacity` entries.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: RefCell::new(LruInner {
+                values: HashMap::new(),
+                tiers: Default::default(),
+                stats: HitStats::default(),
+            }),
+        }
+    }
+
+    /// Hit and miss counters of all gets so far.
+    #[must_use]
+    pub fn stats(&self) -> HitStats {
+        self.inner.borrow().stats
+    }
+
+    /// Replaces the hit and miss counters, to carry on from the ones of a previous run.
+    pub fn restore_stats(&mut self, stats: HitStats) {
+        self.inner.get_mut().stats = stats;
+    }
+}
+
+impl<K: MemSize, V: MemSize> SizedStore for LruMemoryStore<K, V> {
+    fn bytes_used(&self) -> usize {
+        self.inner
+            .borrow()
+            .values
+            .iter()
+            .map(|(key, value)| key.mem_size() + value.mem_size())
+            .sum()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> CacheStore for LruMemoryStore<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let mut inner = self.inner.borrow_mut();
+        let value = inner.values.get(key.borrow()).cloned();
+        inner.stats.record(value.is_some());
+        if value.is_some() {
+            inner.touch(key.borrow());
+        }
+        value
+    }
+
+    fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>) {
+        self.set_with_priority(key, value, Priority::default());
+    }
+
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let inner = self.inner.get_mut();
+        let value = inner.values.remove(key.borrow())?;
+        inner.tiers.iter_mut().any(|tier| tier.remove(key.borrow()));
+        Some(value)
+    }
+
+    fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
+        self.inner.borrow().values.contains_key(key.borrow())
+    }
+}
+
+/// Entries go through [`set`][CacheStore::set], so they might evict each other.
+impl<K: Hash + Eq + Clone, V: Clone> Extend<(K, V)> for LruMemoryStore<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.set(key, value);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> BoundedCacheStore for LruMemoryStore<K, V> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.inner.borrow().values.len()
+    }
+
+    fn victim(&self) -> Option<Self::Key> {
+        let inner = self.inner.borrow();
+        if inner.values.len() < self.capacity {
+            return None;
+        }
+        inner.victim().cloned()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> ResizableCacheStore for LruMemoryStore<K, V> {
+    fn hit_stats(&self) -> HitStats {
+        self.stats()
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let inner = self.inner.get_mut();
+        while inner.values.len() > capacity {
+            let Some(evicted) = inner.tiers.iter_mut().find_map(LruList::pop_back) else {
+                break;
+            };
+            inner.values.remove(&evicted);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> PriorityCacheStore for LruMemoryStore<K, V> {
+    fn set_with_priority(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+        priority: Priority,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let inner = self.inner.get_mut();
+        let key = key.borrow();
+        let existed = inner.tiers.iter_mut().any(|tier| tier.remove(key));
+        if !existed && inner.values.len() >= self.capacity {
+            if let Some(evicted) = inner.tiers.iter_mut().find_map(LruList::pop_back) {
+                inner.values.remove(&evicted);
+            }
+        }
+        inner.values.insert(key.clone(), value.borrow().clone());
+        inner.tiers[priority as usize].push_front(key.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_evicts_least_recent() {
+        let mut store = LruMemoryStore::new(3);
+        for i in 0..3 {
+            store.set(i, i);
+        }
+        store.get(0);
+        store.set(1, 1);
+
+        assert_eq!(store.victim(), Some(2));
+        store.set(3, 3);
+        assert!(!store.exists(2));
+        assert_eq!(store.len(), 3);
+
+        store.set(4, 4);
+        assert!(!store.exists(0));
+        assert!(store.exists(1));
+
+        assert_eq!(store.stats(), HitStats { hits: 1, misses: 0 });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn stats_survive_restarts() {
+        let store = LruMemoryStore::<u8, u8>::new(1);
+        store.get(0);
+        let saved = serde_json::to_string(&store.stats()).unwrap();
+
+        let mut store = LruMemoryStore::<u8, u8>::new(1);
+        store.restore_stats(serde_json::from_str(&saved).unwrap());
+        store.get(0);
+        assert_eq!(store.stats(), HitStats { hits: 0, misses: 2 });
+    }
+
+    #[test]
+    fn lru_evicts_lower_priority_first() {
+        let mut store = LruMemoryStore::new(2);
+        store.set_with_priority(0, 0, Priority::High);
+        store.set_with_priority(1, 1, Priority::Low);
+        store.get(1);
+
+        // 1 goes even if it's the most recently used
+        assert_eq!(store.victim(), Some(1));
+        store.set(2, 2);
+        assert!(store.exists(0));
+        assert!(!store.exists(1));
+
+        // Until there's nothing below the high priority ones
+        store.set_with_priority(2, 2, Priority::High);
+        store.set(3, 3);
+        assert!(!store.exists(0));
+    }
+
+    #[test]
+    fn lru_zero_capacity() {
+        let mut store = LruMemoryStore::new(0);
+        store.set(0, 0);
+        assert_eq!(store.get(0), None);
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+//! [TinyLFU](https://arxiv.org/abs/1512.00727) admission filter for bounded stores.
+//!
+//! A bounded store will always evict some entry to make room for a new one, even if the new one
+//! is never going to be used again. On scan-heavy workloads this means that lots of keys used only
+//! once keep pushing out the entries that are actually used frequently.
+//!
+//! [`TinyLfuStore`] keeps an approximate count of the recent uses of every key in a small
+//! count-min sketch, and only lets a new entry in when it's been used more often than the entry it
+//! would evict. Counters are halved periodically so old popularity fades away.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{CacheStore, bounded::{LruMemoryStore, tinylfu::TinyLfuStore}};
+//! #
+//! let mut store = TinyLfuStore::new(LruMemoryStore::new(2));
+//!
+//! // Some frequently used keys
+//! for _ in 0..5 {
+//!     for key in [0, 1] {
+//!         if store.get(key).is_none() {
+//!             store.set(key, key);
+//!         }
+//!     }
+//! }
+//!
+//! // A scan over lots of keys used once
+//! for key in 100..200 {
+//!     store.set(key, key);
+//! }
+//!
+//! // Doesn't evict the popular ones
+//! assert_eq!(store.get(0), Some(0));
+//! assert_eq!(store.get(1), Some(1));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{
+    hash::{BuildHasher, Hash},
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+use std::{collections::hash_map::RandomState, vec::Vec};
+
+use super::{BoundedCacheStore, Priority, PriorityCacheStore};
+
+/// Rows of the count-min sketch, each indexed by a different hash of the key.
+const DEPTH: usize = 4;
+/// Counters saturate at this value, as only relative popularity matters.
+const MAX_COUNT: u8 = 15;
+
+/// Count-min sketch of 4-bit-like saturating counters with periodic aging.
+struct FrequencySketch {
+    table: Vec<AtomicU8>,
+    width_mask: usize,
+    hasher: RandomState,
+    additions: AtomicUsize,
+    sample_size: usize,
+}
+
+impl FrequencySketch {
+    fn new(capacity: usize) -> Self {
+        // Wide enough to keep collisions low even on tiny stores
+        let width = capacity.saturating_mul(4).max(256).next_power_of_two();
+        Self {
+            table: (0..width * DEPTH).map(|_| AtomicU8::new(0)).collect(),
+            width_mask: width - 1,
+            hasher: RandomState::new(),
+            additions: AtomicUsize::new(0),
+            sample_size: width * 10,
+        }
+    }
+
+    fn indexes(&self, key: &impl Hash) -> impl Iterator<Item = usize> + '_ {
+        let hash = self.hasher.hash_one(key);
+        // Double hashing to get a different index per row out of a single hash
+        #[allow(clippy::cast_possible_truncation)]
+        let (h1, h2) = (hash as usize, (hash >> 32) as usize | 1);
+        (0..DEPTH).map(move |row| {
+            row * (self.width_mask + 1) + (h1.wrapping_add(row.wrapping_mul(h2)) & self.width_mask)
+        })
+    }
+
+    fn frequency(&self, key: &impl Hash) -> u8 {
+        self.indexes(key)
+            .map(|idx| self.table[idx].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or_default()
+    }
+
+    fn increment(&self, key: &impl Hash) {
+        for idx in self.indexes(key) {
+            let _ = self.table[idx].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < MAX_COUNT).then_some(count + 1)
+            });
+        }
+
+        if self.additions.fetch_add(1, Ordering::Relaxed) + 1 >= self.sample_size {
+            self.age();
+        }
+    }
+
+    /// Halves all counters so the sketch favors recent popularity.
+    fn age(&self) {
+        self.additions.store(0, Ordering::Relaxed);
+        for counter in &self.table {
+            let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count / 2)
+            });
+        }
+    }
+}
+
+/// Wrapper around a [`BoundedCacheStore`] that filters which new entries are allowed in, based on
+/// the recent frequency of use of their keys.
+///
+/// Sets of keys that are not in the store yet are silently dropped if the store is full and the
+/// key isn't used more often than the entry that would be evicted.
+///
+/// Generics:
+/// - `K`: Type of the key used for cache indexing.
+/// - `V`: Type of the value stored in the cache store.
+/// - `S`: [`BoundedCacheStore`] which this wraps around.
+pub struct TinyLfuStore<K, V, S: BoundedCacheStore<Key = K, Value = V>> {
+    pub store: S,
+    sketch: FrequencySketch,
+    __phantom: PhantomData<(K, V)>,
+}
+
+impl<K: Hash, V, S: BoundedCacheStore<Key = K, Value = V>> TinyLfuStore<K, V, S> {
+    /// Make a new [`TinyLfuStore`] around a bounded store, the sketch is sized after its capacity.
+    pub fn new(store: S) -> Self {
+        let sketch = FrequencySketch::new(store.capacity());
+        Self {
+            store,
+            sketch,
+            __phantom: PhantomData,
+        }
+    }
+
+    /// Estimated amount of recent uses of a key.
+    pub fn frequency(&self, key: impl Borrow<K>) -> u8 {
+        self.sketch.frequency(key.borrow())
+    }
+
+    /// Records a use of a key and checks if it's allowed into the store.
+    fn admit(&self, key: &K) -> bool {
+        self.sketch.increment(key);
+        self.store.exists(key)
+            || self
+                .store
+                .victim()
+                .is_none_or(|victim| self.sketch.frequency(key) > self.sketch.frequency(&victim))
+    }
+}
+
+impl<K: Hash, V, S: BoundedCacheStore<Key = K, Value = V>> CacheStore for TinyLfuStore<K, V, S> {
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        self.sketch.increment(key.borrow());
+        self.store.get(key)
+    }
+
+    fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>) {
+        if self.admit(key.borrow()) {
+            self.store.set(key, value);
+        }
+    }
+
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        self.store.remove(key)
+    }
+
+    fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
+        self.store.exists(key)
+    }
+}
+
+impl<K: Hash, V, S: BoundedCacheStore<Key = K, Value = V>> BoundedCacheStore
+    for TinyLfuStore<K, V, S>
+{
+    fn capacity(&self) -> usize {
+        self.store.capacity()
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn victim(&self) -> Option<Self::Key> {
+        self.store.victim()
+    }
+}
+
+impl<K: Hash, V, S: PriorityCacheStore<Key = K, Value = V>> PriorityCacheStore
+    for TinyLfuStore<K, V, S>
+{
+    fn set_with_priority(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+        priority: Priority,
+    ) {
+        if self.admit(key.borrow()) {
+            self.store.set_with_priority(key, value, priority);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounded::LruMemoryStore;
+
+    #[test]
+    fn rejects_one_hit_wonders() {
+        let mut store = TinyLfuStore::new(LruMemoryStore::new(1));
+        store.set(0, 0);
+        store.get(0);
+
+        store.set(1, 1);
+        assert!(!store.exists(1));
+        assert!(store.exists(0));
+    }
+
+    #[test]
+    fn admits_frequent_keys() {
+        let mut store = TinyLfuStore::new(LruMemoryStore::new(1));
+        store.set(0, 0);
+        for _ in 0..3 {
+            store.get(1);
+        }
+
+        store.set(1, 1);
+        assert!(store.exists(1));
+        assert!(!store.exists(0));
+    }
+}
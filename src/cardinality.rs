@@ -0,0 +1,250 @@
+//! Estimation of how many distinct keys a store has seen.
+//!
+//! Stores that can't enumerate their keys, like remote backends, can't tell how many there are
+//! either. [`CardinalityStore`] feeds every key written through it to a [`HyperLogLog`] sketch,
+//! which estimates the amount of distinct keys in a few KiB of memory, no matter how many there
+//! are, with an error around `1.04 / sqrt(2^precision)`.
+//!
+//! Sketches of several nodes can be [merged][HyperLogLog::merge] to estimate the keys of all of
+//! them without counting twice the keys they share.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, cardinality::CardinalityStore, stores::MemoryStore};
+//! #
+//! let mut store = CardinalityStore::new(MemoryStore::new());
+//!
+//! for i in 0..10_000 {
+//!     store.try_set(i % 1000, i).unwrap();
+//! }
+//! let estimate = store.estimated_keys();
+//! assert!((950..1050).contains(&estimate));
+//! ```
+
+use crate::{__internal_prelude::*, size::SizedStore};
+
+use core::hash::{Hash, Hasher};
+use std::{hash::DefaultHasher, vec, vec::Vec};
+
+/// Precision used unless told otherwise, 4 KiB of registers for an error around 1.6%.
+pub const DEFAULT_PRECISION: u8 = 12;
+
+/// [HyperLogLog] sketch, estimates the amount of distinct items inserted into it.
+///
+/// It uses `2^precision` registers of a byte each. Items are hashed with [`DefaultHasher`], which
+/// isn't guaranteed to be the same across versions of Rust, so only sketches built by the same
+/// binary should be merged.
+///
+/// [HyperLogLog]: https://en.wikipedia.org/wiki/HyperLogLog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// Make a new empty sketch, with a precision clamped between 4 and 16.
+    #[must_use]
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.clamp(4, 16);
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Precision of the sketch, after clamping.
+    #[must_use]
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Adds an item to the sketch.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        // The precision is at most 16 bits and the rank at most 65
+        let index = (hash >> (64 - self.precision)) as usize;
+        // Position of the first set bit of the rest of the hash, past its end if there's none
+        let rest = hash << self.precision;
+        let rank = (rest.leading_zeros().min(64 - u32::from(self.precision)) + 1) as u8;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Estimated amount of distinct items inserted.
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|register| (-f64::from(*register)).exp2())
+            .sum();
+        let estimate = alpha * m * m / sum;
+
+        // Few items leave many registers empty, counting them is more accurate then
+        let zeros: usize = self
+            .registers
+            .iter()
+            .map(|register| usize::from(*register == 0))
+            .sum();
+        if estimate <= 2.5 * m && zeros > 0 {
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+
+    /// Adds the items of another sketch to this one, as if they had been inserted here. Returns
+    /// whether it could, which it can't if their precisions differ.
+    pub fn merge(&mut self, other: &Self) -> bool {
+        if self.precision != other.precision {
+            return false;
+        }
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        true
+    }
+
+    /// Forgets every item.
+    pub fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRECISION)
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] that estimates how many distinct keys were written through
+/// it, see the [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+pub struct CardinalityStore<S> {
+    pub store: S,
+    sketch: HyperLogLog,
+}
+
+impl<S: TryCacheStore> CardinalityStore<S>
+where
+    S::Key: Hash,
+{
+    /// Make a new [`CardinalityStore`] around the given store, with the [`DEFAULT_PRECISION`].
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            sketch: HyperLogLog::default(),
+        }
+    }
+
+    /// Replaces the sketch with an empty one of the given precision, meant to be done right away.
+    #[must_use]
+    pub fn with_precision(mut self, precision: u8) -> Self {
+        self.sketch = HyperLogLog::new(precision);
+        self
+    }
+
+    /// Estimated amount of distinct keys written through the wrapper.
+    pub fn estimated_keys(&self) -> u64 {
+        self.sketch.estimate()
+    }
+
+    /// Sketch of the keys written, to merge it with the ones of other nodes.
+    pub fn sketch(&self) -> &HyperLogLog {
+        &self.sketch
+    }
+
+    /// Forgets every key written so far, like after the inner store was cleared.
+    pub fn reset(&mut self) {
+        self.sketch.clear();
+    }
+}
+
+impl<S: SizedStore> SizedStore for CardinalityStore<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore> TryCacheStore for CardinalityStore<S>
+where
+    S::Key: Hash,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        self.store.try_set(key, value)?;
+        self.sketch.insert(key);
+        Ok(())
+    }
+
+    /// Sketches can't forget keys, removed ones are still counted until the next
+    /// [`reset`][CardinalityStore::reset].
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_within_error() {
+        let mut sketch = HyperLogLog::default();
+        assert_eq!(sketch.estimate(), 0);
+        for i in 0..100_000u32 {
+            sketch.insert(&i);
+        }
+        let estimate = sketch.estimate();
+        assert!((96_000..104_000).contains(&estimate), "{estimate}");
+    }
+
+    #[test]
+    fn merge_counts_shared_items_once() {
+        let (mut a, mut b) = (HyperLogLog::new(10), HyperLogLog::new(10));
+        for i in 0..2000u32 {
+            a.insert(&i);
+            b.insert(&(i + 1000));
+        }
+        assert!(a.merge(&b));
+        let estimate = a.estimate();
+        assert!((2700..3300).contains(&estimate), "{estimate}");
+        assert!(!a.merge(&HyperLogLog::new(11)));
+    }
+}
@@ -0,0 +1,102 @@
+//! Time sources for time-based features, like expiry.
+//!
+//! Everything that depends on time takes a [`Clock`], so it can be tested deterministically with a
+//! [`MockClock`], and used on targets without std by implementing a clock over their own tick
+//! source.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     clock::MockClock,
+//! #     stores::MemoryStore,
+//! #     ttl::TtlStore,
+//! # };
+//! #
+//! let clock = MockClock::default();
+//! let mut store: TtlStore<&str, &str, _, _> =
+//!     TtlStore::new(MemoryStore::new(), Duration::from_secs(60)).with_clock(&clock);
+//!
+//! store.try_set("key", "value").unwrap();
+//! assert_eq!(store.try_get("key").unwrap(), Some("value"));
+//!
+//! // No need to actually wait a minute
+//! clock.advance(Duration::from_secs(60));
+//! assert_eq!(store.try_get("key").unwrap(), None);
+//! ```
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Source of the current time.
+pub trait Clock {
+    /// Current time as a [`Duration`] since some fixed point in the past, which is up to the
+    /// implementation. It's only compared against other times of the same clock.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C: Clock + ?Sized> Clock for std::sync::Arc<C> {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// [`Clock`] over the system time, measuring since the unix epoch.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+/// [`Clock`] that only moves when told to, for tests. Starts at zero.
+///
+/// Time can be moved from a shared reference, so a store can hold a reference to the clock (or an
+/// [`Arc`][std::sync::Arc] of it) while the test moves it.
+#[derive(Debug, Default)]
+pub struct MockClock {
+    nanos: AtomicU64,
+}
+
+impl MockClock {
+    /// Makes a new clock that starts at the given time.
+    #[must_use]
+    pub fn new(now: Duration) -> Self {
+        let clock = Self::default();
+        clock.set(now);
+        clock
+    }
+
+    /// Moves the clock to the given time, which can be in the past.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, by: Duration) {
+        self.set(self.now().saturating_add(by));
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
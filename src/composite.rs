@@ -0,0 +1,292 @@
+//! Stores keyed by two components that can be invalidated by either of them.
+//!
+//! [`CompositeKeyStore`] wraps a store keyed by `(K1, K2)` tuples and keeps indexes of which keys
+//! share each component, so all entries of a tenant, a user, a page... can be dropped at once with
+//! [`invalidate_k1`][CompositeKeyStore::invalidate_k1] or
+//! [`invalidate_k2`][CompositeKeyStore::invalidate_k2].
+//!
+//! A store keyed by a context and a resource, like the top-level site and URL of browsers'
+//! double-keyed HTTP caches, can hand out a [`Partition`] for each context with
+//! [`partition`][CompositeKeyStore::partition]. It's keyed by the resource alone and can only see
+//! the entries of its context, so the same resource cached under different contexts never leaks
+//! across them, and it can be [cleared][Partition::clear] without touching the others.
+//!
+//! Invalidations don't reach the store, invalidated entries are just treated as misses until they
+//! get set again. For the same reason only entries set through the wrapper are served. Entries
+//! removed with [`try_remove`][TryCacheStore::try_remove] are removed from the store too.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, composite::CompositeKeyStore, stores::MemoryStore};
+//! #
+//! let mut store = CompositeKeyStore::new(MemoryStore::new());
+//!
+//! store.try_set(("tenant-a", "/"), "index of a").unwrap();
+//! store.try_set(("tenant-a", "/about"), "about a").unwrap();
+//! store.try_set(("tenant-b", "/"), "index of b").unwrap();
+//!
+//! // Drop every page of a tenant
+//! assert_eq!(store.invalidate_k1(&"tenant-a"), 2);
+//! assert_eq!(store.try_get(("tenant-a", "/about")).unwrap(), None);
+//! assert_eq!(store.try_get(("tenant-b", "/")).unwrap(), Some("index of b"));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::hash::Hash;
+use std::collections::{HashMap, HashSet};
+
+/// Wrapper around a [`TryCacheStore`] keyed by pairs, that can invalidate all entries sharing one
+/// of the components of their key.
+///
+/// Generics:
+/// - `K1`: Type of the first component of the key.
+/// - `K2`: Type of the second component of the key.
+/// - `S`: [`TryCacheStore`] which this wraps around, keyed by `(K1, K2)`.
+pub struct CompositeKeyStore<K1, K2, S> {
+    pub store: S,
+    /// Second components of the live keys, by their first one.
+    by_k1: HashMap<K1, HashSet<K2>>,
+    /// First components of the live keys, by their second one.
+    by_k2: HashMap<K2, HashSet<K1>>,
+}
+
+/// Removes `other` from the set indexed by `component`, dropping the set if it's left empty.
+/// Returns whether it was there.
+fn remove_indexed<A: Hash + Eq, B: Hash + Eq>(
+    index: &mut HashMap<A, HashSet<B>>,
+    component: &A,
+    other: &B,
+) -> bool {
+    let Some(others) = index.get_mut(component) else {
+        return false;
+    };
+    let removed = others.remove(other);
+    if others.is_empty() {
+        index.remove(component);
+    }
+    removed
+}
+
+impl<K1, K2, S> CompositeKeyStore<K1, K2, S>
+where
+    K1: Hash + Eq + Clone,
+    K2: Hash + Eq + Clone,
+    S: TryCacheStore<Key = (K1, K2)>,
+{
+    /// Make a new [`CompositeKeyStore`] around the given store.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            by_k1: HashMap::new(),
+            by_k2: HashMap::new(),
+        }
+    }
+
+    /// Whether the key was set through the wrapper and not invalidated since.
+    fn is_live(&self, (k1, k2): &(K1, K2)) -> bool {
+        self.by_k1.get(k1).is_some_and(|k2s| k2s.contains(k2))
+    }
+
+    fn track(&mut self, (k1, k2): &(K1, K2)) {
+        self.by_k1.entry(k1.clone()).or_default().insert(k2.clone());
+        self.by_k2.entry(k2.clone()).or_default().insert(k1.clone());
+    }
+
+    /// Invalidates a single entry, returns whether it was live.
+    pub fn invalidate(&mut self, (k1, k2): &(K1, K2)) -> bool {
+        let removed = remove_indexed(&mut self.by_k1, k1, k2);
+        remove_indexed(&mut self.by_k2, k2, k1);
+        removed
+    }
+
+    /// Invalidates every entry whose key has this first component, returns how many were live.
+    pub fn invalidate_k1(&mut self, k1: &K1) -> usize {
+        let Some(k2s) = self.by_k1.remove(k1) else {
+            return 0;
+        };
+        for k2 in &k2s {
+            remove_indexed(&mut self.by_k2, k2, k1);
+        }
+        k2s.len()
+    }
+
+    /// Invalidates every entry whose key has this second component, returns how many were live.
+    pub fn invalidate_k2(&mut self, k2: &K2) -> usize {
+        let Some(k1s) = self.by_k2.remove(k2) else {
+            return 0;
+        };
+        for k1 in &k1s {
+            remove_indexed(&mut self.by_k1, k1, k2);
+        }
+        k1s.len()
+    }
+
+    /// Returns a view of the entries whose key has this first component, keyed by the second one.
+    pub fn partition(&mut self, k1: K1) -> Partition<'_, K1, K2, S> {
+        Partition { store: self, k1 }
+    }
+}
+
+/// View of the entries of a [`CompositeKeyStore`] that share their first component, made with
+/// [`CompositeKeyStore::partition`].
+///
+/// Generics:
+/// - `K1`: Type of the first component of the key, the one of the partition.
+/// - `K2`: Type of the second component of the key, the one used within the partition.
+/// - `S`: [`TryCacheStore`] the composite store wraps around.
+pub struct Partition<'a, K1, K2, S> {
+    store: &'a mut CompositeKeyStore<K1, K2, S>,
+    k1: K1,
+}
+
+impl<K1, K2, S> Partition<'_, K1, K2, S>
+where
+    K1: Hash + Eq + Clone,
+    K2: Hash + Eq + Clone,
+    S: TryCacheStore<Key = (K1, K2)>,
+{
+    /// First component of the keys this partition sees.
+    pub fn key(&self) -> &K1 {
+        &self.k1
+    }
+
+    /// Invalidates every entry of the partition, returns how many were live.
+    pub fn clear(&mut self) -> usize {
+        self.store.invalidate_k1(&self.k1)
+    }
+
+    fn key_of(&self, k2: &K2) -> (K1, K2) {
+        (self.k1.clone(), k2.clone())
+    }
+}
+
+impl<K1, K2, S> TryCacheStore for Partition<'_, K1, K2, S>
+where
+    K1: Hash + Eq + Clone,
+    K2: Hash + Eq + Clone,
+    S: TryCacheStore<Key = (K1, K2)>,
+{
+    type Key = K2;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(self.key_of(key.borrow()))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(self.key_of(key.borrow()))
+    }
+}
+
+impl<K1, K2, S> TryCacheStore for CompositeKeyStore<K1, K2, S>
+where
+    K1: Hash + Eq + Clone,
+    K2: Hash + Eq + Clone,
+    S: TryCacheStore<Key = (K1, K2)>,
+{
+    type Key = (K1, K2);
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        if !self.is_live(key.borrow()) {
+            return Ok(None);
+        }
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        self.store.try_set(key, value)?;
+        self.track(key);
+        Ok(())
+    }
+
+    /// Removes the entry from the store even if it was invalidated, but only returns its value if
+    /// it was live.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let old = self.store.try_remove(key)?;
+        let live = self.invalidate(key);
+        Ok(old.filter(|_| live))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        if !self.is_live(key.borrow()) {
+            return Ok(false);
+        }
+        self.store.try_exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::vec::Vec;
+
+    #[test]
+    fn invalidate_by_each_component() {
+        let mut store = CompositeKeyStore::new(MemoryStore::new());
+        for k1 in 0..3 {
+            for k2 in 0..3 {
+                store.try_set((k1, k2), k1 * 10 + k2).unwrap();
+            }
+        }
+
+        assert_eq!(store.invalidate_k2(&0), 3);
+        assert_eq!(store.invalidate_k1(&1), 2);
+        assert!(store.invalidate(&(2, 2)));
+        assert!(!store.invalidate(&(2, 2)));
+        assert_eq!(store.invalidate_k1(&1), 0);
+
+        let live: Vec<_> = (0..3)
+            .flat_map(|k1| (0..3).map(move |k2| (k1, k2)))
+            .filter(|key| store.try_exists(key).unwrap())
+            .collect();
+        assert_eq!(live, [(0, 1), (0, 2), (2, 1)]);
+
+        // Set again, it's served again
+        store.try_set((1, 1), 0).unwrap();
+        assert_eq!(store.try_get((1, 1)).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn partitions_are_isolated() {
+        let mut store = CompositeKeyStore::new(MemoryStore::<(&str, &str), u32>::new());
+        store.partition("a.com").try_set("/logo", 1).unwrap();
+        store.partition("a.com").try_set("/font", 2).unwrap();
+        store.partition("b.com").try_set("/font", 3).unwrap();
+
+        assert_eq!(store.partition("c.com").try_get("/logo").unwrap(), None);
+        assert_eq!(store.partition("a.com").clear(), 2);
+        assert!(!store.partition("a.com").try_exists("/font").unwrap());
+        assert_eq!(store.partition("b.com").try_get("/font").unwrap(), Some(3));
+    }
+}
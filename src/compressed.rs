@@ -0,0 +1,327 @@
+//! Compression of cached values, with statistics to tune it.
+//!
+//! [`CompressedStore`] compresses values with LZ4 before they reach the inner store, trading a bit
+//! of CPU for a lot of memory or disk with text-like values. Values that barely shrink, like images
+//! or data that's already compressed, can be [stored raw][CompressedStore::with_raw_fallback]
+//! instead, detected by compressing a sample of them first so it's cheap to tell.
+//!
+//! The store records the ratio of every entry and aggregate [`CompressionStats`], to tell if
+//! compression pays off and where to set the threshold.
+//!
+//! Every value is stored with a leading byte telling whether it's compressed.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, compressed::CompressedStore, stores::MemoryStore};
+//! #
+//! let mut store = CompressedStore::new(MemoryStore::new()).with_raw_fallback(0.9);
+//!
+//! let text = "all work and no play makes jack a dull boy ".repeat(100).into_bytes();
+//! store.try_set("text", &text).unwrap();
+//! assert_eq!(store.try_get("text").unwrap(), Some(text));
+//! assert!(store.entry_ratio(&"text").unwrap() < 0.1);
+//!
+//! // Too short to be worth it
+//! store.try_set("tiny", b"hi".to_vec()).unwrap();
+//! assert_eq!(store.stats().raw, 1);
+//! ```
+
+use crate::{__internal_prelude::*, error::CacheError, size::SizedStore};
+
+use core::hash::Hash;
+use std::{collections::HashMap, vec::Vec};
+
+/// Leading byte of values stored as they are.
+const RAW: u8 = 0;
+/// Leading byte of values compressed with LZ4.
+const LZ4: u8 = 1;
+/// Bytes compressed first to tell if a value is worth compressing, by default.
+pub const DEFAULT_SAMPLE_LEN: usize = 4096;
+
+/// Error of a [`CompressedStore`].
+#[derive(Debug)]
+pub enum CompressedError<E> {
+    /// The inner store failed.
+    Store(E),
+    /// The stored value is malformed.
+    Corrupt,
+}
+impl<E: std::error::Error + 'static> std::error::Error for CompressedError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::Corrupt => None,
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for CompressedError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::Corrupt => writeln!(f, "corrupt compressed value"),
+        }
+    }
+}
+
+impl<E: CacheError> CacheError for CompressedError<E> {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_transient())
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_not_found())
+    }
+}
+
+/// Aggregate statistics of the values set through a [`CompressedStore`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Values stored compressed.
+    pub compressed: u64,
+    /// Values stored raw because they didn't compress well enough.
+    pub raw: u64,
+    /// Bytes of the values before compressing them.
+    pub bytes_in: u64,
+    /// Bytes of the values as stored, without their leading byte.
+    pub bytes_out: u64,
+}
+
+impl CompressionStats {
+    /// Stored bytes per original byte, `1.0` if nothing was set.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ratio(&self) -> f64 {
+        if self.bytes_in == 0 {
+            1.0
+        } else {
+            self.bytes_out as f64 / self.bytes_in as f64
+        }
+    }
+}
+
+/// Sizes of the last value set for an entry.
+#[derive(Debug, Clone, Copy)]
+struct EntrySize {
+    original: usize,
+    stored: usize,
+}
+
+/// Wrapper around a [`TryCacheStore`] of bytes that compresses its values, see the
+/// [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around, with [`Vec<u8>`] values.
+pub struct CompressedStore<S: TryCacheStore> {
+    pub store: S,
+    /// Highest ratio a value can compress to and still be stored compressed.
+    max_ratio: Option<f64>,
+    sample_len: usize,
+    stats: CompressionStats,
+    entries: HashMap<S::Key, EntrySize>,
+}
+
+impl<S: TryCacheStore<Value = Vec<u8>>> CompressedStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    /// Make a new [`CompressedStore`] around the given store, compressing every value.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            max_ratio: None,
+            sample_len: DEFAULT_SAMPLE_LEN,
+            stats: CompressionStats::default(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Stores values raw when compressing them doesn't bring them down to `max_ratio` of their
+    /// size or lower.
+    #[must_use]
+    pub fn with_raw_fallback(mut self, max_ratio: f64) -> Self {
+        self.max_ratio = Some(max_ratio);
+        self
+    }
+
+    /// Sets how many bytes of values larger than that are compressed first to tell if they're
+    /// worth compressing as a whole, [`DEFAULT_SAMPLE_LEN`] by default.
+    #[must_use]
+    pub fn with_sample_len(mut self, sample_len: usize) -> Self {
+        self.sample_len = sample_len;
+        self
+    }
+
+    /// Aggregate statistics of the values set so far.
+    pub fn stats(&self) -> CompressionStats {
+        self.stats
+    }
+
+    /// Forgets the statistics gathered so far, the aggregate and the ones of every entry.
+    pub fn reset_stats(&mut self) {
+        self.stats = CompressionStats::default();
+        self.entries.clear();
+    }
+
+    /// Stored bytes per original byte of the last value set for `key` through the wrapper.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn entry_ratio(&self, key: &S::Key) -> Option<f64> {
+        self.entries.get(key).map(|size| {
+            if size.original == 0 {
+                1.0
+            } else {
+                size.stored as f64 / size.original as f64
+            }
+        })
+    }
+
+    /// Whether a compressed length is good enough for the original one.
+    #[allow(clippy::cast_precision_loss)]
+    fn worth_it(&self, compressed: usize, original: usize) -> bool {
+        self.max_ratio
+            .is_none_or(|max_ratio| compressed as f64 <= original as f64 * max_ratio)
+    }
+
+    /// Compresses a value unless it doesn't pay off, returns it as stored.
+    fn encode(&self, value: &[u8]) -> Vec<u8> {
+        let sampled = self.max_ratio.is_some() && value.len() > self.sample_len;
+        if sampled {
+            let sample = &value[..self.sample_len];
+            if !self.worth_it(lz4_flex::compress(sample).len(), sample.len()) {
+                return raw(value);
+            }
+        }
+
+        let compressed = lz4_flex::compress_prepend_size(value);
+        if !self.worth_it(compressed.len(), value.len()) {
+            return raw(value);
+        }
+        let mut stored = Vec::with_capacity(1 + compressed.len());
+        stored.push(LZ4);
+        stored.extend_from_slice(&compressed);
+        stored
+    }
+}
+
+/// Stores a value as it is.
+fn raw(value: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(1 + value.len());
+    stored.push(RAW);
+    stored.extend_from_slice(value);
+    stored
+}
+
+/// Turns a stored value back into the original one.
+fn decode<E>(stored: &[u8]) -> Result<Vec<u8>, CompressedError<E>> {
+    match stored.split_first() {
+        Some((&RAW, value)) => Ok(value.to_vec()),
+        Some((&LZ4, compressed)) => {
+            lz4_flex::decompress_size_prepended(compressed).map_err(|_| CompressedError::Corrupt)
+        }
+        _ => Err(CompressedError::Corrupt),
+    }
+}
+
+impl<S: TryCacheStore + SizedStore> SizedStore for CompressedStore<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore<Value = Vec<u8>>> TryCacheStore for CompressedStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    type Key = S::Key;
+    type Value = Vec<u8>;
+    type Error = CompressedError<S::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let Some(stored) = self.store.try_get(key).map_err(CompressedError::Store)? else {
+            return Ok(None);
+        };
+        decode(&stored).map(Some)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let (key, value) = (key.borrow(), value.borrow());
+        let stored = self.encode(value);
+        self.store
+            .try_set(key, &stored)
+            .map_err(CompressedError::Store)?;
+
+        let size = EntrySize {
+            original: value.len(),
+            stored: stored.len() - 1,
+        };
+        if stored[0] == LZ4 {
+            self.stats.compressed += 1;
+        } else {
+            self.stats.raw += 1;
+        }
+        self.stats.bytes_in += size.original as u64;
+        self.stats.bytes_out += size.stored as u64;
+        self.entries.insert(key.clone(), size);
+        Ok(())
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let old = self.store.try_remove(key).map_err(CompressedError::Store)?;
+        self.entries.remove(key);
+        old.map(|stored| decode(&stored)).transpose()
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key).map_err(CompressedError::Store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::vec;
+
+    #[test]
+    fn incompressible_values_are_stored_raw() {
+        let mut store = CompressedStore::new(MemoryStore::new())
+            .with_raw_fallback(0.8)
+            .with_sample_len(64);
+        // Bytes of a simple generator, nothing for LZ4 to find
+        let mut state = 7u32;
+        let noise: Vec<u8> = (0..1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state.to_le_bytes()[0]
+            })
+            .collect();
+        store.try_set(0, &noise).unwrap();
+        store.try_set(1, vec![0; 1024]).unwrap();
+
+        assert_eq!(store.try_get(0).unwrap(), Some(noise));
+        assert_eq!(store.try_get(1).unwrap(), Some(vec![0; 1024]));
+        assert_eq!(store.entry_ratio(&0), Some(1.0));
+        assert!(store.entry_ratio(&1).unwrap() < 0.1);
+        let totals = store.stats();
+        assert_eq!(
+            (totals.compressed, totals.raw, totals.bytes_in),
+            (1, 1, 2048)
+        );
+
+        store.store.try_set(2, vec![LZ4, 0xff]).unwrap();
+        assert!(matches!(store.try_get(2), Err(CompressedError::Corrupt)));
+    }
+}
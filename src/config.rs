@@ -0,0 +1,343 @@
+//! Stores described by configuration, to pick and tune the backend without recompiling.
+//!
+//! A [`StoreConfig`] can be deserialized from any format supported by [serde], like a section of
+//! the application's config file, and then [built][StoreConfig::build] into a
+//! [`BoxedThreadSafeStore`] whose concrete type doesn't leak to the rest of the code. Configs can
+//! be nested, like a [tiered][StoreConfig::Tiered] store with a memory store in front of a file
+//! one.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::config::StoreConfig;
+//! #
+//! let config: StoreConfig = serde_json::from_str(r#"{ "memory": { "capacity": 2 } }"#).unwrap();
+//! let store = config.build::<String, String>().unwrap();
+//!
+//! store.dyn_ts_one_try_set(&"key".into(), &"value".into()).unwrap();
+//! assert_eq!(store.dyn_ts_one_try_get(&"key".into()).unwrap().as_deref(), Some("value"));
+//! ```
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    bounded::clock::ClockMemoryStore,
+    dynamic::{BoxedThreadSafeStore, DynThreadSafeTryCacheStore},
+    error::CacheError,
+    stores::{
+        file_stores::{CustomHash, ThreadSafeFileStoreError, ThreadSafeFileStoreSerializable},
+        ThreadSafeMemoryStore,
+    },
+    thread_safe::dumb_wrappers::EmptyDumbError,
+    tiered::{TieredError, TieredStore},
+    TryCacheStore,
+};
+
+use core::{borrow::Borrow, hash::Hash, marker::PhantomData, time::Duration};
+use std::{
+    boxed::Box,
+    collections::HashMap,
+    path::PathBuf,
+    sync::{PoisonError, RwLock},
+};
+
+/// Configuration of a store, see the [module docs][self].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StoreConfig {
+    /// Store in memory, bounded with clock eviction if it has a capacity.
+    Memory {
+        #[serde(default)]
+        capacity: Option<usize>,
+    },
+    /// Store with a file per entry, in the given directory.
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        codec: Codec,
+    },
+    /// [`TieredStore`] with `l1` in front of `l2`, promoting keys read from `l2` this many times
+    /// and hiding removed keys from `l2` for `tombstone_ttl_secs`.
+    Tiered {
+        l1: Box<StoreConfig>,
+        l2: Box<StoreConfig>,
+        #[serde(default = "default_promote_after")]
+        promote_after: u32,
+        #[serde(default)]
+        tombstone_ttl_secs: u64,
+    },
+}
+
+/// Promotes keys on their first read from the second tier, like [`TieredStore`] does.
+fn default_promote_after() -> u32 {
+    1
+}
+
+/// How values are encoded on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Codec {
+    #[default]
+    Bincode,
+}
+
+impl StoreConfig {
+    /// Makes the store described by this configuration.
+    ///
+    /// # Errors
+    /// Fails when setting up the backend does, like creating the directory of a file store.
+    pub fn build<K, V>(&self) -> std::io::Result<BoxedThreadSafeStore<'static, K, V, StoreError>>
+    where
+        K: Clone + Hash + Eq + CustomHash + Serialize + Send + Sync + 'static,
+        V: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        Ok(match self {
+            Self::Memory { capacity: None } => {
+                Box::new(ErrInto::new(ThreadSafeMemoryStore::new(HashMap::new())))
+            }
+            Self::Memory {
+                capacity: Some(capacity),
+            } => Box::new(ErrInto::new(ClockMemoryStore::new(*capacity))),
+            Self::File {
+                path,
+                codec: Codec::Bincode,
+            } => Box::new(ErrInto::new(ThreadSafeFileStoreSerializable::new_on(
+                path.as_path(),
+            )?)),
+            Self::Tiered {
+                l1,
+                l2,
+                promote_after,
+                tombstone_ttl_secs,
+            } => Box::new(SharedTiered(RwLock::new(
+                TieredStore::new(
+                    Unlocked(l1.build()?),
+                    Unlocked(l2.build()?),
+                    Duration::from_secs(*tombstone_ttl_secs),
+                )
+                .with_promotion_after(*promote_after),
+            ))),
+        })
+    }
+}
+
+/// Error of a store built from a [`StoreConfig`], the one of whichever backend it is.
+#[derive(Debug)]
+pub enum StoreError {
+    Memory(EmptyDumbError),
+    File(ThreadSafeFileStoreError),
+}
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Memory(err) => Some(err),
+            Self::File(err) => Some(err),
+        }
+    }
+}
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Memory(err) => writeln!(f, "memory store error: {err}"),
+            Self::File(err) => writeln!(f, "file store error: {err}"),
+        }
+    }
+}
+
+impl CacheError for StoreError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Memory(err) => err.is_transient(),
+            Self::File(err) => err.is_transient(),
+        }
+    }
+
+    fn is_poison(&self) -> bool {
+        match self {
+            Self::Memory(err) => err.is_poison(),
+            Self::File(err) => err.is_poison(),
+        }
+    }
+
+    fn is_not_found(&self) -> bool {
+        match self {
+            Self::Memory(err) => err.is_not_found(),
+            Self::File(err) => err.is_not_found(),
+        }
+    }
+}
+
+impl From<EmptyDumbError> for StoreError {
+    fn from(value: EmptyDumbError) -> Self {
+        Self::Memory(value)
+    }
+}
+impl From<ThreadSafeFileStoreError> for StoreError {
+    fn from(value: ThreadSafeFileStoreError) -> Self {
+        Self::File(value)
+    }
+}
+
+/// Converts the error of the inner store into a [`StoreError`], so all backends can share a box
+/// type.
+struct ErrInto<S, E> {
+    store: S,
+    __phantom: PhantomData<E>,
+}
+
+impl<S, E> ErrInto<S, E> {
+    fn new(store: S) -> Self {
+        Self {
+            store,
+            __phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, V, E, S> DynThreadSafeTryCacheStore<K, V, StoreError> for ErrInto<S, E>
+where
+    S: DynThreadSafeTryCacheStore<K, V, E>,
+    StoreError: From<E>,
+{
+    fn dyn_ts_one_try_get(&self, key: &K) -> Result<Option<V>, StoreError> {
+        self.store.dyn_ts_one_try_get(key).map_err(Into::into)
+    }
+
+    fn dyn_ts_one_try_set(&self, key: &K, value: &V) -> Result<(), StoreError> {
+        self.store
+            .dyn_ts_one_try_set(key, value)
+            .map_err(Into::into)
+    }
+
+    fn dyn_ts_one_try_remove(&self, key: &K) -> Result<Option<V>, StoreError> {
+        self.store.dyn_ts_one_try_remove(key).map_err(Into::into)
+    }
+
+    fn dyn_ts_one_try_exists(&self, key: &K) -> Result<bool, StoreError> {
+        self.store.dyn_ts_one_try_exists(key).map_err(Into::into)
+    }
+}
+
+/// Built store as a [`TryCacheStore`], to put it in a [`TieredStore`].
+struct Unlocked<K, V>(BoxedThreadSafeStore<'static, K, V, StoreError>);
+
+impl<K, V> TryCacheStore for Unlocked<K, V> {
+    type Key = K;
+    type Value = V;
+    type Error = StoreError;
+
+    fn try_get(&self, key: impl Borrow<K>) -> Result<Option<V>, StoreError> {
+        self.0.dyn_ts_one_try_get(key.borrow())
+    }
+
+    fn try_set(&mut self, key: impl Borrow<K>, value: impl Borrow<V>) -> Result<(), StoreError> {
+        self.0.dyn_ts_one_try_set(key.borrow(), value.borrow())
+    }
+
+    fn try_remove(&mut self, key: impl Borrow<K>) -> Result<Option<V>, StoreError> {
+        self.0.dyn_ts_one_try_remove(key.borrow())
+    }
+
+    fn try_exists(&self, key: impl Borrow<K>) -> Result<bool, StoreError> {
+        self.0.dyn_ts_one_try_exists(key.borrow())
+    }
+}
+
+/// Tiered store of built stores. Reads share the lock, as [`TieredStore`] only needs it exclusive
+/// to write. Errors are the ones of whichever tier failed.
+struct SharedTiered<K, V>(RwLock<TieredStore<Unlocked<K, V>, Unlocked<K, V>>>);
+
+/// Error of whichever tier failed.
+fn tier_error(err: TieredError<StoreError, StoreError>) -> StoreError {
+    match err {
+        TieredError::L1(err) | TieredError::L2(err) => err,
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> DynThreadSafeTryCacheStore<K, V, StoreError>
+    for SharedTiered<K, V>
+{
+    fn dyn_ts_one_try_get(&self, key: &K) -> Result<Option<V>, StoreError> {
+        let store = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        store.try_get(key).map_err(tier_error)
+    }
+
+    fn dyn_ts_one_try_set(&self, key: &K, value: &V) -> Result<(), StoreError> {
+        let mut store = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        store.try_set(key, value).map_err(tier_error)
+    }
+
+    fn dyn_ts_one_try_remove(&self, key: &K) -> Result<Option<V>, StoreError> {
+        let mut store = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        store.try_remove(key).map_err(tier_error)
+    }
+
+    fn dyn_ts_one_try_exists(&self, key: &K) -> Result<bool, StoreError> {
+        let store = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        store.try_exists(key).map_err(tier_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::String, vec, vec::Vec};
+
+    #[test]
+    fn bounded_memory_store() {
+        let config: StoreConfig =
+            serde_json::from_str(r#"{ "memory": { "capacity": 1 } }"#).unwrap();
+        assert_eq!(config, StoreConfig::Memory { capacity: Some(1) });
+
+        let store = config.build::<String, u32>().unwrap();
+        store.dyn_ts_one_try_set(&"a".into(), &1).unwrap();
+        store.dyn_ts_one_try_set(&"b".into(), &2).unwrap();
+        assert!(!store.dyn_ts_one_try_exists(&"a".into()).unwrap());
+        assert_eq!(store.dyn_ts_one_try_get(&"b".into()).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: StoreConfig =
+            serde_json::from_value(serde_json::json!({ "file": { "path": dir.path() } })).unwrap();
+
+        let store = config.build::<String, Vec<u32>>().unwrap();
+        store
+            .dyn_ts_one_try_set(&"key".into(), &vec![1, 2])
+            .unwrap();
+        assert_eq!(
+            store.dyn_ts_one_try_get(&"key".into()).unwrap(),
+            Some(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn tiered_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: StoreConfig = serde_json::from_value(serde_json::json!({
+            "tiered": {
+                "l1": { "memory": { "capacity": 1 } },
+                "l2": { "file": { "path": dir.path() } },
+            }
+        }))
+        .unwrap();
+        assert!(matches!(
+            &config,
+            StoreConfig::Tiered {
+                promote_after: 1,
+                tombstone_ttl_secs: 0,
+                ..
+            }
+        ));
+
+        let store = config.build::<String, u32>().unwrap();
+        store.dyn_ts_one_try_set(&"a".into(), &1).unwrap();
+        store.dyn_ts_one_try_set(&"b".into(), &2).unwrap();
+        // Evicted from the first tier but still in the second one
+        assert_eq!(store.dyn_ts_one_try_get(&"a".into()).unwrap(), Some(1));
+        assert_eq!(store.dyn_ts_one_try_remove(&"b".into()).unwrap(), Some(2));
+        assert!(!store.dyn_ts_one_try_exists(&"b".into()).unwrap());
+    }
+}
@@ -0,0 +1,237 @@
+//! Context for errors of a store.
+//!
+//! Errors coming from deep in a composition of stores usually don't tell which operation or key
+//! caused them. [`ContextStore`] wraps a store's errors into a [`CacheOpError`] that carries both.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     context::{CacheOp, ContextStore},
+//! #     write_once::WriteOnceStore,
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let mut store = ContextStore::new(WriteOnceStore::new(MemoryStore::<&str, u32>::new()));
+//!
+//! store.try_set("key", 1).unwrap();
+//! let error = store.try_set("key", 2).unwrap_err();
+//!
+//! assert_eq!(error.op, CacheOp::Set);
+//! assert_eq!(error.key_debug, r#""key""#);
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    error::CacheError,
+    health::{Health, HealthCheck},
+    size::SizedStore,
+};
+
+use core::fmt::Debug;
+use std::{format, string::String};
+
+/// Operation of a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CacheOp {
+    Get,
+    Set,
+    Exists,
+    Replace,
+    SetIfAbsent,
+    ReplaceOnly,
+    Remove,
+}
+impl core::fmt::Display for CacheOp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Get => "get",
+            Self::Set => "set",
+            Self::Exists => "exists",
+            Self::Replace => "replace",
+            Self::SetIfAbsent => "set if absent",
+            Self::ReplaceOnly => "replace only",
+            Self::Remove => "remove",
+        })
+    }
+}
+
+/// Error of a store along with the operation and key that caused it.
+#[derive(Debug)]
+pub struct CacheOpError<E> {
+    pub op: CacheOp,
+    /// [`Debug`] representation of the key.
+    pub key_debug: String,
+    pub source: E,
+}
+impl<E: std::error::Error + 'static> std::error::Error for CacheOpError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for CacheOpError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "{} of key {} failed: {}",
+            self.op, self.key_debug, self.source
+        )
+    }
+}
+
+impl<E: CacheError> CacheError for CacheOpError<E> {
+    fn is_transient(&self) -> bool {
+        self.source.is_transient()
+    }
+
+    fn is_poison(&self) -> bool {
+        self.source.is_poison()
+    }
+
+    fn is_not_found(&self) -> bool {
+        self.source.is_not_found()
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] that adds the operation and key to its errors, as a
+/// [`CacheOpError`].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+#[derive(Debug, Clone, Default)]
+pub struct ContextStore<S> {
+    pub store: S,
+}
+
+impl<S: TryCacheStore> ContextStore<S>
+where
+    S::Key: Debug,
+{
+    /// Make a new [`ContextStore`] around the given store.
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    fn context<T>(
+        op: CacheOp,
+        key: &S::Key,
+        result: Result<T, S::Error>,
+    ) -> Result<T, CacheOpError<S::Error>> {
+        result.map_err(|source| CacheOpError {
+            op,
+            key_debug: format!("{key:?}"),
+            source,
+        })
+    }
+}
+
+impl<S: SizedStore> SizedStore for ContextStore<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+/// Pings aren't operations on a key, so their errors are left as they are.
+impl<S: HealthCheck> HealthCheck for ContextStore<S> {
+    type Error = S::Error;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        self.store.ping()
+    }
+}
+
+impl<S: TryCacheStore> TryCacheStore for ContextStore<S>
+where
+    S::Key: Debug,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = CacheOpError<S::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        Self::context(CacheOp::Get, key, self.store.try_get(key))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        Self::context(CacheOp::Set, key, self.store.try_set(key, value))
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        Self::context(CacheOp::Remove, key, self.store.try_remove(key))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        Self::context(CacheOp::Exists, key, self.store.try_exists(key))
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        Self::context(CacheOp::Replace, key, self.store.try_replace(key, value))
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        Self::context(
+            CacheOp::SetIfAbsent,
+            key,
+            self.store.try_set_if_absent(key, value),
+        )
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        Self::context(
+            CacheOp::ReplaceOnly,
+            key,
+            self.store.try_replace_only(key, value),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        stores::MemoryStore,
+        write_once::{WriteOnceError, WriteOnceStore},
+    };
+    use std::string::ToString;
+
+    #[test]
+    fn error_has_context() {
+        let mut store = ContextStore::new(WriteOnceStore::new(MemoryStore::<u8, &str>::new()));
+        store.try_set(1, "one").unwrap();
+
+        let error = store.try_replace(1, "uno").unwrap_err();
+        assert_eq!(error.op, CacheOp::Replace);
+        assert_eq!(error.key_debug, "1");
+        assert!(matches!(error.source, WriteOnceError::AlreadySet));
+        assert!(error
+            .to_string()
+            .starts_with("replace of key 1 failed: key is already set"));
+    }
+}
@@ -0,0 +1,226 @@
+//! Caching of only the values that were expensive to generate.
+//!
+//! Values that are cheap to generate again don't earn their spot in a bounded store, they evict
+//! entries that took much longer to compute. [`CostAwareStore`] times the generator of a
+//! [`TryGenCacheStore`] and only keeps the values that took at least a given threshold to
+//! generate. Cheaper ones are still returned, just not stored, so they're generated again the next
+//! time.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     clock::MockClock,
+//! #     cost::CostAwareStore,
+//! #     generative::{GenCacheStoreWrapper, TryGenCacheStore},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let clock = MockClock::default();
+//! // Pretend that bigger numbers take longer
+//! let generator = |&n: &u64, ()| {
+//!     clock.advance(Duration::from_millis(n));
+//!     n * 2
+//! };
+//! let mut store = CostAwareStore::new(
+//!     GenCacheStoreWrapper::new(MemoryStore::new(), generator),
+//!     Duration::from_millis(10),
+//! )
+//! .with_clock(&clock);
+//!
+//! assert_eq!(store.try_get_or_new(1, ()).unwrap(), 2);
+//! assert_eq!(store.try_get_or_new(50, ()).unwrap(), 100);
+//! // Only the expensive one was kept
+//! assert_eq!(store.try_get(1).unwrap(), None);
+//! assert_eq!(store.try_get(50).unwrap(), Some(100));
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    clock::{Clock, SystemClock},
+    generative::TryGenCacheStore,
+    size::SizedStore,
+};
+
+use core::time::Duration;
+
+/// Wrapper around a [`TryGenCacheStore`] that only stores values that were expensive to generate,
+/// see the [module docs][self].
+///
+/// Values set directly, without generating them, are always stored.
+///
+/// Generics:
+/// - `S`: [`TryGenCacheStore`] which this wraps around.
+/// - `C`: [`Clock`] used to time the generator, the system time by default.
+pub struct CostAwareStore<S, C: Clock = SystemClock> {
+    pub store: S,
+    clock: C,
+    min_cost: Duration,
+    skipped: u64,
+}
+
+impl<S: TryGenCacheStore> CostAwareStore<S> {
+    /// Make a new [`CostAwareStore`] around the given store, only storing values that took at
+    /// least `min_cost` to generate.
+    pub fn new(store: S, min_cost: Duration) -> Self {
+        Self {
+            store,
+            clock: SystemClock,
+            min_cost,
+            skipped: 0,
+        }
+    }
+}
+
+impl<S: TryGenCacheStore, C: Clock> CostAwareStore<S, C> {
+    /// Replaces the clock used to time the generator.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> CostAwareStore<S, C2> {
+        CostAwareStore {
+            store: self.store,
+            clock,
+            min_cost: self.min_cost,
+            skipped: self.skipped,
+        }
+    }
+
+    /// Least time a value must take to generate to be stored.
+    pub fn min_cost(&self) -> Duration {
+        self.min_cost
+    }
+
+    /// Sets the least time a value must take to generate to be stored.
+    pub fn set_min_cost(&mut self, min_cost: Duration) {
+        self.min_cost = min_cost;
+    }
+
+    /// Amount of generated values that weren't stored because they were too cheap.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Generates a value timing it, and stores it if it was expensive enough.
+    fn gen_and_store(
+        &mut self,
+        key: &<S as TryGenCacheStore>::Key,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        let start = self.clock.now();
+        let value = self.store.try_gen(key, args)?;
+        if self.clock.now().saturating_sub(start) >= self.min_cost {
+            self.store.try_set(key, &value)?;
+        } else {
+            self.skipped += 1;
+        }
+        Ok(value)
+    }
+}
+
+impl<S: TryGenCacheStore + SizedStore, C: Clock> SizedStore for CostAwareStore<S, C> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryGenCacheStore, C: Clock> TryCacheStore for CostAwareStore<S, C> {
+    type Key = <S as TryGenCacheStore>::Key;
+    type Value = <S as TryGenCacheStore>::Value;
+    type Error = <S as TryGenCacheStore>::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key)
+    }
+}
+
+impl<S: TryGenCacheStore, C: Clock> TryGenCacheStore for CostAwareStore<S, C> {
+    type Key = <S as TryGenCacheStore>::Key;
+    type Value = <S as TryGenCacheStore>::Value;
+    type Error = <S as TryGenCacheStore>::Error;
+    type Args = S::Args;
+
+    fn try_gen(
+        &self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        self.store.try_gen(key, args)
+    }
+
+    fn try_get_or_gen(
+        &self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        self.store.try_get_or_gen(key, args)
+    }
+
+    fn try_get_or_new(
+        &mut self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        let key = key.borrow();
+        if let Some(value) = self.store.try_get(key)? {
+            return Ok(value);
+        }
+        self.gen_and_store(key, args)
+    }
+
+    fn try_gen_new(
+        &mut self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        self.gen_and_store(key.borrow(), args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, generative::TryGenCacheStoreWrapper, stores::MemoryStore};
+    use core::convert::Infallible;
+
+    #[test]
+    fn cheap_values_are_generated_again() {
+        let clock = MockClock::default();
+        let calls = core::cell::Cell::new(0);
+        let generator = |&n: &u64, cost: u64| {
+            calls.set(calls.get() + 1);
+            clock.advance(Duration::from_millis(cost));
+            Ok::<_, Infallible>(n)
+        };
+        let mut store = CostAwareStore::new(
+            TryGenCacheStoreWrapper::new(MemoryStore::new(), generator),
+            Duration::from_millis(5),
+        )
+        .with_clock(&clock);
+
+        store.try_get_or_new(0, 1).unwrap();
+        store.try_get_or_new(0, 1).unwrap();
+        assert_eq!((calls.get(), store.skipped()), (2, 2));
+
+        store.try_gen_new(0, 5).unwrap();
+        store.try_get_or_new(0, 1).unwrap();
+        assert_eq!((calls.get(), store.skipped()), (3, 2));
+    }
+}
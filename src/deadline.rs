@@ -0,0 +1,410 @@
+//! Deadlines and cancellation of operations, propagated down to the stores doing the work.
+//!
+//! A request that already timed out shouldn't keep a remote store busy. The `*_with_ctx` methods
+//! of [`TryCacheStoreCtx`] and [`TryGenCacheStoreCtx`] take an [`OpContext`] with a deadline
+//! and/or a [`CancellationToken`]. They fail right away if it's already over, and otherwise run
+//! the operation with the context set as the [current][OpContext::current] one of the thread. So
+//! every store and generator down the composition can read it, without each wrapper having to pass
+//! it along, and abort its own work, like [`GrpcStore`][crate::grpc::GrpcStore] does with its
+//! requests.
+//!
+//! Deadlines are times of a [`Clock`], the system time unless the context is given
+//! [another][OpContext::with_clock] one.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     deadline::{CancellationToken, DeadlineError, OpContext, TryGenCacheStoreCtx},
+//! #     generative::GenCacheStoreWrapper,
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let generator = |&n: &u32, ()| {
+//!     // Work that takes long can stop once nobody waits for it
+//!     if OpContext::current().is_some_and(|ctx| ctx.is_over()) {
+//!         return 0;
+//!     }
+//!     n * 2
+//! };
+//! let mut store = GenCacheStoreWrapper::new(MemoryStore::new(), generator);
+//!
+//! let token = CancellationToken::new();
+//! let ctx = OpContext::new().with_timeout(Duration::from_secs(5)).with_token(token.clone());
+//! assert_eq!(store.try_get_or_new_with_ctx(&ctx, 2, ()).unwrap(), 4);
+//!
+//! // The caller went away
+//! token.cancel();
+//! assert!(matches!(
+//!     store.try_get_or_new_with_ctx(&ctx, 3, ()),
+//!     Err(DeadlineError::Cancelled)
+//! ));
+//! ```
+
+use crate::{__internal_prelude::*, error::CacheError, generative::TryGenCacheStore};
+
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use std::sync::Arc;
+
+use crate::clock::{Clock, SystemClock};
+
+std::thread_local! {
+    /// Context of the operation running in this thread, if any.
+    static CURRENT: RefCell<Option<OpContext>> = const { RefCell::new(None) };
+}
+
+/// Flag shared between the caller of operations and whoever decides they're not needed anymore.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Make a new [`CancellationToken`] that's not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations of every context with this token, or clones of it.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token was cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Deadline and cancellation of an operation, see the [module docs][self].
+#[derive(Clone)]
+pub struct OpContext {
+    clock: Arc<dyn Clock + Send + Sync>,
+    deadline: Option<Duration>,
+    token: Option<CancellationToken>,
+}
+
+impl core::fmt::Debug for OpContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("OpContext")
+            .field("deadline", &self.deadline)
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for OpContext {
+    fn default() -> Self {
+        Self {
+            clock: Arc::new(SystemClock),
+            deadline: None,
+            token: None,
+        }
+    }
+}
+
+impl OpContext {
+    /// Make a new [`OpContext`] without deadline nor cancellation, on the system time.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the clock the deadline is measured with. A deadline already set keeps its time
+    /// according to the previous clock, so it's meant to be done right away.
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the time of the clock after which operations are aborted.
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to `timeout` from now.
+    #[must_use]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        let deadline = self.clock.now().saturating_add(timeout);
+        self.with_deadline(deadline)
+    }
+
+    /// Sets the token that aborts operations once cancelled.
+    #[must_use]
+    pub fn with_token(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Deadline of the context as a time of its clock, if any.
+    #[must_use]
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Time left until the deadline, if any. Zero once it's past.
+    #[must_use]
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_sub(self.clock.now()))
+    }
+
+    /// Whether operations under this context should stop, because of its deadline or token.
+    #[must_use]
+    pub fn is_over(&self) -> bool {
+        self.check::<Infallible>().is_err()
+    }
+
+    /// Fails if operations under this context should stop.
+    ///
+    /// # Errors
+    /// With the reason they should.
+    pub fn check<E>(&self) -> Result<(), DeadlineError<E>> {
+        if self
+            .token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(DeadlineError::Cancelled);
+        }
+        if self
+            .remaining()
+            .is_some_and(|remaining| remaining.is_zero())
+        {
+            return Err(DeadlineError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+
+    /// Context of the operation running in this thread, if it was started with one.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Runs `f` with this as the current context, restoring the previous one after.
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        /// Restores the previous context, even if `f` panics.
+        struct Restore(Option<OpContext>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self.clone()))));
+        f()
+    }
+}
+
+/// Error of an operation run with an [`OpContext`].
+#[derive(Debug)]
+pub enum DeadlineError<E> {
+    /// The store failed.
+    Store(E),
+    /// The deadline passed before the operation started.
+    DeadlineExceeded,
+    /// The token was cancelled before the operation started.
+    Cancelled,
+}
+impl<E: std::error::Error + 'static> std::error::Error for DeadlineError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::DeadlineExceeded | Self::Cancelled => None,
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for DeadlineError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::DeadlineExceeded => writeln!(f, "deadline exceeded"),
+            Self::Cancelled => writeln!(f, "operation cancelled"),
+        }
+    }
+}
+
+impl<E: CacheError> CacheError for DeadlineError<E> {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_transient())
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_not_found())
+    }
+}
+
+/// Operations of a [`TryCacheStore`] under an [`OpContext`], implemented by every store.
+#[allow(clippy::missing_errors_doc)]
+pub trait TryCacheStoreCtx: TryCacheStore {
+    /// Attempt to get a value, unless the context is over.
+    fn try_get_with_ctx(
+        &self,
+        ctx: &OpContext,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, DeadlineError<Self::Error>> {
+        ctx.check()?;
+        ctx.scope(|| self.try_get(key))
+            .map_err(DeadlineError::Store)
+    }
+
+    /// Attempt to set a value, unless the context is over.
+    fn try_set_with_ctx(
+        &mut self,
+        ctx: &OpContext,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), DeadlineError<Self::Error>> {
+        ctx.check()?;
+        ctx.scope(|| self.try_set(key, value))
+            .map_err(DeadlineError::Store)
+    }
+
+    /// Attempt to remove an entry, unless the context is over.
+    fn try_remove_with_ctx(
+        &mut self,
+        ctx: &OpContext,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, DeadlineError<Self::Error>> {
+        ctx.check()?;
+        ctx.scope(|| self.try_remove(key))
+            .map_err(DeadlineError::Store)
+    }
+
+    /// Attempt to check if an entry exists, unless the context is over.
+    fn try_exists_with_ctx(
+        &self,
+        ctx: &OpContext,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<bool, DeadlineError<Self::Error>> {
+        ctx.check()?;
+        ctx.scope(|| self.try_exists(key))
+            .map_err(DeadlineError::Store)
+    }
+}
+
+impl<S: TryCacheStore> TryCacheStoreCtx for S {}
+
+/// Operations of a [`TryGenCacheStore`] under an [`OpContext`], implemented by every generative
+/// store. The generator can read the context with [`OpContext::current`].
+#[allow(clippy::missing_errors_doc)]
+pub trait TryGenCacheStoreCtx: TryGenCacheStore {
+    /// Attempt to get the value from cache or generate a new one adding it, unless the context is
+    /// over.
+    fn try_get_or_new_with_ctx(
+        &mut self,
+        ctx: &OpContext,
+        key: impl Borrow<<Self as TryGenCacheStore>::Key>,
+        args: Self::Args,
+    ) -> Result<<Self as TryGenCacheStore>::Value, DeadlineError<<Self as TryCacheStore>::Error>>
+    {
+        ctx.check()?;
+        ctx.scope(|| self.try_get_or_new(key, args))
+            .map_err(DeadlineError::Store)
+    }
+
+    /// Attempt to generate a new value and add it, unless the context is over.
+    fn try_gen_new_with_ctx(
+        &mut self,
+        ctx: &OpContext,
+        key: impl Borrow<<Self as TryGenCacheStore>::Key>,
+        args: Self::Args,
+    ) -> Result<<Self as TryGenCacheStore>::Value, DeadlineError<<Self as TryCacheStore>::Error>>
+    {
+        ctx.check()?;
+        ctx.scope(|| self.try_gen_new(key, args))
+            .map_err(DeadlineError::Store)
+    }
+}
+
+impl<S: TryGenCacheStore> TryGenCacheStoreCtx for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+
+    #[test]
+    fn context_is_current_within_operations() {
+        struct Probe;
+        impl TryCacheStore for Probe {
+            type Key = ();
+            type Value = bool;
+            type Error = Infallible;
+
+            fn try_get(&self, _: impl Borrow<()>) -> Result<Option<bool>, Infallible> {
+                Ok(OpContext::current().map(|ctx| ctx.deadline().is_some()))
+            }
+
+            fn try_set(
+                &mut self,
+                _: impl Borrow<()>,
+                _: impl Borrow<bool>,
+            ) -> Result<(), Infallible> {
+                Ok(())
+            }
+
+            fn try_remove(&mut self, _: impl Borrow<()>) -> Result<Option<bool>, Infallible> {
+                Ok(None)
+            }
+        }
+
+        let ctx = OpContext::new().with_timeout(Duration::from_secs(30));
+        assert_eq!(Probe.try_get_with_ctx(&ctx, ()).unwrap(), Some(true));
+        assert_eq!(Probe.try_get(()).unwrap(), None);
+
+        let expired = OpContext::new().with_deadline(Duration::ZERO);
+        assert!(matches!(
+            MemoryStore::<u8, u8>::new().try_set_with_ctx(&expired, 0, 0),
+            Err(DeadlineError::DeadlineExceeded)
+        ));
+    }
+
+    #[test]
+    fn deadline_follows_the_clock() {
+        let clock = Arc::new(MockClock::default());
+        let ctx = OpContext::new()
+            .with_clock(Arc::clone(&clock))
+            .with_timeout(Duration::from_secs(30));
+        let mut store = MemoryStore::<u8, u8>::new();
+        assert_eq!(ctx.remaining(), Some(Duration::from_secs(30)));
+        store.try_set_with_ctx(&ctx, 0, 0).unwrap();
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(ctx.remaining(), Some(Duration::ZERO));
+        assert!(matches!(
+            store.try_get_with_ctx(&ctx, 0),
+            Err(DeadlineError::DeadlineExceeded)
+        ));
+    }
+
+    #[test]
+    fn cancelled_removes_keep_entries() {
+        let mut store = MemoryStore::<u8, u8>::new();
+        store.try_set(0, 0).unwrap();
+
+        let token = CancellationToken::new();
+        let ctx = OpContext::new().with_token(token.clone());
+        token.cancel();
+        assert!(matches!(
+            store.try_remove_with_ctx(&ctx, 0),
+            Err(DeadlineError::Cancelled)
+        ));
+        assert_eq!(
+            store.try_remove_with_ctx(&OpContext::new(), 0).unwrap(),
+            Some(0)
+        );
+    }
+}
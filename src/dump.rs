@@ -0,0 +1,116 @@
+//! JSON dumps of the entries of stores, to capture the state of a cache quickly.
+//!
+//! Every [`EnumerableStore`] gets [`dump_json`][DumpJson::dump_json], which writes its keys and
+//! values, and [`dump_json_summary`][DumpJson::dump_json_summary], which only writes a
+//! [summary][ValueSummary] of each value, for values that can't be serialized or shouldn't end up
+//! in a bug report.
+//!
+//! Dumps are a JSON array of `{"key": …, "value": …}` objects, in no particular order.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{dump::DumpJson, stores::MemoryStore};
+//! #
+//! let store: MemoryStore<&str, String> = [("greeting", String::from("hi"))].into_iter().collect();
+//!
+//! let mut dump = Vec::new();
+//! store.dump_json(&mut dump).unwrap();
+//! assert_eq!(String::from_utf8(dump).unwrap(), r#"[{"key":"greeting","value":"hi"}]"#);
+//!
+//! let mut summary = Vec::new();
+//! store.dump_json_summary(&mut summary).unwrap();
+//! assert!(String::from_utf8(summary).unwrap().contains(r#""type":"alloc::string::String""#));
+//! ```
+
+use crate::{enumerable::EnumerableStore, size::MemSize};
+
+use serde::{ser::SerializeSeq, Serialize, Serializer};
+use std::io::Write;
+
+/// What a dump tells about a value when it doesn't write it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ValueSummary {
+    /// Name of the type of the value.
+    #[serde(rename = "type")]
+    pub type_name: &'static str,
+    /// Bytes the value takes, as told by [`MemSize`].
+    pub size: usize,
+}
+
+impl ValueSummary {
+    /// Summary of the given value.
+    pub fn of<V: MemSize>(value: &V) -> Self {
+        Self {
+            type_name: core::any::type_name::<V>(),
+            size: value.mem_size(),
+        }
+    }
+}
+
+/// Entry of a dump.
+#[derive(Serialize)]
+struct DumpEntry<'a, K, V> {
+    key: &'a K,
+    value: V,
+}
+
+/// JSON dumps of an [`EnumerableStore`], see the [module docs][self].
+pub trait DumpJson: EnumerableStore {
+    /// Writes all keys and values as JSON.
+    ///
+    /// # Errors
+    /// When serializing an entry or writing fails.
+    fn dump_json(&self, writer: impl Write) -> serde_json::Result<()>
+    where
+        Self::Key: Serialize,
+        Self::Value: Serialize,
+    {
+        let mut serializer = serde_json::Serializer::new(writer);
+        let mut entries = serializer.serialize_seq(None)?;
+        self.try_for_each_entry(|key, value| entries.serialize_element(&DumpEntry { key, value }))?;
+        entries.end()
+    }
+
+    /// Writes all keys as JSON, with a summary of their values.
+    ///
+    /// # Errors
+    /// When serializing a key or writing fails.
+    fn dump_json_summary(&self, writer: impl Write) -> serde_json::Result<()>
+    where
+        Self::Key: Serialize,
+        Self::Value: MemSize,
+    {
+        let mut serializer = serde_json::Serializer::new(writer);
+        let mut entries = serializer.serialize_seq(None)?;
+        self.try_for_each_entry(|key, value| {
+            entries.serialize_element(&DumpEntry {
+                key,
+                value: ValueSummary::of(value),
+            })
+        })?;
+        entries.end()
+    }
+}
+
+impl<S: EnumerableStore> DumpJson for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::{string::String, vec::Vec};
+
+    #[test]
+    fn summaries_hide_values() {
+        let store: MemoryStore<u8, Vec<u8>> = [(1, b"secret".to_vec())].into_iter().collect();
+
+        let mut summary = Vec::new();
+        store.dump_json_summary(&mut summary).unwrap();
+        let summary = String::from_utf8(summary).unwrap();
+        assert!(!summary.contains("secret"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&summary).unwrap()[0]["key"],
+            1
+        );
+    }
+}
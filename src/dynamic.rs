@@ -0,0 +1,172 @@
+//! Object safe versions of the store traits, to choose backends at runtime.
+//!
+//! The main traits take `impl Borrow<..>` arguments, which makes them generic and so they can't be
+//! used as trait objects. The traits here mirror them taking plain references instead, they are
+//! implemented for every store and boxed stores implement the main traits back, so a
+//! [`BoxedStore`] can be used anywhere a store is expected.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     dynamic::BoxedStore,
+//! #     stores::MemoryStore,
+//! #     ttl::TtlStore,
+//! # };
+//! # use core::convert::Infallible;
+//! #
+//! fn backend(expiring: bool) -> BoxedStore<'static, &'static str, u32, Infallible> {
+//!     if expiring {
+//!         Box::new(TtlStore::new(MemoryStore::new(), Duration::from_secs(60)))
+//!     } else {
+//!         Box::new(MemoryStore::new())
+//!     }
+//! }
+//!
+//! let mut store = backend(true);
+//! store.try_set("key", 1).unwrap();
+//! assert_eq!(store.try_get("key").unwrap(), Some(1));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use std::boxed::Box;
+
+/// Object safe analogous of [`TryCacheStore`], implemented for all of them.
+#[allow(clippy::missing_errors_doc)]
+pub trait DynTryCacheStore<K, V, E> {
+    /// Same as [`TryCacheStore::try_get`].
+    fn dyn_try_get(&self, key: &K) -> Result<Option<V>, E>;
+    /// Same as [`TryCacheStore::try_set`].
+    fn dyn_try_set(&mut self, key: &K, value: &V) -> Result<(), E>;
+    /// Same as [`TryCacheStore::try_remove`].
+    fn dyn_try_remove(&mut self, key: &K) -> Result<Option<V>, E>;
+    /// Same as [`TryCacheStore::try_exists`].
+    fn dyn_try_exists(&self, key: &K) -> Result<bool, E>;
+}
+
+impl<T: TryCacheStore> DynTryCacheStore<T::Key, T::Value, T::Error> for T {
+    fn dyn_try_get(&self, key: &T::Key) -> Result<Option<T::Value>, T::Error> {
+        self.try_get(key)
+    }
+
+    fn dyn_try_set(&mut self, key: &T::Key, value: &T::Value) -> Result<(), T::Error> {
+        self.try_set(key, value)
+    }
+
+    fn dyn_try_remove(&mut self, key: &T::Key) -> Result<Option<T::Value>, T::Error> {
+        self.try_remove(key)
+    }
+
+    fn dyn_try_exists(&self, key: &T::Key) -> Result<bool, T::Error> {
+        self.try_exists(key)
+    }
+}
+
+/// Boxed [`TryCacheStore`] of any type.
+pub type BoxedStore<'a, K, V, E> = Box<dyn DynTryCacheStore<K, V, E> + 'a>;
+
+impl<K, V, E> TryCacheStore for BoxedStore<'_, K, V, E> {
+    type Key = K;
+    type Value = V;
+    type Error = E;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        (**self).dyn_try_get(key.borrow())
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        (**self).dyn_try_set(key.borrow(), value.borrow())
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        (**self).dyn_try_remove(key.borrow())
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        (**self).dyn_try_exists(key.borrow())
+    }
+}
+
+/// Object safe analogous of [`ThreadSafeTryCacheStore`], implemented for all of them.
+///
+/// Lock handles can't be named without knowing the store, so it only has the operations that lock
+/// just for their own duration.
+#[cfg(feature = "thread-safe")]
+#[allow(clippy::missing_errors_doc)]
+pub trait DynThreadSafeTryCacheStore<K, V, E> {
+    /// Same as [`ThreadSafeTryCacheStore::ts_one_try_get`].
+    fn dyn_ts_one_try_get(&self, key: &K) -> Result<Option<V>, E>;
+    /// Same as [`ThreadSafeTryCacheStore::ts_one_try_set`].
+    fn dyn_ts_one_try_set(&self, key: &K, value: &V) -> Result<(), E>;
+    /// Same as [`ThreadSafeTryCacheStore::ts_one_try_remove`].
+    fn dyn_ts_one_try_remove(&self, key: &K) -> Result<Option<V>, E>;
+    /// Same as [`ThreadSafeTryCacheStore::ts_one_try_exists`].
+    fn dyn_ts_one_try_exists(&self, key: &K) -> Result<bool, E>;
+}
+
+#[cfg(feature = "thread-safe")]
+impl<K, V, E, T> DynThreadSafeTryCacheStore<K, V, E> for T
+where
+    T: for<'lock> ThreadSafeTryCacheStore<'lock, Key = K, Value = V, Error = E>,
+{
+    fn dyn_ts_one_try_get(&self, key: &K) -> Result<Option<V>, E> {
+        self.ts_one_try_get(key)
+    }
+
+    fn dyn_ts_one_try_set(&self, key: &K, value: &V) -> Result<(), E> {
+        self.ts_one_try_set(key, value)
+    }
+
+    fn dyn_ts_one_try_remove(&self, key: &K) -> Result<Option<V>, E> {
+        self.ts_one_try_remove(key)
+    }
+
+    fn dyn_ts_one_try_exists(&self, key: &K) -> Result<bool, E> {
+        self.ts_one_try_exists(key)
+    }
+}
+
+/// Boxed [`ThreadSafeTryCacheStore`] of any type, that can be shared across threads.
+#[cfg(feature = "thread-safe")]
+pub type BoxedThreadSafeStore<'a, K, V, E> =
+    Box<dyn DynThreadSafeTryCacheStore<K, V, E> + Send + Sync + 'a>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+
+    #[test]
+    fn boxed_store_is_a_store() {
+        let mut store: BoxedStore<'_, usize, usize, Infallible> = Box::new(MemoryStore::new());
+        store.try_set(0, 1).unwrap();
+        assert_eq!(store.try_get(0).unwrap(), Some(1));
+        assert!(!store.try_exists(1).unwrap());
+    }
+
+    #[cfg(feature = "thread-safe")]
+    #[test]
+    fn boxed_thread_safe_store() {
+        use crate::{stores::ThreadSafeMemoryStore, thread_safe::dumb_wrappers::EmptyDumbError};
+        use std::collections::HashMap;
+
+        let store: BoxedThreadSafeStore<'_, usize, usize, EmptyDumbError> =
+            Box::new(ThreadSafeMemoryStore::new(HashMap::new()));
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                let store = &store;
+                scope.spawn(move || store.dyn_ts_one_try_set(&i, &i).unwrap());
+            }
+        });
+        assert_eq!(store.dyn_ts_one_try_get(&3).unwrap(), Some(3));
+    }
+}
@@ -0,0 +1,335 @@
+//! Encryption of cached values, with a key per namespace.
+//!
+//! [`EncryptedStore`] encrypts values with ChaCha20-Poly1305 before they reach the inner store, so
+//! stores on shared disks or remote machines only see ciphertext. The key is chosen per entry by a
+//! key provider, any `Fn(&K) -> Keyring`, so caches holding the data of several customers can
+//! encrypt each of them with their own key, by namespace, key prefix or anything else.
+//!
+//! Keys can be rotated by adding a new current key to a [`Keyring`] and keeping the old one as a
+//! [previous][Keyring::with_previous] key. Entries under old keys are still read, and written
+//! under the new one the next time they're set or [re-encrypted][EncryptedStore::reencrypt].
+//!
+//! Every value is stored as the id of its key, a random nonce and the ciphertext. The cache key and
+//! the key id are authenticated along with the value, so a value copied under another cache key in
+//! the inner store fails to decrypt instead of being served for it.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, encrypted::{EncryptedStore, Keyring}, stores::MemoryStore};
+//! #
+//! let provider = |key: &String| match key.split_once('/') {
+//!     Some(("acme", _)) => Keyring::new(1, [1; 32]),
+//!     _ => Keyring::new(2, [2; 32]),
+//! };
+//! let mut store = EncryptedStore::new(MemoryStore::new(), provider);
+//!
+//! store.try_set(String::from("acme/invoice"), b"secret".to_vec()).unwrap();
+//! assert_eq!(
+//!     store.try_get(String::from("acme/invoice")).unwrap(),
+//!     Some(b"secret".to_vec())
+//! );
+//! ```
+
+use crate::{__internal_prelude::*, error::CacheError, size::SizedStore};
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use std::vec::Vec;
+
+/// Key values are encrypted with.
+pub type EncryptionKey = [u8; 32];
+/// Id stored next to each value to tell which key it was encrypted with.
+pub type KeyId = u32;
+
+/// Length of the nonce stored next to each value.
+const NONCE_LEN: usize = 12;
+/// Length of the header of stored values, the key id and the nonce.
+const HEADER_LEN: usize = 4 + NONCE_LEN;
+
+/// Error of an [`EncryptedStore`].
+#[derive(Debug)]
+pub enum EncryptedError<E> {
+    /// The inner store failed.
+    Store(E),
+    /// The value was encrypted with a key that's not in the keyring of its entry anymore.
+    UnknownKey(KeyId),
+    /// The value is malformed or was tampered with.
+    Decryption,
+}
+impl<E: std::error::Error + 'static> std::error::Error for EncryptedError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::UnknownKey(_) | Self::Decryption => None,
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for EncryptedError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::UnknownKey(id) => writeln!(f, "unknown encryption key {id}"),
+            Self::Decryption => writeln!(f, "failed to decrypt value"),
+        }
+    }
+}
+
+impl<E: CacheError> CacheError for EncryptedError<E> {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_transient())
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_not_found())
+    }
+}
+
+/// Keys of an entry: the current one, which values are encrypted with, and the previous ones,
+/// which values encrypted before rotating can still be decrypted with.
+#[derive(Clone)]
+pub struct Keyring {
+    current: (KeyId, EncryptionKey),
+    previous: Vec<(KeyId, EncryptionKey)>,
+}
+
+impl Keyring {
+    /// Make a new [`Keyring`] with just its current key.
+    #[must_use]
+    pub fn new(id: KeyId, key: EncryptionKey) -> Self {
+        Self {
+            current: (id, key),
+            previous: Vec::new(),
+        }
+    }
+
+    /// Adds a previous key, only used to decrypt.
+    #[must_use]
+    pub fn with_previous(mut self, id: KeyId, key: EncryptionKey) -> Self {
+        self.previous.push((id, key));
+        self
+    }
+
+    fn get(&self, id: KeyId) -> Option<&EncryptionKey> {
+        core::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|(known, _)| *known == id)
+            .map(|(_, key)| key)
+    }
+}
+
+impl core::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Never print the keys themselves
+        let previous: Vec<_> = self.previous.iter().map(|(id, _)| id).collect();
+        f.debug_struct("Keyring")
+            .field("current", &self.current.0)
+            .field("previous", &previous)
+            .finish()
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] of bytes that encrypts its values, see the
+/// [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around, with [`Vec<u8>`] values and keys that can be
+///   seen as bytes, which ciphertexts are bound to.
+/// - `F`: Key provider, returns the [`Keyring`] of a key.
+pub struct EncryptedStore<S, F> {
+    pub store: S,
+    provider: F,
+}
+
+impl<S, F> EncryptedStore<S, F>
+where
+    S: TryCacheStore<Value = Vec<u8>>,
+    S::Key: AsRef<[u8]>,
+    F: Fn(&S::Key) -> Keyring,
+{
+    /// Make a new [`EncryptedStore`] around the given store and key provider.
+    pub fn new(store: S, provider: F) -> Self {
+        Self { store, provider }
+    }
+
+    /// Encrypts the entry of `key` with its current key if it was encrypted with a previous one.
+    /// Returns whether it was.
+    ///
+    /// # Errors
+    /// Fails when the inner store does or when the entry can't be decrypted.
+    pub fn reencrypt(&mut self, key: &S::Key) -> Result<bool, EncryptedError<S::Error>> {
+        let Some(stored) = self.store.try_get(key).map_err(EncryptedError::Store)? else {
+            return Ok(false);
+        };
+        let keyring = (self.provider)(key);
+        let (id, value) = decrypt(&keyring, key.as_ref(), &stored)?;
+        if id == keyring.current.0 {
+            return Ok(false);
+        }
+        self.store
+            .try_set(key, encrypt(&keyring, key.as_ref(), &value))
+            .map_err(EncryptedError::Store)?;
+        Ok(true)
+    }
+}
+
+/// Data authenticated along with a value, binding it to its cache key and key id.
+fn associated_data(id: KeyId, cache_key: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(4 + cache_key.len());
+    aad.extend_from_slice(&id.to_le_bytes());
+    aad.extend_from_slice(cache_key);
+    aad
+}
+
+/// Encrypts the value of `cache_key` with the current key of the keyring.
+fn encrypt(keyring: &Keyring, cache_key: &[u8], value: &[u8]) -> Vec<u8> {
+    let (id, key) = &keyring.current;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: value,
+        aad: &associated_data(*id, cache_key),
+    };
+    let ciphertext = ChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce, payload)
+        .expect("values are shorter than the limit of the cipher");
+
+    let mut stored = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    stored.extend_from_slice(&id.to_le_bytes());
+    stored.extend_from_slice(&nonce);
+    stored.extend_from_slice(&ciphertext);
+    stored
+}
+
+/// Decrypts the stored value of `cache_key`, returns the id of the key it was encrypted with along
+/// with it.
+fn decrypt<E>(
+    keyring: &Keyring,
+    cache_key: &[u8],
+    stored: &[u8],
+) -> Result<(KeyId, Vec<u8>), EncryptedError<E>> {
+    let Some((header, ciphertext)) = stored.split_at_checked(HEADER_LEN) else {
+        return Err(EncryptedError::Decryption);
+    };
+    let (id, nonce) = header.split_at(4);
+    let id = KeyId::from_le_bytes(id.try_into().expect("4 bytes"));
+    let key = keyring.get(id).ok_or(EncryptedError::UnknownKey(id))?;
+    let payload = Payload {
+        msg: ciphertext,
+        aad: &associated_data(id, cache_key),
+    };
+    let value = ChaCha20Poly1305::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| EncryptedError::Decryption)?;
+    Ok((id, value))
+}
+
+impl<S: SizedStore, F> SizedStore for EncryptedStore<S, F> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S, F> TryCacheStore for EncryptedStore<S, F>
+where
+    S: TryCacheStore<Value = Vec<u8>>,
+    S::Key: AsRef<[u8]>,
+    F: Fn(&S::Key) -> Keyring,
+{
+    type Key = S::Key;
+    type Value = Vec<u8>;
+    type Error = EncryptedError<S::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let Some(stored) = self.store.try_get(key).map_err(EncryptedError::Store)? else {
+            return Ok(None);
+        };
+        let (_, value) = decrypt(&(self.provider)(key), key.as_ref(), &stored)?;
+        Ok(Some(value))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        let stored = encrypt(&(self.provider)(key), key.as_ref(), value.borrow());
+        self.store
+            .try_set(key, stored)
+            .map_err(EncryptedError::Store)
+    }
+
+    /// The entry is removed even if it can't be decrypted, failing afterwards.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let Some(stored) = self.store.try_remove(key).map_err(EncryptedError::Store)? else {
+            return Ok(None);
+        };
+        let (_, value) = decrypt(&(self.provider)(key), key.as_ref(), &stored)?;
+        Ok(Some(value))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key).map_err(EncryptedError::Store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use core::cell::Cell;
+    use std::vec;
+
+    #[test]
+    fn rotation_keeps_old_entries() {
+        let rotated = Cell::new(false);
+        let provider = |_: &&str| {
+            if rotated.get() {
+                Keyring::new(2, [2; 32]).with_previous(1, [1; 32])
+            } else {
+                Keyring::new(1, [1; 32])
+            }
+        };
+        let mut store = EncryptedStore::new(MemoryStore::new(), provider);
+        store.try_set("a", vec![0; 8]).unwrap();
+        assert_ne!(store.store.try_get("a").unwrap(), Some(vec![0; 8]));
+
+        rotated.set(true);
+        assert_eq!(store.try_get("a").unwrap(), Some(vec![0; 8]));
+        assert!(store.reencrypt(&"a").unwrap());
+        assert!(!store.reencrypt(&"a").unwrap());
+
+        // Dropping the old key doesn't affect re-encrypted entries
+        let mut other = EncryptedStore::new(store.store, |_: &&str| Keyring::new(2, [2; 32]));
+        assert_eq!(other.try_get("a").unwrap(), Some(vec![0; 8]));
+        other.store.try_set("b", vec![0; 4]).unwrap();
+        assert!(matches!(
+            other.try_get("b"),
+            Err(EncryptedError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn values_are_bound_to_their_key() {
+        let provider = |_: &&str| Keyring::new(1, [1; 32]);
+        let mut store = EncryptedStore::new(MemoryStore::new(), provider);
+        store.try_set("acme/invoice", b"secret".to_vec()).unwrap();
+
+        let stored = store.store.try_get("acme/invoice").unwrap().unwrap();
+        store.store.try_set("acme/other", stored).unwrap();
+        assert!(matches!(
+            store.try_get("acme/other"),
+            Err(EncryptedError::Decryption)
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+//! Stores whose entries can be gone over.
+//!
+//! Not every store can list what it has, like remote ones or file stores that only keep the hash
+//! of the keys. The ones that can implement [`EnumerableStore`], which tooling like
+//! [dumps][crate::dump] builds on.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{enumerable::EnumerableStore, stores::MemoryStore};
+//! #
+//! let store: MemoryStore<&str, u32> = [("a", 1), ("b", 2)].into_iter().collect();
+//!
+//! let mut total = 0;
+//! store
+//!     .try_for_each_entry(|_, value| {
+//!         total += value;
+//!         Ok::<_, ()>(())
+//!     })
+//!     .unwrap();
+//! assert_eq!(total, 3);
+//! ```
+
+use crate::{__internal_prelude::*, pattern::KeyPattern, stores::MemoryStore};
+
+use core::hash::Hash;
+use std::vec::Vec;
+
+/// Trait for a [`TryCacheStore`] that can go over all its entries.
+pub trait EnumerableStore: TryCacheStore {
+    /// Calls `f` with every entry, in no particular order, stopping at the first error.
+    ///
+    /// # Errors
+    /// With the first error of `f`.
+    fn try_for_each_entry<E>(
+        &self,
+        f: impl FnMut(&Self::Key, &Self::Value) -> Result<(), E>,
+    ) -> Result<(), E>;
+
+    /// Keys that match a [pattern][crate::pattern], in no particular order.
+    fn keys_matching(&self, pattern: &impl KeyPattern) -> Vec<Self::Key>
+    where
+        Self::Key: AsRef<str> + Clone,
+    {
+        let mut keys = Vec::new();
+        let _ = self.try_for_each_entry(|key, _| {
+            if pattern.matches(key.as_ref()) {
+                keys.push(key.clone());
+            }
+            Ok::<_, Infallible>(())
+        });
+        keys
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> EnumerableStore for MemoryStore<K, V> {
+    fn try_for_each_entry<E>(&self, mut f: impl FnMut(&K, &V) -> Result<(), E>) -> Result<(), E> {
+        self.iter().try_for_each(|(key, value)| f(key, value))
+    }
+}
@@ -0,0 +1,69 @@
+//! Classification of store errors.
+//!
+//! Wrappers that react to errors, like retrying or falling back to another store, need to know
+//! what kind of failure they got without knowing the concrete error type of the store. Errors of
+//! this crate implement [`CacheError`] for that.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{error::CacheError, thread_safe::dumb_wrappers::EmptyDumbError};
+//! #
+//! fn should_retry(error: &impl CacheError) -> bool {
+//!     error.is_transient() && !error.is_poison()
+//! }
+//!
+//! assert!(should_retry(&EmptyDumbError::WouldBlock));
+//! assert!(!should_retry(&EmptyDumbError::Poisoned));
+//! ```
+
+use crate::__internal_prelude::*;
+
+/// Trait to classify errors of a store, every check is false unless the error says otherwise.
+pub trait CacheError {
+    /// Whether trying the same operation again could succeed, like when a lock was busy.
+    fn is_transient(&self) -> bool {
+        false
+    }
+    /// Whether it's caused by a lock poisoned by a thread that panicked holding it.
+    fn is_poison(&self) -> bool {
+        false
+    }
+    /// Whether it's caused by something that's missing, like a file removed meanwhile.
+    fn is_not_found(&self) -> bool {
+        false
+    }
+}
+
+impl CacheError for Infallible {}
+
+/// Error of the blanket [`ThreadSafeTryCacheStore`] implementation.
+impl CacheError for () {}
+
+#[cfg(feature = "std")]
+impl CacheError for std::io::Error {
+    fn is_transient(&self) -> bool {
+        use std::io::ErrorKind;
+        matches!(
+            self.kind(),
+            ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+        )
+    }
+
+    fn is_not_found(&self) -> bool {
+        self.kind() == std::io::ErrorKind::NotFound
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn io_errors() {
+        assert!(Error::from(ErrorKind::Interrupted).is_transient());
+        assert!(Error::from(ErrorKind::NotFound).is_not_found());
+        let denied = Error::from(ErrorKind::PermissionDenied);
+        assert!(!denied.is_transient() && !denied.is_not_found() && !denied.is_poison());
+    }
+}
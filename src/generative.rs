@@ -130,6 +130,24 @@ impl<K, V, A, F: Fn(&K, A) -> V, S: CacheStore<Key = K, Value = V>>
     }
 }
 
+impl<K, V, A, S: CacheStore<Key = K, Value = V> + Clone, F: Fn(&K, A) -> V + Clone> Clone
+    for GenCacheStoreWrapper<K, V, A, S, F>
+{
+    fn clone(&self) -> Self {
+        Self::new(self.store.clone(), self.generator.clone())
+    }
+}
+
+impl<K, V, A, S: CacheStore<Key = K, Value = V> + core::fmt::Debug, F: Fn(&K, A) -> V>
+    core::fmt::Debug for GenCacheStoreWrapper<K, V, A, S, F>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GenCacheStoreWrapper")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Implement [`GenCacheStore`]
 impl<K, V, A, S: CacheStore<Key = K, Value = V>, F: Fn(&K, A) -> V> GenCacheStore
     for GenCacheStoreWrapper<K, V, A, S, F>
@@ -254,6 +272,79 @@ impl<
     }
 }
 
+impl<
+        K,
+        V,
+        E,
+        A,
+        FnErr: Into<E>,
+        F: Fn(&K, A) -> Result<V, FnErr> + Clone,
+        S: TryCacheStore<Key = K, Value = V, Error = E> + Clone,
+    > Clone for TryGenCacheStoreWrapper<K, V, E, A, FnErr, S, F>
+{
+    fn clone(&self) -> Self {
+        Self::new(self.store.clone(), self.try_generator.clone())
+    }
+}
+
+impl<
+        K,
+        V,
+        E,
+        A,
+        FnErr: Into<E>,
+        F: Fn(&K, A) -> Result<V, FnErr>,
+        S: TryCacheStore<Key = K, Value = V, Error = E> + core::fmt::Debug,
+    > core::fmt::Debug for TryGenCacheStoreWrapper<K, V, E, A, FnErr, S, F>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TryGenCacheStoreWrapper")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
+
+/// [`TryGenCacheStoreWrapper`] where the errors of both the store and the generator are turned
+/// into an [`anyhow::Error`], so there's no need for an error type to gather them.
+///
+/// # Examples
+/// ```rust
+/// # use ezcache::{generative::AnyhowGenCacheStoreWrapper, prelude::*};
+/// #
+/// let mut store = AnyhowGenCacheStoreWrapper::new_anyhow(
+///     MemoryStore::<String, u32>::new(),
+///     |key: &String, ()| key.parse::<u32>(),
+/// );
+///
+/// assert_eq!(store.try_get_or_new("42".to_string(), ()).unwrap(), 42);
+/// assert!(store.try_get_or_new("nope".to_string(), ()).is_err());
+/// ```
+#[cfg(feature = "anyhow")]
+pub type AnyhowGenCacheStoreWrapper<K, V, A, FnErr, S, F> = TryGenCacheStoreWrapper<
+    K,
+    V,
+    anyhow::Error,
+    A,
+    FnErr,
+    TryCacheStoreErrorMap<K, V, <S as TryCacheStore>::Error, anyhow::Error, S>,
+    F,
+>;
+
+#[cfg(feature = "anyhow")]
+impl<K, V, A, FnErr, F, S> AnyhowGenCacheStoreWrapper<K, V, A, FnErr, S, F>
+where
+    FnErr: Into<anyhow::Error>,
+    F: Fn(&K, A) -> Result<V, FnErr>,
+    S: TryCacheStore<Key = K, Value = V>,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    /// Make a new [`TryGenCacheStore`] from a fallible store and fallible generator function,
+    /// turning the errors of both into an [`anyhow::Error`].
+    pub fn new_anyhow(store: S, try_generator: F) -> Self {
+        Self::new(TryCacheStoreErrorMap::from_store(store), try_generator)
+    }
+}
+
 /// Functions with multiple stages will return the same type of error without any way to detect at
 /// what point it failed, and not undoing the changes. If you don't like this you'll have to
 /// manually follow the steps done by the function and handle the errors yourself.
@@ -0,0 +1,510 @@
+//! Sharing a store with other processes, in any language, over gRPC.
+//!
+//! [`GrpcServer`] is a tonic service that serves any thread safe store of bytes with the protocol
+//! at `proto/ezcache.proto` in the repository, so clients can be generated for any language from
+//! it. [`GrpcStore`] is the client for Rust, a [`TryCacheStore`] that forwards every operation to
+//! a server.
+//!
+//! Keys and values are raw bytes, so every client has to agree on how they are encoded. The
+//! protocol doesn't support holding locks between requests, but `SetIfAbsent`, `Replace` and
+//! `Remove` run atomically on the server.
+//!
+//! Operations run with an [`OpContext`] send its deadline along with the request, and stop
+//! waiting for the answer once it passes.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use std::net::SocketAddr;
+//! # use ezcache::{grpc::{GrpcServer, GrpcStore}, stores::ThreadSafeMemoryStore, TryCacheStore};
+//! # use tonic::transport::{Endpoint, Server};
+//! #
+//! // In the process that owns the store
+//! # async fn serve() -> Result<(), tonic::transport::Error> {
+//! let server = GrpcServer::new(ThreadSafeMemoryStore::<Vec<u8>, Vec<u8>>::default());
+//! let address: SocketAddr = "127.0.0.1:50051".parse().unwrap();
+//! Server::builder().add_service(server).serve(address).await
+//! # }
+//!
+//! // Anywhere else
+//! let mut store = GrpcStore::connect(&Endpoint::from_static("http://127.0.0.1:50051")).unwrap();
+//! store.try_set(b"key".to_vec(), b"value".to_vec()).unwrap();
+//! assert_eq!(store.try_get(b"key".to_vec()).unwrap(), Some(b"value".to_vec()));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{
+    convert::Infallible,
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{boxed::Box, string::ToString, sync::Arc, vec::Vec};
+
+use tonic::{
+    body::Body,
+    client::Grpc,
+    codegen::{http, Body as HttpBody, Service, StdError},
+    server::{NamedService, UnaryService},
+    transport::{Channel, Endpoint},
+    Code, Request, Response, Status,
+};
+use tonic_prost::ProstCodec;
+
+use crate::{
+    deadline::{DeadlineError, OpContext},
+    error::CacheError,
+    health::{Health, HealthCheck},
+    thread_safe::ThreadSafeTryCacheStore,
+};
+
+/// Messages of the protocol, matching the ones at `proto/ezcache.proto`.
+pub mod proto {
+    use std::vec::Vec;
+
+    /// Request of `Get`, `Exists` and `Remove`.
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct KeyRequest {
+        #[prost(bytes = "vec", tag = "1")]
+        pub key: Vec<u8>,
+    }
+
+    /// Request of `Set`, `SetIfAbsent` and `Replace`.
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct EntryRequest {
+        #[prost(bytes = "vec", tag = "1")]
+        pub key: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub value: Vec<u8>,
+    }
+
+    /// Response of `Get`, `Replace` and `Remove`.
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct ValueResponse {
+        #[prost(bytes = "vec", optional, tag = "1")]
+        pub value: Option<Vec<u8>>,
+    }
+
+    /// Response of `Exists` and `SetIfAbsent`.
+    #[derive(Clone, Copy, PartialEq, Eq, prost::Message)]
+    pub struct BoolResponse {
+        #[prost(bool, tag = "1")]
+        pub value: bool,
+    }
+
+    /// Request of `Ping`, response of `Set` and `Ping`.
+    #[derive(Clone, Copy, PartialEq, Eq, prost::Message)]
+    pub struct Empty {}
+}
+
+use proto::{BoolResponse, Empty, EntryRequest, KeyRequest, ValueResponse};
+
+/// Full name of the service in the protocol.
+pub const SERVICE_NAME: &str = "ezcache.Cache";
+
+type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+/// Turns a store error into a status, telling clients if they can retry.
+fn status_of<E: CacheError + Display>(err: &E) -> Status {
+    let code = if err.is_transient() {
+        Code::Unavailable
+    } else {
+        Code::Internal
+    };
+    Status::new(code, err.to_string())
+}
+
+/// [`UnaryService`] that runs a blocking handler once, on the blocking threads of the runtime as
+/// stores may do io.
+struct Handler<F>(Option<F>);
+
+impl<Req, Res, F> UnaryService<Req> for Handler<F>
+where
+    Req: Send + 'static,
+    Res: Send + 'static,
+    F: FnOnce(Req) -> Result<Res, Status> + Send + 'static,
+{
+    type Response = Res;
+    type Future = BoxFuture<Response<Res>, Status>;
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        let handler = self.0.take().expect("handlers are only called once");
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || handler(request.into_inner()))
+                .await
+                .map_err(|err| Status::internal(err.to_string()))?
+                .map(Response::new)
+        })
+    }
+}
+
+/// Answers a request with a handler.
+fn unary<B, Req, Res, F>(
+    request: http::Request<B>,
+    handler: F,
+) -> BoxFuture<http::Response<Body>, Infallible>
+where
+    B: HttpBody + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    F: FnOnce(Req) -> Result<Res, Status> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = tonic::server::Grpc::new(ProstCodec::<Res, Req>::default());
+        Ok(grpc.unary(Handler(Some(handler)), request).await)
+    })
+}
+
+/// gRPC service around a thread safe store of bytes, to add to a tonic server.
+///
+/// Generics:
+/// - `S`: [`ThreadSafeTryCacheStore`] of bytes which this serves.
+pub struct GrpcServer<S> {
+    pub store: Arc<S>,
+}
+
+impl<S> GrpcServer<S> {
+    /// Make a new [`GrpcServer`] serving the given store.
+    pub fn new(store: S) -> Self {
+        Self::from_arc(Arc::new(store))
+    }
+
+    /// Make a new [`GrpcServer`] serving a store that's also used elsewhere.
+    pub fn from_arc(store: Arc<S>) -> Self {
+        Self { store }
+    }
+}
+
+impl<S> Clone for GrpcServer<S> {
+    fn clone(&self) -> Self {
+        Self::from_arc(Arc::clone(&self.store))
+    }
+}
+
+impl<S> NamedService for GrpcServer<S> {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<S, E, B> Service<http::Request<B>> for GrpcServer<S>
+where
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = Vec<u8>, Value = Vec<u8>, Error = E>
+        + Send
+        + Sync
+        + 'static,
+    E: CacheError + Display,
+    B: HttpBody + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let store = Arc::clone(&self.store);
+        match request.uri().path().strip_prefix("/ezcache.Cache/") {
+            Some("Get") => unary(request, move |KeyRequest { key }| {
+                let value = store.ts_one_try_get(&key).map_err(|err| status_of(&err))?;
+                Ok(ValueResponse { value })
+            }),
+            Some("Set") => unary(request, move |EntryRequest { key, value }| {
+                store
+                    .ts_one_try_set(&key, &value)
+                    .map_err(|err| status_of(&err))?;
+                Ok(Empty {})
+            }),
+            Some("Exists") => unary(request, move |KeyRequest { key }| {
+                let value = store
+                    .ts_one_try_exists(&key)
+                    .map_err(|err| status_of(&err))?;
+                Ok(BoolResponse { value })
+            }),
+            Some("SetIfAbsent") => unary(request, move |EntryRequest { key, value }| {
+                let value = store
+                    .ts_one_try_set_if_absent(&key, &value)
+                    .map_err(|err| status_of(&err))?;
+                Ok(BoolResponse { value })
+            }),
+            Some("Replace") => unary(request, move |EntryRequest { key, value }| {
+                let value = store
+                    .ts_one_try_replace(&key, &value)
+                    .map_err(|err| status_of(&err))?;
+                Ok(ValueResponse { value })
+            }),
+            Some("Remove") => unary(request, move |KeyRequest { key }| {
+                let value = store
+                    .ts_one_try_remove(&key)
+                    .map_err(|err| status_of(&err))?;
+                Ok(ValueResponse { value })
+            }),
+            Some("Ping") => unary(request, |Empty {}| Ok(Empty {})),
+            _ => Box::pin(async { Ok(Status::unimplemented("unknown method").into_http()) }),
+        }
+    }
+}
+
+/// Error of a [`GrpcStore`].
+#[derive(Debug)]
+pub enum GrpcError {
+    /// The runtime for the client couldn't be started.
+    Io(std::io::Error),
+    /// The server couldn't be reached.
+    Transport(tonic::transport::Error),
+    /// The server answered with an error.
+    Status(Status),
+}
+impl std::error::Error for GrpcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Transport(err) => Some(err),
+            Self::Status(err) => Some(err),
+        }
+    }
+}
+impl core::fmt::Display for GrpcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Io(err) => writeln!(f, "io error: {err}"),
+            Self::Transport(err) => writeln!(f, "transport error: {err}"),
+            Self::Status(err) => writeln!(f, "server error: {err}"),
+        }
+    }
+}
+
+impl CacheError for GrpcError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Io(err) => err.is_transient(),
+            Self::Transport(_) => true,
+            Self::Status(status) => status.code() == Code::Unavailable,
+        }
+    }
+}
+
+/// [`TryCacheStore`] of bytes served by a [`GrpcServer`], or any other server of the protocol.
+///
+/// It runs its own runtime to block on the requests, so it can't be used within an async context.
+pub struct GrpcStore {
+    client: Grpc<Channel>,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl GrpcStore {
+    /// Connects to the server at the given endpoint.
+    ///
+    /// # Errors
+    /// Fails if the runtime can't be started or the server can't be reached.
+    pub fn connect(endpoint: &Endpoint) -> Result<Self, GrpcError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(GrpcError::Io)?;
+        let channel = runtime
+            .block_on(endpoint.connect())
+            .map_err(GrpcError::Transport)?;
+        Ok(Self {
+            client: Grpc::new(channel),
+            runtime,
+        })
+    }
+
+    /// Calls a method of the server, within the deadline of the [current context][OpContext] if
+    /// there's one.
+    fn call<Req, Res>(&self, method: &'static str, request: Req) -> Result<Res, GrpcError>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let ctx = OpContext::current();
+        let remaining = ctx.as_ref().and_then(OpContext::remaining);
+        if let Some(ctx) = &ctx {
+            ctx.check::<Infallible>().map_err(|err| {
+                let status = match err {
+                    DeadlineError::Cancelled => Status::cancelled("operation cancelled"),
+                    _ => Status::deadline_exceeded("deadline exceeded"),
+                };
+                GrpcError::Status(status)
+            })?;
+        }
+
+        let mut request = Request::new(request);
+        if let Some(remaining) = remaining {
+            // Lets the server give up too
+            request.set_timeout(remaining);
+        }
+        let mut client = self.client.clone();
+        let call = async move {
+            client.ready().await.map_err(GrpcError::Transport)?;
+            let path = http::uri::PathAndQuery::from_static(method);
+            client
+                .unary(request, path, ProstCodec::<Req, Res>::default())
+                .await
+                .map(Response::into_inner)
+                .map_err(GrpcError::Status)
+        };
+        self.runtime.block_on(async move {
+            match remaining {
+                Some(remaining) => {
+                    tokio::time::timeout(remaining, call)
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(GrpcError::Status(Status::deadline_exceeded(
+                                "deadline exceeded",
+                            )))
+                        })
+                }
+                None => call.await,
+            }
+        })
+    }
+}
+
+impl TryCacheStore for GrpcStore {
+    type Key = Vec<u8>;
+    type Value = Vec<u8>;
+    type Error = GrpcError;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let request = KeyRequest {
+            key: key.borrow().clone(),
+        };
+        let response: ValueResponse = self.call("/ezcache.Cache/Get", request)?;
+        Ok(response.value)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let request = EntryRequest {
+            key: key.borrow().clone(),
+            value: value.borrow().clone(),
+        };
+        let Empty {} = self.call("/ezcache.Cache/Set", request)?;
+        Ok(())
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let request = KeyRequest {
+            key: key.borrow().clone(),
+        };
+        let response: ValueResponse = self.call("/ezcache.Cache/Remove", request)?;
+        Ok(response.value)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let request = KeyRequest {
+            key: key.borrow().clone(),
+        };
+        let response: BoolResponse = self.call("/ezcache.Cache/Exists", request)?;
+        Ok(response.value)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let request = EntryRequest {
+            key: key.borrow().clone(),
+            value: value.borrow().clone(),
+        };
+        let response: BoolResponse = self.call("/ezcache.Cache/SetIfAbsent", request)?;
+        Ok(response.value)
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let request = EntryRequest {
+            key: key.borrow().clone(),
+            value: value.borrow().clone(),
+        };
+        let response: ValueResponse = self.call("/ezcache.Cache/Replace", request)?;
+        Ok(response.value)
+    }
+}
+
+/// Pings the server, servers without `Ping` are healthy too as they answered.
+impl HealthCheck for GrpcStore {
+    type Error = GrpcError;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        match self.call("/ezcache.Cache/Ping", Empty {}) {
+            Ok(Empty {}) => Ok(Health::Healthy),
+            Err(GrpcError::Status(status)) if status.code() == Code::Unimplemented => {
+                Ok(Health::Healthy)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::ThreadSafeMemoryStore;
+    use std::{net::SocketAddr, thread};
+    use tonic::transport::{server::TcpIncoming, Server};
+
+    #[test]
+    fn client_reaches_served_store() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let incoming = {
+            let _context = runtime.enter();
+            TcpIncoming::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap()
+        };
+        let address = incoming.local_addr().unwrap();
+        let server = GrpcServer::new(ThreadSafeMemoryStore::<Vec<u8>, Vec<u8>>::default());
+        thread::spawn(move || {
+            runtime.block_on(
+                Server::builder()
+                    .add_service(server)
+                    .serve_with_incoming(incoming),
+            )
+        });
+
+        let endpoint = Endpoint::from_shared(std::format!("http://{address}")).unwrap();
+        let mut store = GrpcStore::connect(&endpoint).unwrap();
+        assert_eq!(store.ping().unwrap(), Health::Healthy);
+        assert_eq!(store.try_get(b"key".to_vec()).unwrap(), None);
+        assert!(store
+            .try_set_if_absent(b"key".to_vec(), b"a".to_vec())
+            .unwrap());
+        assert!(!store
+            .try_set_if_absent(b"key".to_vec(), b"b".to_vec())
+            .unwrap());
+        assert_eq!(
+            store.try_replace(b"key".to_vec(), b"c".to_vec()).unwrap(),
+            Some(b"a".to_vec())
+        );
+        assert!(store.try_exists(b"key".to_vec()).unwrap());
+        assert_eq!(store.try_get(b"key".to_vec()).unwrap(), Some(b"c".to_vec()));
+        assert_eq!(
+            store.try_remove(b"key".to_vec()).unwrap(),
+            Some(b"c".to_vec())
+        );
+        assert!(!store.try_exists(b"key".to_vec()).unwrap());
+
+        let expired = OpContext::new().with_deadline(core::time::Duration::ZERO);
+        let result = expired.scope(|| store.try_get(b"key".to_vec()));
+        assert!(
+            matches!(result, Err(GrpcError::Status(status)) if status.code() == Code::DeadlineExceeded)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Stores keyed by the digest of the keys instead of the keys themselves.
+//!
+//! Keys like full request bodies or big composite structures can take far more memory than the
+//! values cached for them. [`HashedKeyStore`] replaces them with their SHA-256 [`KeyDigest`]
+//! before they reach the inner store, so any key hashable with [`Hash`] only takes 32 bytes.
+//!
+//! Two keys with the same digest would share their entry. That's unlikely enough to ignore for
+//! most uses, but [verification][HashedKeyStore::with_verification] keeps the original key next
+//! to the value and treats entries of other keys as misses, at the cost of storing the key again.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, hashed::HashedKeyStore, stores::MemoryStore};
+//! #
+//! let mut store: HashedKeyStore<String, _, _> = HashedKeyStore::new(MemoryStore::new());
+//!
+//! let body = "a".repeat(1 << 20);
+//! store.try_set(&body, 1).unwrap();
+//! assert_eq!(store.try_get(&body).unwrap(), Some(1));
+//! ```
+
+use crate::{__internal_prelude::*, size::SizedStore};
+
+use core::hash::{Hash, Hasher};
+use sha2::{Digest, Sha256};
+
+/// SHA-256 digest a key is stored under.
+pub type KeyDigest = [u8; 32];
+
+/// [`Hasher`] feeding everything a key hashes into SHA-256.
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(
+            digest[..8]
+                .try_into()
+                .expect("digest is longer than 8 bytes"),
+        )
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+/// Returns the digest `key` is stored under.
+///
+/// It depends on the [`Hash`] implementation of the key, which is not guaranteed to be the same
+/// across platforms or versions of Rust, so it shouldn't be persisted.
+pub fn key_digest<K: Hash + ?Sized>(key: &K) -> KeyDigest {
+    let mut hasher = Sha256Hasher(Sha256::new());
+    key.hash(&mut hasher);
+    hasher.0.finalize().into()
+}
+
+/// Wrapper around a [`TryCacheStore`] keyed by [`KeyDigest`]s, that takes keys of any hashable
+/// type and only stores their digest.
+///
+/// The values of the inner store hold the original key when
+/// [verification][HashedKeyStore::with_verification] is enabled, and [`None`] otherwise.
+///
+/// Generics:
+/// - `K`: Type of the keys, before hashing.
+/// - `V`: Type of the values.
+/// - `S`: [`TryCacheStore`] which this wraps around, keyed by [`KeyDigest`] with `(Option<K>, V)`
+///   values.
+pub struct HashedKeyStore<K, V, S> {
+    pub store: S,
+    verify: bool,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, S> HashedKeyStore<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: TryCacheStore<Key = KeyDigest, Value = (Option<K>, V)>,
+{
+    /// Make a new [`HashedKeyStore`] around the given store, without verification.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            verify: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Keeps the original key along with every value set from now on, and treats entries whose
+    /// key doesn't match the one asked for as misses.
+    ///
+    /// Entries set before enabling it are served without checking.
+    #[must_use]
+    pub fn with_verification(mut self) -> Self {
+        self.verify = true;
+        self
+    }
+
+    /// Gets the entry of `key` if it belongs to it.
+    fn entry_of(&self, key: &K) -> Result<Option<V>, S::Error> {
+        Ok(match self.store.try_get(key_digest(key))? {
+            Some((Some(stored), _)) if self.verify && stored != *key => None,
+            entry => entry.map(|(_, value)| value),
+        })
+    }
+}
+
+impl<K, V, S: SizedStore> SizedStore for HashedKeyStore<K, V, S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<K, V, S> TryCacheStore for HashedKeyStore<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: TryCacheStore<Key = KeyDigest, Value = (Option<K>, V)>,
+{
+    type Key = K;
+    type Value = V;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.entry_of(key.borrow())
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        let stored = self.verify.then(|| key.clone());
+        self.store
+            .try_set(key_digest(key), (stored, value.borrow().clone()))
+    }
+
+    /// With verification, entries of other keys are left alone.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        if self.verify && self.entry_of(key)?.is_none() {
+            return Ok(None);
+        }
+        let old = self.store.try_remove(key_digest(key))?;
+        Ok(old.map(|(_, value)| value))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        if self.verify {
+            return Ok(self.entry_of(key)?.is_some());
+        }
+        self.store.try_exists(key_digest(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::{string::String, vec};
+
+    #[test]
+    fn verification_rejects_other_keys() {
+        let mut store = HashedKeyStore::new(MemoryStore::new()).with_verification();
+        let key = vec![String::from("large"); 1000];
+        store.try_set(&key, 1).unwrap();
+        assert_eq!(store.try_get(&key).unwrap(), Some(1));
+
+        // Same as if another key had the same digest
+        let other = vec![String::from("other")];
+        store
+            .store
+            .try_set(key_digest(&other), (Some(key.clone()), 2))
+            .unwrap();
+        assert_eq!(store.try_get(&other).unwrap(), None);
+        assert!(!store.try_exists(&other).unwrap());
+    }
+}
@@ -0,0 +1,82 @@
+//! Health checks of the backends of stores, for readiness endpoints.
+//!
+//! Stores that depend on something that can go away, like a disk or a server, implement
+//! [`HealthCheck`]. [`ping`][HealthCheck::ping] fails when the store can't serve requests at all
+//! and returns a [`Health`] otherwise, which can still warn about something that needs attention
+//! soon, like a disk almost full.
+//!
+//! Wrappers that only forward operations check the store they wrap, and a
+//! [`TieredStore`][crate::tiered::TieredStore] checks both of its tiers.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{health::{Health, HealthCheck}, stores::file_stores::ThreadSafeFileStore};
+//! #
+//! # let dir = tempfile::tempdir().unwrap();
+//! let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(dir.path().to_path_buf()).unwrap();
+//!
+//! // Like in a readiness endpoint
+//! let ready = match store.ping() {
+//!     Ok(Health::Healthy) => true,
+//!     Ok(Health::Degraded(reason)) => {
+//!         eprintln!("cache degraded: {reason}");
+//!         true
+//!     }
+//!     Err(_) => false,
+//! };
+//! assert!(ready);
+//! ```
+
+use std::string::String;
+
+/// How well a reachable backend is doing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// Working normally.
+    Healthy,
+    /// Working, but something needs attention, with a description of what.
+    Degraded(String),
+}
+
+impl Health {
+    /// Whether it's [`Health::Healthy`].
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, Self::Healthy)
+    }
+
+    /// The worse of both, or `self` if they are as bad.
+    #[must_use]
+    pub fn worst(self, other: Self) -> Self {
+        match (&self, &other) {
+            (Self::Healthy, Self::Degraded(_)) => other,
+            _ => self,
+        }
+    }
+}
+
+/// Trait for a store whose backend can be checked, see the [module docs][self].
+pub trait HealthCheck {
+    type Error;
+
+    /// Checks that the backend can serve requests, without touching any entry.
+    ///
+    /// # Errors
+    /// When the backend can't serve requests, like when it can't be reached.
+    fn ping(&self) -> Result<Health, Self::Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worst_keeps_first_reason() {
+        let a = Health::Degraded(String::from("a"));
+        let b = Health::Degraded(String::from("b"));
+        assert_eq!(Health::Healthy.worst(a.clone()), a);
+        assert_eq!(a.clone().worst(Health::Healthy), a);
+        assert_eq!(a.clone().worst(b), a);
+        assert!(Health::Healthy.worst(Health::Healthy).is_healthy());
+    }
+}
@@ -0,0 +1,145 @@
+//! Generators that fetch HTTP resources, refreshing them with conditional requests.
+//!
+//! [`http_fetch`] downloads the url of a key into a [`CachedResponse`], keeping the `ETag` and
+//! `Last-Modified` headers along with the body. When given the response cached before, it sends
+//! them back as `If-None-Match` and `If-Modified-Since`, so a resource that didn't change is
+//! answered with an empty `304 Not Modified` and the old body is reused.
+//!
+//! Its arguments are the client to use and the previous response, if any, so it can be used as
+//! the generator of any generative wrapper through [`http_fetch_generator`].
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use ezcache::{http::http_fetch_generator, prelude::*, TryCacheStoreErrorMap};
+//! #
+//! let store: TryCacheStoreErrorMap<_, _, _, Box<dyn std::error::Error>, _> =
+//!     MemoryStore::new().into();
+//! let mut store = TryGenCacheStoreWrapper::new(store, http_fetch_generator::<&str>());
+//! let client = reqwest::blocking::Client::new();
+//!
+//! let url = "https://www.rust-lang.org";
+//! let response = store.try_get_or_new(url, (&client, None)).unwrap();
+//!
+//! // Later on, only downloads it again if it changed
+//! let response = store.try_gen_new(url, (&client, Some(&response))).unwrap();
+//! println!("{} bytes", response.body.len());
+//! ```
+
+use std::{string::String, vec::Vec};
+
+use reqwest::{
+    blocking::Client,
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+
+/// Body of a response along with the headers to check if it changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CachedResponse {
+    pub body: Vec<u8>,
+    /// `ETag` header of the response, if it had one.
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the response, if it had one.
+    pub last_modified: Option<String>,
+}
+
+/// Generator function of [`http_fetch`], with the type generative wrappers expect.
+pub type HttpFetchGenerator<K> =
+    fn(&K, (&Client, Option<&CachedResponse>)) -> Result<CachedResponse, reqwest::Error>;
+
+/// Fetches the url `key`, conditionally if there's a `previous` response for it.
+///
+/// # Errors
+/// Fails when the request does or the response has an error status.
+pub fn http_fetch<K: AsRef<str>>(
+    key: &K,
+    (client, previous): (&Client, Option<&CachedResponse>),
+) -> Result<CachedResponse, reqwest::Error> {
+    let mut request = client.get(key.as_ref());
+    if let Some(previous) = previous {
+        if let Some(etag) = &previous.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send()?.error_for_status()?;
+    if let Some(previous) = previous.filter(|_| response.status() == StatusCode::NOT_MODIFIED) {
+        return Ok(previous.clone());
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+    Ok(CachedResponse {
+        body: response.bytes()?.to_vec(),
+        etag,
+        last_modified,
+    })
+}
+
+/// Returns [`http_fetch`] as a generator for generative wrappers, so its type doesn't have to be
+/// spelled out.
+#[must_use]
+pub fn http_fetch_generator<K: AsRef<str>>() -> HttpFetchGenerator<K> {
+    http_fetch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        borrow::ToOwned,
+        format,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serves `requests` requests, with a body only to the ones not sending its `ETag` back.
+    fn serve(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let lines: Vec<String> = BufReader::new(&stream)
+                    .lines()
+                    .map(|line| line.unwrap().trim_end().to_owned())
+                    .take_while(|line| !line.is_empty())
+                    .collect();
+                let response = if lines
+                    .iter()
+                    .any(|line| line.eq_ignore_ascii_case("if-none-match: \"v1\""))
+                {
+                    "HTTP/1.1 304 Not Modified\r\nconnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncontent-length: 4\r\nconnection: close\r\n\r\nbody"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn refresh_reuses_unmodified_body() {
+        let url = serve(2);
+        let client = Client::new();
+
+        let first = http_fetch(&url, (&client, None)).unwrap();
+        assert_eq!(first.body, b"body");
+        assert_eq!(first.etag.as_deref(), Some("\"v1\""));
+
+        let second = http_fetch(&url, (&client, Some(&first))).unwrap();
+        assert_eq!(second, first);
+    }
+}
@@ -0,0 +1,291 @@
+//! Caching HTTP responses, independently of the web framework.
+//!
+//! [`HttpCache`] has what's common to the middlewares of the [axum][crate::axum] and
+//! [actix-web][crate::actix_web] integrations: which requests are cached and under what key,
+//! which responses can be cached, and reading and keeping them in a thread safe store of
+//! [`CachedResponse`]s. Middlewares only translate between the requests and responses of their
+//! framework and these, so they can be written for other frameworks too.
+//!
+//! Only `GET` and `HEAD` requests are cached, keyed by their method, path and query, and only
+//! `200 OK` responses with a body of known size, up to a [limit][HttpCache::with_max_body].
+//! Responses that set cookies, have `Cache-Control: no-store` or `private`, or `Vary: *` never
+//! are. Request headers the responses depend on, like `Accept-Language`, can be made part of the
+//! key with [`with_vary`][HttpCache::with_vary].
+//!
+//! As the cache is shared by everyone, requests with cookies skip it altogether, and responses to
+//! requests with an `Authorization` header are only kept when they allow it with `public`,
+//! `s-maxage` or `must-revalidate`, as told by RFC 9111.
+//!
+//! Middlewares add an `X-Cache` header to every response, `HIT` when it came from the store and
+//! `MISS` otherwise, to tell how well the cache does from the outside. Errors of the store never
+//! fail a request, it's answered by the handler then.
+//!
+//! # Examples
+//! ```rust
+//! # use std::{sync::Arc, time::Duration};
+//! # use ezcache::{http_cache::{CachedResponse, HttpCache}, stores::ThreadSafeMemoryStore};
+//! #
+//! let store = Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default());
+//! let cache = HttpCache::new(store, Duration::from_secs(60)).with_vary("accept-language");
+//!
+//! let headers = [("accept-language", &b"es"[..])];
+//! let key = cache.key("GET", "/report?year=2024", |name| {
+//!     let values = headers.iter().filter(|(header, _)| *header == name);
+//!     values.map(|(_, value)| *value).collect::<Vec<_>>()
+//! });
+//! assert_eq!(key.as_deref(), Some("GET /report?year=2024\naccept-language: es"));
+//! assert_eq!(cache.key("POST", "/report", |_| None), None);
+//!
+//! assert_eq!(cache.key("GET", "/report", |name| (name == "cookie").then_some(&b"id=1"[..])), None);
+//!
+//! let public = [("cache-control", &b"public, max-age=60"[..])];
+//! assert!(cache.is_cacheable(200, [("content-type", &b"text/plain"[..])], Some(12), false));
+//! assert!(!cache.is_cacheable(200, [("cache-control", &b"private"[..])], Some(12), false));
+//! // Responses to authorized requests must say they can be shared
+//! assert!(!cache.is_cacheable(200, [("content-type", &b"text/plain"[..])], Some(12), true));
+//! assert!(cache.is_cacheable(200, public, Some(12), true));
+//! ```
+
+use core::time::Duration;
+use std::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    thread_safe::ThreadSafeTryCacheStore,
+};
+
+/// Header telling whether a response came from the cache.
+pub const X_CACHE: &str = "x-cache";
+
+/// Names of the directives of a `Cache-Control` header, lowercase.
+fn directives(value: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    value.split(|&byte| byte == b',').map(|directive| {
+        let name = directive
+            .split(|&byte| byte == b'=')
+            .next()
+            .unwrap_or(directive);
+        name.trim_ascii().to_ascii_lowercase()
+    })
+}
+
+/// Response as kept in the store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CachedResponse {
+    pub status: u16,
+    /// Names and values of the headers, in order.
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+    /// When it expires, as told by the [`Clock`] of the cache.
+    pub expires: Duration,
+}
+
+/// Response cache for middlewares, see the [module docs][self].
+///
+/// Generics:
+/// - `S`: [`ThreadSafeTryCacheStore`] the responses are kept in.
+/// - `C`: [`Clock`] used to expire responses, the system time by default.
+pub struct HttpCache<S, C = SystemClock> {
+    store: Arc<S>,
+    ttl: Duration,
+    vary: Vec<String>,
+    max_body: usize,
+    clock: C,
+}
+
+impl<S> HttpCache<S> {
+    /// Make a new [`HttpCache`] keeping responses in `store` for `ttl`, with bodies of up to 1 MiB.
+    pub fn new(store: Arc<S>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            vary: Vec::new(),
+            max_body: 1 << 20,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<S, C> HttpCache<S, C> {
+    /// Keeps a response apart for each value of the given request header.
+    #[must_use]
+    pub fn with_vary(mut self, header: impl AsRef<str>) -> Self {
+        self.vary.push(header.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Only caches responses whose body is up to `max_body` bytes long.
+    #[must_use]
+    pub fn with_max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    /// Replaces the clock used to expire responses.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> HttpCache<S, C2> {
+        HttpCache {
+            store: self.store,
+            ttl: self.ttl,
+            vary: self.vary,
+            max_body: self.max_body,
+            clock,
+        }
+    }
+
+    /// Longest body of the responses cached.
+    pub fn max_body(&self) -> usize {
+        self.max_body
+    }
+
+    /// Key of the response to a request, [`None`] if it's not to be cached, like when it has
+    /// cookies.
+    ///
+    /// `target` is the path and query of the request and `header_values` gives the values of a
+    /// request header by its lowercase name.
+    pub fn key<'h, I>(
+        &self,
+        method: &str,
+        target: &str,
+        mut header_values: impl FnMut(&str) -> I,
+    ) -> Option<String>
+    where
+        I: IntoIterator<Item = &'h [u8]>,
+    {
+        if method != "GET" && method != "HEAD"
+            || header_values("cookie").into_iter().next().is_some()
+        {
+            return None;
+        }
+        let mut key = String::from(method);
+        key.push(' ');
+        key.push_str(if target.is_empty() { "/" } else { target });
+        for name in &self.vary {
+            for value in header_values(name) {
+                key.push('\n');
+                key.push_str(name);
+                key.push_str(": ");
+                key.push_str(&String::from_utf8_lossy(value));
+            }
+        }
+        Some(key)
+    }
+
+    /// Whether a response can be cached, before reading its body, whose length is [`None`] if it's
+    /// not known. `authorized` tells whether the request had an `Authorization` header.
+    pub fn is_cacheable<'h>(
+        &self,
+        status: u16,
+        headers: impl IntoIterator<Item = (&'h str, &'h [u8])>,
+        body_len: Option<u64>,
+        authorized: bool,
+    ) -> bool {
+        if status != 200
+            || body_len
+                .is_none_or(|len| usize::try_from(len).map_or(true, |len| len > self.max_body))
+        {
+            return false;
+        }
+        let mut shared = !authorized;
+        let allowed = headers.into_iter().all(|(name, value)| {
+            if name.eq_ignore_ascii_case("set-cookie") {
+                false
+            } else if name.eq_ignore_ascii_case("vary") {
+                value != b"*"
+            } else if name.eq_ignore_ascii_case("cache-control") {
+                directives(value).all(|directive| match &*directive {
+                    b"no-store" | b"private" => false,
+                    b"public" | b"s-maxage" | b"must-revalidate" => {
+                        shared = true;
+                        true
+                    }
+                    _ => true,
+                })
+            } else {
+                true
+            }
+        });
+        allowed && shared
+    }
+}
+
+impl<S, C> HttpCache<S, C>
+where
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = String, Value = CachedResponse>
+        + Send
+        + Sync
+        + 'static,
+    C: Clock,
+{
+    /// The response kept for `key`, if there's one that didn't expire and it could be read.
+    pub async fn lookup(&self, key: &str) -> Option<CachedResponse> {
+        let (store, key) = (Arc::clone(&self.store), String::from(key));
+        tokio::task::spawn_blocking(move || store.ts_one_try_get(&key).ok())
+            .await
+            .ok()
+            .flatten()
+            .flatten()
+            .filter(|cached| cached.expires > self.clock.now())
+    }
+
+    /// Keeps a response for `key` until it expires, if the store allows.
+    pub async fn keep(
+        &self,
+        key: String,
+        status: u16,
+        headers: Vec<(String, Vec<u8>)>,
+        body: Vec<u8>,
+    ) {
+        let cached = CachedResponse {
+            status,
+            headers,
+            body,
+            expires: self.clock.now() + self.ttl,
+        };
+        let store = Arc::clone(&self.store);
+        // A response that couldn't be kept is still a response
+        _ = tokio::task::spawn_blocking(move || store.ts_one_try_set(&key, &cached).is_ok()).await;
+    }
+}
+
+impl<S, C: Clone> Clone for HttpCache<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            ttl: self.ttl,
+            vary: self.vary.clone(),
+            max_body: self.max_body,
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::ThreadSafeMemoryStore};
+
+    #[test]
+    fn lookup_expires() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let clock = Arc::new(MockClock::default());
+        let cache = HttpCache::new(
+            Arc::new(ThreadSafeMemoryStore::<String, CachedResponse>::default()),
+            Duration::from_mins(1),
+        )
+        .with_clock(Arc::clone(&clock));
+
+        runtime.block_on(async {
+            let key = String::from("GET /");
+            assert_eq!(cache.lookup(&key).await, None);
+            cache
+                .keep(key.clone(), 200, Vec::new(), b"hi".to_vec())
+                .await;
+            assert_eq!(cache.lookup(&key).await.unwrap().body, b"hi");
+
+            clock.advance(Duration::from_mins(1));
+            assert_eq!(cache.lookup(&key).await, None);
+        });
+    }
+}
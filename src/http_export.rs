@@ -0,0 +1,231 @@
+//! Read-only HTTP access to the entries of a store.
+//!
+//! [`serve`] answers `GET` and `HEAD` requests for `/<key>` with the raw value of the key, so
+//! other services can fetch cached artifacts, like the ones of a
+//! [`ThreadSafeFileStore`][crate::stores::file_stores::ThreadSafeFileStore], without linking this
+//! crate. Keys are percent-decoded from the path and parsed with [`FromStr`].
+//!
+//! Responses carry an `ETag` with the SHA-256 of the value, and requests sending it back in
+//! `If-None-Match`, weak or among others, get an empty `304 Not Modified`. Missing keys are a
+//! `404`, and errors of the store a `500`.
+//!
+//! Keys are read with [`ts_one_try_get`][ThreadSafeTryCacheStore::ts_one_try_get], and as clients
+//! choose them, the store shouldn't keep anything for keys it doesn't have. The file and memory
+//! stores don't.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use std::sync::Arc;
+//! # use ezcache::{http_export::serve, stores::file_stores::ThreadSafeFileStore};
+//! #
+//! # async fn run() -> std::io::Result<()> {
+//! let store = Arc::new(ThreadSafeFileStore::<String, Vec<u8>>::new_on("cache")?);
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+//! // `curl http://127.0.0.1:8080/some-key` now returns the value of "some-key"
+//! serve(store, listener).await
+//! # }
+//! ```
+
+use core::{convert::Infallible, str::FromStr};
+use std::{format, io, string::String, sync::Arc, vec::Vec};
+
+use http_body_util::{Empty, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+
+use crate::thread_safe::ThreadSafeTryCacheStore;
+
+type Body = http_body_util::Either<Full<Bytes>, Empty<Bytes>>;
+
+/// Serves the entries of `store` to every connection accepted on `listener`, until accepting
+/// fails.
+///
+/// Each connection runs in its own task, and reads of the store in the blocking threads of the
+/// runtime as they may do io.
+///
+/// # Errors
+/// Fails when accepting a connection does.
+pub async fn serve<S, K, V, E>(store: Arc<S>, listener: TcpListener) -> io::Result<()>
+where
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = K, Value = V, Error = E>
+        + Send
+        + Sync
+        + 'static,
+    K: FromStr + Send + 'static,
+    V: AsRef<[u8]>,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let store = Arc::clone(&store);
+        tokio::spawn(async move {
+            let service = service_fn(move |request| respond(Arc::clone(&store), request));
+            // Errors of a single connection are the client's business
+            _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+/// Answers a single request.
+async fn respond<S, K, V, E>(
+    store: Arc<S>,
+    request: Request<Incoming>,
+) -> Result<Response<Body>, Infallible>
+where
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = K, Value = V, Error = E>
+        + Send
+        + Sync
+        + 'static,
+    K: FromStr + Send + 'static,
+    V: AsRef<[u8]>,
+{
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return Ok(empty(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let path = request.uri().path().trim_start_matches('/');
+    let Ok(Ok(key)) = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .map(|key| key.parse::<K>())
+    else {
+        return Ok(empty(StatusCode::BAD_REQUEST));
+    };
+
+    let read = tokio::task::spawn_blocking(move || {
+        store
+            .ts_one_try_get(&key)
+            .map(|value| value.map(|value| value.as_ref().to_vec()))
+            .map_err(drop)
+    })
+    .await;
+    let value = match read {
+        Ok(Ok(Some(value))) => value,
+        Ok(Ok(None)) => return Ok(empty(StatusCode::NOT_FOUND)),
+        Ok(Err(())) | Err(_) => return Ok(empty(StatusCode::INTERNAL_SERVER_ERROR)),
+    };
+
+    let etag = etag_of(&value);
+    let builder = Response::builder()
+        .header(ETAG, &etag)
+        .header(CONTENT_TYPE, "application/octet-stream");
+    let not_modified = request
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|tags| tags.to_str().ok())
+        .is_some_and(|tags| matches_any(tags, &etag));
+    let response = if not_modified {
+        builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::Right(Empty::new()))
+    } else if request.method() == Method::HEAD {
+        builder
+            .header(CONTENT_LENGTH, value.len())
+            .body(Body::Right(Empty::new()))
+    } else {
+        builder.body(Body::Left(Full::new(value.into())))
+    };
+    Ok(response.expect("headers are valid"))
+}
+
+/// Makes an empty response with a status.
+fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::Right(Empty::new()));
+    *response.status_mut() = status;
+    response
+}
+
+/// Whether an `If-None-Match` list of tags has `etag`, comparing weakly as the header does.
+fn matches_any(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Quoted SHA-256 of a value, in hex.
+fn etag_of(value: &[u8]) -> String {
+    let hash: Vec<String> = Sha256::digest(value)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("\"{}\"", hash.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::file_stores::ThreadSafeFileStore;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        string::ToString,
+        thread,
+    };
+
+    /// Sends a raw request, returns the raw response.
+    fn request(address: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_entries_with_etags() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(ThreadSafeFileStore::<String, Vec<u8>>::new_on(dir.path()).unwrap());
+        store
+            .ts_one_try_set(&"a key".to_string(), &b"value".to_vec())
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || runtime.block_on(serve(store, listener)));
+
+        let response = request(
+            address,
+            "GET /a%20key HTTP/1.1\r\nhost: test\r\nconnection: close\r\n\r\n",
+        );
+        let etag = etag_of(b"value");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(&format!("etag: {etag}")));
+        assert!(response.ends_with("\r\n\r\nvalue"));
+
+        let response = request(
+            address,
+            &format!(
+                "GET /a%20key HTTP/1.1\r\nhost: test\r\nif-none-match: {etag}\r\n\
+                 connection: close\r\n\r\n"
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified"));
+
+        let response = request(
+            address,
+            "GET /missing HTTP/1.1\r\nhost: test\r\nconnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+    }
+
+    #[test]
+    fn matches_tag_lists() {
+        let etag = etag_of(b"value");
+        assert!(matches_any(&etag, &etag));
+        assert!(matches_any(&format!("W/{etag}"), &etag));
+        assert!(matches_any(&format!("\"other\", W/{etag}"), &etag));
+        assert!(matches_any("*", &etag));
+        assert!(!matches_any("\"other\", W/\"another\"", &etag));
+    }
+}
@@ -0,0 +1,183 @@
+//! Stores that can find and invalidate entries by attributes of their values.
+//!
+//! [`IndexedStore`] runs an indexer, any `Fn(&V) -> I`, over every value set through it and keeps
+//! an index of which keys have each attribute, so all entries whose value references the same
+//! user, tenant, document... can be [found][IndexedStore::keys_with] or
+//! [invalidated][IndexedStore::invalidate_with] at once. Several indexes can be kept by wrapping
+//! one [`IndexedStore`] in another.
+//!
+//! Invalidations don't reach the store, invalidated entries are just treated as misses until they
+//! get set again. For the same reason only entries set through the wrapper are served. Entries
+//! removed with [`try_remove`][TryCacheStore::try_remove] are removed from the store too.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, indexed::IndexedStore, stores::MemoryStore};
+//! #
+//! // Comments cached by id, indexed by their author
+//! let mut store = IndexedStore::new(MemoryStore::new(), |(author, _): &(u32, &str)| *author);
+//!
+//! store.try_set(1, (42, "first!")).unwrap();
+//! store.try_set(2, (7, "hello")).unwrap();
+//! store.try_set(3, (42, "me again")).unwrap();
+//!
+//! // User 42 was renamed, drop everything showing their old name
+//! assert_eq!(store.invalidate_with(&42), 2);
+//! assert_eq!(store.try_get(1).unwrap(), None);
+//! assert_eq!(store.try_get(2).unwrap(), Some((7, "hello")));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::hash::Hash;
+use std::collections::{HashMap, HashSet};
+
+/// Wrapper around a [`TryCacheStore`] that indexes its entries by an attribute of their values.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `I`: Type of the attribute entries are indexed by.
+/// - `F`: Indexer, returns the attribute of a value.
+pub struct IndexedStore<S: TryCacheStore, I, F> {
+    pub store: S,
+    indexer: F,
+    /// Attribute of the value of each live key.
+    attributes: HashMap<S::Key, I>,
+    /// Live keys, by the attribute of their value.
+    keys: HashMap<I, HashSet<S::Key>>,
+}
+
+impl<S, I, F> IndexedStore<S, I, F>
+where
+    S: TryCacheStore,
+    S::Key: Hash + Eq + Clone,
+    I: Hash + Eq + Clone,
+    F: Fn(&S::Value) -> I,
+{
+    /// Make a new [`IndexedStore`] around the given store and indexer.
+    pub fn new(store: S, indexer: F) -> Self {
+        Self {
+            store,
+            indexer,
+            attributes: HashMap::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Returns the live keys whose value has this attribute.
+    pub fn keys_with(&self, attribute: &I) -> impl Iterator<Item = &S::Key> + '_ {
+        self.keys.get(attribute).into_iter().flatten()
+    }
+
+    /// Invalidates a single entry, returns whether it was live.
+    pub fn invalidate(&mut self, key: &S::Key) -> bool {
+        let Some(attribute) = self.attributes.remove(key) else {
+            return false;
+        };
+        if let Some(keys) = self.keys.get_mut(&attribute) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.keys.remove(&attribute);
+            }
+        }
+        true
+    }
+
+    /// Invalidates every entry whose value has this attribute, returns how many were live.
+    pub fn invalidate_with(&mut self, attribute: &I) -> usize {
+        let Some(keys) = self.keys.remove(attribute) else {
+            return 0;
+        };
+        for key in &keys {
+            self.attributes.remove(key);
+        }
+        keys.len()
+    }
+
+    /// Indexes `key` under `attribute`, dropping it from the index of its previous value.
+    fn track(&mut self, key: &S::Key, attribute: I) {
+        self.invalidate(key);
+        self.keys
+            .entry(attribute.clone())
+            .or_default()
+            .insert(key.clone());
+        self.attributes.insert(key.clone(), attribute);
+    }
+}
+
+impl<S, I, F> TryCacheStore for IndexedStore<S, I, F>
+where
+    S: TryCacheStore,
+    S::Key: Hash + Eq + Clone,
+    I: Hash + Eq + Clone,
+    F: Fn(&S::Value) -> I,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        if !self.attributes.contains_key(key.borrow()) {
+            return Ok(None);
+        }
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let (key, value) = (key.borrow(), value.borrow());
+        self.store.try_set(key, value)?;
+        let attribute = (self.indexer)(value);
+        self.track(key, attribute);
+        Ok(())
+    }
+
+    /// Removes the entry from the store even if it was invalidated, but only returns its value if
+    /// it was live.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let old = self.store.try_remove(key)?;
+        let live = self.invalidate(key);
+        Ok(old.filter(|_| live))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        if !self.attributes.contains_key(key.borrow()) {
+            return Ok(false);
+        }
+        self.store.try_exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::vec::Vec;
+
+    #[test]
+    fn index_follows_overwrites() {
+        let mut store = IndexedStore::new(MemoryStore::new(), |value: &u32| value % 2);
+        for key in 0..4 {
+            store.try_set(key, key).unwrap();
+        }
+        // Moves key 0 from the even entries to the odd ones
+        store.try_set(0, 1).unwrap();
+
+        let mut odd: Vec<_> = store.keys_with(&1).copied().collect();
+        odd.sort_unstable();
+        assert_eq!(odd, [0, 1, 3]);
+
+        assert_eq!(store.invalidate_with(&0), 1);
+        assert!(!store.try_exists(2).unwrap());
+        assert!(store.invalidate(&3));
+        assert_eq!(store.keys_with(&1).count(), 2);
+        assert_eq!(store.try_get(0).unwrap(), Some(1));
+    }
+}
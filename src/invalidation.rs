@@ -0,0 +1,406 @@
+//! Invalidation of local caches across nodes.
+//!
+//! In tiered setups every node keeps a local store in front of a shared one, and a write on one
+//! node leaves the others serving their old local copies. [`CoherentStore`] wraps the local store
+//! of a node and publishes every key written or [invalidated][CoherentStore::invalidate] through
+//! it on a [`Transport`], while treating the keys published by other nodes as misses until they
+//! get set again locally.
+//!
+//! The transport is up to the application (Redis pub/sub, a message queue...).
+//! [`InvalidationBus`] is an in-process one, to connect stores within the same program or test.
+//!
+//! Invalidations are received lazily, when the store is accessed, so they take effect on the next
+//! access after arriving. Until then they wait in the transport, which for [`InvalidationBus`]
+//! means every key published by other nodes is queued in memory, so nodes that can go idle for
+//! long should [receive][CoherentStore::receive_invalidations] them every now and then. Invalidated
+//! keys are removed from the local store on the next write through the wrapper, and only the ones
+//! it has are remembered until then.
+//!
+//! # Examples
+//! ```rust
+//! # use std::sync::Arc;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     invalidation::{CoherentStore, InvalidationBus},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let bus = Arc::new(InvalidationBus::new());
+//! let node = || {
+//!     CoherentStore::new(MemoryStore::<&str, &str>::new(), InvalidationBus::join(&bus))
+//! };
+//! let (mut node_a, mut node_b) = (node(), node());
+//!
+//! node_b.try_set("config", "old").unwrap();
+//! node_a.try_set("config", "new").unwrap();
+//!
+//! // Node b drops its old copy, so it can fetch the new one from the shared store
+//! assert_eq!(node_b.try_get("config").unwrap(), None);
+//! assert_eq!(node_a.try_get("config").unwrap(), Some("new"));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{convert::Infallible, hash::Hash};
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    vec::Vec,
+};
+
+/// Trait for a channel that carries invalidated keys between nodes.
+pub trait Transport<K> {
+    type Error;
+
+    /// Tells every other node that `key` changed.
+    ///
+    /// # Errors
+    /// Fails when sending the key does.
+    fn publish(&self, key: &K) -> Result<(), Self::Error>;
+
+    /// Returns a key published by another node, if any arrived, without blocking.
+    ///
+    /// # Errors
+    /// Fails when receiving does.
+    fn receive(&self) -> Result<Option<K>, Self::Error>;
+}
+
+/// In-process [`Transport`], every node that [joins][InvalidationBus::join] it gets the keys
+/// published by the others.
+///
+/// Keys are queued for each node without bound until it receives them.
+#[derive(Debug)]
+pub struct InvalidationBus<K> {
+    subscribers: Mutex<Subscribers<K>>,
+}
+
+/// Senders of the nodes of an [`InvalidationBus`], by the id of their node.
+#[derive(Debug)]
+struct Subscribers<K> {
+    next_id: usize,
+    senders: Vec<(usize, Sender<K>)>,
+}
+
+/// Node of an [`InvalidationBus`].
+#[derive(Debug)]
+pub struct BusNode<K> {
+    bus: Arc<InvalidationBus<K>>,
+    id: usize,
+    receiver: Receiver<K>,
+}
+
+impl<K> InvalidationBus<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Subscribers {
+                next_id: 0,
+                senders: Vec::new(),
+            }),
+        }
+    }
+
+    /// Adds a node to the bus, it only gets the keys published after joining.
+    pub fn join(this: &Arc<Self>) -> BusNode<K> {
+        let (sender, receiver) = mpsc::channel();
+        let mut subscribers = this
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.senders.push((id, sender));
+        BusNode {
+            bus: Arc::clone(this),
+            id,
+            receiver,
+        }
+    }
+}
+
+impl<K> Default for InvalidationBus<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone> Transport<K> for BusNode<K> {
+    type Error = Infallible;
+
+    fn publish(&self, key: &K) -> Result<(), Self::Error> {
+        let mut subscribers = self
+            .bus
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Nodes that were dropped can't be sent to anymore, so they leave the bus
+        subscribers
+            .senders
+            .retain(|(id, sender)| *id == self.id || sender.send(key.clone()).is_ok());
+        Ok(())
+    }
+
+    fn receive(&self) -> Result<Option<K>, Self::Error> {
+        Ok(self.receiver.try_recv().ok())
+    }
+}
+
+/// Removes the stale keys from the local store, forgetting them. Keys left when it fails stay
+/// stale.
+fn purge_stale<S: TryCacheStore>(store: &mut S, stale: &mut HashSet<S::Key>) -> Result<(), S::Error>
+where
+    S::Key: Hash + Eq,
+{
+    let mut result = Ok(());
+    stale.retain(|key| {
+        result.is_err()
+            || store
+                .try_remove(key)
+                .map_err(|err| result = Err(err))
+                .is_err()
+    });
+    result
+}
+
+/// Error of a [`CoherentStore`].
+#[derive(Debug)]
+pub enum CoherentError<S, T> {
+    /// The local store failed.
+    Store(S),
+    /// The transport failed.
+    Transport(T),
+}
+impl<S: std::error::Error + 'static, T: std::error::Error + 'static> std::error::Error
+    for CoherentError<S, T>
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::Transport(err) => Some(err),
+        }
+    }
+}
+impl<S: core::fmt::Display, T: core::fmt::Display> core::fmt::Display for CoherentError<S, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::Transport(err) => writeln!(f, "transport error: {err}"),
+        }
+    }
+}
+
+/// Wrapper around the local [`TryCacheStore`] of a node that keeps it coherent with other nodes
+/// through a [`Transport`].
+///
+/// Invalidated keys are treated as misses until the next [set][TryCacheStore::try_set] or
+/// [remove][TryCacheStore::try_remove] through the wrapper, which removes them from the local
+/// store. Only keys the local store has are remembered as invalidated, so they take as much as the
+/// keys of the local store at most, besides whatever the transport queues until they're received.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `T`: [`Transport`] of the keys of `S`.
+pub struct CoherentStore<S: TryCacheStore, T> {
+    pub store: S,
+    pub transport: T,
+    stale: Mutex<HashSet<S::Key>>,
+}
+
+impl<S: TryCacheStore, T: Transport<S::Key>> CoherentStore<S, T>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    /// Make a new [`CoherentStore`] from the local store of a node and its transport.
+    pub fn new(store: S, transport: T) -> Self {
+        Self {
+            store,
+            transport,
+            stale: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Treats `key` as a miss on this node and tells the others to do the same.
+    ///
+    /// # Errors
+    /// Fails when the transport does.
+    pub fn invalidate(
+        &self,
+        key: impl Borrow<S::Key>,
+    ) -> Result<(), CoherentError<S::Error, T::Error>> {
+        let key = key.borrow();
+        let mut stale = self.stale.lock().unwrap_or_else(PoisonError::into_inner);
+        self.mark_stale(&mut stale, key.clone())
+            .map_err(CoherentError::Store)?;
+        self.transport
+            .publish(key)
+            .map_err(CoherentError::Transport)
+    }
+
+    /// Receives all invalidations published by other nodes so far without accessing any key, so
+    /// they don't pile up in the transport of a node that's not being used, like from a periodic
+    /// task.
+    ///
+    /// # Errors
+    /// Fails when the transport or the local store does.
+    pub fn receive_invalidations(&self) -> Result<(), CoherentError<S::Error, T::Error>> {
+        let mut stale = self.stale.lock().unwrap_or_else(PoisonError::into_inner);
+        self.receive_all(&mut stale)
+    }
+
+    /// Adds `key` to the set of stale ones if the local store has it, misses need no tracking.
+    fn mark_stale(&self, stale: &mut HashSet<S::Key>, key: S::Key) -> Result<(), S::Error> {
+        if self.store.try_exists(&key)? {
+            stale.insert(key);
+        }
+        Ok(())
+    }
+
+    /// Receives all keys that arrived on the transport into the set of stale ones.
+    fn receive_all(
+        &self,
+        stale: &mut HashSet<S::Key>,
+    ) -> Result<(), CoherentError<S::Error, T::Error>> {
+        while let Some(key) = self.transport.receive().map_err(CoherentError::Transport)? {
+            self.mark_stale(stale, key).map_err(CoherentError::Store)?;
+        }
+        Ok(())
+    }
+
+    /// Receives all invalidations published by other nodes so far, returns if `key` is among the
+    /// invalidated ones.
+    fn is_stale(&self, key: &S::Key) -> Result<bool, CoherentError<S::Error, T::Error>> {
+        let mut stale = self.stale.lock().unwrap_or_else(PoisonError::into_inner);
+        self.receive_all(&mut stale)?;
+        Ok(stale.contains(key))
+    }
+}
+
+impl<S: TryCacheStore, T: Transport<S::Key>> TryCacheStore for CoherentStore<S, T>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = CoherentError<S::Error, T::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        if self.is_stale(key)? {
+            return Ok(None);
+        }
+        self.store.try_get(key).map_err(CoherentError::Store)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        self.store
+            .try_set(key, value)
+            .map_err(CoherentError::Store)?;
+        let mut stale = self.stale.lock().unwrap_or_else(PoisonError::into_inner);
+        self.receive_all(&mut stale)?;
+        // Older invalidations of the key must not override this value
+        stale.remove(key);
+        purge_stale(&mut self.store, &mut stale).map_err(CoherentError::Store)?;
+        drop(stale);
+        self.transport
+            .publish(key)
+            .map_err(CoherentError::Transport)
+    }
+
+    /// Other nodes get the key invalidated.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let mut stale = self.stale.lock().unwrap_or_else(PoisonError::into_inner);
+        self.receive_all(&mut stale)?;
+        // Including the key if stale, so its old value isn't returned
+        purge_stale(&mut self.store, &mut stale).map_err(CoherentError::Store)?;
+        drop(stale);
+        let old = self.store.try_remove(key).map_err(CoherentError::Store)?;
+        self.transport
+            .publish(key)
+            .map_err(CoherentError::Transport)?;
+        Ok(old)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        if self.is_stale(key)? {
+            return Ok(false);
+        }
+        self.store.try_exists(key).map_err(CoherentError::Store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+
+    #[test]
+    fn invalidations_reach_other_nodes() {
+        let bus = Arc::new(InvalidationBus::new());
+        let mut nodes: Vec<_> = (0..3)
+            .map(|_| CoherentStore::new(MemoryStore::<u8, u8>::new(), InvalidationBus::join(&bus)))
+            .collect();
+        for node in &mut nodes {
+            node.try_set(0, 0).unwrap();
+        }
+        // Each set invalidated the copies of the nodes before it
+        assert_eq!(nodes[0].try_get(0).unwrap(), None);
+        assert_eq!(nodes[1].try_get(0).unwrap(), None);
+        assert_eq!(nodes[2].try_get(0).unwrap(), Some(0));
+
+        nodes[0].try_set(0, 1).unwrap();
+        assert_eq!(nodes[0].try_get(0).unwrap(), Some(1));
+        assert!(!nodes[2].try_exists(0).unwrap());
+
+        nodes[0].invalidate(0).unwrap();
+        assert_eq!(nodes[0].try_get(0).unwrap(), None);
+    }
+
+    #[test]
+    fn stale_keys_are_bounded_and_purged() {
+        let bus = Arc::new(InvalidationBus::new());
+        let node = || CoherentStore::new(MemoryStore::<u8, u8>::new(), InvalidationBus::join(&bus));
+        let (mut node_a, mut node_b) = (node(), node());
+        node_b.try_set(0, 0).unwrap();
+
+        // Keys node b doesn't have are misses already
+        for key in 1..=100 {
+            node_a.try_set(key, key).unwrap();
+        }
+        node_a.try_set(0, 1).unwrap();
+        assert_eq!(node_b.try_get(0).unwrap(), None);
+        assert_eq!(node_b.stale.lock().unwrap().len(), 1);
+
+        // Received without accessing the key
+        node_a.try_set(1, 1).unwrap();
+        node_b.receive_invalidations().unwrap();
+        assert!(node_b.transport.receive().unwrap().is_none());
+
+        node_b.try_set(1, 1).unwrap();
+        assert!(node_b.stale.lock().unwrap().is_empty());
+        assert!(!node_b.store.try_exists(0).unwrap());
+    }
+
+    #[test]
+    fn dropped_nodes_leave_the_bus() {
+        let bus = Arc::new(InvalidationBus::<u8>::new());
+        let node = InvalidationBus::join(&bus);
+        drop(InvalidationBus::join(&bus));
+        assert_eq!(bus.subscribers.lock().unwrap().senders.len(), 2);
+
+        node.publish(&0).unwrap();
+        assert_eq!(bus.subscribers.lock().unwrap().senders.len(), 1);
+    }
+}
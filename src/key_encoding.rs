@@ -0,0 +1,279 @@
+//! Encoding of keys for stores shared with other deployments or languages.
+//!
+//! Remote stores like [`GrpcStore`][crate::grpc::GrpcStore] are keyed by raw bytes, and every
+//! client writing to them has to agree on how keys become those bytes. [`EncodedKeyStore`] runs a
+//! [`KeySerializer`] over the key of every operation, so the bytes don't depend on how Rust lays
+//! out or hashes the key and they stay the same across deployments.
+//!
+//! [`Stringify`] and [`RawBytes`] are the simplest encodings, [`MsgPack`] (feature "msgpack")
+//! matches what msgpack clients of other languages write and [`Sha256Hex`] (feature
+//! "hashed-keys") keeps long keys short. [`Prefixed`] puts a prefix and a version in front of any
+//! of them, so bumping the version invalidates every key written by older deployments.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     key_encoding::{EncodedKeyStore, Prefixed, Stringify},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let encoder = Prefixed::new("app", Stringify).with_version(2);
+//! let mut store = EncodedKeyStore::new(MemoryStore::<Vec<u8>, u32>::new(), encoder);
+//!
+//! store.try_set(42, 1).unwrap();
+//! assert_eq!(store.store.try_get(b"app:v2:42".to_vec()).unwrap(), Some(1));
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    health::{Health, HealthCheck},
+    size::SizedStore,
+};
+
+use core::fmt::Display;
+use std::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Turns keys into the bytes they are stored under.
+pub trait KeySerializer<K> {
+    /// Returns the bytes the key is stored under.
+    fn serialize_key(&self, key: &K) -> Vec<u8>;
+}
+
+impl<K, F: Fn(&K) -> Vec<u8>> KeySerializer<K> for F {
+    fn serialize_key(&self, key: &K) -> Vec<u8> {
+        self(key)
+    }
+}
+
+/// Stores keys as the UTF-8 of their [`Display`] form.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stringify;
+
+impl<K: Display> KeySerializer<K> for Stringify {
+    fn serialize_key(&self, key: &K) -> Vec<u8> {
+        key.to_string().into_bytes()
+    }
+}
+
+/// Stores keys that already are bytes as they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawBytes;
+
+impl<K: AsRef<[u8]>> KeySerializer<K> for RawBytes {
+    fn serialize_key(&self, key: &K) -> Vec<u8> {
+        key.as_ref().to_vec()
+    }
+}
+
+/// Stores keys as `MessagePack`, with structs as maps of their fields like most other languages
+/// encode them.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPack;
+
+/// # Panics
+/// When the [`Serialize`][serde::Serialize] implementation of the key fails.
+#[cfg(feature = "msgpack")]
+impl<K: serde::Serialize> KeySerializer<K> for MsgPack {
+    fn serialize_key(&self, key: &K) -> Vec<u8> {
+        rmp_serde::to_vec_named(key).expect("key to serialize into MessagePack")
+    }
+}
+
+/// Stores keys as the lowercase hex of the SHA-256 of what another serializer makes of them.
+#[cfg(feature = "hashed-keys")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hex<E>(pub E);
+
+#[cfg(feature = "hashed-keys")]
+impl<K, E: KeySerializer<K>> KeySerializer<K> for Sha256Hex<E> {
+    fn serialize_key(&self, key: &K) -> Vec<u8> {
+        use sha2::{Digest, Sha256};
+
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        Sha256::digest(self.0.serialize_key(key))
+            .iter()
+            .flat_map(|byte| [HEX[usize::from(byte >> 4)], HEX[usize::from(byte & 0xf)]])
+            .collect()
+    }
+}
+
+/// Puts a prefix, and optionally a version, in front of what another serializer makes of keys,
+/// separated by `:`, like `prefix:v2:key`.
+///
+/// Generics:
+/// - `E`: [`KeySerializer`] for the rest of the key.
+#[derive(Debug, Clone, Default)]
+pub struct Prefixed<E> {
+    prefix: String,
+    version: Option<u32>,
+    inner: E,
+}
+
+impl<E> Prefixed<E> {
+    /// Puts `prefix` in front of what `inner` makes of keys.
+    pub fn new(prefix: impl Into<String>, inner: E) -> Self {
+        Self {
+            prefix: prefix.into(),
+            version: None,
+            inner,
+        }
+    }
+
+    /// Puts a `v{version}` segment after the prefix.
+    #[must_use]
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+}
+
+impl<K, E: KeySerializer<K>> KeySerializer<K> for Prefixed<E> {
+    fn serialize_key(&self, key: &K) -> Vec<u8> {
+        let mut bytes = self.prefix.as_bytes().to_vec();
+        bytes.push(b':');
+        if let Some(version) = self.version {
+            bytes.extend_from_slice(std::format!("v{version}:").as_bytes());
+        }
+        bytes.extend(self.inner.serialize_key(key));
+        bytes
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] keyed by bytes that takes keys of any type, encoding them
+/// with a [`KeySerializer`] before every operation, see the [module docs][self].
+///
+/// Generics:
+/// - `K`: Type of the keys, before encoding.
+/// - `S`: [`TryCacheStore`] which this wraps around, keyed by bytes.
+/// - `E`: [`KeySerializer`] for the keys.
+pub struct EncodedKeyStore<K, S, E> {
+    pub store: S,
+    serializer: E,
+    phantom: PhantomData<K>,
+}
+
+impl<K, S: TryCacheStore<Key = Vec<u8>>, E: KeySerializer<K>> EncodedKeyStore<K, S, E> {
+    /// Make a new [`EncodedKeyStore`] around the given store.
+    pub fn new(store: S, serializer: E) -> Self {
+        Self {
+            store,
+            serializer,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Bytes `key` is stored under.
+    pub fn encode(&self, key: &K) -> Vec<u8> {
+        self.serializer.serialize_key(key)
+    }
+}
+
+impl<K, S: SizedStore, E> SizedStore for EncodedKeyStore<K, S, E> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<K, S: HealthCheck, E> HealthCheck for EncodedKeyStore<K, S, E> {
+    type Error = S::Error;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        self.store.ping()
+    }
+}
+
+impl<K, S: TryCacheStore<Key = Vec<u8>>, E: KeySerializer<K>> TryCacheStore
+    for EncodedKeyStore<K, S, E>
+{
+    type Key = K;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(self.encode(key.borrow()))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.try_set(self.encode(key.borrow()), value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(self.encode(key.borrow()))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(self.encode(key.borrow()))
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_replace(self.encode(key.borrow()), value)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        self.store
+            .try_set_if_absent(self.encode(key.borrow()), value)
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        self.store
+            .try_replace_only(self.encode(key.borrow()), value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+
+    #[test]
+    fn prefixed_segments() {
+        let encoder = Prefixed::new("users", RawBytes);
+        assert_eq!(encoder.serialize_key(b"ada"), b"users:ada");
+        let encoder = encoder.with_version(3);
+        assert_eq!(encoder.serialize_key(b"ada"), b"users:v3:ada");
+
+        let mut store = EncodedKeyStore::new(MemoryStore::new(), |key: &u8| std::vec![*key]);
+        store.try_set(7, "seven").unwrap();
+        assert_eq!(store.store.try_get(std::vec![7]).unwrap(), Some("seven"));
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn msgpack_matches_other_clients() {
+        // What `msgpack.packb(["user", 1])` gives in Python
+        assert_eq!(MsgPack.serialize_key(&("user", 1)), b"\x92\xa4user\x01");
+    }
+
+    #[test]
+    #[cfg(feature = "hashed-keys")]
+    fn sha256_hex_is_stable() {
+        assert_eq!(
+            Sha256Hex(Stringify).serialize_key(&"abc"),
+            b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Leases for read-modify-write of entries without holding locks across user code.
+//!
+//! [`LeasedStore`] wraps a [`RevisionedStore`], which changes the revision of an entry each time
+//! it's set or removed. [`get_lease`][LeasedStore::get_lease] returns the value along with the
+//! revision it had, and [`commit`][LeasedStore::commit] only writes the new value if the entry is
+//! still at that revision, so a value computed from a stale read doesn't overwrite a newer one. The
+//! caller can then take a new lease and try again.
+//!
+//! As the revisions are kept by the inner store, writes that skip the wrapper, like through its
+//! `store` field, are told about too.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, lease::{LeaseError, LeasedStore}, stores::MemoryStore};
+//! #
+//! let mut store = LeasedStore::new(MemoryStore::<&str, u32>::new());
+//! store.try_set("visits", 1).unwrap();
+//!
+//! let lease = store.get_lease("visits").unwrap();
+//! let visits = lease.value().copied().unwrap_or(0);
+//!
+//! // Someone else wrote in the meantime
+//! store.try_set("visits", 10).unwrap();
+//! assert!(matches!(
+//!     store.commit(lease, visits + 1),
+//!     Err(LeaseError::Stale)
+//! ));
+//!
+//! let lease = store.get_lease("visits").unwrap();
+//! let visits = lease.value().copied().unwrap_or(0);
+//! store.commit(lease, visits + 1).unwrap();
+//! assert_eq!(store.try_get("visits").unwrap(), Some(11));
+//! ```
+
+use crate::{__internal_prelude::*, error::CacheError, size::SizedStore};
+
+/// Error of committing a [`Lease`].
+#[derive(Debug)]
+pub enum LeaseError<E> {
+    /// The inner store failed.
+    Store(E),
+    /// The entry was set after the lease was taken.
+    Stale,
+}
+impl<E: std::error::Error + 'static> std::error::Error for LeaseError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::Stale => None,
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for LeaseError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::Stale => writeln!(f, "entry changed since the lease was taken"),
+        }
+    }
+}
+
+impl<E: CacheError> CacheError for LeaseError<E> {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Store(err) => err.is_transient(),
+            Self::Stale => true,
+        }
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_not_found())
+    }
+}
+
+/// Store that keeps a revision of each of its entries, changed every time it's set or removed, to
+/// check [`Lease`]s against.
+pub trait RevisionedStore: TryCacheStore {
+    /// Starts keeping revisions, if the store doesn't already.
+    fn track_revisions(&mut self);
+
+    /// Current revision of the entry of `key`. Missing entries might share one, but an entry
+    /// never gets back a revision it had after being set or removed.
+    fn entry_revision(&self, key: &Self::Key) -> u64;
+}
+
+/// Value of an entry as it was read, and the revision it had then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use = "a lease does nothing unless committed"]
+pub struct Lease<K, V> {
+    key: K,
+    value: Option<V>,
+    revision: u64,
+}
+
+impl<K, V> Lease<K, V> {
+    /// Key of the leased entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Value the entry had when the lease was taken, [`None`] if it was missing.
+    pub fn value(&self) -> Option<&V> {
+        self.value.as_ref()
+    }
+
+    /// Takes the value the entry had when the lease was taken.
+    pub fn into_value(self) -> Option<V> {
+        self.value
+    }
+
+    /// Revision the entry had when the lease was taken.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] that hands out [`Lease`]s of its entries, see the
+/// [module docs][self].
+///
+/// Generics:
+/// - `S`: [`RevisionedStore`] which this wraps around.
+pub struct LeasedStore<S: TryCacheStore> {
+    pub store: S,
+}
+
+impl<S: RevisionedStore> LeasedStore<S>
+where
+    S::Key: Clone,
+{
+    /// Make a new [`LeasedStore`] around the given store, making it keep revisions.
+    pub fn new(mut store: S) -> Self {
+        store.track_revisions();
+        Self { store }
+    }
+
+    /// Current revision of an entry.
+    pub fn revision(&self, key: &S::Key) -> u64 {
+        self.store.entry_revision(key)
+    }
+
+    /// Reads an entry, returning a lease to [`commit`][Self::commit] a new value for it later.
+    ///
+    /// # Errors
+    /// Fails when the inner store does.
+    pub fn get_lease(&self, key: impl Borrow<S::Key>) -> Result<Lease<S::Key, S::Value>, S::Error> {
+        let key = key.borrow();
+        Ok(Lease {
+            value: self.store.try_get(key)?,
+            revision: self.revision(key),
+            key: key.clone(),
+        })
+    }
+
+    /// Sets the leased entry to `value`, unless it was set after the lease was taken.
+    ///
+    /// # Errors
+    /// With [`LeaseError::Stale`] if the entry changed, or when the inner store fails.
+    pub fn commit(
+        &mut self,
+        lease: Lease<S::Key, S::Value>,
+        value: impl Borrow<S::Value>,
+    ) -> Result<(), LeaseError<S::Error>> {
+        if self.revision(&lease.key) != lease.revision {
+            return Err(LeaseError::Stale);
+        }
+        self.try_set(lease.key, value).map_err(LeaseError::Store)
+    }
+}
+
+impl<S: TryCacheStore + SizedStore> SizedStore for LeasedStore<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore> TryCacheStore for LeasedStore<S> {
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+
+    #[test]
+    fn leases_of_missing_entries() {
+        let mut store = LeasedStore::new(MemoryStore::<u8, u8>::new());
+        let first = store.get_lease(0).unwrap();
+        let second = store.get_lease(0).unwrap();
+        assert_eq!((first.value(), first.revision()), (None, 0));
+
+        store.commit(first, 1).unwrap();
+        assert_eq!(store.revision(&0), 1);
+        assert!(matches!(store.commit(second, 2), Err(LeaseError::Stale)));
+        assert_eq!(store.try_get(0).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn removed_and_set_again_is_stale() {
+        let mut store = LeasedStore::new(MemoryStore::<u8, u8>::new());
+        store.try_set(0, 1).unwrap();
+        let lease = store.get_lease(0).unwrap();
+
+        store.try_remove(0).unwrap();
+        store.store.set(0, 1);
+        assert!(matches!(store.commit(lease, 2), Err(LeaseError::Stale)));
+    }
+}
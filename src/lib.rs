@@ -9,7 +9,57 @@
 //!
 //! # Examples
 //! - [stores]: For examples on some common stores implemented.
+//! - [`actix_web`]: For caching the responses of actix-web handlers, by request.
+//! - [`async_gen`]: For generating values asynchronously, once for every task awaiting them.
+//! - [axum]: For caching the responses of axum handlers, by request.
+//! - [bounded]: For stores with a limited capacity and their eviction and admission policies.
+//! - [cardinality]: For estimating how many distinct keys a store has, without listing them.
+//! - [clock]: For controlling time in time-based features.
+//! - [composite]: For keys of two components, invalidated by either of them or partitioned by one.
+//! - [compressed]: For values that are worth compressing, and telling which ones are.
+//! - [config]: For building stores from configuration files.
+//! - [context]: For errors that tell which operation and key caused them.
+//! - [cost]: For caching only the values that were expensive to generate.
+//! - [deadline]: For aborting operations once the caller doesn't need them anymore.
+//! - [dump]: For capturing the entries of a store as JSON, like for bug reports.
+//! - [dynamic]: For stores whose type is only known at runtime.
+//! - [encrypted]: For values that must be encrypted at rest, with a key per customer.
+//! - [enumerable]: For stores whose entries can be gone over.
+//! - [error]: For telling what kind of failure an error of a store is.
 //! - [generative]: For examples on the concept of generative cache stores.
+//! - [grpc]: For sharing a store with other processes, in any language.
+//! - [hashed]: For keys too large to keep in memory, stored by their digest.
+//! - [health]: For telling if the backends of stores work, like in readiness endpoints.
+//! - [http]: For caching HTTP resources and refreshing them only when they change.
+//! - [`http_cache`]: For caching the responses of handlers of any web framework.
+//! - [`http_export`]: For letting other services fetch entries over HTTP.
+//! - [indexed]: For finding and invalidating entries by attributes of their values.
+//! - [invalidation]: For keeping the local caches of several nodes coherent.
+//! - [`key_encoding`]: For keys of remote stores that must match the ones written by other clients.
+//! - [lease]: For read-modify-write of entries that fails if they changed in between.
+//! - [meta]: For getting entries along with metadata like their age or expiry, or set by the user.
+//! - [metered]: For hit ratios and latency percentiles of a store, without a tracing pipeline.
+//! - [negative]: For answering lookups of keys that don't exist without hitting the store.
+//! - [normalize]: For keys that should be cached the same even if written differently.
+//! - [pattern]: For selecting keys by glob or regex, like to invalidate them.
+//! - [recording]: For keeping track of what a store did, to debug it.
+//! - [registry]: For several logical caches over a single store.
+//! - [replay]: For recording generated values and serving them later, like in hermetic tests.
+//! - [scope]: For views of a store that only see the keys under a prefix.
+//! - [seeded]: For warming up the stores of new machines from a seed.
+//! - [sensitive]: For secrets that must be wiped from memory once the store drops them.
+//! - [shutdown]: For making sure stores don't lose data when the program exits.
+//! - [size]: For telling how many bytes stores take.
+//! - [spawn]: For choosing where background work runs.
+//! - [stream]: For going over all entries of a store without loading them at once.
+//! - [testing]: For checking that a store behaves like a map with random operations.
+//! - [throttled]: For limiting the operations and bytes per second that reach a store.
+//! - [tiered]: For a small fast store in front of a big slow one.
+//! - [transcoding]: For keeping values in another format, like a different one per tier.
+//! - [ttl]: For entries that expire, or go stale, after some time.
+//! - [`write_back`]: For buffering writes to slow stores and setting them later.
+//! - [`write_once`]: For entries that can't be overwritten once set.
+//! - [`zero_copy`]: For values in formats that can be read in place, also from other languages.
 //!
 //! # Contributing, Issues & Discussions
 //! For anything related, please consult the official repository:
@@ -19,11 +69,107 @@
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "actix-web")]
+pub mod actix_web;
+#[cfg(feature = "async")]
+pub mod async_gen;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "std")]
+pub mod bounded;
+#[cfg(feature = "std")]
+pub mod cardinality;
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod composite;
+#[cfg(feature = "compression")]
+pub mod compressed;
+#[cfg(feature = "file-stores")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "std")]
+pub mod cost;
+#[cfg(feature = "std")]
+pub mod deadline;
+#[cfg(feature = "json")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod dynamic;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+#[cfg(feature = "std")]
+pub mod enumerable;
+pub mod error;
 pub mod generative;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "hashed-keys")]
+pub mod hashed;
+#[cfg(feature = "std")]
+pub mod health;
+#[cfg(feature = "reqwest")]
+pub mod http;
+#[cfg(feature = "http-cache")]
+pub mod http_cache;
+#[cfg(feature = "http-export")]
+pub mod http_export;
+#[cfg(feature = "std")]
+pub mod indexed;
+#[cfg(feature = "std")]
+pub mod invalidation;
+#[cfg(feature = "std")]
+pub mod key_encoding;
+#[cfg(feature = "std")]
+pub mod lease;
+pub mod meta;
+#[cfg(feature = "std")]
+pub mod metered;
+#[cfg(feature = "std")]
+pub mod negative;
+pub mod normalize;
+#[cfg(feature = "std")]
+pub mod pattern;
+#[cfg(feature = "std")]
+pub mod recording;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod scope;
+#[cfg(feature = "std")]
+pub mod seeded;
+#[cfg(feature = "zeroize")]
+pub mod sensitive;
+#[cfg(feature = "std")]
+pub mod shutdown;
+pub mod size;
+#[cfg(feature = "std")]
+pub mod spawn;
 #[cfg(feature = "std")]
 pub mod stores;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "proptest")]
+pub mod testing;
 #[cfg(feature = "thread-safe")]
 pub mod thread_safe;
+#[cfg(feature = "std")]
+pub mod throttled;
+#[cfg(feature = "std")]
+pub mod tiered;
+#[cfg(feature = "std")]
+pub mod transcoding;
+#[cfg(feature = "std")]
+pub mod ttl;
+#[cfg(feature = "std")]
+pub mod write_back;
+pub mod write_once;
+#[cfg(feature = "flatbuffers")]
+pub mod zero_copy;
 
 use crate::__internal_prelude::*;
 
@@ -37,13 +183,78 @@ pub trait CacheStore {
     fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value>;
     /// Sets a value given its key
     fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>);
+    /// Removes the cache entry given its key, returning its value if it had one
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value>;
     /// Checks if the cache entry exists
     fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
         self.get(key).is_some()
     }
+    /// Sets a value given its key, returning the one it had before if any
+    fn replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Option<Self::Value> {
+        let key = key.borrow();
+        let old = self.get(key);
+        self.set(key, value);
+        old
+    }
+    /// Sets a value given its key only if it doesn't exist yet, returns whether it was set.
+    /// Memcached's `add`.
+    fn set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> bool {
+        let key = key.borrow();
+        let absent = !self.exists(key);
+        if absent {
+            self.set(key, value);
+        }
+        absent
+    }
+    /// Sets a value given its key only if it already exists, returns whether it was set.
+    /// Memcached's `replace`.
+    fn replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> bool {
+        let key = key.borrow();
+        let present = self.exists(key);
+        if present {
+            self.set(key, value);
+        }
+        present
+    }
+}
+
+/// Trait for a [`CacheStore`] that can lend its values instead of cloning them.
+///
+/// Meant for stores that keep values around in a way that can be borrowed, so read-heavy code
+/// doesn't need to pay for a clone on every access.
+pub trait CacheStoreRef: CacheStore {
+    /// Borrowed value, that dereferences to the actual [`Value`][CacheStore::Value].
+    type ValueRef<'a>: core::ops::Deref<Target = Self::Value>
+    where
+        Self: 'a;
+
+    /// Returns an option of a reference to the cache element if present
+    fn get_ref(&self, key: impl Borrow<Self::Key>) -> Option<Self::ValueRef<'_>>;
 }
 
-/// Trait for a fallible cache store, analogous to [CacheStore]
+/// Trait for a [`CacheStore`] whose values can be modified in place.
+pub trait CacheStoreMut: CacheStore {
+    /// Returns an option of a mutable reference to the cache element if present
+    fn get_mut(&mut self, key: impl Borrow<Self::Key>) -> Option<&mut Self::Value>;
+    /// Modifies the cache element in place if present, returns whether it was.
+    fn update(&mut self, key: impl Borrow<Self::Key>, f: impl FnOnce(&mut Self::Value)) -> bool {
+        self.get_mut(key).map(f).is_some()
+    }
+}
+
+/// Trait for a fallible cache store, analogous to [`CacheStore`]
 #[delegatable_trait]
 #[allow(clippy::missing_errors_doc)]
 pub trait TryCacheStore {
@@ -59,10 +270,54 @@ pub trait TryCacheStore {
         key: impl Borrow<Self::Key>,
         value: impl Borrow<Self::Value>,
     ) -> Result<(), Self::Error>;
+    /// Attempts to remove the cache entry given its key, returning its value if it had one.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error>;
     /// Attempts to check if the cache key entry exists.
     fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
         self.try_get(key).map(|v| v.is_some())
     }
+    /// Attempts to set a value given its key, returning the one it had before if any.
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let old = self.try_get(key)?;
+        self.try_set(key, value)?;
+        Ok(old)
+    }
+    /// Attempts to set a value given its key only if it doesn't exist yet, returns whether it was
+    /// set.
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        let absent = !self.try_exists(key)?;
+        if absent {
+            self.try_set(key, value)?;
+        }
+        Ok(absent)
+    }
+    /// Attempts to set a value given its key only if it already exists, returns whether it was
+    /// set.
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        let present = self.try_exists(key)?;
+        if present {
+            self.try_set(key, value)?;
+        }
+        Ok(present)
+    }
 }
 
 /// Allow any [`CacheStore`] to behave as a [`TryCacheStore`] that never fails.
@@ -84,9 +339,63 @@ impl<T: CacheStore> TryCacheStore for T {
         Ok(self.set(key, value))
     }
 
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(self.remove(key))
+    }
+
     fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
         Ok(self.exists(key))
     }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(self.replace(key, value))
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.set_if_absent(key, value))
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.replace_only(key, value))
+    }
+}
+
+/// Trait for a fallible cache store whose values can be modified in place, analogous to
+/// [`CacheStoreMut`]
+#[allow(clippy::missing_errors_doc)]
+pub trait TryCacheStoreMut: TryCacheStore {
+    /// Attempts to modify the cache element in place if present, returns whether it was.
+    fn try_update(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        f: impl FnOnce(&mut Self::Value),
+    ) -> Result<bool, Self::Error>;
+}
+
+/// Allow any [`CacheStoreMut`] to behave as a [`TryCacheStoreMut`] that never fails.
+impl<T: CacheStoreMut> TryCacheStoreMut for T {
+    fn try_update(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        f: impl FnOnce(&mut Self::Value),
+    ) -> Result<bool, Self::Error> {
+        Ok(self.update(key, f))
+    }
 }
 
 /// Struct to convert the error type of a [`TryCacheStore`] into another
@@ -103,6 +412,27 @@ impl<K, V, E, ET: From<E>, S: TryCacheStore<Key = K, Value = V, Error = E>>
     }
 }
 
+impl<K, V, E, ET, S: TryCacheStore<Key = K, Value = V, Error = E> + Clone> Clone
+    for TryCacheStoreErrorMap<K, V, E, ET, S>
+{
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            __phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, V, E, ET, S: TryCacheStore<Key = K, Value = V, Error = E> + core::fmt::Debug>
+    core::fmt::Debug for TryCacheStoreErrorMap<K, V, E, ET, S>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TryCacheStoreErrorMap")
+            .field("store", &self.store)
+            .finish()
+    }
+}
+
 impl<K, V, E, ET: From<E>, S: TryCacheStore<Key = K, Value = V, Error = E>> TryCacheStore
     for TryCacheStoreErrorMap<K, V, E, ET, S>
 {
@@ -122,9 +452,40 @@ impl<K, V, E, ET: From<E>, S: TryCacheStore<Key = K, Value = V, Error = E>> TryC
         self.store.try_set(key, value).map_err(Into::into)
     }
 
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key).map_err(Into::into)
+    }
+
     fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
         self.store.try_exists(key).map_err(Into::into)
     }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_replace(key, value).map_err(Into::into)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        self.store.try_set_if_absent(key, value).map_err(Into::into)
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        self.store.try_replace_only(key, value).map_err(Into::into)
+    }
 }
 
 impl<K, V, E, ET: From<E>, T: TryCacheStore<Key = K, Value = V, Error = E>> From<T>
@@ -151,9 +512,9 @@ pub mod prelude {
     #[cfg(feature = "thread-safe")]
     pub use crate::thread_safe::{
         generative::{ThreadSafeGenTryCacheStoreWrapper, ThreadSafeTryGenCacheStore},
-        ThreadSafeTryCacheStore,
+        ThreadSafeTryCacheStore, ThreadSafeTryCacheStoreMut,
     };
-    pub use crate::{CacheStore, TryCacheStore};
+    pub use crate::{CacheStore, CacheStoreMut, CacheStoreRef, TryCacheStore, TryCacheStoreMut};
 }
 
 mod __internal_prelude {
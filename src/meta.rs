@@ -0,0 +1,162 @@
+//! Metadata of cache entries.
+//!
+//! Stores that keep track of things like when an entry was set or how often it's used can return
+//! that along with the value, so callers can decide what to do with it (like refreshing it) without
+//! extra calls.
+//!
+//! Some stores can also keep a small [`CustomMeta`] map set along with an entry, like its content
+//! type, and return it without reading the value.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{TryCacheStore, meta::TryMetaCacheStore, stores::MemoryStore, ttl::TtlStore};
+//! #
+//! let mut store: TtlStore<&str, &str, _> =
+//!     TtlStore::new(MemoryStore::new(), Duration::from_secs(60));
+//!
+//! store.try_set("key", "value").unwrap();
+//! store.try_get("key").unwrap();
+//!
+//! let (value, meta) = store.try_get_with_meta("key").unwrap().unwrap();
+//! assert_eq!(value, "value");
+//! assert!(meta.expires_in.unwrap() <= Duration::from_secs(60));
+//! // Counting this one too
+//! assert_eq!(meta.hits, Some(2));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::time::Duration;
+
+/// Metadata of a cache entry, each store fills what it knows about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// Time since the entry was set.
+    pub age: Option<Duration>,
+    /// Time left until the entry expires.
+    pub expires_in: Option<Duration>,
+    /// Size of the entry in bytes, as the store accounts for it.
+    pub size: Option<usize>,
+    /// Times the entry was read since it was set.
+    pub hits: Option<u64>,
+}
+
+/// Trait for a [`TryCacheStore`] that can return metadata along with its entries.
+#[allow(clippy::missing_errors_doc)]
+pub trait TryMetaCacheStore: TryCacheStore {
+    /// Attempts to return an entry along with its metadata. It counts as a normal get.
+    fn try_get_with_meta(
+        &self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error>;
+}
+
+/// Trait for a [`TryMetaCacheStore`] that knows the age of all its entries, so health checks can
+/// alert when a cache hasn't been refreshed for too long. Expired entries don't count.
+pub trait AgedCacheStore: TryMetaCacheStore {
+    /// Key and age of the entry set the longest ago, [`None`] if there are no entries.
+    fn oldest_entry(&self) -> Option<(Self::Key, Duration)>;
+    /// Key and age of the entry set the most recently, [`None`] if there are no entries.
+    fn newest_entry(&self) -> Option<(Self::Key, Duration)>;
+}
+
+/// Thread safe analogous of [`TryMetaCacheStore`].
+#[cfg(feature = "thread-safe")]
+#[allow(clippy::missing_errors_doc)]
+pub trait ThreadSafeTryMetaCacheStore<'lock>: ThreadSafeTryCacheStore<'lock> {
+    /// Attempts to return an entry along with its metadata.
+    fn ts_try_get_with_meta(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error>;
+
+    /// Same as `ts_try_get_with_meta` but it performs a one-time lock
+    fn ts_one_try_get_with_meta(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error> {
+        let handle = self.ts_try_slock(key)?;
+        self.ts_try_get_with_meta(&handle)
+    }
+}
+
+/// Value in a [`CustomMeta`] map.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MetaValue {
+    Text(std::string::String),
+    Bytes(std::vec::Vec<u8>),
+}
+
+#[cfg(feature = "std")]
+impl From<std::string::String> for MetaValue {
+    fn from(value: std::string::String) -> Self {
+        Self::Text(value)
+    }
+}
+#[cfg(feature = "std")]
+impl From<&str> for MetaValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.into())
+    }
+}
+#[cfg(feature = "std")]
+impl From<std::vec::Vec<u8>> for MetaValue {
+    fn from(value: std::vec::Vec<u8>) -> Self {
+        Self::Bytes(value)
+    }
+}
+#[cfg(feature = "std")]
+impl From<&[u8]> for MetaValue {
+    fn from(value: &[u8]) -> Self {
+        Self::Bytes(value.into())
+    }
+}
+
+/// Small map of user metadata attached to an entry, like the content type or origin of its value.
+#[cfg(feature = "std")]
+pub type CustomMeta = std::collections::BTreeMap<std::string::String, MetaValue>;
+
+/// Trait for a [`ThreadSafeTryCacheStore`] that keeps a [`CustomMeta`] along with its entries,
+/// which can be read without reading the value.
+#[cfg(feature = "thread-safe")]
+#[allow(clippy::missing_errors_doc)]
+pub trait ThreadSafeTryCustomMetaCacheStore<'lock>: ThreadSafeTryCacheStore<'lock> {
+    /// Attempts to set an entry along with its custom metadata. Setting the entry again in any
+    /// other way drops the metadata.
+    fn ts_try_set_with_meta(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+        meta: &CustomMeta,
+    ) -> Result<(), Self::Error>;
+
+    /// Attempts to return the custom metadata of an entry, [`None`] if it was set without any or
+    /// doesn't exist.
+    fn ts_try_get_custom_meta(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<CustomMeta>, Self::Error>;
+
+    /// Same as `ts_try_set_with_meta` but it performs a one-time lock
+    fn ts_one_try_set_with_meta(
+        &'lock self,
+        key: &'lock Self::Key,
+        value: &Self::Value,
+        meta: &CustomMeta,
+    ) -> Result<(), Self::Error> {
+        let mut handle = self.ts_try_xlock(key)?;
+        self.ts_try_set_with_meta(&mut handle, value, meta)
+    }
+
+    /// Same as `ts_try_get_custom_meta` but it performs a one-time lock
+    fn ts_one_try_get_custom_meta(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Option<CustomMeta>, Self::Error> {
+        let handle = self.ts_try_slock(key)?;
+        self.ts_try_get_custom_meta(&handle)
+    }
+}
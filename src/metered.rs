@@ -0,0 +1,479 @@
+//! Metrics of the operations done on a store, with their latency percentiles.
+//!
+//! [`MeteredStore`] counts hits, misses and errors and keeps a [`LatencyHistogram`] per
+//! [`CacheOp`], so slow backends show up in its [`MeteredStats`] without a full tracing pipeline.
+//!
+//! The histograms are log-linear, like HDR histograms: every power of two is split in 8 buckets,
+//! so percentiles are off by less than an eighth of their value and every histogram takes the same
+//! few KiB, no matter how many operations it counts.
+//!
+//! Reading the clock twice per operation can show on hot paths, so the store can be told to
+//! [time only a sample][MeteredStore::with_sampling] of them, while still counting every hit, miss
+//! and error exactly.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, context::CacheOp, metered::MeteredStore, stores::MemoryStore};
+//! #
+//! let mut store = MeteredStore::new(MemoryStore::<&str, u32>::new());
+//!
+//! store.try_get("key").unwrap();
+//! store.try_set("key", 1).unwrap();
+//! store.try_get("key").unwrap();
+//!
+//! let stats = store.stats();
+//! assert_eq!((stats.hits.hits, stats.hits.misses), (1, 1));
+//! let get = stats.latency(CacheOp::Get);
+//! assert_eq!(get.count, 2);
+//! assert!(get.p50 <= get.p99);
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    bounded::HitStats,
+    clock::{Clock, SystemClock},
+    context::CacheOp,
+    health::{Health, HealthCheck},
+    size::SizedStore,
+};
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{
+    sync::{Mutex, PoisonError},
+    vec,
+    vec::Vec,
+};
+
+/// Bits of the buckets each power of two is split in.
+const SUB_BITS: u32 = 3;
+/// Buckets each power of two is split in.
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+/// Buckets needed to cover every [`u64`] of nanoseconds.
+const BUCKETS: usize = ((64 - SUB_BITS + 1) << SUB_BITS) as usize;
+/// Operations tracked by a [`MeteredStore`], in the order their histograms are kept.
+const OPS: [CacheOp; 7] = [
+    CacheOp::Get,
+    CacheOp::Set,
+    CacheOp::Exists,
+    CacheOp::Replace,
+    CacheOp::SetIfAbsent,
+    CacheOp::ReplaceOnly,
+    CacheOp::Remove,
+];
+
+/// Histogram of latencies with log-linear buckets, see the [module docs][self].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyHistogram {
+    /// Make a new empty [`LatencyHistogram`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+
+    /// Counts a latency.
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(nanos)] += 1;
+        self.count += 1;
+        self.max = self.max.max(nanos);
+    }
+
+    /// Latencies counted so far.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Latency under which a fraction `q` of the counted ones are, rounded down to their bucket.
+    /// Zero if none were counted.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn quantile(&self, q: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_start(index).min(self.max));
+            }
+        }
+        Duration::from_nanos(self.max)
+    }
+
+    /// Percentiles of the counted latencies.
+    #[must_use]
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count,
+            p50: self.quantile(0.5),
+            p95: self.quantile(0.95),
+            p99: self.quantile(0.99),
+            max: Duration::from_nanos(self.max),
+        }
+    }
+}
+
+/// Bucket of a latency in nanoseconds.
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS {
+        return usize::try_from(nanos).unwrap_or_default();
+    }
+    let exp = nanos.ilog2();
+    let sub = (nanos >> (exp - SUB_BITS)) & (SUB_BUCKETS - 1);
+    usize::try_from(u64::from(exp - SUB_BITS + 1) * SUB_BUCKETS + sub).unwrap_or(BUCKETS - 1)
+}
+
+/// Lowest latency in nanoseconds of a bucket.
+fn bucket_start(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    (SUB_BUCKETS + index % SUB_BUCKETS) << shift
+}
+
+/// Picks 1 in every N operations to be observed, for wrappers whose observations aren't cheap.
+#[derive(Debug)]
+pub struct Sampler {
+    every: u64,
+    seen: AtomicU64,
+}
+
+impl Default for Sampler {
+    /// Observes every operation.
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Sampler {
+    /// Make a new [`Sampler`] that observes 1 in `every` operations, starting with the first one.
+    /// Every operation is observed if `every` is `0` or `1`.
+    #[must_use]
+    pub fn new(every: u64) -> Self {
+        Self {
+            every,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the next operation is observed.
+    pub fn sample(&self) -> bool {
+        self.every <= 1
+            || self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.every)
+    }
+}
+
+/// Percentiles of the latencies of an operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencySummary {
+    /// Operations timed, only the sampled ones.
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Snapshot of the metrics of a [`MeteredStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeteredStats {
+    /// Hits and misses of gets.
+    pub hits: HitStats,
+    /// Operations the inner store failed.
+    pub errors: u64,
+    latencies: [LatencySummary; OPS.len()],
+}
+
+impl MeteredStats {
+    /// Latency percentiles of an operation.
+    #[must_use]
+    pub fn latency(&self, op: CacheOp) -> LatencySummary {
+        self.latencies[op_index(op)]
+    }
+}
+
+/// Index of the histogram of an operation.
+fn op_index(op: CacheOp) -> usize {
+    OPS.iter()
+        .position(|&other| other == op)
+        .unwrap_or_default()
+}
+
+/// Counters and histograms of a [`MeteredStore`]. Counters are atomics so operations that aren't
+/// timed don't take the lock of the histograms.
+#[derive(Debug, Default)]
+struct Meters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+    latencies: Mutex<[LatencyHistogram; OPS.len()]>,
+}
+
+/// Wrapper around a [`TryCacheStore`] that keeps metrics of the operations done on it, see the
+/// [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `C`: [`Clock`] used to time operations, the system time by default.
+pub struct MeteredStore<S, C: Clock = SystemClock> {
+    pub store: S,
+    clock: C,
+    sampler: Sampler,
+    meters: Meters,
+}
+
+impl<S: TryCacheStore> MeteredStore<S> {
+    /// Make a new [`MeteredStore`] around the given store.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            clock: SystemClock,
+            sampler: Sampler::default(),
+            meters: Meters::default(),
+        }
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> MeteredStore<S, C> {
+    /// Replaces the clock used to time operations.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> MeteredStore<S, C2> {
+        MeteredStore {
+            store: self.store,
+            clock,
+            sampler: self.sampler,
+            meters: self.meters,
+        }
+    }
+
+    /// Only times 1 in `every` operations, to keep the overhead of reading the clock off hot
+    /// paths. Hits, misses and errors are still counted for all of them.
+    #[must_use]
+    pub fn with_sampling(mut self, every: u64) -> Self {
+        self.sampler = Sampler::new(every);
+        self
+    }
+
+    /// Snapshot of the metrics so far.
+    pub fn stats(&self) -> MeteredStats {
+        let latencies = self
+            .meters
+            .latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        MeteredStats {
+            hits: HitStats {
+                hits: self.meters.hits.load(Ordering::Relaxed),
+                misses: self.meters.misses.load(Ordering::Relaxed),
+            },
+            errors: self.meters.errors.load(Ordering::Relaxed),
+            latencies: core::array::from_fn(|index| latencies[index].summary()),
+        }
+    }
+
+    /// Forgets the metrics gathered so far. Operations running meanwhile might be counted or not.
+    pub fn reset_stats(&self) {
+        let mut latencies = self
+            .meters
+            .latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *latencies = Default::default();
+        for counter in [&self.meters.hits, &self.meters.misses, &self.meters.errors] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Start time of an operation, if it's sampled.
+    fn start(&self) -> Option<Duration> {
+        self.sampler.sample().then(|| self.clock.now())
+    }
+
+    /// Keeps the metrics of an operation started at `start`, only timing it if it's sampled.
+    /// `hit` tells whether its result is a hit, for the operations that count.
+    fn record<T>(
+        &self,
+        op: CacheOp,
+        start: Option<Duration>,
+        result: &Result<T, S::Error>,
+        hit: impl FnOnce(&T) -> Option<bool>,
+    ) {
+        if let Some(start) = start {
+            let duration = self.clock.now().saturating_sub(start);
+            self.meters
+                .latencies
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)[op_index(op)]
+            .record(duration);
+        }
+        let counter = match result {
+            Ok(value) => match hit(value) {
+                Some(true) => &self.meters.hits,
+                Some(false) => &self.meters.misses,
+                None => return,
+            },
+            Err(_) => &self.meters.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<S: SizedStore, C: Clock> SizedStore for MeteredStore<S, C> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: HealthCheck, C: Clock> HealthCheck for MeteredStore<S, C> {
+    type Error = S::Error;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        self.store.ping()
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> TryCacheStore for MeteredStore<S, C> {
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let start = self.start();
+        let result = self.store.try_get(key);
+        self.record(CacheOp::Get, start, &result, |value| Some(value.is_some()));
+        result
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let start = self.start();
+        let result = self.store.try_set(key, value);
+        self.record(CacheOp::Set, start, &result, |()| None);
+        result
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let start = self.start();
+        let result = self.store.try_remove(key);
+        self.record(CacheOp::Remove, start, &result, |_| None);
+        result
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let start = self.start();
+        let result = self.store.try_exists(key);
+        self.record(CacheOp::Exists, start, &result, |_| None);
+        result
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let start = self.start();
+        let result = self.store.try_replace(key, value);
+        self.record(CacheOp::Replace, start, &result, |_| None);
+        result
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let start = self.start();
+        let result = self.store.try_set_if_absent(key, value);
+        self.record(CacheOp::SetIfAbsent, start, &result, |_| None);
+        result
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let start = self.start();
+        let result = self.store.try_replace_only(key, value);
+        self.record(CacheOp::ReplaceOnly, start, &result, |_| None);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+
+    #[test]
+    fn percentiles_within_a_bucket() {
+        let mut histogram = LatencyHistogram::new();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let summary = histogram.summary();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.max, Duration::from_micros(100));
+        for (quantile, exact) in [(summary.p50, 50), (summary.p95, 95), (summary.p99, 99)] {
+            let exact = Duration::from_micros(exact);
+            assert!(quantile <= exact && quantile >= exact * 7 / 8);
+        }
+
+        let stats = MeteredStore::new(MemoryStore::<u8, u8>::new())
+            .with_clock(MockClock::default())
+            .stats();
+        assert_eq!(stats.latency(CacheOp::Get), LatencySummary::default());
+    }
+
+    #[test]
+    fn sampling_keeps_exact_counters() {
+        let mut store = MeteredStore::new(MemoryStore::<u8, u8>::new()).with_sampling(3);
+        store.try_set(0, 0).unwrap();
+        for key in 0..6 {
+            store.try_get(key).unwrap();
+        }
+
+        let stats = store.stats();
+        assert_eq!((stats.hits.hits, stats.hits.misses), (1, 5));
+        assert_eq!(stats.latency(CacheOp::Set).count, 1);
+        assert_eq!(stats.latency(CacheOp::Get).count, 2);
+    }
+}
@@ -0,0 +1,205 @@
+//! Caching of misses.
+//!
+//! In workloads where most lookups are for keys that legitimately don't exist, like DNS, every miss
+//! goes all the way to the backend. [`NegativeCacheStore`] remembers the keys that missed for a
+//! short TTL and answers them from memory, without touching the inner store, until they expire or
+//! get set through it.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{TryCacheStore, negative::NegativeCacheStore, stores::MemoryStore};
+//! #
+//! let mut store =
+//!     NegativeCacheStore::new(MemoryStore::<&str, u32>::new(), Duration::from_secs(5));
+//!
+//! assert_eq!(store.try_get("missing").unwrap(), None);
+//! // Answered from memory for the next 5 seconds
+//! assert_eq!(store.try_get("missing").unwrap(), None);
+//!
+//! store.try_set("missing", 1).unwrap();
+//! assert_eq!(store.try_get("missing").unwrap(), Some(1));
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    clock::{Clock, SystemClock},
+    size::SizedStore,
+};
+
+use core::{hash::Hash, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Keys that missed recently.
+struct Misses<K> {
+    /// When each key missed, as told by the clock of the store.
+    at: HashMap<K, Duration>,
+    /// Size of `at` that triggers dropping the expired misses.
+    prune_at: usize,
+}
+
+/// Wrapper around a [`TryCacheStore`] that remembers its misses for a while.
+///
+/// Entries set directly on the inner store aren't seen until the miss of their key expires.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `C`: [`Clock`] used to expire misses, the system time by default.
+pub struct NegativeCacheStore<S: TryCacheStore, C: Clock = SystemClock> {
+    pub store: S,
+    clock: C,
+    ttl: Duration,
+    misses: Mutex<Misses<S::Key>>,
+}
+
+impl<S: TryCacheStore> NegativeCacheStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    /// Make a new [`NegativeCacheStore`] around the given store, remembering misses for `ttl`.
+    pub fn new(store: S, ttl: Duration) -> Self {
+        Self {
+            store,
+            clock: SystemClock,
+            ttl,
+            misses: Mutex::new(Misses {
+                at: HashMap::new(),
+                prune_at: 64,
+            }),
+        }
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> NegativeCacheStore<S, C>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    /// Replaces the clock used to expire misses, meant to be done right away.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> NegativeCacheStore<S, C2> {
+        NegativeCacheStore {
+            store: self.store,
+            clock,
+            ttl: self.ttl,
+            misses: self.misses,
+        }
+    }
+
+    /// Forgets that a key missed, so the next lookup goes to the inner store. Useful when it was
+    /// set without going through this wrapper.
+    pub fn forget(&self, key: &S::Key) {
+        self.lock_misses().at.remove(key);
+    }
+
+    /// Whether the key missed recently.
+    fn missed(&self, key: &S::Key) -> bool {
+        let now = self.clock.now();
+        self.lock_misses()
+            .at
+            .get(key)
+            .is_some_and(|at| now.saturating_sub(*at) < self.ttl)
+    }
+
+    /// Remembers that the key missed now. Expired misses are dropped every time the amount of
+    /// them doubles, so they don't pile up.
+    fn record_miss(&self, key: &S::Key) {
+        let now = self.clock.now();
+        let mut misses = self.lock_misses();
+        misses.at.insert(key.clone(), now);
+        if misses.at.len() >= misses.prune_at {
+            misses.at.retain(|_, at| now.saturating_sub(*at) < self.ttl);
+            misses.prune_at = (misses.at.len() * 2).max(64);
+        }
+    }
+
+    fn lock_misses(&self) -> MutexGuard<'_, Misses<S::Key>> {
+        self.misses.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: TryCacheStore + SizedStore, C: Clock> SizedStore for NegativeCacheStore<S, C> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> TryCacheStore for NegativeCacheStore<S, C>
+where
+    S::Key: Hash + Eq + Clone,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        if self.missed(key) {
+            return Ok(None);
+        }
+        let value = self.store.try_get(key)?;
+        if value.is_none() {
+            self.record_miss(key);
+        }
+        Ok(value)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        self.store.try_set(key, value)?;
+        self.forget(key);
+        Ok(())
+    }
+
+    /// The key is remembered as a miss, as it's known not to exist now.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let old = self.store.try_remove(key)?;
+        self.record_miss(key);
+        Ok(old)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        if self.missed(key) {
+            return Ok(false);
+        }
+        let exists = self.store.try_exists(key)?;
+        if !exists {
+            self.record_miss(key);
+        }
+        Ok(exists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+
+    #[test]
+    fn misses_expire() {
+        let clock = MockClock::default();
+        let mut store =
+            NegativeCacheStore::new(MemoryStore::new(), Duration::from_secs(1)).with_clock(&clock);
+        assert!(!store.try_exists(0).unwrap());
+
+        // Set behind its back, still a miss until it expires
+        store.store.try_set(0, 0).unwrap();
+        assert_eq!(store.try_get(0).unwrap(), None);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(store.try_get(0).unwrap(), Some(0));
+
+        assert_eq!(store.try_get(1).unwrap(), None);
+        store.try_set(1, 1).unwrap();
+        assert_eq!(store.try_get(1).unwrap(), Some(1));
+    }
+}
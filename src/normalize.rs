@@ -0,0 +1,199 @@
+//! Normalization of keys before they reach a store.
+//!
+//! Keys that mean the same but are written differently, like URLs with different casing or
+//! whitespace around, would otherwise be cached as different entries. [`NormalizedStore`] runs a
+//! [`KeyNormalizer`] over the key of every operation so they all end up on the same one.
+//!
+//! Any `Fn(&K) -> K` is a normalizer, some common ones for strings are provided, and tuples of
+//! normalizers apply them in order.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     normalize::{Lowercase, NormalizedStore, StripQuery, Trim},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let mut store = NormalizedStore::new(MemoryStore::new(), (Trim, StripQuery, Lowercase));
+//!
+//! store.try_set(String::from("https://Example.com/page?utm=1"), 1).unwrap();
+//! assert_eq!(store.try_get(String::from(" https://example.com/PAGE ")).unwrap(), Some(1));
+//! ```
+
+use crate::{__internal_prelude::*, size::SizedStore};
+
+#[cfg(feature = "std")]
+use std::string::String;
+
+/// Maps keys to the canonical form they are stored under.
+pub trait KeyNormalizer<K> {
+    /// Returns the canonical form of the key.
+    fn normalize(&self, key: &K) -> K;
+}
+
+impl<K, F: Fn(&K) -> K> KeyNormalizer<K> for F {
+    fn normalize(&self, key: &K) -> K {
+        self(key)
+    }
+}
+
+impl<K, A: KeyNormalizer<K>, B: KeyNormalizer<K>> KeyNormalizer<K> for (A, B) {
+    fn normalize(&self, key: &K) -> K {
+        self.1.normalize(&self.0.normalize(key))
+    }
+}
+
+impl<K, A: KeyNormalizer<K>, B: KeyNormalizer<K>, C: KeyNormalizer<K>> KeyNormalizer<K>
+    for (A, B, C)
+{
+    fn normalize(&self, key: &K) -> K {
+        self.2.normalize(&self.1.normalize(&self.0.normalize(key)))
+    }
+}
+
+/// Removes leading and trailing whitespace.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trim;
+
+#[cfg(feature = "std")]
+impl KeyNormalizer<String> for Trim {
+    fn normalize(&self, key: &String) -> String {
+        key.trim().into()
+    }
+}
+
+/// Turns the key into lowercase.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lowercase;
+
+#[cfg(feature = "std")]
+impl KeyNormalizer<String> for Lowercase {
+    fn normalize(&self, key: &String) -> String {
+        key.to_lowercase()
+    }
+}
+
+/// Removes the query and fragment of URL keys, everything from the first `?` or `#`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripQuery;
+
+#[cfg(feature = "std")]
+impl KeyNormalizer<String> for StripQuery {
+    fn normalize(&self, key: &String) -> String {
+        key.split(['?', '#']).next().unwrap_or_default().into()
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] that normalizes keys before every operation.
+///
+/// Entries set directly on the inner store are not normalized, so they might not be found through
+/// this wrapper.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `N`: [`KeyNormalizer`] for the keys of the store.
+#[derive(Debug, Clone, Default)]
+pub struct NormalizedStore<S, N> {
+    pub store: S,
+    normalizer: N,
+}
+
+impl<S: TryCacheStore, N: KeyNormalizer<S::Key>> NormalizedStore<S, N> {
+    /// Make a new [`NormalizedStore`] around the given store.
+    pub fn new(store: S, normalizer: N) -> Self {
+        Self { store, normalizer }
+    }
+}
+
+impl<S: SizedStore, N> SizedStore for NormalizedStore<S, N> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore, N: KeyNormalizer<S::Key>> TryCacheStore for NormalizedStore<S, N> {
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(self.normalizer.normalize(key.borrow()))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store
+            .try_set(self.normalizer.normalize(key.borrow()), value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store
+            .try_remove(self.normalizer.normalize(key.borrow()))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store
+            .try_exists(self.normalizer.normalize(key.borrow()))
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store
+            .try_replace(self.normalizer.normalize(key.borrow()), value)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        self.store
+            .try_set_if_absent(self.normalizer.normalize(key.borrow()), value)
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        self.store
+            .try_replace_only(self.normalizer.normalize(key.borrow()), value)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+
+    #[test]
+    fn closure_normalizer() {
+        let mut store = NormalizedStore::new(MemoryStore::new(), |key: &i32| key.abs());
+        store.try_set(-1, "one").unwrap();
+        assert_eq!(store.try_get(1).unwrap(), Some("one"));
+        assert_eq!(store.store.try_get(-1).unwrap(), None);
+    }
+
+    #[test]
+    fn string_normalizers() {
+        let key = String::from("  https://Example.com/a?b=c#d ");
+        assert_eq!(Trim.normalize(&key), "https://Example.com/a?b=c#d");
+        assert_eq!(
+            (Trim, StripQuery, Lowercase).normalize(&key),
+            "https://example.com/a"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Patterns to select keys by, for targeted invalidation.
+//!
+//! [`EnumerableStore::keys_matching`] and [`MemoryStore::remove_matching`] take any
+//! [`KeyPattern`], like a [`Glob`], a closure or, with the "regex" feature, a [`regex::Regex`].
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{enumerable::EnumerableStore, pattern::Glob, stores::MemoryStore};
+//! #
+//! let mut store: MemoryStore<String, u32> = ["user:1:name", "user:1:avatar", "user:2:name"]
+//!     .into_iter()
+//!     .map(|key| (key.to_string(), 0))
+//!     .collect();
+//!
+//! let mut keys = store.keys_matching(&Glob::new("user:*:name"));
+//! keys.sort_unstable();
+//! assert_eq!(keys, ["user:1:name", "user:2:name"]);
+//!
+//! assert_eq!(store.remove_matching(&Glob::new("user:1:*")), 2);
+//! assert_eq!(store.iter().count(), 1);
+//! ```
+//!
+//! [`EnumerableStore::keys_matching`]: crate::enumerable::EnumerableStore::keys_matching
+//! [`MemoryStore::remove_matching`]: crate::stores::MemoryStore::remove_matching
+
+use std::vec::Vec;
+
+/// Something keys can match.
+pub trait KeyPattern {
+    /// Whether the key matches.
+    fn matches(&self, key: &str) -> bool;
+}
+
+impl<F: Fn(&str) -> bool> KeyPattern for F {
+    fn matches(&self, key: &str) -> bool {
+        self(key)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl KeyPattern for regex::Regex {
+    fn matches(&self, key: &str) -> bool {
+        self.is_match(key)
+    }
+}
+
+/// Piece of a [`Glob`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    /// Any run of characters, even an empty one.
+    Any,
+    /// Any single character.
+    One,
+    Char(char),
+}
+
+/// Shell-like pattern that must match the whole key.
+///
+/// `*` matches any run of characters, `?` any single character and `\` makes the next character
+/// match only itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    /// Parses a glob pattern, a trailing `\` matches itself.
+    #[must_use]
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::with_capacity(pattern.len());
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::Any,
+                '?' => Token::One,
+                '\\' => Token::Char(chars.next().unwrap_or('\\')),
+                c => Token::Char(c),
+            });
+        }
+        Self { tokens }
+    }
+}
+
+impl KeyPattern for Glob {
+    /// Matches in linear space, backtracking only to the last `*`.
+    fn matches(&self, key: &str) -> bool {
+        let key: Vec<char> = key.chars().collect();
+        let (mut t, mut k) = (0, 0);
+        // Token after the last `*` and the key position it was tried at
+        let mut star = None;
+        while k < key.len() {
+            match self.tokens.get(t) {
+                Some(Token::Any) => {
+                    t += 1;
+                    star = Some((t, k));
+                }
+                Some(Token::One) => (t, k) = (t + 1, k + 1),
+                Some(&Token::Char(c)) if c == key[k] => (t, k) = (t + 1, k + 1),
+                _ => {
+                    let Some((star_t, star_k)) = star else {
+                        return false;
+                    };
+                    // Let the `*` take one more character
+                    star = Some((star_t, star_k + 1));
+                    (t, k) = (star_t, star_k + 1);
+                }
+            }
+        }
+        self.tokens[t..].iter().all(|&token| token == Token::Any)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matching() {
+        let glob = Glob::new("a*b?c\\*");
+        assert!(glob.matches("ab-c*"));
+        assert!(glob.matches("a-b-b-c*"));
+        assert!(!glob.matches("ab-c"));
+        assert!(!glob.matches("ab-cd"));
+        assert!(Glob::new("*").matches(""));
+        assert!(!Glob::new("?").matches(""));
+    }
+}
@@ -0,0 +1,348 @@
+//! Recording of the operations done on a store, to debug its behavior.
+//!
+//! [`RecordingStore`] wraps around any store and keeps the last operations done through it, with
+//! what happened and how long they took, so tests and bug reports can show what the cache actually
+//! did. With the "serde" feature the records can be serialized to save or attach the trace.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     context::CacheOp,
+//! #     recording::{Outcome, RecordingStore},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let mut store = RecordingStore::new(MemoryStore::<&str, u32>::new(), 16);
+//!
+//! store.try_get("key").unwrap();
+//! store.try_set("key", 1).unwrap();
+//! store.try_get("key").unwrap();
+//!
+//! let outcomes: Vec<_> = store.history().iter().map(|r| (r.op, r.outcome)).collect();
+//! assert_eq!(outcomes, [
+//!     (CacheOp::Get, Outcome::Miss),
+//!     (CacheOp::Set, Outcome::Written),
+//!     (CacheOp::Get, Outcome::Hit),
+//! ]);
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+use std::{
+    collections::VecDeque,
+    hash::DefaultHasher,
+    sync::{Mutex, PoisonError},
+    vec::Vec,
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    context::CacheOp,
+    metered::Sampler,
+    size::SizedStore,
+};
+
+/// What came out of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outcome {
+    /// The key had a value.
+    Hit,
+    /// The key had no value.
+    Miss,
+    /// A value was set.
+    Written,
+    /// A value was not set because the key already had one.
+    Skipped,
+    /// The store failed.
+    Failed,
+}
+
+/// Single operation done on a [`RecordingStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Record {
+    pub op: CacheOp,
+    /// Hash of the key, so records don't hold the keys themselves.
+    pub key_hash: u64,
+    pub outcome: Outcome,
+    /// Time the inner store took, as told by the clock of the store.
+    pub duration: Duration,
+}
+
+/// Wrapper around a [`TryCacheStore`] that records the last operations done on it.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `C`: [`Clock`] used to time operations, the system time by default.
+pub struct RecordingStore<S, C: Clock = SystemClock> {
+    pub store: S,
+    clock: C,
+    capacity: usize,
+    sampler: Sampler,
+    history: Mutex<VecDeque<Record>>,
+}
+
+impl<S: TryCacheStore> RecordingStore<S> {
+    /// Make a new [`RecordingStore`] that keeps up to `capacity` records, dropping the oldest ones
+    /// first.
+    pub fn new(store: S, capacity: usize) -> Self {
+        Self {
+            store,
+            clock: SystemClock,
+            capacity,
+            sampler: Sampler::default(),
+            history: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> RecordingStore<S, C> {
+    /// Replaces the clock used to time operations.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> RecordingStore<S, C2> {
+        RecordingStore {
+            store: self.store,
+            clock,
+            capacity: self.capacity,
+            sampler: self.sampler,
+            history: self.history,
+        }
+    }
+
+    /// Only records 1 in `every` operations, to keep the overhead of timing and hashing keys off
+    /// hot paths.
+    #[must_use]
+    pub fn with_sampling(mut self, every: u64) -> Self {
+        self.sampler = Sampler::new(every);
+        self
+    }
+
+    /// Returns the kept records, from oldest to newest.
+    pub fn history(&self) -> Vec<Record> {
+        self.history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Forgets all the kept records.
+    pub fn clear_history(&self) {
+        self.history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Times an operation on the inner store, if it's sampled.
+    fn timed<T>(clock: &C, sampler: &Sampler, f: impl FnOnce() -> T) -> (T, Option<Duration>) {
+        if !sampler.sample() {
+            return (f(), None);
+        }
+        let start = clock.now();
+        let result = f();
+        (result, Some(clock.now().saturating_sub(start)))
+    }
+
+    /// Keeps the record of a sampled operation, dropping the oldest one if there's no room.
+    fn push<T>(
+        &self,
+        op: CacheOp,
+        key: &S::Key,
+        result: &Result<T, S::Error>,
+        duration: Option<Duration>,
+        outcome: impl FnOnce(&T) -> Outcome,
+    ) where
+        S::Key: Hash,
+    {
+        let Some(duration) = duration.filter(|_| self.capacity > 0) else {
+            return;
+        };
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let record = Record {
+            op,
+            key_hash: hasher.finish(),
+            outcome: result.as_ref().map_or(Outcome::Failed, outcome),
+            duration,
+        };
+
+        let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        if history.len() == self.capacity {
+            history.pop_front();
+        }
+        history.push_back(record);
+    }
+}
+
+/// Outcome of an operation that checks for a value.
+fn hit_or_miss(hit: bool) -> Outcome {
+    if hit {
+        Outcome::Hit
+    } else {
+        Outcome::Miss
+    }
+}
+
+impl<S: SizedStore, C: Clock> SizedStore for RecordingStore<S, C> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> TryCacheStore for RecordingStore<S, C>
+where
+    S::Key: Hash,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let (result, duration) =
+            Self::timed(&self.clock, &self.sampler, || self.store.try_get(key));
+        self.push(CacheOp::Get, key, &result, duration, |value| {
+            hit_or_miss(value.is_some())
+        });
+        result
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        let (result, duration) = Self::timed(&self.clock, &self.sampler, || {
+            self.store.try_set(key, value)
+        });
+        self.push(CacheOp::Set, key, &result, duration, |()| Outcome::Written);
+        result
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let (result, duration) =
+            Self::timed(&self.clock, &self.sampler, || self.store.try_remove(key));
+        self.push(CacheOp::Remove, key, &result, duration, |old| {
+            hit_or_miss(old.is_some())
+        });
+        result
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        let (result, duration) =
+            Self::timed(&self.clock, &self.sampler, || self.store.try_exists(key));
+        self.push(CacheOp::Exists, key, &result, duration, |&exists| {
+            hit_or_miss(exists)
+        });
+        result
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let (result, duration) = Self::timed(&self.clock, &self.sampler, || {
+            self.store.try_replace(key, value)
+        });
+        self.push(CacheOp::Replace, key, &result, duration, |_| {
+            Outcome::Written
+        });
+        result
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        let (result, duration) = Self::timed(&self.clock, &self.sampler, || {
+            self.store.try_set_if_absent(key, value)
+        });
+        self.push(CacheOp::SetIfAbsent, key, &result, duration, |&set| {
+            if set {
+                Outcome::Written
+            } else {
+                Outcome::Skipped
+            }
+        });
+        result
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        let (result, duration) = Self::timed(&self.clock, &self.sampler, || {
+            self.store.try_replace_only(key, value)
+        });
+        self.push(CacheOp::ReplaceOnly, key, &result, duration, |&set| {
+            if set {
+                Outcome::Written
+            } else {
+                Outcome::Skipped
+            }
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore, write_once::WriteOnceStore};
+
+    #[test]
+    fn keeps_last_records() {
+        let mut store = RecordingStore::new(WriteOnceStore::new(MemoryStore::new()), 2)
+            .with_clock(MockClock::default());
+        store.try_set(0, 0).unwrap();
+        assert!(store.try_set(0, 1).is_err());
+        assert!(!store.try_set_if_absent(0, 2).unwrap());
+
+        let history = store.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            (history[0].op, history[0].outcome),
+            (CacheOp::Set, Outcome::Failed)
+        );
+        assert_eq!(
+            (history[1].op, history[1].outcome),
+            (CacheOp::SetIfAbsent, Outcome::Skipped)
+        );
+        assert_eq!(history[0].key_hash, history[1].key_hash);
+        assert_eq!(history[0].duration, Duration::ZERO);
+
+        store.clear_history();
+        assert!(store.history().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_history() {
+        let store = RecordingStore::new(MemoryStore::<u8, u8>::new(), 4);
+        store.try_get(0).unwrap();
+
+        let trace = serde_json::to_string(&store.history()).unwrap();
+        let history: Vec<Record> = serde_json::from_str(&trace).unwrap();
+        assert_eq!(history, store.history());
+    }
+}
@@ -0,0 +1,899 @@
+//! Several logical caches (groups) over a single physical backend store.
+//!
+//! Each group has its own namespace, so the same key on two different groups never collides, and
+//! its own [`GroupConfig`] (TTL and quotas) and [`GroupStats`].
+//!
+//! Quotas can be set per group and globally for the whole registry, both by amount of entries and
+//! by bytes. When a set goes over any of them, the group either rejects it or evicts its own
+//! oldest entries as set by its [`QuotaPolicy`], so a noisy group can never push out the entries
+//! of other groups.
+//!
+//! Groups are accessed through [`CacheRegistry::group`], which returns a [`CacheGroup`] handle
+//! that implements [`TryCacheStore`] and can be used as any other store.
+//!
+//! All the bookkeeping (tracked keys, expiry times and stats) lives in memory within the registry,
+//! the backend only sees [`NamespacedKey`]s and the raw values. This means that only entries set
+//! through the registry will be served, and expired entries are just treated as misses until they
+//! get overwritten, only [removed][TryCacheStore::try_remove] and evicted ones leave the backend.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     registry::{CacheRegistry, GroupConfig},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! // A single backend for everything
+//! let mut registry: CacheRegistry<&str, &str, _> = CacheRegistry::new(MemoryStore::new());
+//!
+//! // But several groups with their own config
+//! registry.register("users", GroupConfig {
+//!     max_entries: Some(1),
+//!     ..GroupConfig::default()
+//! });
+//! registry.register("pages", GroupConfig {
+//!     ttl: Some(Duration::from_mins(1)),
+//!     ..GroupConfig::default()
+//! });
+//!
+//! let mut users = registry.group("users").unwrap();
+//! users.try_set("alice", "admin").unwrap();
+//! // The "users" group only has budget for one entry
+//! assert!(users.try_set("bob", "user").is_err());
+//!
+//! // Same key, but another namespace
+//! let pages = registry.group("pages").unwrap();
+//! assert_eq!(pages.try_get("alice").unwrap(), None);
+//!
+//! assert_eq!(registry.stats("users").unwrap().rejected, 1);
+//! assert_eq!(registry.stats("pages").unwrap().misses, 1);
+//! ```
+//!
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     registry::{CacheRegistry, GroupConfig, QuotaPolicy},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! // Weigh values by their actual length
+//! let mut registry: CacheRegistry<u32, Vec<u8>, _> =
+//!     CacheRegistry::with_weigher(MemoryStore::new(), Vec::len);
+//! // Everything together can't take more than 8 bytes
+//! registry.set_global_quota(None, Some(8));
+//! registry.register("noisy", GroupConfig {
+//!     policy: QuotaPolicy::Evict,
+//!     ..GroupConfig::default()
+//! });
+//! registry.register("quiet", GroupConfig::default());
+//!
+//! registry.group("quiet").unwrap().try_set(0, vec![0; 4]).unwrap();
+//!
+//! let mut noisy = registry.group("noisy").unwrap();
+//! noisy.try_set(0, vec![1; 4]).unwrap();
+//! // Over the global quota, so the noisy group evicts its own entries to fit this one
+//! noisy.try_set(1, vec![1; 4]).unwrap();
+//! assert_eq!(noisy.try_get(0).unwrap(), None);
+//!
+//! // While the quiet one keeps everything
+//! assert_eq!(registry.group("quiet").unwrap().try_get(0).unwrap(), Some(vec![0; 4]));
+//! ```
+
+use crate::__internal_prelude::*;
+use crate::{
+    clock::{Clock, SystemClock},
+    meta::{AgedCacheStore, EntryMeta, TryMetaCacheStore},
+};
+
+use core::{
+    hash::Hash,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    string::String,
+    vec::Vec,
+};
+
+/// Key used on the backend store of a [`CacheRegistry`]. It's the original key along with the name
+/// of the group it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NamespacedKey<K> {
+    pub namespace: String,
+    pub key: K,
+}
+
+#[cfg(feature = "file-stores")]
+impl<K: crate::stores::file_stores::CustomHash> crate::stores::file_stores::CustomHash
+    for NamespacedKey<K>
+{
+    fn hash(&self) -> String {
+        use crate::stores::file_stores::CustomHash;
+
+        // Hash it all again so filenames don't grow with the namespace length
+        CustomHash::hash(&(CustomHash::hash(&self.namespace) + &self.key.hash()))
+    }
+}
+
+/// Error type used by [`CacheGroup`]s.
+#[derive(Debug)]
+pub enum RegistryError<E> {
+    /// Error from the backend store.
+    Store(E),
+    /// The group reached its entry budget.
+    OverBudget,
+}
+impl<E: std::error::Error + 'static> std::error::Error for RegistryError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::OverBudget => None,
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for RegistryError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::OverBudget => writeln!(f, "group is over its entry budget"),
+        }
+    }
+}
+
+/// What to do when a set would make a group go over its quota, or over the global one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Refuse the set with a [`RegistryError::OverBudget`].
+    #[default]
+    Reject,
+    /// Evict the oldest entries of the group itself until the new entry fits. Entries of other
+    /// groups are never touched.
+    Evict,
+}
+
+/// Configuration of a group of a [`CacheRegistry`].
+#[derive(Debug, Clone, Default)]
+pub struct GroupConfig {
+    /// Time after which entries expire, never expire if [`None`].
+    pub ttl: Option<Duration>,
+    /// Maximum amount of live entries of the group, unlimited if [`None`].
+    pub max_entries: Option<usize>,
+    /// Maximum amount of bytes of the group as measured by the registry weigher, unlimited if
+    /// [`None`].
+    pub max_bytes: Option<usize>,
+    /// Policy followed when the group, or the whole registry, is over its quota.
+    pub policy: QuotaPolicy,
+}
+
+/// Usage of a group or of the whole registry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl QuotaUsage {
+    /// Whether this usage fits within the given limits.
+    fn fits(self, max_entries: Option<usize>, max_bytes: Option<usize>) -> bool {
+        max_entries.is_none_or(|max| self.entries <= max)
+            && max_bytes.is_none_or(|max| self.bytes <= max)
+    }
+}
+
+/// Snapshot of the stats of a group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+    /// Sets refused because of the quotas.
+    pub rejected: u64,
+    /// Entries evicted to make room for others.
+    pub evicted: u64,
+}
+
+/// Atomic counters behind [`GroupStats`], so they can be updated from `&self` methods.
+#[derive(Debug, Default)]
+struct GroupCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    sets: AtomicU64,
+    rejected: AtomicU64,
+    evicted: AtomicU64,
+}
+
+impl GroupCounters {
+    fn snapshot(&self) -> GroupStats {
+        GroupStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            sets: self.sets.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Bookkeeping of a single entry.
+#[derive(Debug, Clone, Copy)]
+struct TrackedEntry {
+    written: Duration,
+    expiry: Option<Duration>,
+    size: usize,
+    /// Position in the write order of its group.
+    seq: u64,
+}
+
+struct GroupState<K> {
+    config: GroupConfig,
+    /// Keys set through this group along with their metadata.
+    entries: HashMap<K, TrackedEntry>,
+    /// Keys by write order, oldest first.
+    order: BTreeMap<u64, K>,
+    next_seq: u64,
+    bytes: usize,
+    counters: GroupCounters,
+}
+
+impl<K: Hash + Eq + Clone> GroupState<K> {
+    fn new(config: GroupConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_seq: 0,
+            bytes: 0,
+            counters: GroupCounters::default(),
+        }
+    }
+
+    fn usage(&self) -> QuotaUsage {
+        QuotaUsage {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+        }
+    }
+
+    fn track(&mut self, key: K, meta: TrackedEntry) {
+        self.bytes += meta.size;
+        self.order.insert(meta.seq, key.clone());
+        self.entries.insert(key, meta);
+    }
+
+    fn untrack(&mut self, key: &K) -> Option<TrackedEntry> {
+        let meta = self.entries.remove(key)?;
+        self.order.remove(&meta.seq);
+        self.bytes -= meta.size;
+        Some(meta)
+    }
+
+    /// Stops tracking all expired entries, returning the freed usage.
+    fn purge_expired(&mut self, now: Duration) -> QuotaUsage {
+        let expired: Vec<K> = self
+            .entries
+            .iter()
+            .filter(|(_, meta)| meta.expiry.is_some_and(|expiry| expiry <= now))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut freed = QuotaUsage::default();
+        for key in expired {
+            if let Some(meta) = self.untrack(&key) {
+                freed.entries += 1;
+                freed.bytes += meta.size;
+            }
+        }
+        freed
+    }
+
+    /// Oldest tracked key that isn't `except`.
+    fn oldest_except(&self, except: &K) -> Option<K> {
+        self.order.values().find(|key| *key != except).cloned()
+    }
+
+    /// Stops tracking an evicted entry, returning its freed usage.
+    fn evict(&mut self, key: &K) -> Option<QuotaUsage> {
+        let meta = self.untrack(key)?;
+        self.counters.evicted.fetch_add(1, Ordering::Relaxed);
+        Some(QuotaUsage {
+            entries: 1,
+            bytes: meta.size,
+        })
+    }
+}
+
+/// Registry of named groups sharing the same backend store.
+///
+/// Generics:
+/// - `K`: Type of the key used by each group.
+/// - `V`: Type of the value stored in the backend.
+/// - `S`: [`TryCacheStore`] used as backend, indexed by [`NamespacedKey`]s.
+/// - `C`: [`Clock`] used for expiry, the system time by default.
+pub struct CacheRegistry<
+    K,
+    V,
+    S: TryCacheStore<Key = NamespacedKey<K>, Value = V>,
+    C: Clock = SystemClock,
+> {
+    pub store: S,
+    clock: C,
+    groups: HashMap<String, GroupState<K>>,
+    weigher: fn(&V) -> usize,
+    global_max_entries: Option<usize>,
+    global_max_bytes: Option<usize>,
+    global_usage: QuotaUsage,
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = NamespacedKey<K>, Value = V>>
+    CacheRegistry<K, V, S>
+{
+    /// Make a new empty [`CacheRegistry`] over a backend store.
+    ///
+    /// Values are weighed by their shallow size ([`core::mem::size_of_val`]), use
+    /// [`CacheRegistry::with_weigher`] if byte quotas should account for heap allocations.
+    pub fn new(store: S) -> Self {
+        Self::with_weigher(store, core::mem::size_of_val)
+    }
+
+    /// Make a new empty [`CacheRegistry`] over a backend store, using a custom function to weigh
+    /// values for byte quotas.
+    pub fn with_weigher(store: S, weigher: fn(&V) -> usize) -> Self {
+        Self {
+            store,
+            clock: SystemClock,
+            groups: HashMap::new(),
+            weigher,
+            global_max_entries: None,
+            global_max_bytes: None,
+            global_usage: QuotaUsage::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = NamespacedKey<K>, Value = V>, C: Clock>
+    CacheRegistry<K, V, S, C>
+{
+    /// Replaces the clock used for expiry. Entries already set keep their expiry according to the
+    /// previous clock, so it's meant to be done right away.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> CacheRegistry<K, V, S, C2> {
+        CacheRegistry {
+            store: self.store,
+            clock,
+            groups: self.groups,
+            weigher: self.weigher,
+            global_max_entries: self.global_max_entries,
+            global_max_bytes: self.global_max_bytes,
+            global_usage: self.global_usage,
+        }
+    }
+
+    /// Sets the quota shared by all groups. When a set goes over it, the group being written
+    /// follows its own [`QuotaPolicy`], so a group can only evict its own entries.
+    pub fn set_global_quota(&mut self, max_entries: Option<usize>, max_bytes: Option<usize>) {
+        self.global_max_entries = max_entries;
+        self.global_max_bytes = max_bytes;
+    }
+
+    /// Registers a new group. If it already exists, only its configuration is replaced, keeping
+    /// both its entries and stats.
+    pub fn register(&mut self, name: impl Into<String>, config: GroupConfig) {
+        self.groups
+            .entry(name.into())
+            .and_modify(|group| group.config = config.clone())
+            .or_insert_with(|| GroupState::new(config));
+    }
+
+    /// Returns a handle to operate over a group, if it's registered.
+    pub fn group(&mut self, name: &str) -> Option<CacheGroup<'_, K, V, S, C>> {
+        let group = self.groups.get_mut(name)?;
+        Some(CacheGroup {
+            name: name.into(),
+            group,
+            store: &mut self.store,
+            clock: &self.clock,
+            weigher: self.weigher,
+            global_max_entries: self.global_max_entries,
+            global_max_bytes: self.global_max_bytes,
+            global_usage: &mut self.global_usage,
+        })
+    }
+
+    /// Returns the stats of a group, if it's registered.
+    #[must_use]
+    pub fn stats(&self, name: &str) -> Option<GroupStats> {
+        self.groups.get(name).map(|group| group.counters.snapshot())
+    }
+
+    /// Returns the tracked usage of a group, if it's registered. Might include expired entries
+    /// that haven't been purged yet.
+    #[must_use]
+    pub fn usage(&self, name: &str) -> Option<QuotaUsage> {
+        self.groups.get(name).map(GroupState::usage)
+    }
+
+    /// Returns the tracked usage of all groups together.
+    #[must_use]
+    pub fn global_usage(&self) -> QuotaUsage {
+        self.global_usage
+    }
+
+    /// Iterator over the names of all registered groups.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.groups.keys().map(String::as_str)
+    }
+}
+
+/// Handle over a single group of a [`CacheRegistry`], behaves as a normal [`TryCacheStore`] that
+/// only sees the keys of its own group.
+pub struct CacheGroup<
+    'a,
+    K,
+    V,
+    S: TryCacheStore<Key = NamespacedKey<K>, Value = V>,
+    C: Clock = SystemClock,
+> {
+    name: String,
+    group: &'a mut GroupState<K>,
+    store: &'a mut S,
+    clock: &'a C,
+    weigher: fn(&V) -> usize,
+    global_max_entries: Option<usize>,
+    global_max_bytes: Option<usize>,
+    global_usage: &'a mut QuotaUsage,
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = NamespacedKey<K>, Value = V>, C: Clock>
+    CacheGroup<'_, K, V, S, C>
+{
+    fn namespaced(&self, key: &K) -> NamespacedKey<K> {
+        NamespacedKey {
+            namespace: self.name.clone(),
+            key: key.clone(),
+        }
+    }
+
+    /// Age of a tracked entry.
+    fn age_of(&self, key: &K) -> Duration {
+        self.clock
+            .now()
+            .saturating_sub(self.group.entries[key].written)
+    }
+
+    /// Usage tracked for an entry, nothing if it isn't tracked.
+    fn tracked_usage(&self, key: &K) -> QuotaUsage {
+        self.group
+            .entries
+            .get(key)
+            .map_or(QuotaUsage::default(), |meta| QuotaUsage {
+                entries: 1,
+                bytes: meta.size,
+            })
+    }
+
+    /// Whether the key was set through this group and hasn't expired yet.
+    fn is_live(&self, key: &K) -> bool {
+        self.group
+            .entries
+            .get(key)
+            .is_some_and(|meta| meta.expiry.is_none_or(|expiry| expiry > self.clock.now()))
+    }
+
+    fn release(&mut self, freed: QuotaUsage) {
+        self.global_usage.entries -= freed.entries;
+        self.global_usage.bytes -= freed.bytes;
+    }
+
+    /// Whether replacing the `old` usage of an entry with `new` fits in both the group and global
+    /// quotas.
+    fn fits(&self, old: QuotaUsage, new: QuotaUsage) -> bool {
+        let after = |usage: QuotaUsage| QuotaUsage {
+            entries: usage.entries - old.entries + new.entries,
+            bytes: usage.bytes - old.bytes + new.bytes,
+        };
+        let config = &self.group.config;
+
+        after(self.group.usage()).fits(config.max_entries, config.max_bytes)
+            && after(*self.global_usage).fits(self.global_max_entries, self.global_max_bytes)
+    }
+
+    /// Name of the group.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Stats of the group.
+    #[must_use]
+    pub fn stats(&self) -> GroupStats {
+        self.group.counters.snapshot()
+    }
+}
+
+/// Evicted entries are removed from the backend. Expired ones are only forgotten by the registry,
+/// they are served as misses but their data stays in the backend until it's overwritten or the
+/// backend drops it by itself.
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = NamespacedKey<K>, Value = V>, C: Clock>
+    TryCacheStore for CacheGroup<'_, K, V, S, C>
+{
+    type Key = K;
+    type Value = V;
+    type Error = RegistryError<S::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let value = if self.is_live(key) {
+            self.store
+                .try_get(self.namespaced(key))
+                .map_err(RegistryError::Store)?
+        } else {
+            None
+        };
+
+        let counter = if value.is_some() {
+            &self.group.counters.hits
+        } else {
+            &self.group.counters.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        Ok(value)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        let value = value.borrow();
+        let now = self.clock.now();
+
+        let mut old = self.tracked_usage(key);
+        let new = QuotaUsage {
+            entries: 1,
+            bytes: (self.weigher)(value),
+        };
+
+        if !self.fits(old, new) {
+            // Expired entries don't count towards the quotas, the old one of this key included
+            let freed = self.group.purge_expired(now);
+            self.release(freed);
+            old = self.tracked_usage(key);
+        }
+        while !self.fits(old, new) {
+            let oldest = match self.group.config.policy {
+                QuotaPolicy::Evict => self.group.oldest_except(key),
+                QuotaPolicy::Reject => None,
+            };
+            let freed = match oldest {
+                Some(oldest) => {
+                    self.store
+                        .try_remove(self.namespaced(&oldest))
+                        .map_err(RegistryError::Store)?;
+                    self.group.evict(&oldest)
+                }
+                None => None,
+            };
+            let Some(freed) = freed else {
+                self.group.counters.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(RegistryError::OverBudget);
+            };
+            self.release(freed);
+        }
+
+        self.store
+            .try_set(self.namespaced(key), value)
+            .map_err(RegistryError::Store)?;
+
+        if let Some(old) = self.group.untrack(key) {
+            self.release(QuotaUsage {
+                entries: 1,
+                bytes: old.size,
+            });
+        }
+        let meta = TrackedEntry {
+            written: now,
+            expiry: self.group.config.ttl.map(|ttl| now + ttl),
+            size: new.bytes,
+            seq: self.group.next_seq,
+        };
+        self.group.next_seq += 1;
+        self.group.track(key.clone(), meta);
+        self.global_usage.entries += 1;
+        self.global_usage.bytes += new.bytes;
+        self.group.counters.sets.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let live = self.is_live(key);
+        let old = self
+            .store
+            .try_remove(self.namespaced(key))
+            .map_err(RegistryError::Store)?;
+        if let Some(old) = self.group.untrack(key) {
+            self.release(QuotaUsage {
+                entries: 1,
+                bytes: old.size,
+            });
+        }
+        Ok(old.filter(|_| live))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        if !self.is_live(key) {
+            return Ok(false);
+        }
+
+        self.store
+            .try_exists(self.namespaced(key))
+            .map_err(RegistryError::Store)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = NamespacedKey<K>, Value = V>, C: Clock>
+    TryMetaCacheStore for CacheGroup<'_, K, V, S, C>
+{
+    fn try_get_with_meta(
+        &self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error> {
+        let key = key.borrow();
+        let Some(value) = self.try_get(key)? else {
+            return Ok(None);
+        };
+
+        // Only tracked entries can be live
+        let tracked = self.group.entries[key];
+        let now = self.clock.now();
+        let meta = EntryMeta {
+            age: Some(now.saturating_sub(tracked.written)),
+            expires_in: tracked.expiry.map(|expiry| expiry.saturating_sub(now)),
+            size: Some(tracked.size),
+            hits: None,
+        };
+        Ok(Some((value, meta)))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = NamespacedKey<K>, Value = V>, C: Clock>
+    AgedCacheStore for CacheGroup<'_, K, V, S, C>
+{
+    fn oldest_entry(&self) -> Option<(Self::Key, Duration)> {
+        let key = self.group.order.values().find(|key| self.is_live(key))?;
+        Some((key.clone(), self.age_of(key)))
+    }
+
+    fn newest_entry(&self) -> Option<(Self::Key, Duration)> {
+        let key = self
+            .group
+            .order
+            .values()
+            .rev()
+            .find(|key| self.is_live(key))?;
+        Some((key.clone(), self.age_of(key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+
+    fn registry() -> CacheRegistry<usize, usize, MemoryStore<NamespacedKey<usize>, usize>> {
+        CacheRegistry::new(MemoryStore::new())
+    }
+
+    #[test]
+    fn groups_are_isolated() {
+        let mut registry = registry();
+        registry.register("a", GroupConfig::default());
+        registry.register("b", GroupConfig::default());
+
+        registry.group("a").unwrap().try_set(0, 1).unwrap();
+        registry.group("b").unwrap().try_set(0, 2).unwrap();
+
+        assert_eq!(registry.group("a").unwrap().try_get(0).unwrap(), Some(1));
+        assert_eq!(registry.group("b").unwrap().try_get(0).unwrap(), Some(2));
+        assert!(registry.group("c").is_none());
+    }
+
+    #[test]
+    fn ttl_expires_entries() {
+        let clock = MockClock::default();
+        let mut registry = registry().with_clock(&clock);
+        registry.register(
+            "a",
+            GroupConfig {
+                ttl: Some(Duration::from_mins(1)),
+                ..GroupConfig::default()
+            },
+        );
+
+        let mut group = registry.group("a").unwrap();
+        group.try_set(0, 1).unwrap();
+        assert_eq!(group.try_get(0).unwrap(), Some(1));
+
+        clock.advance(Duration::from_mins(1));
+        assert_eq!(group.try_get(0).unwrap(), None);
+        assert!(!group.try_exists(0).unwrap());
+        assert_eq!(
+            group.stats(),
+            GroupStats {
+                hits: 1,
+                misses: 1,
+                sets: 1,
+                ..GroupStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn overwrite_expired_key_at_quota() {
+        let clock = MockClock::default();
+        let mut registry = CacheRegistry::with_weigher(MemoryStore::new(), |value: &usize| *value)
+            .with_clock(&clock);
+        registry.register(
+            "a",
+            GroupConfig {
+                ttl: Some(Duration::from_mins(1)),
+                max_bytes: Some(8),
+                ..GroupConfig::default()
+            },
+        );
+
+        let mut group = registry.group("a").unwrap();
+        group.try_set(0, 4).unwrap();
+        group.try_set(1, 4).unwrap();
+        clock.advance(Duration::from_mins(1));
+        // Only fits once both expired entries are purged, the old one of the key too
+        group.try_set(0, 6).unwrap();
+        assert_eq!(group.try_get(0).unwrap(), Some(6));
+        assert_eq!(
+            registry.usage("a").unwrap(),
+            QuotaUsage {
+                entries: 1,
+                bytes: 6
+            }
+        );
+    }
+
+    #[test]
+    fn budget_rejects_new_keys() {
+        let mut registry = registry();
+        registry.register(
+            "a",
+            GroupConfig {
+                max_entries: Some(2),
+                ..GroupConfig::default()
+            },
+        );
+
+        let mut group = registry.group("a").unwrap();
+        group.try_set(0, 0).unwrap();
+        group.try_set(1, 1).unwrap();
+        assert!(matches!(
+            group.try_set(2, 2),
+            Err(RegistryError::OverBudget)
+        ));
+        // Existing keys can still be overwritten
+        group.try_set(1, 2).unwrap();
+        assert_eq!(group.try_get(1).unwrap(), Some(2));
+        assert_eq!(group.stats().rejected, 1);
+    }
+
+    #[test]
+    fn budget_ignores_expired() {
+        let clock = MockClock::default();
+        let mut registry = registry().with_clock(&clock);
+        registry.register(
+            "a",
+            GroupConfig {
+                ttl: Some(Duration::from_mins(1)),
+                max_entries: Some(1),
+                ..GroupConfig::default()
+            },
+        );
+
+        let mut group = registry.group("a").unwrap();
+        group.try_set(0, 0).unwrap();
+        clock.advance(Duration::from_mins(1));
+        // Accepted as the expired entry doesn't count
+        assert!(group.try_set(1, 1).is_ok());
+        assert_eq!(group.try_get(0).unwrap(), None);
+        assert_eq!(group.try_get(1).unwrap(), Some(1));
+        assert_eq!(group.stats().rejected, 0);
+        assert_eq!(registry.usage("a").unwrap().entries, 1);
+    }
+
+    #[test]
+    fn evict_policy_keeps_newest() {
+        let mut registry = registry();
+        registry.register(
+            "a",
+            GroupConfig {
+                max_entries: Some(2),
+                policy: QuotaPolicy::Evict,
+                ..GroupConfig::default()
+            },
+        );
+
+        let mut group = registry.group("a").unwrap();
+        group.try_set(0, 0).unwrap();
+        group.try_set(1, 1).unwrap();
+        group.try_set(2, 2).unwrap();
+
+        assert_eq!(group.try_get(0).unwrap(), None);
+        assert_eq!(group.try_get(1).unwrap(), Some(1));
+        assert_eq!(group.try_get(2).unwrap(), Some(2));
+        assert_eq!(group.stats().evicted, 1);
+        assert_eq!(group.oldest_entry().map(|(key, _)| key), Some(1));
+        assert_eq!(group.newest_entry().map(|(key, _)| key), Some(2));
+
+        // The evicted entry left the backend too
+        assert!(!registry.store.exists(NamespacedKey {
+            namespace: String::from("a"),
+            key: 0,
+        }));
+    }
+
+    #[test]
+    fn global_quota_only_evicts_own_group() {
+        let mut registry = registry();
+        registry.set_global_quota(Some(2), None);
+        registry.register("a", GroupConfig::default());
+        registry.register(
+            "b",
+            GroupConfig {
+                policy: QuotaPolicy::Evict,
+                ..GroupConfig::default()
+            },
+        );
+
+        registry.group("a").unwrap().try_set(0, 0).unwrap();
+        let mut b = registry.group("b").unwrap();
+        b.try_set(0, 0).unwrap();
+        b.try_set(1, 1).unwrap();
+        assert_eq!(b.try_get(0).unwrap(), None);
+
+        let mut a = registry.group("a").unwrap();
+        assert!(matches!(a.try_set(1, 1), Err(RegistryError::OverBudget)));
+        assert_eq!(a.try_get(0).unwrap(), Some(0));
+        assert_eq!(
+            registry.global_usage(),
+            QuotaUsage {
+                entries: 2,
+                bytes: 2 * core::mem::size_of::<usize>(),
+            }
+        );
+    }
+
+    #[test]
+    fn byte_quota() {
+        let mut registry: CacheRegistry<usize, Vec<u8>, _> =
+            CacheRegistry::with_weigher(MemoryStore::new(), Vec::len);
+        registry.register(
+            "a",
+            GroupConfig {
+                max_bytes: Some(4),
+                ..GroupConfig::default()
+            },
+        );
+
+        let mut group = registry.group("a").unwrap();
+        group.try_set(0, std::vec![0; 3]).unwrap();
+        assert!(group.try_set(1, std::vec![0; 2]).is_err());
+        // Overwriting only accounts for the difference
+        group.try_set(0, std::vec![0; 4]).unwrap();
+        assert_eq!(registry.usage("a").unwrap().bytes, 4);
+    }
+}
@@ -0,0 +1,423 @@
+//! Recording of generated values to replay them later without generating them.
+//!
+//! Tests of code that caches the results of live services are flaky and slow when they hit those
+//! services. A [`TapeRecorder`] wraps around a [`TryGenCacheStore`] and writes down the inputs and
+//! outputs of every call to its generator in a [`Tape`], which can be saved with the "serde"
+//! feature. Later runs serve those recordings from a [`ReplayStore`], which never generates
+//! anything, so they're hermetic and match the captured data exactly.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     generative::{GenCacheStoreWrapper, TryGenCacheStore},
+//! #     replay::{ReplayError, ReplayStore, TapeRecorder},
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! // This would be a request to some live service
+//! let fetch = |user: &u32, ()| format!("user {user}");
+//! let mut recorder = TapeRecorder::new(GenCacheStoreWrapper::new(MemoryStore::new(), fetch));
+//! recorder.try_get_or_new(1, ()).unwrap();
+//!
+//! // The tape can be saved and loaded in the test run
+//! let mut replay = ReplayStore::new(MemoryStore::new(), recorder.into_tape());
+//! assert_eq!(replay.try_get_or_new(1, ()).unwrap(), "user 1");
+//! assert!(matches!(replay.try_get_or_new(2, ()), Err(ReplayError::NotRecorded)));
+//! ```
+
+use crate::{
+    __internal_prelude::*, error::CacheError, generative::TryGenCacheStore, size::SizedStore,
+};
+
+use core::hash::Hash;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    vec::Vec,
+};
+
+/// Single call to a generator, with what it was given and what it returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recording<K, A, V> {
+    pub key: K,
+    pub args: A,
+    pub value: V,
+}
+
+/// Recordings of the calls to a generator, in the order they were done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tape<K, A, V> {
+    pub recordings: Vec<Recording<K, A, V>>,
+}
+
+impl<K, A, V> Default for Tape<K, A, V> {
+    fn default() -> Self {
+        Self {
+            recordings: Vec::new(),
+        }
+    }
+}
+
+/// Error of a [`ReplayStore`].
+#[derive(Debug)]
+pub enum ReplayError<E> {
+    /// The inner store failed.
+    Store(E),
+    /// The value had to be generated, but its inputs aren't in the tape.
+    NotRecorded,
+}
+impl<E: std::error::Error + 'static> std::error::Error for ReplayError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::NotRecorded => None,
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for ReplayError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::NotRecorded => writeln!(f, "no recording for the given inputs"),
+        }
+    }
+}
+
+impl<E: CacheError> CacheError for ReplayError<E> {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_transient())
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        match self {
+            Self::Store(err) => err.is_not_found(),
+            Self::NotRecorded => true,
+        }
+    }
+}
+
+/// Wrapper around a [`TryGenCacheStore`] that records the calls to its generator in a [`Tape`],
+/// see the [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryGenCacheStore`] which this wraps around.
+pub struct TapeRecorder<S: TryGenCacheStore> {
+    pub store: S,
+    #[allow(clippy::type_complexity)]
+    tape: Mutex<Tape<<S as TryGenCacheStore>::Key, S::Args, <S as TryGenCacheStore>::Value>>,
+}
+
+impl<S: TryGenCacheStore> TapeRecorder<S>
+where
+    <S as TryGenCacheStore>::Key: Clone,
+    <S as TryGenCacheStore>::Value: Clone,
+    S::Args: Clone,
+{
+    /// Make a new [`TapeRecorder`] around the given store, with an empty tape.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            tape: Mutex::new(Tape::default()),
+        }
+    }
+
+    /// Takes the recordings done so far, leaving the tape empty.
+    pub fn take_tape(
+        &self,
+    ) -> Tape<<S as TryGenCacheStore>::Key, S::Args, <S as TryGenCacheStore>::Value> {
+        core::mem::take(&mut *self.tape.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns the tape with all the recordings done.
+    pub fn into_tape(
+        self,
+    ) -> Tape<<S as TryGenCacheStore>::Key, S::Args, <S as TryGenCacheStore>::Value> {
+        self.tape
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: TryGenCacheStore + SizedStore> SizedStore for TapeRecorder<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryGenCacheStore> TryCacheStore for TapeRecorder<S> {
+    type Key = <S as TryGenCacheStore>::Key;
+    type Value = <S as TryGenCacheStore>::Value;
+    type Error = <S as TryGenCacheStore>::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key)
+    }
+}
+
+/// Every generation goes through [`TapeRecorder::try_gen`], so it's recorded no matter which
+/// method caused it.
+impl<S: TryGenCacheStore> TryGenCacheStore for TapeRecorder<S>
+where
+    <S as TryGenCacheStore>::Key: Clone,
+    <S as TryGenCacheStore>::Value: Clone,
+    S::Args: Clone,
+{
+    type Key = <S as TryGenCacheStore>::Key;
+    type Value = <S as TryGenCacheStore>::Value;
+    type Error = <S as TryGenCacheStore>::Error;
+    type Args = S::Args;
+
+    fn try_gen(
+        &self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        let key = key.borrow();
+        let value = self.store.try_gen(key, args.clone())?;
+        self.tape
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recordings
+            .push(Recording {
+                key: key.clone(),
+                args,
+                value: value.clone(),
+            });
+        Ok(value)
+    }
+
+    fn try_get_or_gen(
+        &self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        let key = key.borrow();
+        match self.store.try_get(key)? {
+            Some(value) => Ok(value),
+            None => self.try_gen(key, args),
+        }
+    }
+
+    fn try_get_or_new(
+        &mut self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        let key = key.borrow();
+        if let Some(value) = self.store.try_get(key)? {
+            return Ok(value);
+        }
+        self.try_gen_new(key, args)
+    }
+
+    fn try_gen_new(
+        &mut self,
+        key: impl Borrow<<S as TryGenCacheStore>::Key>,
+        args: S::Args,
+    ) -> Result<<S as TryGenCacheStore>::Value, <S as TryCacheStore>::Error> {
+        let key = key.borrow();
+        let value = self.try_gen(key, args)?;
+        self.store.try_set(key, &value)?;
+        Ok(value)
+    }
+}
+
+/// Generative store that serves the values of a [`Tape`] instead of generating them, see the
+/// [module docs][self].
+///
+/// When the same inputs were recorded more than once, the last recording is served.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `A`: Type of additional arguments of the recorded generator.
+pub struct ReplayStore<S: TryCacheStore, A> {
+    pub store: S,
+    recordings: HashMap<(S::Key, A), S::Value>,
+}
+
+impl<S: TryCacheStore, A: Hash + Eq> ReplayStore<S, A>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    /// Make a new [`ReplayStore`] around the given store, serving the recordings of the tape.
+    pub fn new(store: S, tape: Tape<S::Key, A, S::Value>) -> Self {
+        let recordings = tape
+            .recordings
+            .into_iter()
+            .map(|recording| ((recording.key, recording.args), recording.value))
+            .collect();
+        Self { store, recordings }
+    }
+
+    /// Amount of distinct inputs that have a recording.
+    pub fn recorded(&self) -> usize {
+        self.recordings.len()
+    }
+}
+
+impl<S: TryCacheStore + SizedStore, A> SizedStore for ReplayStore<S, A> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore, A: Hash + Eq> TryCacheStore for ReplayStore<S, A>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = ReplayError<S::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key).map_err(ReplayError::Store)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.try_set(key, value).map_err(ReplayError::Store)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key).map_err(ReplayError::Store)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key).map_err(ReplayError::Store)
+    }
+}
+
+impl<S: TryCacheStore, A: Hash + Eq> TryGenCacheStore for ReplayStore<S, A>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = ReplayError<S::Error>;
+    type Args = A;
+
+    fn try_gen(
+        &self,
+        key: impl Borrow<S::Key>,
+        args: A,
+    ) -> Result<S::Value, ReplayError<S::Error>> {
+        self.recordings
+            .get(&(key.borrow().clone(), args))
+            .cloned()
+            .ok_or(ReplayError::NotRecorded)
+    }
+
+    fn try_get_or_gen(
+        &self,
+        key: impl Borrow<S::Key>,
+        args: A,
+    ) -> Result<S::Value, ReplayError<S::Error>> {
+        let key = key.borrow();
+        match self.try_get(key)? {
+            Some(value) => Ok(value),
+            None => self.try_gen(key, args),
+        }
+    }
+
+    fn try_get_or_new(
+        &mut self,
+        key: impl Borrow<S::Key>,
+        args: A,
+    ) -> Result<S::Value, ReplayError<S::Error>> {
+        let key = key.borrow();
+        let value = self.try_get_or_gen(key, args)?;
+        self.try_set(key, &value)?;
+        Ok(value)
+    }
+
+    fn try_gen_new(
+        &mut self,
+        key: impl Borrow<S::Key>,
+        args: A,
+    ) -> Result<S::Value, ReplayError<S::Error>> {
+        let key = key.borrow();
+        let value = self.try_gen(key, args)?;
+        self.try_set(key, &value)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generative::GenCacheStoreWrapper, stores::MemoryStore};
+
+    #[test]
+    fn replays_recorded_args() {
+        let mut recorder = TapeRecorder::new(GenCacheStoreWrapper::new(
+            MemoryStore::new(),
+            |&n: &u32, offset: u32| n + offset,
+        ));
+        recorder.try_gen_new(1, 10).unwrap();
+        recorder.try_gen_new(1, 20).unwrap();
+        // Served from the store, not generated
+        recorder.try_get_or_new(1, 30).unwrap();
+        let tape = recorder.take_tape();
+        assert_eq!(tape.recordings.len(), 2);
+        assert!(recorder.take_tape().recordings.is_empty());
+
+        let replay = ReplayStore::new(MemoryStore::new(), tape);
+        assert_eq!(replay.recorded(), 2);
+        assert_eq!(replay.try_gen(1, 10).unwrap(), 11);
+        assert_eq!(replay.try_gen(1, 20).unwrap(), 21);
+        assert!(matches!(
+            replay.try_gen(1, 30),
+            Err(ReplayError::NotRecorded)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_tape() {
+        let mut tape = Tape::default();
+        tape.recordings.push(Recording {
+            key: 1u8,
+            args: (),
+            value: 2u8,
+        });
+
+        let saved = serde_json::to_string(&tape).unwrap();
+        let loaded: Tape<u8, (), u8> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded, tape);
+    }
+}
@@ -0,0 +1,153 @@
+//! Views of a store confined to a prefix of its keys.
+//!
+//! A store keyed by [`NamespacedKey`]s can hand out [`ScopedStore`]s through [`Scope::scope`].
+//! Each one behaves as a normal [`TryCacheStore`] over plain keys, but it only reads and writes
+//! the keys under its own prefix of the parent store. Libraries can be given a scoped store
+//! without being able to see, or clash with, the keys of anyone else.
+//!
+//! Unlike groups of a [`CacheRegistry`][crate::registry::CacheRegistry], scopes keep no state of
+//! their own, they are just a borrow of the parent store along with the prefix.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     registry::NamespacedKey,
+//! #     scope::Scope,
+//! #     stores::MemoryStore,
+//! # };
+//! #
+//! let mut store: MemoryStore<NamespacedKey<&str>, u32> = MemoryStore::new();
+//!
+//! store.scope("tenant-a").try_set("visits", 1).unwrap();
+//! store.scope("tenant-b").try_set("visits", 7).unwrap();
+//!
+//! assert_eq!(store.scope("tenant-a").try_get("visits").unwrap(), Some(1));
+//! assert_eq!(store.scope("tenant-c").try_get("visits").unwrap(), None);
+//! ```
+
+use crate::{__internal_prelude::*, registry::NamespacedKey};
+
+use std::string::String;
+
+/// View of a [`TryCacheStore`] that only sees the keys under a prefix, made with
+/// [`Scope::scope`].
+///
+/// Generics:
+/// - `K`: Type of the key used within the scope.
+/// - `S`: [`TryCacheStore`] this is a view of, indexed by [`NamespacedKey`]s.
+pub struct ScopedStore<'a, K, S: TryCacheStore<Key = NamespacedKey<K>>> {
+    store: &'a mut S,
+    prefix: String,
+}
+
+impl<K: Clone, S: TryCacheStore<Key = NamespacedKey<K>>> ScopedStore<'_, K, S> {
+    /// Prefix of the keys this scope sees.
+    #[must_use]
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn key_of(&self, key: &K) -> NamespacedKey<K> {
+        NamespacedKey {
+            namespace: self.prefix.clone(),
+            key: key.clone(),
+        }
+    }
+}
+
+/// Trait to make [`ScopedStore`] views of a store, implemented by every [`TryCacheStore`] indexed
+/// by [`NamespacedKey`]s.
+pub trait Scope<K>: TryCacheStore<Key = NamespacedKey<K>> + Sized {
+    /// Returns a view of the store that only sees the keys under `prefix`.
+    fn scope(&mut self, prefix: impl Into<String>) -> ScopedStore<'_, K, Self> {
+        ScopedStore {
+            store: self,
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl<K, S: TryCacheStore<Key = NamespacedKey<K>>> Scope<K> for S {}
+
+impl<K: Clone, S: TryCacheStore<Key = NamespacedKey<K>>> TryCacheStore for ScopedStore<'_, K, S> {
+    type Key = K;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(self.key_of(key.borrow()))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(self.key_of(key.borrow()))
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_replace(key, value)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_set_if_absent(key, value)
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let key = self.key_of(key.borrow());
+        self.store.try_replace_only(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+
+    #[test]
+    fn scopes_share_the_backend() {
+        let mut store: MemoryStore<NamespacedKey<u8>, u8> = MemoryStore::new();
+        {
+            let mut scope = store.scope("a");
+            assert!(scope.try_set_if_absent(0, 1).unwrap());
+            assert_eq!(scope.try_replace(0, 2).unwrap(), Some(1));
+            assert_eq!(scope.prefix(), "a");
+        }
+        assert!(!store.scope("b").try_exists(0).unwrap());
+
+        let key = NamespacedKey {
+            namespace: "a".into(),
+            key: 0,
+        };
+        assert_eq!(store.try_get(key).unwrap(), Some(2));
+    }
+}
@@ -0,0 +1,323 @@
+//! Warming of stores from a seed, so new machines don't start with a cold cache.
+//!
+//! [`SeededStore`] fills its inner store from a [`Seed`] the first time it misses, or right away
+//! with [`seed_now`][SeededStore::seed_now]. A seed can be any closure returning entries, like
+//! one downloading and unpacking an archive of a warm cache, or a [`DirSeed`] copying the files
+//! of a directory.
+//!
+//! Seeded entries never overwrite the ones the store already has, so the seed can be left in
+//! place on machines whose store persists across runs, it only fills what's missing.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, seeded::SeededStore, stores::MemoryStore};
+//! #
+//! // This would download the seed from somewhere
+//! let seed = || Ok::<_, std::io::Error>([("home", "<h1>home</h1>"), ("about", "<h1>about</h1>")]);
+//! let mut store = SeededStore::new(MemoryStore::new(), seed);
+//!
+//! store.try_set("home", "<h1>newer home</h1>").unwrap();
+//! assert_eq!(store.seed_now().unwrap(), 1);
+//! assert_eq!(store.try_get("about").unwrap(), Some("<h1>about</h1>"));
+//! assert_eq!(store.try_get("home").unwrap(), Some("<h1>newer home</h1>"));
+//! ```
+
+use crate::{__internal_prelude::*, error::CacheError, size::SizedStore};
+
+use core::hash::Hash;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    string::String,
+    sync::{Mutex, PoisonError},
+    vec::Vec,
+};
+
+/// Source of the entries a [`SeededStore`] is warmed with.
+pub trait Seed<K, V> {
+    type Error;
+
+    /// Fetches the entries of the seed, it's only called once.
+    ///
+    /// # Errors
+    /// Fails when the seed can't be fetched.
+    fn entries(self) -> Result<Vec<(K, V)>, Self::Error>;
+}
+
+impl<K, V, E, I, F> Seed<K, V> for F
+where
+    I: IntoIterator<Item = (K, V)>,
+    F: FnOnce() -> Result<I, E>,
+{
+    type Error = E;
+
+    fn entries(self) -> Result<Vec<(K, V)>, E> {
+        Ok(self()?.into_iter().collect())
+    }
+}
+
+/// [`Seed`] with the files of a directory, keyed by their name. Subdirectories and files whose
+/// name isn't valid UTF-8 are skipped.
+#[derive(Debug, Clone)]
+pub struct DirSeed {
+    path: PathBuf,
+}
+
+impl DirSeed {
+    /// Make a new [`DirSeed`] with the files of the given directory.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Seed<String, Vec<u8>> for DirSeed {
+    type Error = io::Error;
+
+    fn entries(self) -> Result<Vec<(String, Vec<u8>)>, io::Error> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Ok(name) = entry.file_name().into_string() {
+                entries.push((name, fs::read(entry.path())?));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Error of a [`SeededStore`].
+#[derive(Debug)]
+pub enum SeededError<E, SE> {
+    /// The inner store failed.
+    Store(E),
+    /// Fetching the seed failed.
+    Seed(SE),
+}
+impl<E, SE> std::error::Error for SeededError<E, SE>
+where
+    E: std::error::Error + 'static,
+    SE: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::Seed(err) => Some(err),
+        }
+    }
+}
+impl<E: core::fmt::Display, SE: core::fmt::Display> core::fmt::Display for SeededError<E, SE> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::Seed(err) => writeln!(f, "seed error: {err}"),
+        }
+    }
+}
+
+impl<E: CacheError, SE> CacheError for SeededError<E, SE> {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_transient())
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_not_found())
+    }
+}
+
+/// How far along seeding is.
+enum SeedState<D, K, V> {
+    /// The seed wasn't fetched yet.
+    Unfetched(D),
+    /// The seed was fetched on a miss, its entries are served from here until they can be
+    /// written to the store.
+    Pending(HashMap<K, V>),
+    /// Seeded entries are in the store, or the seed failed.
+    Done,
+}
+
+/// Wrapper around a [`TryCacheStore`] that warms it up from a [`Seed`], see the
+/// [module docs][self].
+///
+/// A miss fetches the seed, but the store can't be written through a shared reference, so the
+/// seeded entries are served from memory until the next write moves them to the store. If
+/// fetching fails, the miss returns the error and the store goes on without seeding.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `D`: [`Seed`] the store is warmed with.
+pub struct SeededStore<S: TryCacheStore, D> {
+    pub store: S,
+    state: Mutex<SeedState<D, S::Key, S::Value>>,
+}
+
+impl<S: TryCacheStore, D: Seed<S::Key, S::Value>> SeededStore<S, D>
+where
+    S::Key: Hash + Eq,
+    S::Value: Clone,
+{
+    /// Make a new [`SeededStore`] around the given store, fetching the seed on its first miss.
+    pub fn new(store: S, seed: D) -> Self {
+        Self {
+            store,
+            state: Mutex::new(SeedState::Unfetched(seed)),
+        }
+    }
+
+    /// Fetches the seed if it wasn't yet and writes its entries to the store, skipping the keys
+    /// it already has. Returns how many were written.
+    ///
+    /// # Errors
+    /// Fails when fetching the seed or the inner store does.
+    pub fn seed_now(&mut self) -> Result<usize, SeededError<S::Error, D::Error>> {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let entries: Vec<_> = match core::mem::replace(state, SeedState::Done) {
+            SeedState::Unfetched(seed) => seed.entries().map_err(SeededError::Seed)?,
+            SeedState::Pending(entries) => entries.into_iter().collect(),
+            SeedState::Done => return Ok(0),
+        };
+
+        let mut written = 0;
+        for (key, value) in entries {
+            if self
+                .store
+                .try_set_if_absent(key, value)
+                .map_err(SeededError::Store)?
+            {
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Whether the seed was already fetched, or failed to.
+    pub fn is_seeded(&self) -> bool {
+        !matches!(
+            *self.state.lock().unwrap_or_else(PoisonError::into_inner),
+            SeedState::Unfetched(_)
+        )
+    }
+
+    /// Value of a missed key in the seed, fetching it if needed.
+    #[allow(clippy::type_complexity)]
+    fn seeded(&self, key: &S::Key) -> Result<Option<S::Value>, SeededError<S::Error, D::Error>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        // Left as done if fetching fails
+        *state = match core::mem::replace(&mut *state, SeedState::Done) {
+            SeedState::Unfetched(seed) => {
+                let entries = seed.entries().map_err(SeededError::Seed)?;
+                SeedState::Pending(entries.into_iter().collect())
+            }
+            state => state,
+        };
+        match &*state {
+            SeedState::Pending(entries) => Ok(entries.get(key).cloned()),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<S: TryCacheStore + SizedStore, D> SizedStore for SeededStore<S, D> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore, D: Seed<S::Key, S::Value>> TryCacheStore for SeededStore<S, D>
+where
+    S::Key: Hash + Eq,
+    S::Value: Clone,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = SeededError<S::Error, D::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        match self.store.try_get(key).map_err(SeededError::Store)? {
+            Some(value) => Ok(Some(value)),
+            None => self.seeded(key),
+        }
+    }
+
+    /// Writes the pending seeded entries first, if any, so they don't overwrite this one later.
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let pending = matches!(
+            *self.state.get_mut().unwrap_or_else(PoisonError::into_inner),
+            SeedState::Pending(_)
+        );
+        if pending {
+            self.seed_now()?;
+        }
+        self.store.try_set(key, value).map_err(SeededError::Store)
+    }
+
+    /// Writes the seeded entries first, fetching them if needed, so they don't bring this one back
+    /// later.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.seed_now()?;
+        self.store.try_remove(key).map_err(SeededError::Store)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        if self.store.try_exists(key).map_err(SeededError::Store)? {
+            return Ok(true);
+        }
+        Ok(self.seeded(key)?.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::vec;
+
+    #[test]
+    fn seeds_on_first_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"seeded a").unwrap();
+        fs::write(dir.path().join("b"), b"seeded b").unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+
+        let mut store = SeededStore::new(MemoryStore::new(), DirSeed::new(dir.path()));
+        store
+            .try_set(String::from("b"), b"newer b".to_vec())
+            .unwrap();
+        assert!(!store.is_seeded());
+
+        assert_eq!(
+            store.try_get(String::from("a")).unwrap(),
+            Some(b"seeded a".to_vec())
+        );
+        assert!(store.is_seeded());
+        assert!(!store.try_exists(String::from("nested")).unwrap());
+        // Served from the seed until written
+        assert_eq!(store.store.try_get(String::from("a")).unwrap(), None);
+
+        store.try_set(String::from("c"), vec![]).unwrap();
+        assert_eq!(
+            store.store.try_get(String::from("a")).unwrap(),
+            Some(b"seeded a".to_vec())
+        );
+        assert_eq!(
+            store.try_get(String::from("b")).unwrap(),
+            Some(b"newer b".to_vec())
+        );
+    }
+}
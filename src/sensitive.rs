@@ -0,0 +1,112 @@
+//! Caching of tokens, credentials and other secrets.
+//!
+//! [`SensitiveStore`] keeps values as [`Zeroizing`] buffers, which are overwritten with zeros when
+//! dropped instead of lingering in freed memory. So they are wiped when the inner store drops them,
+//! be it because they were overwritten, [removed][SensitiveStore::remove], evicted or because the
+//! store itself was dropped. Values handed out by the store are [`Zeroizing`] too.
+//!
+//! It only covers the copies the inner store keeps in memory, stores that persist their values
+//! elsewhere, like file stores, are out of its reach.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, sensitive::{SensitiveStore, Zeroizing}, stores::MemoryStore};
+//! #
+//! let mut store = SensitiveStore::new(MemoryStore::new());
+//!
+//! store.try_set("api", Zeroizing::new(String::from("hunter2"))).unwrap();
+//! assert_eq!(store.try_get("api").unwrap().as_deref().map(String::as_str), Some("hunter2"));
+//!
+//! // The token is wiped right away
+//! assert!(store.remove(&"api").unwrap());
+//! assert_eq!(store.try_get("api").unwrap(), None);
+//! ```
+
+use crate::{__internal_prelude::*, size::SizedStore};
+
+pub use zeroize::{Zeroize, Zeroizing};
+
+/// Wrapper around a [`TryCacheStore`] of [`Zeroizing`] values, see the [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around, with [`Zeroizing`] values.
+pub struct SensitiveStore<S> {
+    pub store: S,
+}
+
+impl<S, V> SensitiveStore<S>
+where
+    S: TryCacheStore<Value = Zeroizing<V>>,
+    V: Zeroize,
+{
+    /// Make a new [`SensitiveStore`] around the given store.
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// Wipes the value of `key` and removes the entry, returns whether there was one.
+    ///
+    /// # Errors
+    /// Fails when the inner store does.
+    pub fn remove(&mut self, key: &S::Key) -> Result<bool, S::Error> {
+        Ok(self.store.try_remove(key)?.is_some())
+    }
+}
+
+impl<S: SizedStore> SizedStore for SensitiveStore<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S, V> TryCacheStore for SensitiveStore<S>
+where
+    S: TryCacheStore<Value = Zeroizing<V>>,
+    V: Zeroize,
+{
+    type Key = S::Key;
+    type Value = Zeroizing<V>;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::vec;
+
+    #[test]
+    fn removed_values_are_wiped() {
+        let mut store = SensitiveStore::new(MemoryStore::new());
+        store.try_set(0, Zeroizing::new(vec![7u8; 8])).unwrap();
+        assert!(store.remove(&0).unwrap());
+        assert!(!store.remove(&0).unwrap());
+        assert!(!store.store.try_exists(0).unwrap());
+
+        store.try_set(0, Zeroizing::new(vec![1])).unwrap();
+        assert_eq!(store.try_get(0).unwrap().as_deref(), Some(&vec![1]));
+    }
+}
@@ -0,0 +1,38 @@
+//! Orderly shutdown of stores that hold state not persisted yet.
+//!
+//! Stores that buffer writes, snapshot at intervals or run background tasks implement
+//! [`Shutdown`], so an application can make sure nothing is lost before exiting, and see if
+//! something goes wrong doing so.
+//!
+//! Stores that would lose data otherwise also do the same on drop, but errors can't be reported
+//! from there so they are ignored. Dropping isn't guaranteed either, like on [`std::process::exit`]
+//! or with leaked [`Arc`][std::sync::Arc]s, so call [`shutdown`][Shutdown::shutdown] explicitly.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     shutdown::Shutdown,
+//! #     stores::MemoryStore,
+//! #     write_back::WriteBackStore,
+//! # };
+//! #
+//! let mut store = WriteBackStore::new(MemoryStore::<&str, u32>::new());
+//! store.try_set("key", 1).unwrap();
+//! assert_eq!(store.pending(), 1);
+//!
+//! store.shutdown().unwrap();
+//! assert_eq!(store.pending(), 0);
+//! ```
+
+/// Trait for a store that has to do some work before being dropped not to lose data.
+pub trait Shutdown {
+    type Error;
+
+    /// Persists everything pending and stops background work. The store can still be used after,
+    /// but it shouldn't buffer anything anymore.
+    ///
+    /// # Errors
+    /// Fails when persisting something does, it can be retried then.
+    fn shutdown(&mut self) -> Result<(), Self::Error>;
+}
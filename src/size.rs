@@ -0,0 +1,159 @@
+//! Accounting of how many bytes stores take.
+//!
+//! Stores that know their footprint implement [`SizedStore`], so it can be monitored without
+//! knowing what the store is. Memory stores weigh their entries with [`MemSize`], which counts what
+//! a value takes inline plus what it owns on the heap, and wrappers report the size of the store
+//! they wrap.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{CacheStore, size::SizedStore, stores::MemoryStore};
+//! #
+//! let mut store: MemoryStore<u32, Vec<u8>> = MemoryStore::new();
+//! assert_eq!(store.bytes_used(), 0);
+//!
+//! store.set(0, vec![0; 100]);
+//! assert!(store.bytes_used() >= 100);
+//! ```
+
+use core::mem::size_of;
+
+/// Trait for a store that can tell how many bytes its entries take.
+pub trait SizedStore {
+    /// Bytes taken by the entries of the store, as the store accounts for them. It doesn't
+    /// include the bookkeeping of the store itself.
+    fn bytes_used(&self) -> usize;
+}
+
+/// Size of a value in memory, inline plus heap allocations it owns.
+pub trait MemSize {
+    /// Bytes taken by the value.
+    fn mem_size(&self) -> usize;
+}
+
+macro_rules! impl_mem_size_inline {
+    ($($ty:ty),* $(,)?) => {
+        $(impl MemSize for $ty {
+            fn mem_size(&self) -> usize {
+                size_of::<Self>()
+            }
+        })*
+    };
+}
+
+impl_mem_size_inline!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+/// Only the reference itself, the referenced value isn't owned.
+impl<T: ?Sized> MemSize for &T {
+    fn mem_size(&self) -> usize {
+        size_of::<Self>()
+    }
+}
+
+impl<T: MemSize> MemSize for Option<T> {
+    fn mem_size(&self) -> usize {
+        size_of::<Self>()
+            + self
+                .as_ref()
+                .map_or(0, |value| value.mem_size() - size_of::<T>())
+    }
+}
+
+impl<A: MemSize, B: MemSize> MemSize for (A, B) {
+    fn mem_size(&self) -> usize {
+        size_of::<Self>()
+            + (self.0.mem_size() - size_of::<A>())
+            + (self.1.mem_size() - size_of::<B>())
+    }
+}
+
+#[cfg(feature = "std")]
+impl MemSize for std::string::String {
+    fn mem_size(&self) -> usize {
+        size_of::<Self>() + self.capacity()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: MemSize> MemSize for std::vec::Vec<T> {
+    fn mem_size(&self) -> usize {
+        size_of::<Self>()
+            + (self.capacity() - self.len()) * size_of::<T>()
+            + self.iter().map(MemSize::mem_size).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: MemSize> MemSize for std::boxed::Box<T> {
+    fn mem_size(&self) -> usize {
+        size_of::<Self>() + (**self).mem_size()
+    }
+}
+
+/// Items kept inline are already counted by the size of the vector itself.
+#[cfg(feature = "smallvec")]
+impl<T: MemSize, const N: usize> MemSize for smallvec::SmallVec<[T; N]> {
+    fn mem_size(&self) -> usize {
+        let items = self.iter().map(MemSize::mem_size).sum::<usize>();
+        if self.spilled() {
+            size_of::<Self>() + (self.capacity() - self.len()) * size_of::<T>() + items
+        } else {
+            size_of::<Self>() + items - self.len() * size_of::<T>()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{stores::MemoryStore, write_once::WriteOnceStore, TryCacheStore};
+    use std::{string::String, vec::Vec};
+
+    #[test]
+    fn heap_is_counted() {
+        assert_eq!(0u32.mem_size(), 4);
+        assert_eq!(
+            String::with_capacity(10).mem_size(),
+            size_of::<String>() + 10
+        );
+        let nested = Vec::from([String::from("ab")]);
+        assert!(nested.mem_size() >= size_of::<Vec<String>>() + size_of::<String>() + 2);
+    }
+
+    #[test]
+    #[cfg(feature = "smallvec")]
+    fn inline_bytes_take_no_heap() {
+        use crate::stores::InlineBytes;
+
+        let inline = InlineBytes::<16>::from_slice(&[0; 16]);
+        assert_eq!(inline.mem_size(), size_of::<InlineBytes<16>>());
+        let spilled = InlineBytes::<16>::from_slice(&[0; 17]);
+        assert!(spilled.mem_size() >= size_of::<InlineBytes<16>>() + 17);
+    }
+
+    #[test]
+    fn wrappers_report_inner_store() {
+        let mut store = WriteOnceStore::new(MemoryStore::<u8, String>::new());
+        store.try_set(0u8, String::from("zero")).unwrap();
+        assert_eq!(store.bytes_used(), store.store.bytes_used());
+        assert_eq!(store.bytes_used(), 1 + String::from("zero").mem_size());
+    }
+}
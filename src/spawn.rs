@@ -0,0 +1,213 @@
+//! Pluggable execution of background work.
+//!
+//! Wrappers that do work in the background, like flushing a
+//! [`WriteBackStore`][crate::write_back::WriteBackStore], take a [`Spawner`] instead of spawning
+//! threads themselves, so the work can run wherever the application prefers:
+//! - [`ThreadSpawner`]: A std thread for each task.
+//! - [`TokioSpawner`]: A task on a tokio runtime, with the "tokio" feature.
+//! - [`ManualSpawner`]: Nowhere until it's [ticked][ManualSpawner::tick], for tests.
+//!
+//! # Examples
+//! ```rust
+//! # use core::{ops::ControlFlow, time::Duration};
+//! # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+//! # use ezcache::spawn::{ManualSpawner, Spawner};
+//! #
+//! let spawner = ManualSpawner::new();
+//! let runs = Arc::new(AtomicUsize::new(0));
+//!
+//! let task_runs = Arc::clone(&runs);
+//! spawner.spawn_every(
+//!     Duration::from_secs(60),
+//!     Box::new(move || {
+//!         // Stops after running twice
+//!         if task_runs.fetch_add(1, Ordering::Relaxed) == 1 {
+//!             ControlFlow::Break(())
+//!         } else {
+//!             ControlFlow::Continue(())
+//!         }
+//!     }),
+//! );
+//!
+//! assert_eq!(spawner.tick(), 1);
+//! assert_eq!(spawner.tick(), 0);
+//! assert_eq!(spawner.tick(), 0);
+//! assert_eq!(runs.load(Ordering::Relaxed), 2);
+//! ```
+
+use core::{ops::ControlFlow, time::Duration};
+use std::{
+    boxed::Box,
+    sync::{Mutex, PoisonError},
+    thread,
+    vec::Vec,
+};
+
+/// Background task, run over and over until it returns [`ControlFlow::Break`].
+pub type Task = Box<dyn FnMut() -> ControlFlow<()> + Send>;
+
+/// Trait for something that can run tasks in the background.
+pub trait Spawner {
+    /// Runs `task` in the background once every `interval` until it breaks. The first run happens
+    /// after waiting for `interval` once.
+    fn spawn_every(&self, interval: Duration, task: Task);
+}
+
+impl<T: Spawner + ?Sized> Spawner for &T {
+    fn spawn_every(&self, interval: Duration, task: Task) {
+        (**self).spawn_every(interval, task);
+    }
+}
+
+/// [`Spawner`] that runs each task in its own std thread, sleeping between runs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadSpawner;
+
+impl Spawner for ThreadSpawner {
+    fn spawn_every(&self, interval: Duration, mut task: Task) {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if task().is_break() {
+                return;
+            }
+        });
+    }
+}
+
+/// [`Spawner`] that runs tasks on a tokio runtime.
+///
+/// Tasks are expected to block, doing io with the stores, so each run happens in the blocking
+/// thread pool of the runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct TokioSpawner {
+    handle: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioSpawner {
+    /// Makes a spawner for the runtime of the given handle.
+    #[must_use]
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self { handle }
+    }
+
+    /// Makes a spawner for the current runtime.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime.
+    #[must_use]
+    pub fn current() -> Self {
+        Self::new(tokio::runtime::Handle::current())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawner for TokioSpawner {
+    fn spawn_every(&self, interval: Duration, mut task: Task) {
+        self.handle.spawn(async move {
+            let start = tokio::time::Instant::now() + interval;
+            let mut ticks = tokio::time::interval_at(start, interval);
+            loop {
+                ticks.tick().await;
+                let run = tokio::task::spawn_blocking(move || {
+                    let flow = task();
+                    (task, flow)
+                });
+                match run.await {
+                    Ok((next, ControlFlow::Continue(()))) => task = next,
+                    // Broke or panicked
+                    _ => return,
+                }
+            }
+        });
+    }
+}
+
+/// [`Spawner`] that only runs tasks when told to with [`tick`][ManualSpawner::tick], ignoring
+/// their intervals. Useful to test background work deterministically.
+#[derive(Default)]
+pub struct ManualSpawner {
+    tasks: Mutex<Vec<Task>>,
+}
+
+impl ManualSpawner {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs every task once, returns how many are left running.
+    pub fn tick(&self) -> usize {
+        let mut tasks = self.tasks.lock().unwrap_or_else(PoisonError::into_inner);
+        tasks.retain_mut(|task| task().is_continue());
+        tasks.len()
+    }
+}
+
+impl Spawner for ManualSpawner {
+    fn spawn_every(&self, _interval: Duration, task: Task) {
+        self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn thread_spawner_runs_until_break() {
+        let (sender, receiver) = mpsc::channel();
+        let mut runs = 0;
+        ThreadSpawner.spawn_every(
+            Duration::from_millis(1),
+            Box::new(move || {
+                runs += 1;
+                sender.send(runs).unwrap();
+                if runs == 3 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            }),
+        );
+        // The sender is dropped once the task stops
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_spawner_runs_until_break() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let mut runs = 0;
+        TokioSpawner::new(runtime.handle().clone()).spawn_every(
+            Duration::from_millis(1),
+            Box::new(move || {
+                runs += 1;
+                sender.send(runs).unwrap();
+                if runs == 3 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            }),
+        );
+        let seen = runtime.block_on(async {
+            let mut seen = Vec::new();
+            while seen.len() < 3 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                seen.extend(receiver.try_iter());
+            }
+            seen
+        });
+        assert_eq!(seen, [1, 2, 3]);
+    }
+}
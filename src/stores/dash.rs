@@ -0,0 +1,317 @@
+//! Concurrent memory store over [`DashMap`].
+//!
+//! [`DashMemoryStore`] is a smart thread safe store like
+//! [`ThreadSafeMemoryStore`][super::ThreadSafeMemoryStore], but its map of entries is split into
+//! shards each behind its own lock instead of behind a single one, and it has no unsafe code.
+//! Handles lock just their own key, like in every other store, and the shards are only locked for
+//! as long as it takes to find it, so keys of different shards are found concurrently, which makes
+//! it a good fit for workloads with many threads.
+//!
+//! Entries leave the map once the exclusive handle that removed their value is dropped. Shared
+//! handles of keys that aren't in the store don't lock anything, so they don't add entries just
+//! for being looked up, but the key can be set by others while they're held.
+//!
+//! # Examples
+//! ```rust
+//! # use std::{sync::Arc, thread};
+//! # use ezcache::{prelude::*, stores::dash::DashMemoryStore};
+//! #
+//! let store: Arc<DashMemoryStore<u32, String>> = Arc::new(DashMemoryStore::new());
+//!
+//! let threads: Vec<_> = (0..4)
+//!     .map(|i| {
+//!         let store = Arc::clone(&store);
+//!         thread::spawn(move || store.ts_one_try_set(&i, &i.to_string()).unwrap())
+//!     })
+//!     .collect();
+//! threads.into_iter().for_each(|thread| thread.join().unwrap());
+//!
+//! assert_eq!(store.ts_one_try_get(&3).unwrap(), Some(String::from("3")));
+//! ```
+
+use crate::{__internal_prelude::*, thread_safe::dumb_wrappers::EmptyDumbError};
+
+use core::{
+    hash::Hash,
+    ops::{Deref, DerefMut},
+};
+use std::sync::Arc;
+
+use dashmap::{try_result::TryResult, DashMap};
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock, RwLock};
+
+/// Lock of the value of a key, shared by the handles of the key.
+type Slot<V> = Arc<RwLock<Option<V>>>;
+
+/// Concurrent memory store with sharded locking.
+pub struct DashMemoryStore<K, V> {
+    cache: DashMap<K, Slot<V>>,
+}
+
+impl<K: Hash + Eq, V> Default for DashMemoryStore<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> DashMemoryStore<K, V> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            cache: DashMap::new(),
+        }
+    }
+
+    /// Makes a new empty store with room for at least `capacity` entries.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            cache: DashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Whether `slot` is still the one of `key`, it isn't once its entry left the map while
+    /// waiting on its lock.
+    fn is_current(&self, key: &K, slot: &Slot<V>) -> bool {
+        self.cache
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(&current, slot))
+    }
+}
+
+/// Exclusive lock over a key of a [`DashMemoryStore`]. The entry of the key leaves the store when
+/// it's dropped without a value.
+pub struct DashXLock<'lock, K: Hash + Eq, V> {
+    cache: &'lock DashMap<K, Slot<V>>,
+    key: &'lock K,
+    guard: ArcRwLockWriteGuard<RawRwLock, Option<V>>,
+}
+
+impl<K: Hash + Eq, V> Deref for DashXLock<'_, K, V> {
+    type Target = Option<V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<K: Hash + Eq, V> DerefMut for DashXLock<'_, K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for DashXLock<'_, K, V> {
+    fn drop(&mut self) {
+        if self.guard.is_none() {
+            // Still locked, so nobody can set it in between
+            let slot = ArcRwLockWriteGuard::rwlock(&self.guard);
+            self.cache
+                .remove_if(self.key, |_, current| Arc::ptr_eq(current, slot));
+        }
+    }
+}
+
+/// Shared lock over a key of a [`DashMemoryStore`], either its own read lock or borrowed from an
+/// exclusive one, or nothing if the key wasn't in the store.
+pub enum DashGuard<'lock, 'guard, K: Hash + Eq, V> {
+    Read(ArcRwLockReadGuard<RawRwLock, Option<V>>),
+    Write(&'guard DashXLock<'lock, K, V>),
+    /// The key wasn't in the store, so there's nothing to lock.
+    Absent,
+}
+
+impl<'lock, 'guard, K: Hash + Eq, V> From<&'guard DashXLock<'lock, K, V>>
+    for DashGuard<'lock, 'guard, K, V>
+{
+    fn from(value: &'guard DashXLock<'lock, K, V>) -> Self {
+        Self::Write(value)
+    }
+}
+
+impl<K: Hash + Eq, V> Deref for DashGuard<'_, '_, K, V> {
+    type Target = Option<V>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Read(guard) => guard,
+            Self::Write(guard) => guard,
+            Self::Absent => &None,
+        }
+    }
+}
+
+impl<'lock, K: Hash + Eq + Clone, V: Clone> ThreadSafeTryCacheStore<'lock> for DashMemoryStore<K, V>
+where
+    Self: 'lock,
+{
+    type Key = K;
+    type Value = V;
+    type Error = EmptyDumbError;
+    type SLock<'guard>
+        = DashGuard<'lock, 'guard, K, V>
+    where
+        'lock: 'guard;
+    type XLock = DashXLock<'lock, K, V>;
+
+    fn ts_try_get(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok((**handle).clone())
+    }
+
+    fn ts_try_set(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<(), Self::Error> {
+        **handle = Some(value.clone());
+        Ok(())
+    }
+
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(handle.take())
+    }
+
+    fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
+        Ok(handle.is_some())
+    }
+
+    fn ts_try_replace(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(handle.replace(value.clone()))
+    }
+
+    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        loop {
+            let slot = Arc::clone(&self.cache.entry(key.clone()).or_default());
+            let guard = slot.write_arc();
+            if self.is_current(key, &slot) {
+                return Ok(DashXLock {
+                    cache: &self.cache,
+                    key,
+                    guard,
+                });
+            }
+        }
+    }
+
+    fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
+        loop {
+            let Some(slot) = self.cache.get(key).map(|slot| Arc::clone(&slot)) else {
+                return Ok(DashGuard::Absent);
+            };
+            let guard = slot.read_arc();
+            if self.is_current(key, &slot) {
+                return Ok(DashGuard::Read(guard));
+            }
+        }
+    }
+
+    fn ts_try_xlock_nblock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        loop {
+            let entry = self
+                .cache
+                .try_entry(key.clone())
+                .ok_or(EmptyDumbError::WouldBlock)?;
+            let slot = Arc::clone(&entry.or_default());
+            let guard = slot.try_write_arc().ok_or(EmptyDumbError::WouldBlock)?;
+            if self.is_current(key, &slot) {
+                return Ok(DashXLock {
+                    cache: &self.cache,
+                    key,
+                    guard,
+                });
+            }
+        }
+    }
+
+    fn ts_try_slock_nblock(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Self::SLock<'lock>, Self::Error> {
+        loop {
+            let slot = match self.cache.try_get(key) {
+                TryResult::Present(slot) => Arc::clone(&slot),
+                TryResult::Locked => return Err(EmptyDumbError::WouldBlock),
+                TryResult::Absent => return Ok(DashGuard::Absent),
+            };
+            let guard = slot.try_read_arc().ok_or(EmptyDumbError::WouldBlock)?;
+            if self.is_current(key, &slot) {
+                return Ok(DashGuard::Read(guard));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn locked_key_would_block() {
+        let store = DashMemoryStore::<u8, u8>::new();
+        let mut handle = store.ts_try_xlock(&0).unwrap();
+        store.ts_try_set(&mut handle, &1).unwrap();
+        assert!(matches!(
+            store.ts_try_slock_nblock(&0),
+            Err(EmptyDumbError::WouldBlock)
+        ));
+        drop(handle);
+
+        assert_eq!(store.ts_one_try_get(&0).unwrap(), Some(1));
+        assert!(!store.ts_one_try_exists(&1).unwrap());
+    }
+
+    #[test]
+    fn reads_dont_insert() {
+        let store = DashMemoryStore::<u8, u8>::new();
+        assert_eq!(store.ts_one_try_get(&0).unwrap(), None);
+        assert!(!store
+            .ts_try_exists(&store.ts_try_slock_nblock(&1).unwrap())
+            .unwrap());
+        assert!(store.cache.is_empty());
+    }
+
+    #[test]
+    fn handles_of_other_keys_dont_block() {
+        let store = DashMemoryStore::<u32, u32>::new();
+        let keys: Vec<_> = (0..256).collect();
+        let shared = store.ts_try_slock(&keys[0]).unwrap();
+        // Plenty of keys share a shard with the first one
+        let mut handles: Vec<_> = keys[1..]
+            .iter()
+            .map(|key| store.ts_try_xlock_nblock(key).unwrap())
+            .collect();
+        for handle in &mut handles {
+            store.ts_try_set(handle, &1).unwrap();
+        }
+        assert!(!store.ts_try_exists(&shared).unwrap());
+        assert!(store.ts_try_slock_nblock(&keys[1]).is_err());
+        drop(handles);
+        assert_eq!(store.ts_one_try_get(&keys[1]).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn removed_entries_leave() {
+        let store = DashMemoryStore::<u8, u8>::new();
+        store.ts_one_try_set(&0, &0).unwrap();
+        let mut handle = store.ts_try_xlock(&0).unwrap();
+        assert_eq!(store.ts_try_remove(&mut handle).unwrap(), Some(0));
+        // Still there for the handle to set it again
+        assert_eq!(store.cache.len(), 1);
+        drop(handle);
+        assert!(store.cache.is_empty());
+
+        drop(store.ts_try_xlock(&1).unwrap());
+        assert!(store.cache.is_empty());
+    }
+}
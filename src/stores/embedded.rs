@@ -0,0 +1,405 @@
+//! Read-only stores of entries compiled into the binary, snapshotted from a file store directory.
+//!
+//! A build script [embeds][embed] the directory of a [`ThreadSafeFileStore`], or of a
+//! [`ThreadSafeFileStoreSerializable`], into a snapshot file in `OUT_DIR`, with its packed entries
+//! too. The crate then includes it with [`include_bytes!`] and serves it from an
+//! [`EmbeddedStore`] or an [`EmbeddedStoreSerializable`], which look keys up just like the store
+//! the directory belongs to.
+//!
+//! Embedded entries can't change, so these stores drop whatever is set or removed in them. That
+//! makes them fit as the second tier of a [`TieredStore`][crate::tiered::TieredStore], under the
+//! file store the directory was snapshotted from, wrapped in a [`TryThreadUnsafeWrapper`]: writes
+//! land in the first tier, and keys it doesn't have yet, like on the first run, are served from
+//! the binary and promoted to it instead of being a miss.
+//!
+//! # Examples
+//! In `build.rs`:
+//! ```rust,no_run
+//! ezcache::stores::embedded::embed("cache", "cache.ezsnap").unwrap();
+//! ```
+//!
+//! In the crate:
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     stores::{
+//! #         embedded::{write_snapshot, EmbeddedStore},
+//! #         file_stores::ThreadSafeFileStore,
+//! #     },
+//! #     thread_safe::{ThreadSafeTryCacheStore, TryThreadUnsafeWrapper},
+//! #     tiered::TieredStore,
+//! #     TryCacheStore,
+//! # };
+//! #
+//! # let built = tempfile::tempdir().unwrap();
+//! # ThreadSafeFileStore::<String, Vec<u8>>::new_on(built.path())
+//! #     .unwrap()
+//! #     .ts_one_try_set(&String::from("known"), &b"value".to_vec())
+//! #     .unwrap();
+//! # let mut snapshot = Vec::new();
+//! # write_snapshot(built.path(), &mut snapshot).unwrap();
+//! # #[allow(non_snake_case)]
+//! # let SNAPSHOT: &'static [u8] = snapshot.leak();
+//! # let empty = tempfile::tempdir().unwrap();
+//! # let cache = empty.path();
+//! // static SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/cache.ezsnap"));
+//!
+//! let mut store: TieredStore<_, EmbeddedStore<String, Vec<u8>>> = TieredStore::new(
+//!     TryThreadUnsafeWrapper::new(ThreadSafeFileStore::new_on(cache).unwrap()),
+//!     EmbeddedStore::new(SNAPSHOT).unwrap(),
+//!     Duration::from_mins(1),
+//! );
+//!
+//! // The cache directory is still empty, but the key was known at build time
+//! assert_eq!(
+//!     store.try_get(String::from("known")).unwrap(),
+//!     Some(b"value".to_vec())
+//! );
+//! ```
+//!
+//! [`ThreadSafeFileStore`]: super::file_stores::ThreadSafeFileStore
+//! [`TryThreadUnsafeWrapper`]: crate::thread_safe::TryThreadUnsafeWrapper
+//! [`ThreadSafeFileStoreSerializable`]: super::file_stores::ThreadSafeFileStoreSerializable
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    file_stores::{decode_entry, CustomHash, ThreadSafeFileStoreError},
+    segments,
+};
+use crate::{__internal_prelude::*, size::SizedStore};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    string::String,
+};
+
+/// Start of every snapshot, along with the version of its format.
+const MAGIC: &[u8; 8] = b"ezsnap\0\x01";
+
+/// Writes a snapshot of the entries of the file store directory `dir`, packed ones included,
+/// returns how many it has.
+///
+/// Snapshots start with the bytes `ezsnap\0\x01`, the last one being the version of the format,
+/// followed by a record per entry, in the order of their names so the same directory always gives
+/// the same snapshot. Records are made of:
+/// - The length of the name of the entry, the hash of its key, as a byte, and the name itself.
+/// - The length of the value as a little endian `u64`, and the value itself.
+///
+/// # Errors
+/// Fails when reading the directory or writing the snapshot does.
+pub fn write_snapshot(
+    dir: impl AsRef<Path>,
+    mut writer: impl Write,
+) -> Result<usize, ThreadSafeFileStoreError> {
+    let dir = dir.as_ref();
+    let mut entries = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(file_name) = entry.file_name().into_string().ok() else {
+            continue;
+        };
+        if !entry.metadata()?.is_file() {
+            continue;
+        }
+        let name = file_name
+            .split_once('.')
+            .map_or(&*file_name, |(name, _)| name);
+        entries.insert(String::from(name), std::fs::read(entry.path())?);
+    }
+    // Packed entries are read before their own files by the stores
+    entries.extend(segments::read_all(dir)?);
+
+    writer.write_all(MAGIC)?;
+    let mut written = 0;
+    for (name, value) in &entries {
+        // Not the file of an entry
+        let Ok(name_len) = u8::try_from(name.len()) else {
+            continue;
+        };
+        writer.write_all(&[name_len])?;
+        writer.write_all(name.as_bytes())?;
+        writer.write_all(&(value.len() as u64).to_le_bytes())?;
+        writer.write_all(value)?;
+        written += 1;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Snapshots the file store directory `dir` into `file_name` within `OUT_DIR`, for build scripts.
+/// Tells cargo to run the build script again when the directory changes, and returns where the
+/// snapshot was written.
+///
+/// # Errors
+/// Fails when not run from a build script, or when reading the directory or writing the snapshot
+/// does.
+pub fn embed(
+    dir: impl AsRef<Path>,
+    file_name: impl AsRef<Path>,
+) -> Result<PathBuf, ThreadSafeFileStoreError> {
+    let out_dir = std::env::var_os("OUT_DIR").ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "OUT_DIR isn't set, embedding only works from build scripts",
+        )
+    })?;
+    let path = Path::new(&out_dir).join(file_name);
+    write_snapshot(&dir, BufWriter::new(File::create(&path)?))?;
+    std::println!("cargo:rerun-if-changed={}", dir.as_ref().display());
+    Ok(path)
+}
+
+/// A snapshot that isn't one, or was written by another version of the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidSnapshot;
+impl std::error::Error for InvalidSnapshot {}
+impl core::fmt::Display for InvalidSnapshot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "invalid snapshot")
+    }
+}
+
+/// Values of a snapshot by the names of their entries.
+fn parse(snapshot: &'static [u8]) -> Result<HashMap<&'static str, &'static [u8]>, InvalidSnapshot> {
+    let mut rest = snapshot.strip_prefix(MAGIC).ok_or(InvalidSnapshot)?;
+    let mut entries = HashMap::new();
+    while let Some((&name_len, tail)) = rest.split_first() {
+        let (name, tail) = tail
+            .split_at_checked(name_len.into())
+            .ok_or(InvalidSnapshot)?;
+        let (len, tail) = tail.split_at_checked(8).ok_or(InvalidSnapshot)?;
+        let len = u64::from_le_bytes(len.try_into().map_err(|_| InvalidSnapshot)?);
+        let len = usize::try_from(len).map_err(|_| InvalidSnapshot)?;
+        let (value, tail) = tail.split_at_checked(len).ok_or(InvalidSnapshot)?;
+        entries.insert(
+            core::str::from_utf8(name).map_err(|_| InvalidSnapshot)?,
+            value,
+        );
+        rest = tail;
+    }
+    Ok(entries)
+}
+
+/// Read-only store of the entries of a [`ThreadSafeFileStore`] directory, embedded into the binary,
+/// see the [module docs][self].
+///
+/// Values are made from the embedded bytes, so `&'static [u8]` values are never copied.
+///
+/// Generics:
+/// - `K`: Type of the key used for cache indexing, hashed into the names of the entries.
+/// - `V`: Type of the value stored in the cache store.
+///
+/// [`ThreadSafeFileStore`]: super::file_stores::ThreadSafeFileStore
+pub struct EmbeddedStore<K, V> {
+    entries: HashMap<&'static str, &'static [u8]>,
+    size: usize,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> EmbeddedStore<K, V> {
+    /// Make a new [`EmbeddedStore`] serving the given snapshot, as [embedded][embed].
+    ///
+    /// # Errors
+    /// When `snapshot` isn't a valid snapshot.
+    pub fn new(snapshot: &'static [u8]) -> Result<Self, InvalidSnapshot> {
+        Ok(Self {
+            entries: parse(snapshot)?,
+            size: snapshot.len(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Number of entries embedded.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K, V> SizedStore for EmbeddedStore<K, V> {
+    fn bytes_used(&self) -> usize {
+        self.size
+    }
+}
+
+impl<K: CustomHash, V: From<&'static [u8]>> CacheStore for EmbeddedStore<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        self.entries
+            .get(key.borrow().hash().as_str())
+            .map(|&value| V::from(value))
+    }
+
+    /// Does nothing, embedded entries can't change.
+    fn set(&mut self, _: impl Borrow<Self::Key>, _: impl Borrow<Self::Value>) {}
+
+    /// Does nothing, embedded entries can't change, so there's never a value removed.
+    fn remove(&mut self, _: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        None
+    }
+
+    fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
+        self.entries.contains_key(key.borrow().hash().as_str())
+    }
+}
+
+/// Read-only store of the entries of a [`ThreadSafeFileStoreSerializable`] directory, embedded
+/// into the binary, see the [module docs][self].
+///
+/// Generics:
+/// - `K`: Type of the key used for cache indexing, hashed into the names of the entries.
+/// - `V`: Type of the value stored in the cache store.
+///
+/// [`ThreadSafeFileStoreSerializable`]: super::file_stores::ThreadSafeFileStoreSerializable
+pub struct EmbeddedStoreSerializable<K, V> {
+    entries: HashMap<&'static str, &'static [u8]>,
+    size: usize,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> EmbeddedStoreSerializable<K, V> {
+    /// Make a new [`EmbeddedStoreSerializable`] serving the given snapshot, as [embedded][embed].
+    ///
+    /// # Errors
+    /// When `snapshot` isn't a valid snapshot.
+    pub fn new(snapshot: &'static [u8]) -> Result<Self, InvalidSnapshot> {
+        Ok(Self {
+            entries: parse(snapshot)?,
+            size: snapshot.len(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Number of entries embedded.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K, V> SizedStore for EmbeddedStoreSerializable<K, V> {
+    fn bytes_used(&self) -> usize {
+        self.size
+    }
+}
+
+impl<K: CustomHash + Serialize, V: DeserializeOwned> TryCacheStore
+    for EmbeddedStoreSerializable<K, V>
+{
+    type Key = K;
+    type Value = V;
+    type Error = ThreadSafeFileStoreError;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        self.entries
+            .get(key.hash().as_str())
+            .map(|buf| decode_entry(key, buf))
+            .transpose()
+    }
+
+    /// Does nothing, embedded entries can't change.
+    fn try_set(
+        &mut self,
+        _: impl Borrow<Self::Key>,
+        _: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Does nothing, embedded entries can't change, so there's never a value removed.
+    fn try_remove(
+        &mut self,
+        _: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(None)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        Ok(self.entries.contains_key(key.borrow().hash().as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        stores::{
+            file_stores::{ThreadSafeFileStore, ThreadSafeFileStoreSerializable},
+            MemoryStore,
+        },
+        thread_safe::ThreadSafeTryCacheStore,
+        tiered::TieredStore,
+    };
+    use core::time::Duration;
+    use std::{boxed::Box, vec, vec::Vec};
+    use tempfile::tempdir;
+
+    fn snapshot_of(dir: &Path) -> &'static [u8] {
+        let mut snapshot = Vec::new();
+        write_snapshot(dir, &mut snapshot).unwrap();
+        Box::leak(snapshot.into_boxed_slice())
+    }
+
+    #[test]
+    fn serves_packed_and_unpacked_entries() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_packing(64)
+            .expect("Failed to open the segments");
+        let (small, large) = (String::from("small"), String::from("large"));
+        store.ts_one_try_set(&small, &vec![1; 8]).unwrap();
+        store.ts_one_try_set(&large, &vec![2; 128]).unwrap();
+
+        let mut embedded =
+            EmbeddedStore::<String, &[u8]>::new(snapshot_of(temp_dir.path())).unwrap();
+        assert_eq!(embedded.len(), 2);
+        assert_eq!(embedded.get(&small), Some(&[1; 8][..]));
+        assert_eq!(embedded.get(&large), Some(&[2; 128][..]));
+        assert_eq!(embedded.get(String::from("other")), None);
+
+        embedded.set(&small, &[3][..]);
+        assert_eq!(embedded.remove(&large), None);
+        assert_eq!(embedded.get(&small), Some(&[1; 8][..]));
+        assert!(embedded.exists(&large));
+
+        assert_eq!(
+            EmbeddedStore::<String, Vec<u8>>::new(b"not a snapshot").err(),
+            Some(InvalidSnapshot)
+        );
+    }
+
+    #[test]
+    fn bottom_tier_of_tiered_store() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStoreSerializable::<String, u32>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStoreSerializable");
+        store.ts_one_try_set(&String::from("known"), &1).unwrap();
+        let embedded = EmbeddedStoreSerializable::new(snapshot_of(temp_dir.path())).unwrap();
+
+        let mut store: TieredStore<MemoryStore<String, u32>, _> =
+            TieredStore::new(MemoryStore::new(), embedded, Duration::from_mins(1));
+        assert_eq!(store.try_get(String::from("known")).unwrap(), Some(1));
+        store.try_set(String::from("known"), 2).unwrap();
+        store.try_set(String::from("new"), 3).unwrap();
+        assert_eq!(store.try_get(String::from("known")).unwrap(), Some(2));
+        assert_eq!(store.try_get(String::from("new")).unwrap(), Some(3));
+    }
+}
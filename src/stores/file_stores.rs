@@ -2,17 +2,33 @@ use base64::{prelude::BASE64_URL_SAFE, Engine};
 use serde::{de::DeserializeOwned, Serialize};
 use sha2::{Digest, Sha256};
 
-use crate::{__internal_prelude::*, thread_safe::dumb_wrappers::RwLockAnyGuardKey};
+use super::segments::{self, Segments};
+use crate::{
+    __internal_prelude::*,
+    clock::{Clock, SystemClock},
+    error::CacheError,
+    health::{Health, HealthCheck},
+    meta::{
+        CustomMeta, EntryMeta, MetaValue, ThreadSafeTryCustomMetaCacheStore,
+        ThreadSafeTryMetaCacheStore,
+    },
+    shutdown::Shutdown,
+    size::SizedStore,
+    spawn::Spawner,
+    sync::{AtomicBool, Mutex, RwLock, RwLockWriteGuard},
+    thread_safe::dumb_wrappers::RwLockAnyGuardKey,
+};
 
-use core::hash::Hash;
+use core::{hash::Hash, ops::ControlFlow, time::Duration};
 use std::vec;
 use std::{
-    collections::HashMap,
+    boxed::Box,
+    collections::{HashMap, HashSet},
     fs::{File, OpenOptions},
     io::{Read, Write},
     path::{Path, PathBuf},
     string::String,
-    sync::{Mutex, PoisonError, RwLock, RwLockWriteGuard, TryLockError},
+    sync::{atomic::Ordering, Arc, PoisonError, TryLockError},
     vec::Vec,
 };
 
@@ -23,6 +39,14 @@ pub enum ThreadSafeFileStoreError {
     Bincode(bincode::Error),
     Poisoned,
     WouldBlock,
+    /// The file of the key holds the entry of another key, whose name hashes to the same.
+    KeyMismatch,
+    /// Writing was refused as the filesystem has less space available than
+    /// [configured][ThreadSafeFileStore::with_min_free].
+    StoreFull {
+        /// Bytes that were available.
+        available: u64,
+    },
 }
 impl std::error::Error for ThreadSafeFileStoreError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
@@ -40,6 +64,37 @@ impl std::fmt::Display for ThreadSafeFileStoreError {
             Self::Bincode(err) => writeln!(f, "bincode error: {err}"),
             Self::Poisoned => writeln!(f, "poisoned lock"),
             Self::WouldBlock => writeln!(f, "locking would block"),
+            Self::KeyMismatch => writeln!(f, "entry belongs to another key"),
+            Self::StoreFull { available } => {
+                writeln!(f, "store full, only {available} bytes available")
+            }
+        }
+    }
+}
+
+impl CacheError for ThreadSafeFileStoreError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Io(err) => err.is_transient(),
+            Self::Bincode(err) => {
+                matches!(&**err, bincode::ErrorKind::Io(err) if err.is_transient())
+            }
+            Self::Poisoned | Self::KeyMismatch | Self::StoreFull { .. } => false,
+            Self::WouldBlock => true,
+        }
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Poisoned)
+    }
+
+    fn is_not_found(&self) -> bool {
+        match self {
+            Self::Io(err) => err.is_not_found(),
+            Self::Bincode(err) => {
+                matches!(&**err, bincode::ErrorKind::Io(err) if err.is_not_found())
+            }
+            _ => false,
         }
     }
 }
@@ -80,12 +135,949 @@ impl<T: AsRef<[u8]>> CustomHash for T {
     }
 }
 
+/// Extension given to the files of entries, so other tools can open them right from the store
+/// directory. Extensions can only have ASCII letters and digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileExtension {
+    /// Same extension for every entry.
+    Fixed(String),
+    /// Extension told by the given key of the [`CustomMeta`] of each entry, either an extension
+    /// itself or a content type like `image/png`. Entries without it, or with a value that doesn't
+    /// tell one, get no extension.
+    FromMeta(String),
+}
+
+/// Whether a file extension is safe to append to a file name.
+fn is_extension(extension: &str) -> bool {
+    (1..=16).contains(&extension.len()) && extension.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Extension told by a value of the custom metadata, either an extension itself or a content type.
+fn extension_of(value: &str) -> Option<&str> {
+    // Content types whose subtype isn't the usual extension
+    const KNOWN: [(&str, &str); 8] = [
+        ("text/plain", "txt"),
+        ("text/markdown", "md"),
+        ("text/javascript", "js"),
+        ("application/javascript", "js"),
+        ("application/octet-stream", "bin"),
+        ("application/gzip", "gz"),
+        ("image/svg+xml", "svg"),
+        ("audio/mpeg", "mp3"),
+    ];
+
+    let value = value.split(';').next().unwrap_or(value).trim();
+    let extension = if let Some(&(_, extension)) = KNOWN
+        .iter()
+        .find(|(content_type, _)| content_type.eq_ignore_ascii_case(value))
+    {
+        extension
+    } else if let Some((_, subtype)) = value.split_once('/') {
+        let subtype = subtype.split('+').next().unwrap_or(subtype);
+        subtype.strip_prefix("x-").unwrap_or(subtype)
+    } else {
+        value.trim_start_matches('.')
+    };
+    is_extension(extension).then_some(extension)
+}
+
+/// Reads the whole file of an entry along with its metadata, [`None`] if there's no such file.
+fn read_entry(path: &Path) -> Result<Option<(Vec<u8>, EntryMeta)>, ThreadSafeFileStoreError> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let metadata = file.metadata()?;
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    let meta = EntryMeta {
+        age: metadata.modified().ok().and_then(|at| at.elapsed().ok()),
+        expires_in: None,
+        size: Some(buf.len()),
+        hits: None,
+    };
+    Ok(Some((buf, meta)))
+}
+
+/// Reads an entry, from the segments if it's packed or from its own file otherwise.
+fn load_entry(
+    dir: &StoreDir,
+    name: &str,
+) -> Result<Option<(Vec<u8>, EntryMeta)>, ThreadSafeFileStoreError> {
+    if let Some((buf, age)) = dir
+        .segments
+        .as_ref()
+        .map(|segments| segments.read(name))
+        .transpose()?
+        .flatten()
+    {
+        let meta = EntryMeta {
+            age: Some(age),
+            expires_in: None,
+            size: Some(buf.len()),
+            hits: None,
+        };
+        return Ok(Some((buf, meta)));
+    }
+    read_entry(&dir.entry_file(name)?)
+}
+
+/// Writes an entry, packing it if it's small enough or moving it out of the segments otherwise,
+/// along with its custom metadata or dropping the one it had.
+fn store_entry(
+    dir: &StoreDir,
+    name: &str,
+    bytes: &[u8],
+    meta: Option<&CustomMeta>,
+) -> Result<(), ThreadSafeFileStoreError> {
+    let old = dir.entry_file(name)?;
+    match &dir.segments {
+        Some(segments) if segments.packs(bytes.len()) => {
+            segments.write_many([(name, bytes)])?;
+            remove_file(&old)?;
+        }
+        segments => {
+            let new = dir.file_of(name, meta);
+            write_file(&new, bytes)?;
+            if new != old {
+                remove_file(&old)?;
+            }
+            if let Some(segments) = segments {
+                segments.remove([name])?;
+            }
+        }
+    }
+    dir.write_meta(name, meta)
+}
+
+/// Deletes an entry wherever it is, along with its custom metadata.
+fn remove_entry(dir: &StoreDir, name: &str) -> Result<(), ThreadSafeFileStoreError> {
+    remove_file(&dir.entry_file(name)?)?;
+    if let Some(segments) = &dir.segments {
+        segments.remove([name])?;
+    }
+    dir.write_meta(name, None)
+}
+
+/// Removes a file, if there's one.
+fn remove_file(path: &Path) -> Result<(), ThreadSafeFileStoreError> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Moves a file, copying it when it's on another filesystem.
+fn move_file(from: &Path, to: &Path) -> Result<(), ThreadSafeFileStoreError> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(ref error) if error.kind() == std::io::ErrorKind::CrossesDevices => {
+            std::fs::copy(from, to)?;
+            remove_file(from)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Subdirectory of a store where the [`CustomMeta`] of its entries is kept, a file per entry named
+/// as the entry.
+const META_DIR: &str = ".meta";
+
+/// Directory a store keeps its entries in, along with its segments if packing. Swapped as a whole
+/// when [rotating][ThreadSafeFileStore::rotate].
+struct StoreDir {
+    path: PathBuf,
+    segments: Option<Segments>,
+    /// Whether any entry might have custom metadata, so plain sets know if there's some to drop.
+    has_meta: AtomicBool,
+    extension: Option<FileExtension>,
+}
+
+impl StoreDir {
+    /// Directory that packs no entries and gives their files no extension, it must exist already.
+    fn unpacked(path: PathBuf) -> Self {
+        Self {
+            has_meta: AtomicBool::new(path.join(META_DIR).is_dir()),
+            path,
+            segments: None,
+            extension: None,
+        }
+    }
+
+    /// Creates the directory if needed, and opens its segments when packing below a threshold.
+    fn open(
+        path: PathBuf,
+        packing: Option<usize>,
+        extension: Option<FileExtension>,
+        clock: &SharedClock,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        std::fs::create_dir_all(&path)?;
+        let segments = packing
+            .map(|threshold| Segments::open(&path, threshold, Arc::clone(clock)))
+            .transpose()?;
+        Ok(Self {
+            segments,
+            extension,
+            ..Self::unpacked(path)
+        })
+    }
+
+    fn size(&self) -> usize {
+        dir_size(&self.path)
+            + dir_size(&self.path.join(segments::DIR))
+            + dir_size(&self.path.join(META_DIR))
+    }
+
+    /// File of an entry, if it had the given custom metadata.
+    fn file_of(&self, name: &str, meta: Option<&CustomMeta>) -> PathBuf {
+        let extension = match &self.extension {
+            None => None,
+            Some(FileExtension::Fixed(extension)) => Some(extension.as_str()),
+            Some(FileExtension::FromMeta(key)) => match meta.and_then(|meta| meta.get(key)) {
+                Some(MetaValue::Text(value)) => extension_of(value),
+                Some(MetaValue::Bytes(_)) | None => None,
+            },
+        };
+        match extension {
+            Some(extension) => self.path.join(std::format!("{name}.{extension}")),
+            None => self.path.join(name),
+        }
+    }
+
+    /// File of an entry, its custom metadata is only read if the extension depends on it.
+    fn entry_file(&self, name: &str) -> Result<PathBuf, ThreadSafeFileStoreError> {
+        let meta = match self.extension {
+            Some(FileExtension::FromMeta(_)) => self.read_meta(name)?,
+            _ => None,
+        };
+        Ok(self.file_of(name, meta.as_ref()))
+    }
+
+    /// Custom metadata of an entry, [`None`] if it has none.
+    fn read_meta(&self, name: &str) -> Result<Option<CustomMeta>, ThreadSafeFileStoreError> {
+        if !self.has_meta.load(Ordering::Acquire) {
+            return Ok(None);
+        }
+        match std::fs::read(self.path.join(META_DIR).join(name)) {
+            Ok(buf) => Ok(Some(bincode::deserialize(&buf)?)),
+            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Writes the custom metadata of an entry, or drops it if [`None`].
+    fn write_meta(
+        &self,
+        name: &str,
+        meta: Option<&CustomMeta>,
+    ) -> Result<(), ThreadSafeFileStoreError> {
+        let path = self.path.join(META_DIR);
+        match meta {
+            Some(meta) => {
+                if !self.has_meta.load(Ordering::Acquire) {
+                    std::fs::create_dir_all(&path)?;
+                    self.has_meta.store(true, Ordering::Release);
+                }
+                write_file(&path.join(name), &bincode::serialize(meta)?)
+            }
+            None if self.has_meta.load(Ordering::Acquire) => remove_file(&path.join(name)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether there's a file at `path`, without failing when there isn't.
+fn file_exists(path: &Path) -> Result<bool, ThreadSafeFileStoreError> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.is_file()),
+        Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Clock shared by the parts of a file store that measure time.
+pub(crate) type SharedClock = Arc<dyn Clock + Send + Sync>;
+
+/// Cache of whether the files of entries exist, so checking the same key again doesn't stat its
+/// file. Disabled until given a max age.
+struct StatCache<K> {
+    max_age: Option<Duration>,
+    /// Clock the age of what's known is measured with, and when each key was known.
+    clock: SharedClock,
+    known: Mutex<HashMap<K, (Duration, bool)>>,
+}
+
+impl<K> StatCache<K> {
+    fn new(clock: SharedClock) -> Self {
+        Self {
+            max_age: None,
+            clock,
+            known: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forgets everything, after files were changed behind the back of the cache.
+    fn clear(&self) -> Result<(), ThreadSafeFileStoreError> {
+        self.known.lock()?.clear();
+        Ok(())
+    }
+}
+
+impl<K: Hash + Eq + Clone> StatCache<K> {
+    /// Whether the file of `key`, at `path`, exists. Only stats it if what's known about it is
+    /// older than the max age.
+    fn exists(&self, key: &K, path: &Path) -> Result<bool, ThreadSafeFileStoreError> {
+        let Some(max_age) = self.max_age else {
+            return file_exists(path);
+        };
+        if let Some((at, exists)) = self.known.lock()?.get(key) {
+            if self.clock.now().saturating_sub(*at) < max_age {
+                return Ok(*exists);
+            }
+        }
+        let exists = file_exists(path)?;
+        self.record(key, exists)?;
+        Ok(exists)
+    }
+
+    /// Records whether the file of `key` exists, if the cache is enabled.
+    fn record(&self, key: &K, exists: bool) -> Result<(), ThreadSafeFileStoreError> {
+        if self.max_age.is_some() {
+            self.known
+                .lock()?
+                .insert(key.clone(), (self.clock.now(), exists));
+        }
+        Ok(())
+    }
+}
+
+/// Times each key was set or removed through a store since it was opened.
+struct Revisions<K>(Mutex<HashMap<K, u64>>);
+
+impl<K> Revisions<K> {
+    fn new() -> Self {
+        Self(Mutex::new(HashMap::new()))
+    }
+}
+
+impl<K: Hash + Eq + Clone> Revisions<K> {
+    fn get(&self, key: &K) -> Result<u64, ThreadSafeFileStoreError> {
+        Ok(self.0.lock()?.get(key).copied().unwrap_or(0))
+    }
+
+    /// Counts a set or removal of `key`.
+    fn bump(&self, key: &K) -> Result<(), ThreadSafeFileStoreError> {
+        let mut revisions = self.0.lock()?;
+        if let Some(revision) = revisions.get_mut(key) {
+            *revision += 1;
+        } else {
+            revisions.insert(key.clone(), 1);
+        }
+        Ok(())
+    }
+}
+
+/// Sum of the sizes of the files in a store directory. Files that can't be read are not counted.
+fn dir_size(path: &Path) -> usize {
+    std::fs::read_dir(path).map_or(0, |entries| {
+        entries
+            .filter_map(|entry| entry.ok()?.metadata().ok())
+            .filter(std::fs::Metadata::is_file)
+            .map(|metadata| usize::try_from(metadata.len()).unwrap_or(usize::MAX))
+            .sum()
+    })
+}
+
+/// Space of the filesystem a store directory is on.
+struct DiskSpace {
+    /// Bytes unprivileged processes can still write.
+    available: u64,
+    total: u64,
+    read_only: bool,
+}
+
+/// Space of the filesystem `path` is on, [`None`] on platforms where it can't be told.
+#[cfg(unix)]
+#[allow(clippy::useless_conversion)] // Field types vary between platforms
+fn disk_space(path: &Path) -> std::io::Result<Option<DiskSpace>> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let mut stat = core::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // Written by the successful call above
+    let stat = unsafe { stat.assume_init() };
+    let fragment = u64::from(stat.f_frsize);
+    Ok(Some(DiskSpace {
+        available: u64::from(stat.f_bavail).saturating_mul(fragment),
+        total: u64::from(stat.f_blocks).saturating_mul(fragment),
+        read_only: stat.f_flag & libc::ST_RDONLY != 0,
+    }))
+}
+
+#[cfg(not(unix))]
+fn disk_space(_path: &Path) -> std::io::Result<Option<DiskSpace>> {
+    Ok(None)
+}
+
+/// Filesystems with less than this fraction of their space available are reported as degraded.
+const LOW_SPACE_DIVISOR: u64 = 20;
+
+/// Health of a store directory, which must still be there.
+fn dir_health(path: &Path) -> Result<Health, ThreadSafeFileStoreError> {
+    if !std::fs::metadata(path)?.is_dir() {
+        return Err(std::io::Error::other("store path is not a directory").into());
+    }
+    Ok(match disk_space(path)? {
+        Some(space) if space.read_only => {
+            Health::Degraded(String::from("read-only filesystem, entries can't be set"))
+        }
+        Some(space) if space.available < space.total / LOW_SPACE_DIVISOR => Health::Degraded(
+            std::format!("{} of {} bytes free", space.available, space.total),
+        ),
+        _ => Health::Healthy,
+    })
+}
+
+/// Least space to leave available on the filesystem of a file store, see
+/// [`ThreadSafeFileStore::with_min_free`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinFree {
+    Bytes(u64),
+    /// Percent of the size of the filesystem, up to 100.
+    Percent(u8),
+}
+
+/// Called with the bytes available when a write is refused for lack of space.
+type OnFull = Box<dyn Fn(u64) + Send + Sync>;
+
+/// Refuses writes once the filesystem of a store is almost full.
+#[derive(Default)]
+struct SpaceGuard {
+    min_free: Option<MinFree>,
+    on_full: Option<OnFull>,
+}
+
+impl SpaceGuard {
+    /// Fails if writing to `path` would leave less space than configured, on platforms where the
+    /// space can be told.
+    fn check(&self, path: &Path) -> Result<(), ThreadSafeFileStoreError> {
+        let Some(min_free) = self.min_free else {
+            return Ok(());
+        };
+        let Some(space) = disk_space(path)? else {
+            return Ok(());
+        };
+        let min_free = match min_free {
+            MinFree::Bytes(bytes) => bytes,
+            MinFree::Percent(percent) => space.total / 100 * u64::from(percent.min(100)),
+        };
+        if space.available >= min_free {
+            return Ok(());
+        }
+        if let Some(on_full) = &self.on_full {
+            on_full(space.available);
+        }
+        Err(ThreadSafeFileStoreError::StoreFull {
+            available: space.available,
+        })
+    }
+}
+
+/// Progress of [compacting][ThreadSafeFileStore::compact] the segments of a file store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactProgress {
+    /// Segments rewritten so far.
+    pub segments_done: usize,
+    /// Segments to rewrite in total.
+    pub segments_total: usize,
+    /// Bytes freed so far.
+    pub bytes_freed: u64,
+}
+
+/// When to compact the segments of a file store in the background, see
+/// [`ThreadSafeFileStore::spawn_compactor`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionPolicy {
+    /// Compacts once the segments take this many times the space of the packed entries.
+    pub max_amplification: f64,
+    /// Waits until no packed entry was read nor written for this long, so compacting doesn't get
+    /// in the way of traffic.
+    pub min_idle: Duration,
+}
+
+impl Default for CompactionPolicy {
+    /// Compacts segments twice as large as needed, after a minute without packed operations.
+    fn default() -> Self {
+        Self {
+            max_amplification: 2.0,
+            min_idle: Duration::from_mins(1),
+        }
+    }
+}
+
+/// Compacts the segments of a directory if the policy says so.
+fn compact_by(dir: &StoreDir, policy: &CompactionPolicy) -> Result<(), ThreadSafeFileStoreError> {
+    let Some(segments) = &dir.segments else {
+        return Ok(());
+    };
+    if segments.idle_for()? >= policy.min_idle
+        && segments.amplification()? >= policy.max_amplification
+    {
+        segments.compact(&mut |_| {})?;
+    }
+    Ok(())
+}
+
+/// Locks of the keys of a store, one for each key ever locked. They're boxed so the map growing
+/// doesn't move them, as handles keep pointing to them once the map is unlocked, and they're never
+/// removed.
+type KeyLocks<K> = Mutex<HashMap<K, Box<RwLock<()>>>>;
+
+/// Lock of `key`, inserted if it isn't there yet. The map is unlocked before returning, so waiting
+/// on the lock doesn't keep other keys from being locked.
+fn key_lock<'a, K: Clone + Hash + Eq>(
+    locks: &'a KeyLocks<K>,
+    key: &K,
+) -> Result<&'a RwLock<()>, ThreadSafeFileStoreError> {
+    let mut locks = locks.lock()?;
+    if !locks.contains_key(key) {
+        locks.insert(key.clone(), Box::default());
+    }
+    // Detach the lock itself from the HashMap guard lifetime
+    let lock: *const RwLock<()> = &raw const *locks[key];
+    Ok(unsafe { &*lock })
+}
+
+/// Lock of `key` if it was locked before or `has_entry` finds its entry, [`None`] otherwise. Reads of
+/// absent keys are answered without inserting a lock, so probing keys doesn't grow the map. It's
+/// kept locked while checking, so no entry can be written meanwhile.
+fn existing_key_lock<'a, K: Clone + Hash + Eq>(
+    locks: &'a KeyLocks<K>,
+    key: &K,
+    has_entry: impl FnOnce() -> Result<bool, ThreadSafeFileStoreError>,
+) -> Result<Option<&'a RwLock<()>>, ThreadSafeFileStoreError> {
+    let mut locks = locks.lock()?;
+    if !locks.contains_key(key) {
+        if !has_entry()? {
+            return Ok(None);
+        }
+        locks.insert(key.clone(), Box::default());
+    }
+    // Detach the lock itself from the HashMap guard lifetime
+    let lock: *const RwLock<()> = &raw const *locks[key];
+    Ok(Some(unsafe { &*lock }))
+}
+
+/// Names of the entries with a handle taken.
+fn busy_names<K: CustomHash>(locks: &HashMap<K, Box<RwLock<()>>>) -> HashSet<String> {
+    locks
+        .iter()
+        .filter(|(_, lock)| matches!(lock.try_write(), Err(TryLockError::WouldBlock)))
+        .map(|(key, _)| key.hash())
+        .collect()
+}
+
+/// Copies the entries with their own file in `src` that `dst` is missing or has older, keeping
+/// their modification time, along with their custom metadata. Entries of `dst` with a handle taken
+/// are skipped. Returns how many were copied.
+fn sync_dirs<K: CustomHash>(
+    src: &StoreDir,
+    dst: &StoreDir,
+    locks: &KeyLocks<K>,
+) -> Result<usize, ThreadSafeFileStoreError> {
+    // Holding the map keeps new handles from being taken while syncing
+    let locks = locks.lock()?;
+    let busy = busy_names(&locks);
+
+    let mut synced = 0;
+    for entry in std::fs::read_dir(&src.path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let Some(file_name) = entry.file_name().into_string().ok() else {
+            continue;
+        };
+        let name = file_name
+            .split_once('.')
+            .map_or(&*file_name, |(name, _)| name);
+        if !metadata.is_file() || busy.contains(name) {
+            continue;
+        }
+
+        let modified = metadata.modified()?;
+        let path = dst.path.join(&file_name);
+        let dst_modified = match std::fs::metadata(&path) {
+            Ok(metadata) => Some(metadata.modified()?),
+            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => dst
+                .segments
+                .as_ref()
+                .map(|segments| segments.written(name))
+                .transpose()?
+                .flatten(),
+            Err(error) => return Err(error.into()),
+        };
+        if dst_modified.is_some_and(|at| at >= modified) {
+            continue;
+        }
+
+        // Not copied over, in case it's linked somewhere else
+        remove_file(&path)?;
+        std::fs::copy(entry.path(), &path)?;
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+        if let Some(segments) = &dst.segments {
+            segments.remove([name])?;
+        }
+        let meta = src.read_meta(name)?;
+        dst.write_meta(name, meta.as_ref())?;
+        synced += 1;
+    }
+    Ok(synced)
+}
+
+/// Deletes the entries of a store directory, and its segments, older than `max_age`, skipping the
+/// ones with a handle taken, along with their custom metadata. Returns how many were deleted.
+fn purge_dir<K: CustomHash>(
+    dir: &StoreDir,
+    locks: &KeyLocks<K>,
+    max_age: Duration,
+) -> Result<usize, ThreadSafeFileStoreError> {
+    // Holding the map keeps new handles from being taken while purging
+    let locks = locks.lock()?;
+    let busy = busy_names(&locks);
+
+    let mut purged = 0;
+    for entry in std::fs::read_dir(&dir.path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file()
+            || entry.file_name().to_str().is_some_and(|name| {
+                busy.contains(name.split_once('.').map_or(name, |(name, _)| name))
+            })
+        {
+            continue;
+        }
+
+        let age = metadata.modified().ok().and_then(|at| at.elapsed().ok());
+        if age.is_none_or(|age| age <= max_age) {
+            continue;
+        }
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => purged += 1,
+            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    if let Some(segments) = &dir.segments {
+        purged += segments.purge(max_age, &busy)?;
+    }
+    if dir.has_meta.load(Ordering::Acquire) {
+        for entry in std::fs::read_dir(dir.path.join(META_DIR))? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().into_string().ok() else {
+                continue;
+            };
+            let packed = dir
+                .segments
+                .as_ref()
+                .map_or(Ok(false), |segments| segments.contains(&name))?;
+            if !packed && !file_exists(&dir.entry_file(&name)?)? {
+                remove_file(&entry.path())?;
+            }
+        }
+    }
+    Ok(purged)
+}
+
+/// Syncs to disk the files of the entries a store touched, as told by the keys in its lock map,
+/// its segments and the directory itself.
+fn sync_dir<K: CustomHash>(
+    dir: &StoreDir,
+    locks: &KeyLocks<K>,
+) -> Result<(), ThreadSafeFileStoreError> {
+    for key in locks.lock()?.keys() {
+        match File::open(dir.entry_file(&key.hash())?) {
+            Ok(file) => file.sync_all()?,
+            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    if let Some(segments) = &dir.segments {
+        segments.sync()?;
+    }
+    // Directories can't be opened as files everywhere
+    #[cfg(unix)]
+    File::open(&dir.path)?.sync_all()?;
+    Ok(())
+}
+
+/// Writes the file of an entry, replacing it if there was one. The old file is unlinked instead of
+/// truncated, so hard links to it, like [exported][ThreadSafeFileStore::export_entry] entries, keep
+/// what they had.
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), ThreadSafeFileStoreError> {
+    remove_file(path)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+/// Writes many entries of a store, already encoded, taking the locks of all their keys in one pass.
+/// The ones small enough to be packed are appended to the segments in a single write. Of several
+/// entries of the same key only the last one is written, dropping any custom metadata they had.
+/// Returns how many were written.
+fn set_many_in<'a, K: Clone + Hash + Eq + CustomHash + 'a, B: AsRef<[u8]>>(
+    dir: &StoreDir,
+    locks: &KeyLocks<K>,
+    stats: &StatCache<K>,
+    revisions: &Revisions<K>,
+    entries: impl IntoIterator<Item = (&'a K, B)>,
+) -> Result<usize, ThreadSafeFileStoreError> {
+    let entries: HashMap<&K, B> = entries.into_iter().collect();
+
+    let mut key_locks = entries
+        .keys()
+        .map(|key| key_lock(locks, key))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Taken in the same order by every call, so two of them can't wait on each other
+    key_locks.sort_unstable_by_key(|lock| core::ptr::from_ref(*lock));
+    let guards = key_locks
+        .into_iter()
+        .map(RwLock::write)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let segments = dir.segments.as_ref();
+    let (packed, own): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .map(|(key, bytes)| (CustomHash::hash(*key), bytes.as_ref()))
+        .partition(|(_, bytes)| segments.is_some_and(|segments| segments.packs(bytes.len())));
+    if let Some(segments) = segments {
+        segments.write_many(packed.iter().map(|(name, bytes)| (name.as_str(), *bytes)))?;
+        for (name, _) in &packed {
+            remove_file(&dir.entry_file(name)?)?;
+            dir.write_meta(name, None)?;
+        }
+    }
+    for (name, bytes) in &own {
+        store_entry(dir, name, bytes, None)?;
+    }
+    for key in entries.keys() {
+        stats.record(key, true)?;
+        revisions.bump(key)?;
+    }
+    drop(guards);
+    Ok(entries.len())
+}
+
+/// State of a file store and everything done with it that doesn't depend on how values are
+/// encoded, shared by [`ThreadSafeFileStore`] and [`ThreadSafeFileStoreSerializable`].
+struct FileStoreCore<K> {
+    dir: Mutex<Arc<StoreDir>>,
+    /// Threshold below which entries are packed, if they are.
+    packing: Option<usize>,
+    clock: SharedClock,
+    cache: KeyLocks<K>,
+    stats: StatCache<K>,
+    revisions: Revisions<K>,
+    space: SpaceGuard,
+}
+
+impl<K: CustomHash> FileStoreCore<K> {
+    fn new_on(path: impl AsRef<Path> + TryInto<PathBuf>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&path)?;
+        let path = path
+            .try_into()
+            .map_err(|_| std::io::Error::other("error converting from path"))?;
+        let clock: SharedClock = Arc::new(SystemClock);
+        Ok(Self {
+            dir: Mutex::new(Arc::new(StoreDir::unpacked(path))),
+            packing: None,
+            stats: StatCache::new(Arc::clone(&clock)),
+            clock,
+            cache: Mutex::new(HashMap::new()),
+            revisions: Revisions::new(),
+            space: SpaceGuard::default(),
+        })
+    }
+
+    fn with_packing(mut self, threshold: usize) -> Result<Self, ThreadSafeFileStoreError> {
+        let dir = self.dir();
+        let extension = dir.extension.clone();
+        self.dir = Mutex::new(Arc::new(StoreDir::open(
+            dir.path.clone(),
+            Some(threshold),
+            extension,
+            &self.clock,
+        )?));
+        self.packing = Some(threshold);
+        Ok(self)
+    }
+
+    fn with_clock(mut self, clock: SharedClock) -> Self {
+        if let Some(segments) = &self.dir().segments {
+            segments.set_clock(Arc::clone(&clock));
+        }
+        // What's known was known at times of the previous clock
+        self.stats = StatCache {
+            max_age: self.stats.max_age,
+            ..StatCache::new(Arc::clone(&clock))
+        };
+        self.clock = clock;
+        self
+    }
+
+    fn with_extension(
+        mut self,
+        extension: FileExtension,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        if matches!(&extension, FileExtension::Fixed(fixed) if !is_extension(fixed)) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "file extensions can only have ASCII letters and digits",
+            )
+            .into());
+        }
+        let path = self.dir().path.clone();
+        self.dir = Mutex::new(Arc::new(StoreDir::open(
+            path,
+            self.packing,
+            Some(extension),
+            &self.clock,
+        )?));
+        Ok(self)
+    }
+
+    fn rotate(&self, new_path: PathBuf) -> Result<PathBuf, ThreadSafeFileStoreError> {
+        let new_dir = StoreDir::open(
+            new_path,
+            self.packing,
+            self.dir().extension.clone(),
+            &self.clock,
+        )?;
+        let _locks = self.cache.lock()?;
+        let old_dir = core::mem::replace(
+            &mut *self.dir.lock().unwrap_or_else(PoisonError::into_inner),
+            Arc::new(new_dir),
+        );
+        self.stats.clear()?;
+        Ok(old_dir.path.clone())
+    }
+
+    /// Directory the store is currently on.
+    fn dir(&self) -> Arc<StoreDir> {
+        Arc::clone(&self.dir.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn get_path_of(&self, key: &K) -> Result<PathBuf, ThreadSafeFileStoreError> {
+        self.dir().entry_file(&key.hash())
+    }
+
+    /// Reads the bytes of an entry, wherever it is.
+    fn load(&self, key: &K) -> Result<Option<(Vec<u8>, EntryMeta)>, ThreadSafeFileStoreError> {
+        load_entry(&self.dir(), &key.hash())
+    }
+
+    /// Writes the bytes of an entry, wherever it belongs, and its custom metadata or drops it.
+    fn store(
+        &self,
+        key: &K,
+        bytes: &[u8],
+        meta: Option<&CustomMeta>,
+    ) -> Result<(), ThreadSafeFileStoreError> {
+        let dir = self.dir();
+        self.space.check(&dir.path)?;
+        store_entry(&dir, &key.hash(), bytes, meta)
+    }
+
+    /// Whether the entry is in the segments.
+    fn is_packed(&self, key: &K) -> Result<bool, ThreadSafeFileStoreError> {
+        self.dir()
+            .segments
+            .as_ref()
+            .map_or(Ok(false), |segments| segments.contains(&key.hash()))
+    }
+
+    /// Whether the entry is anywhere on disk, skipping the stat cache.
+    fn has_entry(&self, key: &K) -> Result<bool, ThreadSafeFileStoreError> {
+        Ok(self.is_packed(key)? || file_exists(&self.get_path_of(key)?)?)
+    }
+
+    fn purge_older_than(&self, max_age: Duration) -> Result<usize, ThreadSafeFileStoreError> {
+        let dir = self.dir();
+        let purged = purge_dir(&dir, &self.cache, max_age)?;
+        self.stats.clear()?;
+        Ok(purged)
+    }
+
+    fn sync_from(&self, src: &Self) -> Result<usize, ThreadSafeFileStoreError> {
+        let dir = self.dir();
+        self.space.check(&dir.path)?;
+        let synced = sync_dirs(&src.dir(), &dir, &self.cache)?;
+        self.stats.clear()?;
+        Ok(synced)
+    }
+
+    fn compact(
+        &self,
+        progress: &mut dyn FnMut(CompactProgress),
+    ) -> Result<CompactProgress, ThreadSafeFileStoreError> {
+        self.dir()
+            .segments
+            .as_ref()
+            .map_or(Ok(CompactProgress::default()), |segments| {
+                segments.compact(progress)
+            })
+    }
+
+    fn space_amplification(&self) -> Result<f64, ThreadSafeFileStoreError> {
+        self.dir()
+            .segments
+            .as_ref()
+            .map_or(Ok(1.0), Segments::amplification)
+    }
+}
+
+/// Spawns the compactor of a store of either kind, see [`ThreadSafeFileStore::spawn_compactor`].
+/// The store is reached through `core`.
+fn spawn_compactor<S: Send + Sync + 'static, K: CustomHash + 'static>(
+    store: &Arc<S>,
+    core: fn(&S) -> &FileStoreCore<K>,
+    spawner: &impl Spawner,
+    interval: Duration,
+    policy: CompactionPolicy,
+    mut on_error: impl FnMut(ThreadSafeFileStoreError) + Send + 'static,
+) {
+    let store = Arc::downgrade(store);
+    spawner.spawn_every(
+        interval,
+        Box::new(move || {
+            let Some(store) = store.upgrade() else {
+                return ControlFlow::Break(());
+            };
+            if let Err(error) = compact_by(&core(&store).dir(), &policy) {
+                on_error(error);
+            }
+            ControlFlow::Continue(())
+        }),
+    );
+}
+
 // ---- Raw (No Serialization)
 
 /// Thread safe store based on files
 pub struct ThreadSafeFileStore<K, V> {
-    path: PathBuf,
-    cache: Mutex<HashMap<K, RwLock<()>>>,
+    core: FileStoreCore<K>,
     value_phantom: PhantomData<V>,
 }
 
@@ -97,18 +1089,327 @@ impl<K: CustomHash, V> ThreadSafeFileStore<K, V> {
     /// # Errors
     /// Fails when any underlying io call does.
     pub fn new_on(path: impl AsRef<Path> + TryInto<PathBuf>) -> std::io::Result<Self> {
-        std::fs::create_dir_all(&path)?;
         Ok(Self {
-            path: path.try_into().map_err(|_| {
-                std::io::Error::new(std::io::ErrorKind::Other, "error converting from path")
-            })?,
-            cache: Mutex::new(HashMap::new()),
+            core: FileStoreCore::new_on(path)?,
             value_phantom: PhantomData,
         })
     }
 
-    fn get_path_of(&self, key: &K) -> PathBuf {
-        self.path.join(key.hash())
+    /// Remembers whether the file of each key exists for up to `max_age`, so checking if it
+    /// [exists][ThreadSafeTryCacheStore::ts_try_exists] again doesn't hit the filesystem. Entries
+    /// set and purged through the store are always known, but files created or deleted by others
+    /// take up to `max_age` to be noticed.
+    #[must_use]
+    pub fn with_stat_cache(mut self, max_age: Duration) -> Self {
+        self.core.stats.max_age = Some(max_age);
+        self
+    }
+
+    /// Replaces the clock used to tell how long the packed entries have been idle for a
+    /// [`CompactionPolicy`] and the age of what the [stat cache][Self::with_stat_cache] knows, the
+    /// system time by default.
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.core = self.core.with_clock(Arc::new(clock));
+        self
+    }
+
+    /// Refuses to write entries once the filesystem of the store has less than `min_free` space
+    /// available, failing with [`StoreFull`][ThreadSafeFileStoreError::StoreFull] instead of
+    /// filling the volume. The space is checked before every write, only on unix.
+    #[must_use]
+    pub fn with_min_free(mut self, min_free: MinFree) -> Self {
+        self.core.space.min_free = Some(min_free);
+        self
+    }
+
+    /// Calls `on_full` with the bytes available whenever a write is refused for lack of
+    /// [space][Self::with_min_free], like to purge old entries.
+    #[must_use]
+    pub fn with_on_full(mut self, on_full: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.core.space.on_full = Some(Box::new(on_full));
+        self
+    }
+
+    /// Packs entries smaller than `threshold` bytes, as written to disk, into segment files shared
+    /// by many entries instead of giving each of them its own file. It saves inodes and space for
+    /// stores of many tiny values. Larger entries still get their own file.
+    ///
+    /// Segments are kept in a `.packed` subdirectory, where the values packed before are found
+    /// again when reopening the store. Overwritten packed entries keep taking space in their
+    /// segment until it's [compacted][Self::compact].
+    ///
+    /// # Errors
+    /// Fails when reading the existing segments does.
+    pub fn with_packing(mut self, threshold: usize) -> Result<Self, ThreadSafeFileStoreError> {
+        self.core = self.core.with_packing(threshold)?;
+        Ok(self)
+    }
+
+    /// Gives the files of entries an extension, so they can be opened right from the store
+    /// directory by tools that go by it, like image viewers. Packed entries have no file of their
+    /// own, so they get none.
+    ///
+    /// The extension must be the same every time the store is opened on a directory, entries
+    /// written with another one aren't found.
+    ///
+    /// # Errors
+    /// Fails when a fixed extension has anything but ASCII letters and digits, or when reopening
+    /// the segments does.
+    pub fn with_extension(
+        mut self,
+        extension: FileExtension,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        self.core = self.core.with_extension(extension)?;
+        Ok(self)
+    }
+
+    /// Switches the store to a fresh directory at `new_path`, created if needed, and returns the
+    /// path of the old one. Nothing is moved or deleted, so the old directory can be deleted in
+    /// the background while the store keeps serving from the new one, which is how to drop the
+    /// whole cache under traffic.
+    ///
+    /// No handle can be taken while switching. Operations on handles already taken land in
+    /// either directory.
+    ///
+    /// # Errors
+    /// Fails when creating the new directory or its segments does or when the store is poisoned,
+    /// the store is left on the old directory then.
+    pub fn rotate(
+        &self,
+        new_path: impl Into<PathBuf>,
+    ) -> Result<PathBuf, ThreadSafeFileStoreError> {
+        self.core.rotate(new_path.into())
+    }
+
+    /// Deletes the entries whose file wasn't modified in `max_age`, returns how many were deleted.
+    ///
+    /// No handle can be taken while purging and entries with a handle held are left alone, so it's
+    /// safe to call while the store is in use.
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned.
+    pub fn purge_older_than(&self, max_age: Duration) -> Result<usize, ThreadSafeFileStoreError> {
+        self.core.purge_older_than(max_age)
+    }
+
+    /// Copies into the store the entries of `src` it's missing or has an older version of, like
+    /// rsync by the modification time of their files, which is kept. Enough to replicate a local
+    /// cache to a network share and back. Returns how many entries were copied.
+    ///
+    /// No handle can be taken in the store while syncing and entries with a handle held are left
+    /// alone. Only entries with their own file are synced, packed ones in `src` aren't, and both
+    /// stores should give files the same [extension][Self::with_extension].
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned, the entries before
+    /// the failing one might have been copied.
+    pub fn sync_from(&self, src: &Self) -> Result<usize, ThreadSafeFileStoreError> {
+        self.core.sync_from(&src.core)
+    }
+
+    /// Rewrites the packed entries into fresh segments, freeing the space still taken by the ones
+    /// overwritten or moved out since they were packed. `progress` is called after each segment
+    /// rewritten, and the totals are returned. Does nothing unless
+    /// [packing][Self::with_packing].
+    ///
+    /// Packed entries can't be read nor written while a segment is rewritten, which takes as long
+    /// as reading it and writing what's left of it.
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned. Segments rewritten
+    /// before the failing one stay so, and no entry is lost either way.
+    pub fn compact(
+        &self,
+        mut progress: impl FnMut(CompactProgress),
+    ) -> Result<CompactProgress, ThreadSafeFileStoreError> {
+        self.core.compact(&mut progress)
+    }
+
+    /// Space the segments take over the space of the packed entries in them, `1.0` when there's
+    /// nothing to [compact][Self::compact].
+    ///
+    /// # Errors
+    /// Fails when the store is poisoned.
+    pub fn space_amplification(&self) -> Result<f64, ThreadSafeFileStoreError> {
+        self.core.space_amplification()
+    }
+
+    /// Spawns a task that [compacts][Self::compact] the store every `interval` when `policy` says
+    /// so, it stops once the store is dropped. Errors of a compaction are passed to `on_error`,
+    /// like to log them, and it's tried again on the next check.
+    pub fn spawn_compactor(
+        this: &Arc<Self>,
+        spawner: &impl Spawner,
+        interval: Duration,
+        policy: CompactionPolicy,
+        on_error: impl FnMut(ThreadSafeFileStoreError) + Send + 'static,
+    ) where
+        K: Send + 'static,
+        V: Send + Sync + 'static,
+    {
+        spawn_compactor(
+            this,
+            |store| &store.core,
+            spawner,
+            interval,
+            policy,
+            on_error,
+        );
+    }
+}
+
+impl<K: Clone + Hash + Eq + CustomHash, V: AsRef<[u8]>> ThreadSafeFileStore<K, V> {
+    /// Times the entry of `key` was set through the store since it was opened, `0` if never.
+    /// Usable to build cache-busting URLs or to tell cheaply if an entry changed.
+    ///
+    /// # Errors
+    /// Fails when the store is poisoned.
+    pub fn entry_revision(&self, key: &K) -> Result<u64, ThreadSafeFileStoreError> {
+        self.core.revisions.get(key)
+    }
+
+    /// Sets many entries at once, taking the locks of all their keys in one pass instead of one
+    /// by one. Of several entries of the same key only the last one is set. Returns how many
+    /// entries were set.
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned, the entries before
+    /// the failing one might have been set.
+    pub fn ts_try_set_many<'a>(
+        &self,
+        entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+    ) -> Result<usize, ThreadSafeFileStoreError>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.as_ref()));
+        let dir = self.core.dir();
+        self.core.space.check(&dir.path)?;
+        set_many_in(
+            &dir,
+            &self.core.cache,
+            &self.core.stats,
+            &self.core.revisions,
+            entries,
+        )
+    }
+}
+
+impl<K: Clone + Hash + Eq + CustomHash, V: Clone + AsRef<[u8]> + From<Vec<u8>>>
+    ThreadSafeFileStore<K, V>
+{
+    /// Moves the file at `path` into the store as the value of `key`, without reading it, for
+    /// files produced by other tools. It's renamed when on the same filesystem as the store and
+    /// copied otherwise. Adopted entries get their own file even if small enough to be packed, and
+    /// no custom metadata.
+    ///
+    /// # Errors
+    /// Fails when moving the file or any other underlying io call does, or when the store is
+    /// poisoned. The file is left where it was if it couldn't be moved.
+    pub fn adopt(&self, key: &K, path: impl AsRef<Path>) -> Result<(), ThreadSafeFileStoreError> {
+        let _handle = self.ts_try_xlock(key)?;
+        let dir = self.core.dir();
+        self.core.space.check(&dir.path)?;
+        let name = CustomHash::hash(key);
+        let old = dir.entry_file(&name)?;
+        let new = dir.file_of(&name, None);
+        move_file(path.as_ref(), &new)?;
+        if new != old {
+            remove_file(&old)?;
+        }
+        if let Some(segments) = &dir.segments {
+            segments.remove([name.as_str()])?;
+        }
+        dir.write_meta(&name, None)?;
+        self.core.stats.record(key, true)?;
+        self.core.revisions.bump(key)
+    }
+
+    /// Puts the value of `key` at `dest`, for other processes to use, without reading it into
+    /// memory. The file of the entry is hard linked there, or copied if it can't be, like when on
+    /// another filesystem. Setting the entry later doesn't change what was exported. Returns
+    /// whether there was an entry to export.
+    ///
+    /// # Errors
+    /// Fails when there's already a file at `dest`, when linking and copying the file or any
+    /// other underlying io call fails, or when the store is poisoned.
+    pub fn export_entry(
+        &self,
+        key: &K,
+        dest: impl AsRef<Path>,
+    ) -> Result<bool, ThreadSafeFileStoreError> {
+        let dest = dest.as_ref();
+        let _handle = self.ts_try_slock(key)?;
+        let dir = self.core.dir();
+        let name = CustomHash::hash(key);
+        // Packed entries have no file to link, but they are small
+        if let Some((buf, _)) = dir
+            .segments
+            .as_ref()
+            .map(|segments| segments.read(&name))
+            .transpose()?
+            .flatten()
+        {
+            let mut file = OpenOptions::new().write(true).create_new(true).open(dest)?;
+            file.write_all(&buf)?;
+            return Ok(true);
+        }
+
+        let file = dir.entry_file(&name)?;
+        if !file_exists(&file)? {
+            return Ok(false);
+        }
+        match std::fs::hard_link(&file, dest) {
+            Ok(()) => {}
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    std::io::ErrorKind::AlreadyExists | std::io::ErrorKind::NotFound
+                ) =>
+            {
+                return Err(error.into())
+            }
+            Err(_) => {
+                std::fs::copy(&file, dest)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Syncs the files written through the store to disk, entries are always written right away.
+impl<K: CustomHash, V> Shutdown for ThreadSafeFileStore<K, V> {
+    type Error = ThreadSafeFileStoreError;
+
+    fn shutdown(&mut self) -> Result<(), Self::Error> {
+        let dir = self.core.dir();
+        sync_dir(&dir, &self.core.cache)
+    }
+}
+
+/// Checks that the directory is still there, and warns when its filesystem is read-only or has less
+/// than a twentieth of its space available.
+impl<K: CustomHash, V> HealthCheck for ThreadSafeFileStore<K, V> {
+    type Error = ThreadSafeFileStoreError;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        dir_health(&self.core.dir().path)
+    }
+}
+
+/// Scans the directory, so it's as slow as the amount of entries. Segments count whole, including
+/// the space of overwritten entries.
+impl<K, V> SizedStore for ThreadSafeFileStore<K, V> {
+    fn bytes_used(&self) -> usize {
+        self.core
+            .dir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .size()
     }
 }
 
@@ -130,146 +1431,378 @@ where
         &'lock self,
         handle: &Self::SLock<'_>,
     ) -> Result<Option<Self::Value>, Self::Error> {
-        let path = self.get_path_of(handle.get_key());
-        match File::open(path) {
-            Ok(mut fil) => {
-                let mut buf = vec![];
-                fil.read_to_end(&mut buf)?;
-                Ok(Some(buf.into()))
-            }
-            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
-            Err(error) => Err(error.into()),
+        let entry = self.core.load(handle.get_key())?;
+        Ok(entry.map(|(buf, _)| buf.into()))
+    }
+
+    fn ts_try_set(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<(), Self::Error> {
+        self.core.store(handle.1, value.as_ref(), None)?;
+        self.core.stats.record(handle.1, true)?;
+        self.core.revisions.bump(handle.1)?;
+        Ok(())
+    }
+
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let Some((buf, _)) = self.core.load(handle.1)? else {
+            return Ok(None);
+        };
+        remove_entry(&self.core.dir(), &CustomHash::hash(handle.1))?;
+        self.core.stats.record(handle.1, false)?;
+        self.core.revisions.bump(handle.1)?;
+        Ok(Some(buf.into()))
+    }
+
+    fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
+        let key = handle.get_key();
+        if self.core.is_packed(key)? {
+            return Ok(true);
         }
+        self.core.stats.exists(key, &self.core.get_path_of(key)?)
+    }
+
+    /// Keys without an entry that were never locked are answered without adding a lock for them.
+    fn ts_one_try_get(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let Some(lock) = existing_key_lock(&self.core.cache, key, || self.core.has_entry(key))?
+        else {
+            return Ok(None);
+        };
+        self.ts_try_get(&(lock.read()?, key).into())
+    }
+
+    /// Keys without an entry that were never locked are answered without adding a lock for them.
+    fn ts_one_try_exists(&'lock self, key: &'lock Self::Key) -> Result<bool, Self::Error> {
+        let Some(lock) = existing_key_lock(&self.core.cache, key, || self.core.has_entry(key))?
+        else {
+            return Ok(false);
+        };
+        self.ts_try_exists(&(lock.read()?, key).into())
+    }
+
+    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.write()?, key))
+    }
+
+    fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.read()?, key).into())
+    }
+
+    fn ts_try_xlock_nblock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.try_write()?, key))
+    }
+
+    fn ts_try_slock_nblock(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Self::SLock<'lock>, Self::Error> {
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.try_read()?, key).into())
+    }
+}
+
+/// The age of an entry is told by the modification time of its file.
+impl<'lock, K: Clone + Hash + Eq + CustomHash, V: Clone + AsRef<[u8]> + From<Vec<u8>>>
+    ThreadSafeTryMetaCacheStore<'lock> for ThreadSafeFileStore<K, V>
+where
+    Self: 'lock,
+{
+    fn ts_try_get_with_meta(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error> {
+        let entry = self.core.load(handle.get_key())?;
+        Ok(entry.map(|(buf, meta)| (buf.into(), meta)))
+    }
+}
+
+/// Custom metadata is kept in its own file, next to the one of the entry.
+impl<'lock, K: Clone + Hash + Eq + CustomHash, V: Clone + AsRef<[u8]> + From<Vec<u8>>>
+    ThreadSafeTryCustomMetaCacheStore<'lock> for ThreadSafeFileStore<K, V>
+where
+    Self: 'lock,
+{
+    fn ts_try_set_with_meta(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+        meta: &CustomMeta,
+    ) -> Result<(), Self::Error> {
+        self.core.store(handle.1, value.as_ref(), Some(meta))?;
+        self.core.stats.record(handle.1, true)?;
+        self.core.revisions.bump(handle.1)?;
+        Ok(())
+    }
+
+    fn ts_try_get_custom_meta(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<CustomMeta>, Self::Error> {
+        self.core
+            .dir()
+            .read_meta(&CustomHash::hash(handle.get_key()))
+    }
+}
+
+// ---- With Serialization
+
+/// Thread safe store based on files with serialization
+///
+/// Each file holds the serialized key along with the value, so an entry read through a key whose
+/// [`CustomHash`] collides with the one of another key fails with
+/// [`KeyMismatch`][ThreadSafeFileStoreError::KeyMismatch] instead of returning the wrong value.
+pub struct ThreadSafeFileStoreSerializable<K, V> {
+    core: FileStoreCore<K>,
+    value_phantom: PhantomData<V>,
+}
+
+impl<K: CustomHash, V> ThreadSafeFileStoreSerializable<K, V> {
+    /// Makes a new instance from a directory path
+    /// Doesn't perform any file lock, you must ensure this path isn't used by other processes
+    /// or even this one itself.
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does.
+    pub fn new_on(path: impl AsRef<Path> + TryInto<PathBuf>) -> std::io::Result<Self> {
+        Ok(Self {
+            core: FileStoreCore::new_on(path)?,
+            value_phantom: PhantomData,
+        })
     }
 
-    fn ts_try_set(
-        &'lock self,
-        handle: &mut Self::XLock,
-        value: &Self::Value,
-    ) -> Result<(), Self::Error> {
-        let serialized = value.as_ref();
+    /// Same as [`ThreadSafeFileStore::with_stat_cache`].
+    #[must_use]
+    pub fn with_stat_cache(mut self, max_age: Duration) -> Self {
+        self.core.stats.max_age = Some(max_age);
+        self
+    }
 
-        let path = self.get_path_of(handle.1);
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        file.write_all(serialized)?;
-        Ok(())
+    /// Same as [`ThreadSafeFileStore::with_clock`].
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.core = self.core.with_clock(Arc::new(clock));
+        self
     }
 
-    fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
-        let path = self.get_path_of(handle.get_key());
-        Ok(std::fs::metadata(path)?.is_file())
+    /// Same as [`ThreadSafeFileStore::with_min_free`].
+    #[must_use]
+    pub fn with_min_free(mut self, min_free: MinFree) -> Self {
+        self.core.space.min_free = Some(min_free);
+        self
     }
 
-    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
+    /// Same as [`ThreadSafeFileStore::with_on_full`].
+    #[must_use]
+    pub fn with_on_full(mut self, on_full: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.core.space.on_full = Some(Box::new(on_full));
+        self
+    }
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::XLock = unsafe { ((*value).write()?, key) };
-        drop(cache_lock);
+    /// Same as [`ThreadSafeFileStore::with_packing`], the threshold is compared with the
+    /// serialized size of entries.
+    ///
+    /// # Errors
+    /// Fails when reading the existing segments does.
+    pub fn with_packing(mut self, threshold: usize) -> Result<Self, ThreadSafeFileStoreError> {
+        self.core = self.core.with_packing(threshold)?;
+        Ok(self)
+    }
 
-        Ok(lock)
+    /// Same as [`ThreadSafeFileStore::with_extension`].
+    ///
+    /// # Errors
+    /// Fails when a fixed extension has anything but ASCII letters and digits, or when reopening
+    /// the segments does.
+    pub fn with_extension(
+        mut self,
+        extension: FileExtension,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        self.core = self.core.with_extension(extension)?;
+        Ok(self)
     }
 
-    fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
+    /// Same as [`ThreadSafeFileStore::rotate`].
+    ///
+    /// # Errors
+    /// Fails when creating the new directory or its segments does or when the store is poisoned,
+    /// the store is left on the old directory then.
+    pub fn rotate(
+        &self,
+        new_path: impl Into<PathBuf>,
+    ) -> Result<PathBuf, ThreadSafeFileStoreError> {
+        self.core.rotate(new_path.into())
+    }
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::SLock<'_> = unsafe { ((*value).read()?, key).into() };
-        drop(cache_lock);
+    /// Same as [`ThreadSafeFileStore::purge_older_than`].
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned.
+    pub fn purge_older_than(&self, max_age: Duration) -> Result<usize, ThreadSafeFileStoreError> {
+        self.core.purge_older_than(max_age)
+    }
 
-        Ok(lock)
+    /// Same as [`ThreadSafeFileStore::sync_from`].
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned, the entries before
+    /// the failing one might have been copied.
+    pub fn sync_from(&self, src: &Self) -> Result<usize, ThreadSafeFileStoreError> {
+        self.core.sync_from(&src.core)
     }
 
-    fn ts_try_xlock_nblock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
+    /// Same as [`ThreadSafeFileStore::compact`].
+    ///
+    /// # Errors
+    /// Fails when any underlying io call does or when the store is poisoned. Segments rewritten
+    /// before the failing one stay so, and no entry is lost either way.
+    pub fn compact(
+        &self,
+        mut progress: impl FnMut(CompactProgress),
+    ) -> Result<CompactProgress, ThreadSafeFileStoreError> {
+        self.core.compact(&mut progress)
+    }
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::XLock = unsafe { ((*value).try_write()?, key) };
-        drop(cache_lock);
+    /// Same as [`ThreadSafeFileStore::space_amplification`].
+    ///
+    /// # Errors
+    /// Fails when the store is poisoned.
+    pub fn space_amplification(&self) -> Result<f64, ThreadSafeFileStoreError> {
+        self.core.space_amplification()
+    }
 
-        Ok(lock)
+    /// Same as [`ThreadSafeFileStore::spawn_compactor`].
+    pub fn spawn_compactor(
+        this: &Arc<Self>,
+        spawner: &impl Spawner,
+        interval: Duration,
+        policy: CompactionPolicy,
+        on_error: impl FnMut(ThreadSafeFileStoreError) + Send + 'static,
+    ) where
+        K: Send + 'static,
+        V: Send + Sync + 'static,
+    {
+        spawn_compactor(
+            this,
+            |store| &store.core,
+            spawner,
+            interval,
+            policy,
+            on_error,
+        );
     }
+}
 
-    fn ts_try_slock_nblock(
-        &'lock self,
-        key: &'lock Self::Key,
-    ) -> Result<Self::SLock<'lock>, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
+/// Serializes an entry along with its key.
+fn encode_entry<K: Serialize, V: Serialize>(
+    key: &K,
+    value: &V,
+) -> Result<Vec<u8>, ThreadSafeFileStoreError> {
+    Ok(bincode::serialize(&(bincode::serialize(key)?, value))?)
+}
+
+/// Deserializes the value of an entry, checking it was written for `key`.
+pub(super) fn decode_entry<K: Serialize, V: DeserializeOwned>(
+    key: &K,
+    buf: &[u8],
+) -> Result<V, ThreadSafeFileStoreError> {
+    let (entry_key, value): (Vec<u8>, V) = bincode::deserialize(buf)?;
+    if entry_key != bincode::serialize(key)? {
+        return Err(ThreadSafeFileStoreError::KeyMismatch);
+    }
+    Ok(value)
+}
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::SLock<'_> = unsafe { ((*value).try_read()?, key).into() };
-        drop(cache_lock);
+impl<K: Clone + Hash + Eq + CustomHash + Serialize, V: Serialize>
+    ThreadSafeFileStoreSerializable<K, V>
+{
+    /// Times the entry of `key` was set through the store since it was opened, `0` if never.
+    /// Usable to build cache-busting URLs or to tell cheaply if an entry changed.
+    ///
+    /// # Errors
+    /// Fails when the store is poisoned.
+    pub fn entry_revision(&self, key: &K) -> Result<u64, ThreadSafeFileStoreError> {
+        self.core.revisions.get(key)
+    }
 
-        Ok(lock)
+    /// Sets many entries at once, taking the locks of all their keys in one pass instead of one
+    /// by one. Of several entries of the same key only the last one is set. Returns how many
+    /// entries were set.
+    ///
+    /// # Errors
+    /// Fails when serializing or any underlying io call does, or when the store is poisoned. The
+    /// entries before the failing one might have been set.
+    pub fn ts_try_set_many<'a>(
+        &self,
+        entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+    ) -> Result<usize, ThreadSafeFileStoreError>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key, encode_entry(key, value)?)))
+            .collect::<Result<Vec<_>, ThreadSafeFileStoreError>>()?;
+        let dir = self.core.dir();
+        self.core.space.check(&dir.path)?;
+        set_many_in(
+            &dir,
+            &self.core.cache,
+            &self.core.stats,
+            &self.core.revisions,
+            entries,
+        )
     }
 }
 
-// ---- With Serialization
+/// Syncs the files written through the store to disk, entries are always written right away.
+impl<K: CustomHash, V> Shutdown for ThreadSafeFileStoreSerializable<K, V> {
+    type Error = ThreadSafeFileStoreError;
 
-/// Thread safe store based on files with serialization
-pub struct ThreadSafeFileStoreSerializable<K, V> {
-    path: PathBuf,
-    cache: Mutex<HashMap<K, RwLock<()>>>,
-    value_phantom: PhantomData<V>,
+    fn shutdown(&mut self) -> Result<(), Self::Error> {
+        let dir = self.core.dir();
+        sync_dir(&dir, &self.core.cache)
+    }
 }
 
-impl<K: CustomHash, V> ThreadSafeFileStoreSerializable<K, V> {
-    /// Makes a new instance from a directory path
-    /// Doesn't perform any file lock, you must ensure this path isn't used by other processes
-    /// or even this one itself.
-    ///
-    /// # Errors
-    /// Fails when any underlying io call does.
-    pub fn new_on(path: impl AsRef<Path> + TryInto<PathBuf>) -> std::io::Result<Self> {
-        std::fs::create_dir_all(&path)?;
-        Ok(Self {
-            path: path.try_into().map_err(|_| {
-                std::io::Error::new(std::io::ErrorKind::Other, "error converting from path")
-            })?,
-            cache: Mutex::new(HashMap::new()),
-            value_phantom: PhantomData,
-        })
+/// Checks that the directory is still there, and warns when its filesystem is read-only or has less
+/// than a twentieth of its space available.
+impl<K: CustomHash, V> HealthCheck for ThreadSafeFileStoreSerializable<K, V> {
+    type Error = ThreadSafeFileStoreError;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        dir_health(&self.core.dir().path)
     }
+}
 
-    fn get_path_of(&self, key: &K) -> PathBuf {
-        self.path.join(key.hash())
+/// Scans the directory, so it's as slow as the amount of entries. Segments count whole, including
+/// the space of overwritten entries.
+impl<K, V> SizedStore for ThreadSafeFileStoreSerializable<K, V> {
+    fn bytes_used(&self) -> usize {
+        self.core
+            .dir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .size()
     }
 }
 
-impl<'lock, K: Clone + Hash + Eq + CustomHash, V: Clone + Serialize + DeserializeOwned>
-    ThreadSafeTryCacheStore<'lock> for ThreadSafeFileStoreSerializable<K, V>
+impl<
+        'lock,
+        K: Clone + Hash + Eq + CustomHash + Serialize,
+        V: Clone + Serialize + DeserializeOwned,
+    > ThreadSafeTryCacheStore<'lock> for ThreadSafeFileStoreSerializable<K, V>
 where
     Self: 'lock,
 {
@@ -286,15 +1819,10 @@ where
         &'lock self,
         handle: &Self::SLock<'_>,
     ) -> Result<Option<Self::Value>, Self::Error> {
-        let path = self.get_path_of(handle.get_key());
-        match File::open(path) {
-            Ok(mut fil) => {
-                let mut buf = vec![];
-                fil.read_to_end(&mut buf)?;
-                Ok(bincode::deserialize(buf.as_slice()).map(Some)?)
-            }
-            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
-            Err(error) => Err(error.into()),
+        let key = handle.get_key();
+        match self.core.load(key)? {
+            Some((buf, _)) => decode_entry(key, &buf).map(Some),
+            None => Ok(None),
         }
     }
 
@@ -303,92 +1831,134 @@ where
         handle: &mut Self::XLock,
         value: &Self::Value,
     ) -> Result<(), Self::Error> {
-        let serialized = bincode::serialize(&value)?;
-
-        let path = self.get_path_of(handle.1);
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        file.write_all(&serialized)?;
+        self.core
+            .store(handle.1, &encode_entry(handle.1, value)?, None)?;
+        self.core.stats.record(handle.1, true)?;
+        self.core.revisions.bump(handle.1)?;
         Ok(())
     }
 
+    /// An entry of another key whose [`CustomHash`] collides is left alone, failing with
+    /// [`KeyMismatch`][ThreadSafeFileStoreError::KeyMismatch].
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let Some((buf, _)) = self.core.load(handle.1)? else {
+            return Ok(None);
+        };
+        let value = decode_entry(handle.1, &buf)?;
+        remove_entry(&self.core.dir(), &CustomHash::hash(handle.1))?;
+        self.core.stats.record(handle.1, false)?;
+        self.core.revisions.bump(handle.1)?;
+        Ok(Some(value))
+    }
+
     fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
-        let path = self.get_path_of(handle.get_key());
-        Ok(std::fs::metadata(path)?.is_file())
+        let key = handle.get_key();
+        if self.core.is_packed(key)? {
+            return Ok(true);
+        }
+        self.core.stats.exists(key, &self.core.get_path_of(key)?)
     }
 
-    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
+    /// Keys without an entry that were never locked are answered without adding a lock for them.
+    fn ts_one_try_get(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let Some(lock) = existing_key_lock(&self.core.cache, key, || self.core.has_entry(key))?
+        else {
+            return Ok(None);
         };
-
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::XLock = unsafe { ((*value).write()?, key) };
-        drop(cache_lock);
-
-        Ok(lock)
+        self.ts_try_get(&(lock.read()?, key).into())
     }
 
-    fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
+    /// Keys without an entry that were never locked are answered without adding a lock for them.
+    fn ts_one_try_exists(&'lock self, key: &'lock Self::Key) -> Result<bool, Self::Error> {
+        let Some(lock) = existing_key_lock(&self.core.cache, key, || self.core.has_entry(key))?
+        else {
+            return Ok(false);
         };
+        self.ts_try_exists(&(lock.read()?, key).into())
+    }
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::SLock<'_> = unsafe { ((*value).read()?, key).into() };
-        drop(cache_lock);
+    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.write()?, key))
+    }
 
-        Ok(lock)
+    fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.read()?, key).into())
     }
 
     fn ts_try_xlock_nblock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
-
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::XLock = unsafe { ((*value).try_write()?, key) };
-        drop(cache_lock);
-
-        Ok(lock)
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.try_write()?, key))
     }
 
     fn ts_try_slock_nblock(
         &'lock self,
         key: &'lock Self::Key,
     ) -> Result<Self::SLock<'lock>, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
+        let lock = key_lock(&self.core.cache, key)?;
+        Ok((lock.try_read()?, key).into())
+    }
+}
+
+/// The age of an entry is told by the modification time of its file, and its size is the
+/// serialized one.
+impl<
+        'lock,
+        K: Clone + Hash + Eq + CustomHash + Serialize,
+        V: Clone + Serialize + DeserializeOwned,
+    > ThreadSafeTryMetaCacheStore<'lock> for ThreadSafeFileStoreSerializable<K, V>
+where
+    Self: 'lock,
+{
+    fn ts_try_get_with_meta(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error> {
+        let key = handle.get_key();
+        let Some((buf, meta)) = self.core.load(key)? else {
+            return Ok(None);
         };
+        Ok(Some((decode_entry(key, &buf)?, meta)))
+    }
+}
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::SLock<'_> = unsafe { ((*value).try_read()?, key).into() };
-        drop(cache_lock);
+/// Custom metadata is kept in its own file, next to the one of the entry, so it's read without
+/// deserializing the value.
+impl<
+        'lock,
+        K: Clone + Hash + Eq + CustomHash + Serialize,
+        V: Clone + Serialize + DeserializeOwned,
+    > ThreadSafeTryCustomMetaCacheStore<'lock> for ThreadSafeFileStoreSerializable<K, V>
+where
+    Self: 'lock,
+{
+    fn ts_try_set_with_meta(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+        meta: &CustomMeta,
+    ) -> Result<(), Self::Error> {
+        self.core
+            .store(handle.1, &encode_entry(handle.1, value)?, Some(meta))?;
+        self.core.stats.record(handle.1, true)?;
+        self.core.revisions.bump(handle.1)?;
+        Ok(())
+    }
 
-        Ok(lock)
+    fn ts_try_get_custom_meta(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<CustomMeta>, Self::Error> {
+        self.core
+            .dir()
+            .read_meta(&CustomHash::hash(handle.get_key()))
     }
 }
 
@@ -399,6 +1969,7 @@ mod tests {
     use std::println;
 
     use super::*;
+    use crate::clock::MockClock;
     use serde::{Deserialize, Serialize};
     use tempfile::tempdir;
 
@@ -512,4 +2083,545 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn reads_dont_insert() {
+        let temp_dir = tempdir().unwrap();
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path()).unwrap();
+        assert_eq!(store.ts_one_try_get(&String::from("absent")).unwrap(), None);
+        assert!(!store.ts_one_try_exists(&String::from("absent")).unwrap());
+        assert!(store.core.cache.lock().unwrap().is_empty());
+
+        // Entries written by another store on the same directory are still found
+        ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .unwrap()
+            .ts_one_try_set(&String::from("key"), &b"value".to_vec())
+            .unwrap();
+        assert_eq!(
+            store.ts_one_try_get(&String::from("key")).unwrap(),
+            Some(b"value".to_vec())
+        );
+        assert!(store.ts_one_try_exists(&String::from("key")).unwrap());
+    }
+
+    #[test]
+    fn colliding_keys_mismatch() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStoreSerializable::<String, u8>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore");
+        let (key, other) = (String::from("key"), String::from("other"));
+        store.ts_one_try_set(&key, &1).unwrap();
+
+        // Same as if both names hashed to the same file
+        std::fs::copy(
+            store.core.get_path_of(&key).unwrap(),
+            store.core.get_path_of(&other).unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            store.ts_one_try_get(&other),
+            Err(ThreadSafeFileStoreError::KeyMismatch)
+        ));
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn exists_with_stat_cache() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_stat_cache(Duration::from_hours(1));
+        let key = String::from("key");
+        assert!(!store.ts_one_try_exists(&key).unwrap());
+
+        store.ts_one_try_set(&key, &vec![1]).unwrap();
+        assert!(store.ts_one_try_exists(&key).unwrap());
+
+        // Deleted by someone else, not noticed until the cached stat is too old
+        std::fs::remove_file(store.core.get_path_of(&key).unwrap()).unwrap();
+        assert!(store.ts_one_try_exists(&key).unwrap());
+        store.purge_older_than(Duration::MAX).unwrap();
+        assert!(!store.ts_one_try_exists(&key).unwrap());
+    }
+
+    #[test]
+    fn stat_cache_staleness_is_bounded() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let clock = Arc::new(MockClock::default());
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_stat_cache(Duration::from_mins(1))
+            .with_clock(Arc::clone(&clock));
+        let key = String::from("key");
+        store.ts_one_try_set(&key, &vec![1]).unwrap();
+
+        std::fs::remove_file(store.core.get_path_of(&key).unwrap()).unwrap();
+        clock.advance(Duration::from_secs(59));
+        assert!(store.ts_one_try_exists(&key).unwrap());
+        clock.advance(Duration::from_secs(1));
+        assert!(!store.ts_one_try_exists(&key).unwrap());
+
+        // Created by someone else, noticed as late
+        std::fs::write(store.core.get_path_of(&key).unwrap(), [1]).unwrap();
+        assert!(!store.ts_one_try_exists(&key).unwrap());
+        clock.advance(Duration::from_mins(1));
+        assert!(store.ts_one_try_exists(&key).unwrap());
+    }
+
+    #[test]
+    fn set_many_keeps_last() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStoreSerializable::<String, u32>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore");
+        let keys: Vec<_> = (0..100u32).map(|i| std::format!("{i}")).collect();
+        let values: Vec<_> = (0..100).collect();
+
+        let entries = keys.iter().zip(&values).chain([(&keys[0], &values[1])]);
+        assert_eq!(store.ts_try_set_many(entries).unwrap(), 100);
+        assert_eq!(store.ts_one_try_get(&keys[0]).unwrap(), Some(1));
+        assert_eq!(store.ts_one_try_get(&keys[99]).unwrap(), Some(99));
+
+        store.ts_one_try_set(&keys[0], &2).unwrap();
+        assert_eq!(store.entry_revision(&keys[0]).unwrap(), 2);
+        assert_eq!(store.entry_revision(&keys[99]).unwrap(), 1);
+    }
+
+    #[test]
+    fn set_many_waits_on_handles_without_blocking_others() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore");
+        let (a, b, c) = (String::from("a"), String::from("b"), String::from("c"));
+
+        let handle = store.ts_try_xlock(&a).unwrap();
+        let (started, has_started) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            let setter = scope.spawn(|| {
+                started.send(()).unwrap();
+                let entries = [(&a, &vec![1]), (&b, &vec![2])];
+                store.ts_try_set_many(entries).unwrap()
+            });
+            has_started.recv().unwrap();
+            // While it waits on `a`, other keys can still be locked
+            store.ts_one_try_set(&c, &vec![3]).unwrap();
+            // `b` may be locked by it, so look at its file rather than through the store
+            assert!(!store.core.get_path_of(&b).unwrap().exists());
+            assert!(!setter.is_finished());
+            drop(handle);
+            assert_eq!(setter.join().unwrap(), 2);
+        });
+        assert_eq!(store.ts_one_try_get(&a).unwrap(), Some(vec![1]));
+        assert_eq!(store.ts_one_try_get(&b).unwrap(), Some(vec![2]));
+        assert_eq!(store.ts_one_try_get(&c).unwrap(), Some(vec![3]));
+    }
+
+    #[test]
+    fn packs_small_entries() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let open = || {
+            ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+                .expect("Failed to create ThreadSafeFileStore")
+                .with_packing(64)
+                .expect("Failed to open the segments")
+        };
+        let store = open();
+        let (small, large) = (String::from("small"), String::from("large"));
+        let entries = [(&small, &vec![1; 8]), (&large, &vec![2; 128])];
+        assert_eq!(store.ts_try_set_many(entries).unwrap(), 2);
+
+        assert!(!store.core.get_path_of(&small).unwrap().exists());
+        assert!(store.core.get_path_of(&large).unwrap().exists());
+        assert!(store.ts_one_try_exists(&small).unwrap());
+
+        // The index is rebuilt from the segments
+        let store = open();
+        assert_eq!(store.ts_one_try_get(&small).unwrap(), Some(vec![1; 8]));
+
+        // Outgrowing the threshold moves the entry to its own file
+        store.ts_one_try_set(&small, &vec![3; 128]).unwrap();
+        let store = open();
+        assert!(store.core.get_path_of(&small).unwrap().exists());
+        assert_eq!(store.ts_one_try_get(&small).unwrap(), Some(vec![3; 128]));
+    }
+
+    #[test]
+    fn remove_packed_and_unpacked() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let open = || {
+            ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+                .expect("Failed to create ThreadSafeFileStore")
+                .with_packing(64)
+                .expect("Failed to open the segments")
+        };
+        let store = open();
+        let (small, large) = (String::from("small"), String::from("large"));
+        let entries = [(&small, &vec![1; 8]), (&large, &vec![2; 128])];
+        assert_eq!(store.ts_try_set_many(entries).unwrap(), 2);
+
+        assert_eq!(store.ts_one_try_remove(&small).unwrap(), Some(vec![1; 8]));
+        assert_eq!(store.ts_one_try_remove(&large).unwrap(), Some(vec![2; 128]));
+        assert!(!store.core.get_path_of(&large).unwrap().exists());
+        assert_eq!(store.ts_one_try_remove(&large).unwrap(), None);
+
+        // Removed from the segments too
+        let store = open();
+        assert!(!store.ts_one_try_exists(&small).unwrap());
+    }
+
+    #[test]
+    fn file_get_with_meta() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore");
+
+        let key = String::from("key");
+        store
+            .ts_one_try_set(&key, &vec![0; 16])
+            .expect("to not fail");
+
+        let (value, meta) = store
+            .ts_one_try_get_with_meta(&key)
+            .expect("to not fail")
+            .expect("Value not found");
+        assert_eq!(value, vec![0; 16]);
+        assert_eq!(meta.size, Some(16));
+        assert!(meta.age.is_some());
+    }
+
+    #[test]
+    fn custom_meta_roundtrip() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let open = || {
+            ThreadSafeFileStoreSerializable::<String, u32>::new_on(temp_dir.path())
+                .expect("Failed to create ThreadSafeFileStore")
+        };
+        let store = open();
+        let key = String::from("key");
+        let meta = CustomMeta::from([
+            (String::from("type"), "text/plain".into()),
+            (String::from("etag"), b"\x01\x02".as_slice().into()),
+        ]);
+        store.ts_one_try_set_with_meta(&key, &1, &meta).unwrap();
+
+        let store = open();
+        assert_eq!(store.ts_one_try_get_custom_meta(&key).unwrap(), Some(meta));
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(1));
+
+        // Setting it plainly drops the metadata
+        store.ts_one_try_set(&key, &2).unwrap();
+        assert_eq!(store.ts_one_try_get_custom_meta(&key).unwrap(), None);
+    }
+
+    #[test]
+    fn extension_from_meta() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_extension(FileExtension::FromMeta(String::from("content-type")))
+            .expect("Failed to set the extension");
+        let key = String::from("image");
+        let meta = CustomMeta::from([(String::from("content-type"), "image/svg+xml".into())]);
+        store
+            .ts_one_try_set_with_meta(&key, &vec![1], &meta)
+            .unwrap();
+
+        let path = store.core.get_path_of(&key).unwrap();
+        assert_eq!(path.extension().unwrap(), "svg");
+        assert!(path.exists());
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(vec![1]));
+
+        // Without metadata the file loses the extension
+        store.ts_one_try_set(&key, &vec![2]).unwrap();
+        assert!(!path.exists());
+        assert_eq!(store.core.get_path_of(&key).unwrap().extension(), None);
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(vec![2]));
+
+        assert_eq!(extension_of("text/plain; charset=utf-8"), Some("txt"));
+        assert_eq!(extension_of("application/x-tar"), Some("tar"));
+        assert_eq!(extension_of("../../etc"), None);
+    }
+
+    #[test]
+    fn adopt_external_file() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path().join("store"))
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_packing(64)
+            .expect("Failed to open the segments");
+        let key = String::from("artifact");
+        store.ts_one_try_set(&key, &vec![1; 8]).unwrap();
+
+        let artifact = temp_dir.path().join("artifact.bin");
+        std::fs::write(&artifact, [2; 8]).unwrap();
+        store.adopt(&key, &artifact).unwrap();
+
+        assert!(!artifact.exists());
+        assert!(!store.core.is_packed(&key).unwrap());
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(vec![2; 8]));
+        assert_eq!(store.entry_revision(&key).unwrap(), 2);
+    }
+
+    #[test]
+    fn export_is_kept_on_set() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path().join("store"))
+            .expect("Failed to create ThreadSafeFileStore");
+        let key = String::from("artifact");
+        let dest = temp_dir.path().join("artifact.bin");
+        assert!(!store.export_entry(&key, &dest).unwrap());
+
+        store.ts_one_try_set(&key, &vec![1; 8]).unwrap();
+        assert!(store.export_entry(&key, &dest).unwrap());
+        assert!(store.export_entry(&key, &dest).is_err());
+
+        store.ts_one_try_set(&key, &vec![2; 8]).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), vec![1; 8]);
+    }
+
+    #[test]
+    fn sync_copies_newer() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let open = |name: &str| {
+            ThreadSafeFileStoreSerializable::<String, u32>::new_on(temp_dir.path().join(name))
+                .expect("Failed to create ThreadSafeFileStore")
+        };
+        let (local, share) = (open("local"), open("share"));
+        let (a, b) = (String::from("a"), String::from("b"));
+        share.ts_one_try_set(&b, &0).unwrap();
+        File::options()
+            .write(true)
+            .open(share.core.get_path_of(&b).unwrap())
+            .and_then(|file| file.set_modified(std::time::UNIX_EPOCH))
+            .unwrap();
+        local.ts_one_try_set(&a, &1).unwrap();
+        local.ts_one_try_set(&b, &2).unwrap();
+
+        assert_eq!(share.sync_from(&local).unwrap(), 2);
+        assert_eq!(share.ts_one_try_get(&a).unwrap(), Some(1));
+        assert_eq!(share.ts_one_try_get(&b).unwrap(), Some(2));
+        let modified = |store: &ThreadSafeFileStoreSerializable<String, u32>| {
+            std::fs::metadata(store.core.get_path_of(&a).unwrap())
+                .and_then(|metadata| metadata.modified())
+                .unwrap()
+        };
+        assert_eq!(modified(&share), modified(&local));
+
+        // Nothing newer left
+        assert_eq!(share.sync_from(&local).unwrap(), 0);
+        assert_eq!(local.sync_from(&share).unwrap(), 0);
+    }
+
+    #[test]
+    fn rotate_to_fresh_dir() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path().join("a"))
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_packing(64)
+            .expect("Failed to open the segments");
+        let key = String::from("key");
+        store.ts_one_try_set(&key, &vec![1; 8]).unwrap();
+
+        let old = store.rotate(temp_dir.path().join("b")).unwrap();
+        assert_eq!(old, temp_dir.path().join("a"));
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), None);
+
+        store.ts_one_try_set(&key, &vec![2; 8]).unwrap();
+        std::fs::remove_dir_all(old).unwrap();
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(vec![2; 8]));
+    }
+
+    #[test]
+    fn file_bytes_used() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path().to_path_buf())
+            .expect("Failed to create ThreadSafeFileStore");
+        assert_eq!(store.bytes_used(), 0);
+
+        store
+            .ts_one_try_set(&String::from("key"), &vec![0; 10])
+            .expect("to not fail");
+        assert_eq!(store.bytes_used(), 10);
+    }
+
+    #[test]
+    fn purge_skips_fresh_and_locked() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore");
+        let (old, locked) = (String::from("old"), String::from("locked"));
+        store.ts_one_try_set(&old, &vec![0]).expect("to not fail");
+        store
+            .ts_one_try_set(&locked, &vec![1])
+            .expect("to not fail");
+
+        assert_eq!(store.purge_older_than(Duration::from_mins(1)).unwrap(), 0);
+
+        std::thread::sleep(Duration::from_millis(20));
+        let handle = store.ts_try_slock(&locked).expect("to not fail");
+        assert_eq!(store.purge_older_than(Duration::ZERO).unwrap(), 1);
+        assert!(store.ts_try_exists(&handle).expect("to not fail"));
+        drop(handle);
+
+        assert_eq!(store.ts_one_try_get(&old).expect("to not fail"), None);
+        assert_eq!(store.purge_older_than(Duration::ZERO).unwrap(), 1);
+    }
+
+    #[test]
+    fn ping_fails_without_dir() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path().join("a"))
+            .expect("Failed to create ThreadSafeFileStore");
+        assert!(store.ping().is_ok());
+
+        std::fs::remove_dir_all(temp_dir.path().join("a")).unwrap();
+        assert!(matches!(
+            store.ping(),
+            Err(ThreadSafeFileStoreError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn writes_refused_when_full() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let refused = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&refused);
+        let store = ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+            .expect("Failed to create ThreadSafeFileStore")
+            .with_min_free(MinFree::Bytes(u64::MAX))
+            .with_on_full(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+
+        let key = String::from("key");
+        assert!(matches!(
+            store.ts_one_try_set(&key, &vec![1]),
+            Err(ThreadSafeFileStoreError::StoreFull { .. })
+        ));
+        assert_eq!(refused.load(Ordering::Relaxed), 1);
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), None);
+    }
+
+    #[test]
+    fn compaction_keeps_live_entries() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let open = || {
+            ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+                .expect("Failed to create ThreadSafeFileStore")
+                .with_packing(64)
+                .expect("Failed to open the segments")
+        };
+        let store = open();
+        let (kept, moved) = (String::from("kept"), String::from("moved"));
+        for i in 0..4 {
+            store.ts_one_try_set(&kept, &vec![i; 8]).unwrap();
+        }
+        store.ts_one_try_set(&moved, &vec![1; 8]).unwrap();
+        store.ts_one_try_set(&moved, &vec![2; 128]).unwrap();
+        assert!(store.space_amplification().unwrap() > 1.0);
+
+        let mut reports = 0;
+        let done = store.compact(|_| reports += 1).unwrap();
+        assert_eq!((reports, done.segments_done), (1, 1));
+        assert!(done.bytes_freed > 0);
+        assert!(store.space_amplification().unwrap() <= 1.0);
+        assert_eq!(store.ts_one_try_get(&kept).unwrap(), Some(vec![3; 8]));
+
+        // Dropping the record of the move doesn't bring back the packed value
+        let store = Arc::new(open());
+        assert_eq!(store.ts_one_try_get(&kept).unwrap(), Some(vec![3; 8]));
+        assert_eq!(store.ts_one_try_get(&moved).unwrap(), Some(vec![2; 128]));
+
+        store.ts_one_try_set(&kept, &vec![4; 8]).unwrap();
+        let spawner = crate::spawn::ManualSpawner::new();
+        let policy = CompactionPolicy {
+            min_idle: Duration::ZERO,
+            ..CompactionPolicy::default()
+        };
+        ThreadSafeFileStore::spawn_compactor(
+            &store,
+            &spawner,
+            Duration::from_secs(1),
+            policy,
+            |error| panic!("compaction failed: {error}"),
+        );
+        spawner.tick();
+        assert!(store.space_amplification().unwrap() <= 1.0);
+        assert_eq!(store.ts_one_try_get(&kept).unwrap(), Some(vec![4; 8]));
+    }
+
+    #[test]
+    fn compactor_waits_until_idle() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let clock = Arc::new(MockClock::default());
+        let store = Arc::new(
+            ThreadSafeFileStore::<String, Vec<u8>>::new_on(temp_dir.path())
+                .expect("Failed to create ThreadSafeFileStore")
+                .with_packing(64)
+                .expect("Failed to open the segments")
+                .with_clock(Arc::clone(&clock)),
+        );
+        let key = String::from("key");
+        for i in 0..4 {
+            store.ts_one_try_set(&key, &vec![i; 8]).unwrap();
+        }
+
+        let spawner = crate::spawn::ManualSpawner::new();
+        ThreadSafeFileStore::spawn_compactor(
+            &store,
+            &spawner,
+            Duration::from_secs(1),
+            CompactionPolicy::default(),
+            |error| panic!("compaction failed: {error}"),
+        );
+        clock.advance(Duration::from_secs(59));
+        spawner.tick();
+        assert!(store.space_amplification().unwrap() > 1.0);
+
+        // Reads count as use too
+        assert_eq!(store.ts_one_try_get(&key).unwrap(), Some(vec![3; 8]));
+        clock.advance(Duration::from_secs(59));
+        spawner.tick();
+        assert!(store.space_amplification().unwrap() > 1.0);
+
+        clock.advance(Duration::from_secs(1));
+        spawner.tick();
+        assert!(store.space_amplification().unwrap() <= 1.0);
+    }
+
+    #[test]
+    fn compactor_reports_errors() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let store = Arc::new(
+            ThreadSafeFileStoreSerializable::<String, u32>::new_on(temp_dir.path())
+                .expect("Failed to create ThreadSafeFileStoreSerializable")
+                .with_packing(64)
+                .expect("Failed to open the segments"),
+        );
+        let key = String::from("key");
+        for i in 0..4 {
+            store.ts_one_try_set(&key, &i).unwrap();
+        }
+        std::fs::remove_dir_all(temp_dir.path().join(".packed")).unwrap();
+
+        let errors = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&errors);
+        let spawner = crate::spawn::ManualSpawner::new();
+        let policy = CompactionPolicy {
+            min_idle: Duration::ZERO,
+            ..CompactionPolicy::default()
+        };
+        ThreadSafeFileStoreSerializable::spawn_compactor(
+            &store,
+            &spawner,
+            Duration::from_secs(1),
+            policy,
+            move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            },
+        );
+        spawner.tick();
+        spawner.tick();
+        assert_eq!(errors.load(Ordering::Relaxed), 2);
+    }
 }
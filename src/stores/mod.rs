@@ -2,12 +2,28 @@
 //! - [`MemoryStore`]: So just [`HashMap`] cool wrapping around. You'll see it most for examples.
 //! - [`ThreadSafeMemoryStore`]: Concurrent store in memory. Uses unsafe under the hood but should
 //!   be optimized enough.
+//! - [`WindowedMemoryStore`][windowed::WindowedMemoryStore]: Groups entries into time windows,
+//!   dropping whole windows once they are too old.
+//!
+//! With feature "arc-swap":
+//! - [`ReadMostlyStore`][read_mostly::ReadMostlyStore]: Store in memory with lock-free reads and
+//!   copy-on-write updates, for data that rarely changes.
+//!
+//! With feature "dashmap":
+//! - [`DashMemoryStore`][dash::DashMemoryStore]: Concurrent store in memory with a sharded map
+//!   and no unsafe code, for workloads with many threads.
+//!
+//! With feature "smallvec":
+//! - [`InlineBytes`]: Bytes kept inline up to a size, as values of memory stores of many tiny
+//!   entries that would otherwise take a heap allocation each.
 //!
 //! With feature "file-stores":
 //! - [`ThreadSafeFileStore`][file_stores::ThreadSafeFileStore]: A thread safe cache stores that
 //!   works over files in a directory.
 //! - [`ThreadSafeFileStoreSerializable`][file_stores::ThreadSafeFileStoreSerializable]: Same as
 //!   [`ThreadSafeFileStore`][file_stores::ThreadSafeFileStore] BUT it serializes structs.
+//! - [`PersistentMemoryStore`][persistent::PersistentMemoryStore]: A [`MemoryStore`] that
+//!   snapshots itself to a file every now and then, and loads it back on startup.
 //!
 //! # Examples
 //!
@@ -68,26 +84,113 @@
 //! ```
 
 // ------- File Store
+#[cfg(feature = "dashmap")]
+pub mod dash;
+#[cfg(feature = "file-stores")]
+pub mod embedded;
 #[cfg(feature = "file-stores")]
 pub mod file_stores;
+#[cfg(feature = "file-stores")]
+pub mod persistent;
+#[cfg(feature = "arc-swap")]
+pub mod read_mostly;
+#[cfg(feature = "file-stores")]
+mod segments;
+pub mod windowed;
 
-use crate::__internal_prelude::*;
+use crate::{
+    __internal_prelude::*,
+    lease::RevisionedStore,
+    pattern::KeyPattern,
+    size::{MemSize, SizedStore},
+    CacheStoreRef,
+};
 
+#[cfg(feature = "thread-safe")]
+use crate::sync::RwLock;
 #[cfg(feature = "thread-safe")]
 use crate::thread_safe::dumb_wrappers::EmptyDumbError;
+
+use crate::sync::{RwLockReadGuard, RwLockWriteGuard};
+use core::{borrow::Borrow, fmt::Debug, hash::Hash, ops::Deref};
+use std::collections::{hash_map, HashMap};
 #[cfg(feature = "thread-safe")]
-use std::sync::{Mutex, RwLock};
+use std::{boxed::Box, sync::PoisonError};
 
-use core::{borrow::Borrow, hash::Hash, ops::Deref};
-use std::{
-    collections::HashMap,
-    sync::{RwLockReadGuard, RwLockWriteGuard},
-};
+/// Bytes kept inline up to `N` of them, and on the heap past that. As values of a memory store of
+/// many tiny entries it saves an allocation per entry, and the fragmentation that comes with them.
+///
+/// ```rust
+/// # use ezcache::{CacheStore, size::SizedStore, stores::{InlineBytes, MemoryStore}};
+/// #
+/// let mut store: MemoryStore<u32, InlineBytes<24>> = MemoryStore::new();
+/// store.set(0, InlineBytes::from_slice(b"tiny"));
+/// assert!(!store.get(0).unwrap().spilled());
+/// ```
+#[cfg(feature = "smallvec")]
+pub type InlineBytes<const N: usize> = smallvec::SmallVec<[u8; N]>;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        transparent,
+        bound(deserialize = "K: serde::Deserialize<'de> + Hash + Eq, V: serde::Deserialize<'de>")
+    )
+)]
 /// Simple thread unsafe in memory cache store.
+///
+/// With the "serde" feature it (de)serializes as a map of its entries.
 pub struct MemoryStore<K, V> {
     cache: HashMap<K, V>,
+    /// Revisions of the entries, only if [tracked][MemoryStore::with_revisions].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    revisions: Option<Revisions<K>>,
+}
+
+/// Revisions of the entries of a [`MemoryStore`]. They're taken from a counter of the whole store,
+/// so an entry removed and set again never gets back a revision it had.
+#[derive(Clone)]
+struct Revisions<K> {
+    last: u64,
+    of: HashMap<K, u64>,
+}
+
+impl<K: Hash + Eq, V: PartialEq> PartialEq for MemoryStore<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cache == other.cache
+    }
+}
+impl<K: Hash + Eq, V: Eq> Eq for MemoryStore<K, V> {}
+
+/// Only shows the entries, not their revisions.
+#[allow(clippy::missing_fields_in_debug)]
+impl<K: Debug, V: Debug> Debug for MemoryStore<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+/// [`Debug`] representation of a store that only shows its keys, to log stores with sensitive
+/// values.
+pub struct Redacted<'a, K, V>(&'a HashMap<K, V>);
+
+impl<K: Debug, V> Debug for Redacted<'_, K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        struct Hidden;
+        impl Debug for Hidden {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str("<redacted>")
+            }
+        }
+
+        f.debug_map()
+            .entries(self.0.keys().map(|key| (key, Hidden)))
+            .finish()
+    }
 }
 
 impl<K, V> MemoryStore<K, V> {
@@ -95,12 +198,185 @@ impl<K, V> MemoryStore<K, V> {
     pub fn new() -> Self {
         Self {
             cache: HashMap::default(),
+            revisions: None,
         }
     }
 
     #[must_use]
     pub fn from_hashmap(hashmap: HashMap<K, V>) -> Self {
-        Self { cache: hashmap }
+        Self {
+            cache: hashmap,
+            revisions: None,
+        }
+    }
+
+    /// [`Debug`] representation that hides the values.
+    #[must_use]
+    pub fn redacted(&self) -> Redacted<'_, K, V> {
+        Redacted(&self.cache)
+    }
+
+    /// Iterator over references to all entries.
+    #[must_use]
+    pub fn iter(&self) -> hash_map::Iter<'_, K, V> {
+        self.cache.iter()
+    }
+
+    /// Removes all entries, returning them as owned pairs.
+    pub fn drain(&mut self) -> hash_map::Drain<'_, K, V> {
+        if let Some(revisions) = &mut self.revisions {
+            revisions.of.clear();
+        }
+        self.cache.drain()
+    }
+
+    /// Amount of entries the store can hold without reallocating.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+}
+
+impl<K: Hash + Eq, V> MemoryStore<K, V> {
+    /// Makes a new empty store with room for at least `capacity` entries.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            cache: HashMap::with_capacity(capacity),
+            revisions: None,
+        }
+    }
+
+    /// Makes room for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.cache.reserve(additional);
+    }
+
+    /// Tracks the [revisions][Self::entry_revision] of the entries from now on. They cost a key
+    /// clone and insert on every set, and count towards [`bytes_used`][SizedStore::bytes_used].
+    #[must_use]
+    pub fn with_revisions(mut self) -> Self {
+        self.track_revisions();
+        self
+    }
+
+    /// Same as [`with_revisions`][Self::with_revisions], for a store already in use.
+    pub fn track_revisions(&mut self) {
+        self.revisions.get_or_insert_with(|| Revisions {
+            last: 0,
+            of: HashMap::new(),
+        });
+    }
+
+    /// Revision of the entry of `key`, changed every time it's set or could've been changed
+    /// through [`get_mut`][CacheStoreMut::get_mut]. It's `0` if the entry is missing, was there
+    /// before revisions were tracked, or they aren't. A removed entry set again never gets back a
+    /// revision it had.
+    pub fn entry_revision(&self, key: &K) -> u64 {
+        self.revisions
+            .as_ref()
+            .and_then(|revisions| revisions.of.get(key).copied())
+            .unwrap_or(0)
+    }
+
+    /// Gives `key` a new revision, if they're tracked.
+    fn bump_revision(&mut self, key: &K)
+    where
+        K: Clone,
+    {
+        let Some(revisions) = &mut self.revisions else {
+            return;
+        };
+        revisions.last += 1;
+        if let Some(revision) = revisions.of.get_mut(key) {
+            *revision = revisions.last;
+        } else {
+            revisions.of.insert(key.clone(), revisions.last);
+        }
+    }
+
+    /// Drops the revision of a removed entry.
+    fn forget_revision(&mut self, key: &K) {
+        if let Some(revisions) = &mut self.revisions {
+            revisions.of.remove(key);
+        }
+    }
+
+    /// Removes the entries whose key matches a [pattern][crate::pattern], returning how many.
+    pub fn remove_matching(&mut self, pattern: &impl KeyPattern) -> usize
+    where
+        K: AsRef<str>,
+    {
+        let len = self.cache.len();
+        self.cache.retain(|key, _| !pattern.matches(key.as_ref()));
+        if let Some(revisions) = &mut self.revisions {
+            revisions.of.retain(|key, _| !pattern.matches(key.as_ref()));
+        }
+        len - self.cache.len()
+    }
+
+    /// Gives back as much memory as possible, like after draining a big store.
+    pub fn shrink_to_fit(&mut self) {
+        self.cache.shrink_to_fit();
+        if let Some(revisions) = &mut self.revisions {
+            revisions.of.shrink_to_fit();
+        }
+    }
+}
+
+impl<K, V> From<HashMap<K, V>> for MemoryStore<K, V> {
+    fn from(value: HashMap<K, V>) -> Self {
+        Self::from_hashmap(value)
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for MemoryStore<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Self::from_hashmap(iter.into_iter().collect())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Extend<(K, V)> for MemoryStore<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.bump_revision(&key);
+            self.cache.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> IntoIterator for MemoryStore<K, V> {
+    type Item = (K, V);
+    type IntoIter = hash_map::IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cache.into_iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a MemoryStore<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = hash_map::Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Tracked revisions count as their key and a `u64` each.
+impl<K: MemSize, V: MemSize> SizedStore for MemoryStore<K, V> {
+    fn bytes_used(&self) -> usize {
+        let revisions = self.revisions.as_ref().map_or(0, |revisions| {
+            revisions
+                .of
+                .keys()
+                .map(|key| key.mem_size() + core::mem::size_of::<u64>())
+                .sum()
+        });
+        self.iter()
+            .map(|(key, value)| key.mem_size() + value.mem_size())
+            .sum::<usize>()
+            + revisions
     }
 }
 
@@ -113,13 +389,92 @@ impl<K: Hash + Eq + Sized + Clone, V: Clone> CacheStore for MemoryStore<K, V> {
     }
 
     fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>) {
-        self.cache
-            .insert(key.borrow().clone(), value.borrow().clone());
+        let key = key.borrow();
+        self.bump_revision(key);
+        self.cache.insert(key.clone(), value.borrow().clone());
+    }
+
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let key = key.borrow();
+        let old = self.cache.remove(key)?;
+        self.forget_revision(key);
+        Some(old)
     }
 
     fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
         self.cache.contains_key(key.borrow())
     }
+
+    fn replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Option<Self::Value> {
+        let key = key.borrow();
+        self.bump_revision(key);
+        self.cache.insert(key.clone(), value.borrow().clone())
+    }
+
+    fn set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> bool {
+        let key = key.borrow();
+        match self.cache.entry(key.clone()) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(value.borrow().clone());
+                self.bump_revision(key);
+                true
+            }
+        }
+    }
+
+    fn replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> bool {
+        let key = key.borrow();
+        let Some(old) = self.cache.get_mut(key) else {
+            return false;
+        };
+        *old = value.borrow().clone();
+        self.bump_revision(key);
+        true
+    }
+}
+
+impl<K: Hash + Eq + Sized + Clone, V: Clone> RevisionedStore for MemoryStore<K, V> {
+    fn track_revisions(&mut self) {
+        Self::track_revisions(self);
+    }
+
+    fn entry_revision(&self, key: &K) -> u64 {
+        Self::entry_revision(self, key)
+    }
+}
+
+impl<K: Hash + Eq + Sized + Clone, V: Clone> CacheStoreRef for MemoryStore<K, V> {
+    type ValueRef<'a>
+        = &'a V
+    where
+        Self: 'a;
+
+    fn get_ref(&self, key: impl Borrow<Self::Key>) -> Option<Self::ValueRef<'_>> {
+        self.cache.get(key.borrow())
+    }
+}
+
+impl<K: Hash + Eq + Sized + Clone, V: Clone> CacheStoreMut for MemoryStore<K, V> {
+    fn get_mut(&mut self, key: impl Borrow<Self::Key>) -> Option<&mut Self::Value> {
+        let key = key.borrow();
+        if self.cache.contains_key(key) {
+            self.bump_revision(key);
+        }
+        self.cache.get_mut(key)
+    }
 }
 
 /// Wrapper around a [`RwLockReadGuard`] and a [`RwLockWriteGuard`] to allow any to be used.
@@ -158,12 +513,22 @@ impl<T> Deref for RwLockAnyGuard<'_, '_, T> {
 /// reviewed the unsafe usage and the safe code to do this would be too complex for me.
 ///
 /// All unsafe usage is mainly to detach inner locks from the hashmap lock itself tho, so as long
-/// as the hashmap itself doesn't move the value or the entry gets deleted, nothing should happen,
-/// and I think both can't happen at least now.
+/// as the hashmap itself doesn't move the value or the entry gets deleted, nothing should happen.
+/// Entries are boxed so the map growing doesn't move them, and they are only deleted through
+/// methods that take the store mutably.
+///
+/// Locking a key that's already in the store only takes the map lock shared, so reads of existing
+/// keys don't wait on each other, only the first lock of a new key takes it exclusively.
+///
+/// Reads aren't lock-free though, they still touch the counter of the shared lock. Swapping in
+/// copies of the map instead would make every new key copy all the others, and couldn't be model
+/// checked with loom. For data that's read far more than it's written,
+/// [`ReadMostlyStore`][read_mostly::ReadMostlyStore] has lock-free reads, the `multi_thread`
+/// benchmarks compare both.
 #[derive(Default)]
 #[cfg(feature = "thread-safe")]
 pub struct ThreadSafeMemoryStore<K, V> {
-    cache: Mutex<HashMap<K, RwLock<Option<V>>>>,
+    cache: RwLock<HashMap<K, Box<RwLock<Option<V>>>>>,
 }
 
 #[cfg(feature = "thread-safe")]
@@ -171,14 +536,133 @@ impl<K: Hash + Eq, V> ThreadSafeMemoryStore<K, V> {
     #[must_use]
     pub fn new(cache: HashMap<K, V>) -> Self {
         Self {
-            cache: Mutex::new(
+            cache: RwLock::new(
                 cache
                     .into_iter()
-                    .map(|(k, v)| (k, RwLock::new(Some(v))))
+                    .map(|(k, v)| (k, Box::new(RwLock::new(Some(v)))))
                     .collect(),
             ),
         }
     }
+
+    /// Removes all entries, returning them as owned pairs. Taking it mutably guarantees no key
+    /// is locked meanwhile.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain()
+            .filter_map(unlock_entry)
+    }
+
+    /// Amount of entries the store can hold without reallocating.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .capacity()
+    }
+
+    /// Makes room for at least `additional` more entries. Taking it mutably guarantees no key is
+    /// locked while entries move.
+    pub fn reserve(&mut self, additional: usize) {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .reserve(additional);
+    }
+
+    /// Gives back as much memory as possible, like after draining a big store. Taking it mutably
+    /// guarantees no key is locked while entries move.
+    pub fn shrink_to_fit(&mut self) {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .shrink_to_fit();
+    }
+}
+
+#[cfg(feature = "thread-safe")]
+impl<K: Hash + Eq + Clone, V> ThreadSafeMemoryStore<K, V> {
+    /// Lock of the entry of `key`, [`None`] if it was never locked.
+    fn existing_entry(&self, key: &K) -> Result<Option<&RwLock<Option<V>>>, EmptyDumbError> {
+        // Detach the lock itself from the HashMap guard lifetime
+        let entry = self
+            .cache
+            .read()?
+            .get(key)
+            .map(|entry| -> *const RwLock<Option<V>> { &raw const **entry });
+        Ok(entry.map(|entry| unsafe { &*entry }))
+    }
+
+    /// Lock of the entry of `key`, inserted if it isn't there yet. The key is only cloned then.
+    fn entry(&self, key: &K) -> Result<&RwLock<Option<V>>, EmptyDumbError> {
+        if let Some(entry) = self.existing_entry(key)? {
+            return Ok(entry);
+        }
+
+        let mut cache = self.cache.write()?;
+        // Another thread might have inserted it meanwhile
+        if !cache.contains_key(key) {
+            cache.insert(key.clone(), Box::default());
+        }
+        let entry: *const RwLock<Option<V>> = &raw const *cache[key];
+        Ok(unsafe { &*entry })
+    }
+}
+
+/// Takes the value out of an entry of a [`ThreadSafeMemoryStore`], if it has any. Poisoned locks
+/// are still read, as the poisoning thread can't be holding them anymore.
+#[cfg(feature = "thread-safe")]
+fn unlock_entry<K, V>((key, lock): (K, Box<RwLock<Option<V>>>)) -> Option<(K, V)> {
+    let value = lock.into_inner().unwrap_or_else(PoisonError::into_inner)?;
+    Some((key, value))
+}
+
+#[cfg(feature = "thread-safe")]
+impl<K: Hash + Eq, V> From<HashMap<K, V>> for ThreadSafeMemoryStore<K, V> {
+    fn from(value: HashMap<K, V>) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(feature = "thread-safe")]
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for ThreadSafeMemoryStore<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+/// Taking it mutably guarantees no key is locked meanwhile.
+#[cfg(feature = "thread-safe")]
+impl<K: Hash + Eq, V> Extend<(K, V)> for ThreadSafeMemoryStore<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(
+                iter.into_iter()
+                    .map(|(k, v)| (k, Box::new(RwLock::new(Some(v))))),
+            );
+    }
+}
+
+#[cfg(feature = "thread-safe")]
+impl<K, V> IntoIterator for ThreadSafeMemoryStore<K, V> {
+    type Item = (K, V);
+    type IntoIter = core::iter::FilterMap<
+        hash_map::IntoIter<K, Box<RwLock<Option<V>>>>,
+        fn((K, Box<RwLock<Option<V>>>)) -> Option<(K, V)>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cache
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .into_iter()
+            .filter_map(unlock_entry)
+    }
 }
 
 #[cfg(feature = "thread-safe")]
@@ -212,58 +696,56 @@ where
         Ok(())
     }
 
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(handle.take())
+    }
+
     fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
         Ok((*handle).is_some())
     }
 
-    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
+    /// Keys never locked before are answered without adding them to the store.
+    fn ts_one_try_get(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let Some(entry) = self.existing_entry(key)? else {
+            return Ok(None);
         };
+        Ok(entry.read()?.clone())
+    }
 
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::XLock = unsafe { (*value).write()? };
-        drop(cache_lock);
+    /// Keys never locked before are answered without adding them to the store.
+    fn ts_one_try_exists(&'lock self, key: &'lock Self::Key) -> Result<bool, Self::Error> {
+        let Some(entry) = self.existing_entry(key)? else {
+            return Ok(false);
+        };
+        Ok(entry.read()?.is_some())
+    }
 
+    fn ts_try_replace(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(handle.replace(value.clone()))
+    }
+
+    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        let lock: Self::XLock = self.entry(key)?.write()?;
         Ok(lock)
     }
 
     fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
-
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::SLock<'_> = unsafe { (*value).read()?.into() };
-        drop(cache_lock);
-
+        let lock: Self::SLock<'_> = self.entry(key)?.read()?.into();
         Ok(lock)
     }
 
     fn ts_try_xlock_nblock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
-
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::XLock = unsafe { (*value).try_write()? };
-        drop(cache_lock);
-
+        let lock: Self::XLock = self.entry(key)?.try_write()?;
         Ok(lock)
     }
 
@@ -271,26 +753,185 @@ where
         &'lock self,
         key: &'lock Self::Key,
     ) -> Result<Self::SLock<'lock>, Self::Error> {
-        let mut cache_lock = self.cache.lock()?;
-        let value = if let Some(thing) = cache_lock.get(key) {
-            thing
-        } else {
-            cache_lock.insert(key.clone(), RwLock::default());
-            cache_lock.get(key).unwrap()
-        };
-
-        // Detach the lock itself from the HashMap guard lifetime
-        let value: *const _ = value;
-        let lock: Self::SLock<'_> = unsafe { (*value).try_read()?.into() };
-        drop(cache_lock);
-
+        let lock: Self::SLock<'_> = self.entry(key)?.try_read()?.into();
         Ok(lock)
     }
 }
 
+#[cfg(feature = "thread-safe")]
+impl<'lock, K: Hash + Eq + Sized + Clone, V: Clone> ThreadSafeTryCacheStoreMut<'lock>
+    for ThreadSafeMemoryStore<K, V>
+where
+    Self: 'lock,
+{
+    fn ts_try_update(
+        &'lock self,
+        handle: &mut Self::XLock,
+        f: impl FnOnce(&mut Self::Value),
+    ) -> Result<bool, Self::Error> {
+        Ok(handle.as_mut().map(f).is_some())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{ThreadSafeMemoryStore, ThreadSafeTryCacheStore};
+    use super::{
+        CacheStore, CacheStoreMut, CacheStoreRef, MemoryStore, SizedStore, ThreadSafeMemoryStore,
+        ThreadSafeTryCacheStore, ThreadSafeTryCacheStoreMut,
+    };
+    use std::{format, vec, vec::Vec};
+
+    #[test]
+    fn get_ref_borrows() {
+        let mut store: MemoryStore<&str, Vec<u8>> = MemoryStore::new();
+        store.set("key", vec![1, 2, 3]);
+        assert_eq!(store.get_ref("key").map(Vec::len), Some(3));
+        assert!(store.get_ref("other").is_none());
+    }
+
+    #[test]
+    fn shrink_after_drain() {
+        let mut store: MemoryStore<usize, usize> = (0..1000).map(|i| (i, i)).collect();
+        store.drain();
+        store.shrink_to_fit();
+        assert_eq!(store.capacity(), 0);
+
+        let mut store: ThreadSafeMemoryStore<usize, usize> = ThreadSafeMemoryStore::default();
+        store.reserve(100);
+        assert!(store.capacity() >= 100);
+        store.shrink_to_fit();
+        assert_eq!(store.capacity(), 0);
+    }
+
+    #[test]
+    fn update_in_place() {
+        let mut store: MemoryStore<&str, u32> = MemoryStore::new();
+        store.set("hits", 1);
+        assert!(store.update("hits", |hits| *hits += 1));
+        assert!(!store.update("misses", |misses| *misses += 1));
+        assert_eq!(store.get("hits"), Some(2));
+
+        let store: ThreadSafeMemoryStore<&str, u32> = [("hits", 1)].into_iter().collect();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| store.ts_one_try_update(&"hits", |hits| *hits += 1).unwrap());
+            }
+        });
+        assert_eq!(store.ts_one_try_get(&"hits").unwrap(), Some(5));
+    }
+
+    #[test]
+    fn revisions_are_pruned() {
+        let mut store: MemoryStore<u32, u32> = MemoryStore::new();
+        store.set(0, 0);
+        assert_eq!(store.entry_revision(&0), 0);
+
+        let mut store = store.with_revisions();
+        store.set(0, 1);
+        store.set(1, 1);
+        assert_eq!((store.entry_revision(&0), store.entry_revision(&1)), (1, 2));
+        assert_eq!(store.bytes_used(), 4 * 4 + 2 * (4 + 8));
+
+        store.remove(0);
+        assert_eq!(store.entry_revision(&0), 0);
+        store.set(0, 1);
+        assert_eq!(store.entry_revision(&0), 3);
+
+        store.drain();
+        assert_eq!(store.bytes_used(), 0);
+    }
+
+    #[test]
+    fn replace_and_set_if_absent() {
+        let mut store: MemoryStore<&str, u32> = MemoryStore::new().with_revisions();
+        assert!(store.set_if_absent("key", 1));
+        assert!(!store.set_if_absent("key", 2));
+        assert_eq!(store.replace("key", 3), Some(1));
+        assert_eq!(store.get("key"), Some(3));
+        assert_eq!(store.entry_revision(&"key"), 2);
+
+        let store: ThreadSafeMemoryStore<&str, usize> = ThreadSafeMemoryStore::default();
+        let winners = std::thread::scope(|scope| {
+            let store = &store;
+            let threads: Vec<_> = (0..4)
+                .map(|i| scope.spawn(move || store.ts_one_try_set_if_absent(&"key", &i).unwrap()))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .filter(|&won| won)
+                .count()
+        });
+        assert_eq!(winners, 1);
+    }
+
+    #[test]
+    fn replace_only_needs_entry() {
+        let mut store: MemoryStore<&str, u32> = MemoryStore::new();
+        assert!(!store.replace_only("key", 1));
+        assert!(!store.exists("key"));
+        store.set("key", 1);
+        assert!(store.replace_only("key", 2));
+        assert_eq!(store.get("key"), Some(2));
+
+        let store: ThreadSafeMemoryStore<&str, u32> = ThreadSafeMemoryStore::default();
+        assert!(!store.ts_one_try_replace_only(&"key", &1).unwrap());
+        assert!(!store.ts_one_try_exists(&"key").unwrap());
+        store.ts_one_try_set(&"key", &1).unwrap();
+        assert!(store.ts_one_try_replace_only(&"key", &2).unwrap());
+        assert_eq!(store.ts_one_try_get(&"key").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn remove_returns_old_value() {
+        let mut store: MemoryStore<&str, u32> = MemoryStore::new();
+        assert_eq!(store.remove("key"), None);
+        store.set("key", 1);
+        assert_eq!(store.remove("key"), Some(1));
+        assert!(!store.exists("key"));
+
+        let store: ThreadSafeMemoryStore<&str, u32> = ThreadSafeMemoryStore::default();
+        store.ts_one_try_set(&"key", &1).unwrap();
+        assert_eq!(store.ts_one_try_remove(&"key").unwrap(), Some(1));
+        assert_eq!(store.ts_one_try_remove(&"key").unwrap(), None);
+    }
+
+    #[test]
+    fn debug_redacted() {
+        let store = MemoryStore::from_hashmap([("key", "secret")].into());
+        assert_eq!(
+            format!("{store:?}"),
+            r#"MemoryStore { cache: {"key": "secret"} }"#
+        );
+        assert_eq!(format!("{:?}", store.redacted()), r#"{"key": <redacted>}"#);
+    }
+
+    #[test]
+    fn drain_and_into_iter() {
+        let mut store = MemoryStore::new();
+        store.set(0, 0);
+        store.set(1, 1);
+
+        let mut drained: Vec<_> = store.drain().collect();
+        drained.sort_unstable();
+        assert_eq!(drained, [(0, 0), (1, 1)]);
+        assert!(!store.exists(0));
+
+        store.set(2, 2);
+        assert_eq!(store.into_iter().collect::<Vec<_>>(), [(2, 2)]);
+
+        let mut store: MemoryStore<_, _> = (0..2).map(|i| (i, i)).collect();
+        store.extend([(2, 2)]);
+        assert_eq!(store.iter().count(), 3);
+        assert_eq!(store.get(2), Some(2));
+        assert_eq!(store.clone(), store);
+
+        let store = ThreadSafeMemoryStore::<usize, usize>::default();
+        store.ts_one_try_set(&3, &3).unwrap();
+        // Locked exclusively without setting it, leaving an empty slot
+        drop(store.ts_try_xlock(&4).unwrap());
+        assert_eq!(store.into_iter().collect::<Vec<_>>(), [(3, 3)]);
+    }
 
     #[test]
     fn xlock_diff_keys() {
@@ -327,6 +968,32 @@ mod tests {
         drop((s1, s2));
     }
 
+    #[test]
+    fn reads_dont_insert() {
+        let store = ThreadSafeMemoryStore::<usize, usize>::default();
+        assert_eq!(store.ts_one_try_get(&0).unwrap(), None);
+        assert!(!store.ts_one_try_exists(&1).unwrap());
+        assert!(store.cache.read().unwrap().is_empty());
+
+        store.ts_one_try_set(&0, &0).unwrap();
+        assert_eq!(store.ts_one_try_get(&0).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn locks_survive_growth() {
+        let store = ThreadSafeMemoryStore::<usize, usize>::default();
+        store.ts_one_try_set(&0, &0).unwrap();
+
+        let mut handle = store.ts_try_xlock(&0).expect("to xlock first key");
+        let keys: Vec<usize> = (1..1024).collect();
+        for key in &keys {
+            store.ts_one_try_set(key, key).unwrap();
+        }
+        store.ts_try_set(&mut handle, &1).unwrap();
+        drop(handle);
+        assert_eq!(store.ts_one_try_get(&0).unwrap(), Some(1));
+    }
+
     #[test]
     fn xlock_slock_same_key() {
         let store = ThreadSafeMemoryStore::<usize, usize>::default();
@@ -353,3 +1020,52 @@ mod tests {
         drop((x1, s1, s2));
     }
 }
+
+#[cfg(all(test, loom))]
+mod loom {
+    use super::{ThreadSafeMemoryStore, ThreadSafeTryCacheStore};
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn loom_set_if_absent_once() {
+        loom::model(|| {
+            let store = Arc::new(ThreadSafeMemoryStore::<u8, u8>::default());
+            let threads: std::vec::Vec<_> = (0..2)
+                .map(|i| {
+                    let store = Arc::clone(&store);
+                    thread::spawn(move || store.ts_one_try_set_if_absent(&0, &i).unwrap())
+                })
+                .collect();
+
+            let set = threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .filter(|&set| set)
+                .count();
+            assert_eq!(set, 1);
+        });
+    }
+
+    #[test]
+    fn loom_xlock_hides_intermediate_values() {
+        loom::model(|| {
+            let store = Arc::new(ThreadSafeMemoryStore::<u8, u8>::default());
+
+            let writer = {
+                let store = Arc::clone(&store);
+                thread::spawn(move || {
+                    let mut handle = store.ts_try_xlock(&0).unwrap();
+                    store.ts_try_set(&mut handle, &1).unwrap();
+                    store.ts_try_set(&mut handle, &2).unwrap();
+                })
+            };
+            // Other keys can be used meanwhile
+            store.ts_one_try_set(&1, &1).unwrap();
+            let value = store.ts_one_try_get(&0).unwrap();
+            writer.join().unwrap();
+
+            assert!(matches!(value, None | Some(2)));
+            assert_eq!(store.ts_one_try_get(&1).unwrap(), Some(1));
+        });
+    }
+}
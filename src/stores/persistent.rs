@@ -0,0 +1,256 @@
+//! Memory store that survives restarts by snapshotting its contents to a file.
+//!
+//! [`PersistentMemoryStore`] serves everything from a [`MemoryStore`], but writes all of it to a
+//! file every now and then and when dropped, and loads it back when opened. Entries set after the
+//! last snapshot are lost if the process dies, in exchange of not touching the disk on every
+//! operation like [file stores][super::file_stores] do.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{TryCacheStore, stores::persistent::PersistentMemoryStore};
+//! #
+//! # let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("cache.bin");
+//!
+//! let mut store: PersistentMemoryStore<String, u32> =
+//!     PersistentMemoryStore::open(&path, Duration::from_secs(60)).unwrap();
+//! store.try_set(String::from("key"), 1).unwrap();
+//! drop(store);
+//!
+//! let store: PersistentMemoryStore<String, u32> =
+//!     PersistentMemoryStore::open(&path, Duration::from_secs(60)).unwrap();
+//! assert_eq!(store.try_get(String::from("key")).unwrap(), Some(1));
+//! ```
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    __internal_prelude::*,
+    clock::{Clock, SystemClock},
+    shutdown::Shutdown,
+    size::{MemSize, SizedStore},
+    stores::{file_stores::ThreadSafeFileStoreError, MemoryStore},
+};
+
+use core::{hash::Hash, time::Duration};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// Memory store that snapshots its entries to a file at an interval and on drop.
+///
+/// Snapshots are only taken on operations that change the store once the interval elapsed, there
+/// are no background threads. They are written to a temporary file next to the snapshot and then
+/// renamed over it, so a crash while writing one leaves the previous snapshot intact. Errors of the
+/// snapshot on drop are ignored, call [`snapshot`][Self::snapshot] before to handle them.
+///
+/// Generics:
+/// - `K`: Type of the key used for cache indexing.
+/// - `V`: Type of the value stored in the cache store.
+/// - `C`: [`Clock`] used to tell when the interval elapsed, the system time by default.
+pub struct PersistentMemoryStore<K: Hash + Eq + Serialize, V: Serialize, C: Clock = SystemClock> {
+    pub store: MemoryStore<K, V>,
+    path: PathBuf,
+    interval: Duration,
+    clock: C,
+    last_snapshot: Duration,
+}
+
+impl<K: Hash + Eq + Serialize + DeserializeOwned, V: Serialize + DeserializeOwned>
+    PersistentMemoryStore<K, V>
+{
+    /// Opens the store snapshotted at `path`, or an empty one if there's no snapshot yet, that
+    /// snapshots itself every `interval`.
+    ///
+    /// # Errors
+    /// Fails when the snapshot exists but can't be read.
+    pub fn open(
+        path: impl AsRef<Path>,
+        interval: Duration,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        Self::open_with_clock(path, interval, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq + Serialize + DeserializeOwned, V: Serialize + DeserializeOwned, C: Clock>
+    PersistentMemoryStore<K, V, C>
+{
+    /// Same as [`open`][PersistentMemoryStore::open], with the given clock to tell the interval.
+    ///
+    /// # Errors
+    /// Fails when the snapshot exists but can't be read.
+    pub fn open_with_clock(
+        path: impl AsRef<Path>,
+        interval: Duration,
+        clock: C,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        let path = path.as_ref().to_path_buf();
+        let store = match File::open(&path) {
+            Ok(file) => bincode::deserialize_from(BufReader::new(file))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => MemoryStore::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Self {
+            store,
+            path,
+            interval,
+            last_snapshot: clock.now(),
+            clock,
+        })
+    }
+}
+
+impl<K: Hash + Eq + Serialize, V: Serialize, C: Clock> PersistentMemoryStore<K, V, C> {
+    /// Writes all entries to the snapshot file right away.
+    ///
+    /// # Errors
+    /// Fails when the snapshot can't be written.
+    pub fn snapshot(&mut self) -> Result<(), ThreadSafeFileStoreError> {
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut file, &self.store)?;
+        file.into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.last_snapshot = self.clock.now();
+        Ok(())
+    }
+
+    /// Snapshots if the interval since the last one elapsed.
+    fn maybe_snapshot(&mut self) -> Result<(), ThreadSafeFileStoreError> {
+        if self.clock.now().saturating_sub(self.last_snapshot) >= self.interval {
+            self.snapshot()?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes a snapshot right away.
+impl<K: Hash + Eq + Serialize, V: Serialize, C: Clock> Shutdown for PersistentMemoryStore<K, V, C> {
+    type Error = ThreadSafeFileStoreError;
+
+    fn shutdown(&mut self) -> Result<(), Self::Error> {
+        self.snapshot()
+    }
+}
+
+impl<K: Hash + Eq + Serialize, V: Serialize, C: Clock> Drop for PersistentMemoryStore<K, V, C> {
+    fn drop(&mut self) {
+        let _ = self.snapshot();
+    }
+}
+
+/// Only the entries in memory, not the snapshot file.
+impl<K: Hash + Eq + Serialize + MemSize, V: Serialize + MemSize, C: Clock> SizedStore
+    for PersistentMemoryStore<K, V, C>
+{
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<K: Hash + Eq + Clone + Serialize, V: Clone + Serialize, C: Clock> TryCacheStore
+    for PersistentMemoryStore<K, V, C>
+{
+    type Key = K;
+    type Value = V;
+    type Error = ThreadSafeFileStoreError;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(self.store.get(key))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.set(key, value);
+        self.maybe_snapshot()
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let old = self.store.remove(key);
+        self.maybe_snapshot()?;
+        Ok(old)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        Ok(self.store.exists(key))
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let old = self.store.replace(key, value);
+        self.maybe_snapshot()?;
+        Ok(old)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let set = self.store.set_if_absent(key, value);
+        self.maybe_snapshot()?;
+        Ok(set)
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let set = self.store.replace_only(key, value);
+        self.maybe_snapshot()?;
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn snapshots_after_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.bin");
+        let clock = MockClock::default();
+        let open = || {
+            PersistentMemoryStore::<u8, u8, _>::open_with_clock(
+                &path,
+                Duration::from_secs(10),
+                &clock,
+            )
+            .unwrap()
+        };
+
+        let mut store = open();
+        store.try_set(0, 0).unwrap();
+        assert!(!path.exists());
+
+        clock.advance(Duration::from_secs(10));
+        store.try_set(1, 1).unwrap();
+        assert_eq!(open().store.iter().count(), 2);
+
+        store.try_set(2, 2).unwrap();
+        drop(store);
+        assert_eq!(open().try_get(2).unwrap(), Some(2));
+    }
+}
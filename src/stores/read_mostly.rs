@@ -0,0 +1,133 @@
+//! Memory store for data that is constantly read but rarely updated.
+//!
+//! [`ReadMostlyStore`] keeps an immutable map behind an [`ArcSwap`]. Reads never lock nor wait on
+//! writers, they just look into the current map. Updates copy the whole map, change the copy and
+//! swap it in, so they are expensive but readers see either all of a batch or none of it. Great for
+//! cached configuration or routing tables.
+//!
+//! # Examples
+//! ```rust
+//! # use std::{sync::Arc, thread};
+//! # use ezcache::{CacheStore, stores::read_mostly::ReadMostlyStore};
+//! #
+//! let store = Arc::new(ReadMostlyStore::new());
+//! store.update_all(|routes| {
+//!     routes.insert("/", "index");
+//!     routes.insert("/about", "about");
+//! });
+//!
+//! let reader = {
+//!     let store = Arc::clone(&store);
+//!     thread::spawn(move || store.get("/about"))
+//! };
+//! assert_eq!(reader.join().unwrap(), Some("about"));
+//!
+//! // Readers keep the map they loaded even if it's swapped meanwhile
+//! let routes = store.snapshot();
+//! store.update_all(|routes| routes.clear());
+//! assert_eq!(routes.len(), 2);
+//! assert!(!store.exists("/"));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::hash::Hash;
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwap;
+
+/// Memory store with lock-free reads and copy-on-write updates.
+///
+/// Every [`set`][CacheStore::set] copies the whole map, batch them with
+/// [`update_all`][ReadMostlyStore::update_all] when setting several entries at once.
+pub struct ReadMostlyStore<K, V> {
+    cache: ArcSwap<HashMap<K, V>>,
+}
+
+impl<K, V> Default for ReadMostlyStore<K, V> {
+    fn default() -> Self {
+        Self::from_hashmap(HashMap::new())
+    }
+}
+
+impl<K, V> ReadMostlyStore<K, V> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn from_hashmap(hashmap: HashMap<K, V>) -> Self {
+        Self {
+            cache: ArcSwap::from_pointee(hashmap),
+        }
+    }
+
+    /// Returns the current map. It doesn't change even if the store gets updated meanwhile.
+    #[must_use]
+    pub fn snapshot(&self) -> Arc<HashMap<K, V>> {
+        self.cache.load_full()
+    }
+}
+
+impl<K: Clone, V: Clone> ReadMostlyStore<K, V> {
+    /// Updates a copy of the map and swaps it in, readers see all the changes at once.
+    ///
+    /// If another update swaps the map in meanwhile, `f` runs again over a copy of the new one, so
+    /// no update is lost.
+    pub fn update_all(&self, mut f: impl FnMut(&mut HashMap<K, V>)) {
+        self.cache.rcu(|map| {
+            let mut map = HashMap::clone(map);
+            f(&mut map);
+            map
+        });
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> CacheStore for ReadMostlyStore<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        self.cache.load().get(key.borrow()).cloned()
+    }
+
+    fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>) {
+        let (key, value) = (key.borrow(), value.borrow());
+        self.update_all(|map| {
+            map.insert(key.clone(), value.clone());
+        });
+    }
+
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let key = key.borrow();
+        let mut old = None;
+        self.update_all(|map| old = map.remove(key));
+        old
+    }
+
+    fn exists(&self, key: impl Borrow<Self::Key>) -> bool {
+        self.cache.load().contains_key(key.borrow())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_updates_are_kept() {
+        let store = ReadMostlyStore::new();
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let store = &store;
+                scope.spawn(move || store.update_all(|map| _ = map.insert(i, i)));
+            }
+        });
+        assert_eq!(store.snapshot().len(), 8);
+
+        let mut store = store;
+        store.set(8, 8);
+        assert_eq!(store.get(8), Some(8));
+    }
+}
@@ -0,0 +1,497 @@
+//! Segment files the file stores pack their small entries into.
+//!
+//! Segments live in the [`DIR`] subdirectory of a store and are logs of records appended one after
+//! the other, each of them made of:
+//! - The length of the name of the entry, the hash of its key, as a byte, and the name itself.
+//! - When it was written, in seconds since the unix epoch, as a little endian `u64`.
+//! - The length of the value as a little endian `u32`, or [`MOVED`] if the entry left the segments.
+//! - The value itself.
+//!
+//! Only the last record of each name counts. Where they are is kept in an index in memory, rebuilt
+//! by reading all segments when opening them.
+//!
+//! [Compacting][Segments::compact] rewrites the records that still count of every segment but the
+//! last one into the last one, and deletes them, oldest first. A segment is only deleted once all
+//! older ones are, so dropping its [`MOVED`] records can't bring back an older record.
+
+use std::{
+    collections::{HashMap, HashSet},
+    format,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    string::String,
+    sync::PoisonError,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+    vec::Vec,
+};
+
+use super::file_stores::{CompactProgress, SharedClock, ThreadSafeFileStoreError};
+use crate::sync::Mutex;
+
+/// Subdirectory of the store the segments are in.
+pub(crate) const DIR: &str = ".packed";
+/// Size after which a segment is left alone and a new one is started.
+const SEGMENT_SIZE: u64 = 64 << 20;
+/// Length of the records of entries that left the segments.
+const MOVED: u32 = u32::MAX;
+
+/// Where the value of a packed entry is.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    segment: u32,
+    /// Offset of the value, past the header of its record.
+    offset: u64,
+    len: u32,
+    /// Seconds since the unix epoch.
+    written: u64,
+}
+
+struct State {
+    index: HashMap<String, Location>,
+    /// Segment being appended to, and its length.
+    current: u32,
+    current_len: u64,
+    /// Length of all segments, and of the records in the index.
+    total_len: u64,
+    live_len: u64,
+    /// Clock the segments are used by, and the last time of it a packed entry was read or
+    /// written.
+    clock: SharedClock,
+    last_used: Duration,
+}
+
+/// Segments of a store, see the [module docs][self].
+pub(crate) struct Segments {
+    dir: PathBuf,
+    threshold: usize,
+    state: Mutex<State>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+/// Length of a whole record of a value, header included.
+fn record_len(name: &str, len: u32) -> u64 {
+    (1 + name.len() + 8 + 4) as u64 + u64::from(len)
+}
+
+/// Numbers of the segments in `dir`, in order.
+fn segment_numbers(dir: &Path) -> std::io::Result<Vec<u32>> {
+    let mut numbers: Vec<u32> = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            entry
+                .ok()?
+                .file_name()
+                .to_str()?
+                .strip_suffix(".seg")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+/// Reads the records of a segment into the index, returns the length of its valid part, which is
+/// shorter than the file if the last record was cut short.
+fn read_segment(segment: u32, buf: &[u8], index: &mut HashMap<String, Location>) -> u64 {
+    let mut pos = 0;
+    while let Some(&name_len) = buf.get(pos) {
+        let header_len = 1 + usize::from(name_len) + 8 + 4;
+        let Some(header) = buf.get(pos..pos + header_len) else {
+            break;
+        };
+        let name = String::from_utf8_lossy(&header[1..=name_len.into()]).into_owned();
+        let (written, len) = header[1 + usize::from(name_len)..].split_at(8);
+        let written = u64::from_le_bytes(written.try_into().expect("8 bytes"));
+        let len = u32::from_le_bytes(len.try_into().expect("4 bytes"));
+
+        let value_len = if len == MOVED { 0 } else { len as usize };
+        if buf.len() < pos + header_len + value_len {
+            break;
+        }
+        if len == MOVED {
+            index.remove(&name);
+        } else {
+            let location = Location {
+                segment,
+                offset: (pos + header_len) as u64,
+                len,
+                written,
+            };
+            index.insert(name, location);
+        }
+        pos += header_len + value_len;
+    }
+    pos as u64
+}
+
+/// Appends a record to `buf`, `None` values are [`MOVED`] records.
+fn push_record(buf: &mut Vec<u8>, name: &str, value: Option<&[u8]>, written: u64) -> u64 {
+    let name_len = u8::try_from(name.len()).expect("names are key hashes");
+    buf.push(name_len);
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(&written.to_le_bytes());
+    let len = value.map_or(MOVED, |value| {
+        u32::try_from(value.len()).expect("packed values are small")
+    });
+    buf.extend_from_slice(&len.to_le_bytes());
+    let value_offset = buf.len() as u64;
+    buf.extend_from_slice(value.unwrap_or_default());
+    value_offset
+}
+
+/// Reads the packed values of the store at `store_dir` by their names, without opening its
+/// segments for writing nor fixing them.
+pub(crate) fn read_all(
+    store_dir: &Path,
+) -> Result<Vec<(String, Vec<u8>)>, ThreadSafeFileStoreError> {
+    let dir = store_dir.join(DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut index = HashMap::new();
+    let mut bufs = HashMap::new();
+    for segment in segment_numbers(&dir)? {
+        let mut buf = Vec::new();
+        File::open(segment_path(&dir, segment))?.read_to_end(&mut buf)?;
+        read_segment(segment, &buf, &mut index);
+        bufs.insert(segment, buf);
+    }
+    Ok(index
+        .into_iter()
+        .map(|(name, location)| {
+            let start = usize::try_from(location.offset).expect("read from memory");
+            let value = &bufs[&location.segment][start..start + location.len as usize];
+            (name, value.to_vec())
+        })
+        .collect())
+}
+
+impl Segments {
+    /// Opens the segments of the store at `store_dir`, packing values smaller than `threshold`.
+    pub(crate) fn open(
+        store_dir: &Path,
+        threshold: usize,
+        clock: SharedClock,
+    ) -> Result<Self, ThreadSafeFileStoreError> {
+        let dir = store_dir.join(DIR);
+        std::fs::create_dir_all(&dir)?;
+
+        let numbers = segment_numbers(&dir)?;
+        let mut index = HashMap::new();
+        let mut current_len = 0;
+        let mut total_len = 0;
+        for &segment in &numbers {
+            let path = segment_path(&dir, segment);
+            let mut buf = Vec::new();
+            File::open(&path)?.read_to_end(&mut buf)?;
+            current_len = read_segment(segment, &buf, &mut index);
+            total_len += current_len;
+            if current_len < buf.len() as u64 {
+                // Cut short by a crash while appending, drop the partial record
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(current_len)?;
+            }
+        }
+
+        let live_len = index
+            .iter()
+            .map(|(name, location)| record_len(name, location.len))
+            .sum();
+        Ok(Self {
+            dir,
+            // Lengths of packed values must fit in a record and not be taken for moved ones
+            threshold: threshold.min(MOVED as usize),
+            state: Mutex::new(State {
+                index,
+                current: numbers.last().copied().unwrap_or_default(),
+                current_len,
+                total_len,
+                live_len,
+                last_used: clock.now(),
+                clock,
+            }),
+        })
+    }
+
+    /// Whether a value of this length is packed.
+    pub(crate) fn packs(&self, len: usize) -> bool {
+        len < self.threshold
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> Result<bool, ThreadSafeFileStoreError> {
+        Ok(self.state.lock()?.index.contains_key(name))
+    }
+
+    /// When a packed value was written, to the second.
+    pub(crate) fn written(
+        &self,
+        name: &str,
+    ) -> Result<Option<SystemTime>, ThreadSafeFileStoreError> {
+        let index = &self.state.lock()?.index;
+        Ok(index
+            .get(name)
+            .map(|location| UNIX_EPOCH + Duration::from_secs(location.written)))
+    }
+
+    /// Where a packed value is, counts as using the segments.
+    fn locate(&self, name: &str) -> Result<Option<Location>, ThreadSafeFileStoreError> {
+        let mut state = self.state.lock()?;
+        state.last_used = state.clock.now();
+        Ok(state.index.get(name).copied())
+    }
+
+    /// Reads a packed value along with its age.
+    pub(crate) fn read(
+        &self,
+        name: &str,
+    ) -> Result<Option<(Vec<u8>, Duration)>, ThreadSafeFileStoreError> {
+        let Some(mut location) = self.locate(name)? else {
+            return Ok(None);
+        };
+        let mut file = loop {
+            match File::open(segment_path(&self.dir, location.segment)) {
+                Ok(file) => break file,
+                // Compacted meanwhile, the entry was moved to another segment
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    match self.locate(name)? {
+                        Some(moved) if moved.segment != location.segment => location = moved,
+                        Some(_) => return Err(error.into()),
+                        None => return Ok(None),
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        };
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut value = std::vec![0; location.len as usize];
+        file.read_exact(&mut value)?;
+        let age = Duration::from_secs(now().saturating_sub(location.written));
+        Ok(Some((value, age)))
+    }
+
+    /// Packs many values, appending them to the current segment in a single write.
+    pub(crate) fn write_many<'a>(
+        &self,
+        entries: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    ) -> Result<(), ThreadSafeFileStoreError> {
+        let written = now();
+        let mut buf = Vec::new();
+        let offsets: Vec<_> = entries
+            .into_iter()
+            .map(|(name, value)| {
+                let offset = push_record(&mut buf, name, Some(value), written);
+                (name, offset, value.len())
+            })
+            .collect();
+        if offsets.is_empty() {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock()?;
+        if state.current_len >= SEGMENT_SIZE {
+            state.current += 1;
+            state.current_len = 0;
+        }
+        let (segment, base) = (state.current, self.append(state.current, &buf)?);
+        state.current_len = base + buf.len() as u64;
+        state.total_len += buf.len() as u64;
+        state.last_used = state.clock.now();
+        for (name, offset, len) in offsets {
+            let location = Location {
+                segment,
+                offset: base + offset,
+                len: u32::try_from(len).expect("packed values are small"),
+                written,
+            };
+            state.live_len += record_len(name, location.len);
+            if let Some(old) = state.index.insert(name.into(), location) {
+                state.live_len -= record_len(name, old.len);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves entries out of the segments, returns how many were packed.
+    pub(crate) fn remove<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<usize, ThreadSafeFileStoreError> {
+        let mut state = self.state.lock()?;
+        let mut buf = Vec::new();
+        let mut removed = Vec::new();
+        for name in names {
+            if state.index.contains_key(name) {
+                push_record(&mut buf, name, None, now());
+                removed.push(name);
+            }
+        }
+        if removed.is_empty() {
+            return Ok(0);
+        }
+        state.current_len = self.append(state.current, &buf)? + buf.len() as u64;
+        state.total_len += buf.len() as u64;
+        state.last_used = state.clock.now();
+        for name in &removed {
+            if let Some(old) = state.index.remove(*name) {
+                state.live_len -= record_len(name, old.len);
+            }
+        }
+        Ok(removed.len())
+    }
+
+    /// Moves out of the segments the entries written more than `max_age` ago, except the `busy`
+    /// ones. Returns how many were.
+    pub(crate) fn purge(
+        &self,
+        max_age: Duration,
+        busy: &HashSet<String>,
+    ) -> Result<usize, ThreadSafeFileStoreError> {
+        let now = now();
+        let old: Vec<String> = self
+            .state
+            .lock()?
+            .index
+            .iter()
+            .filter(|(name, location)| {
+                !busy.contains(*name)
+                    && Duration::from_secs(now.saturating_sub(location.written)) > max_age
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.remove(old.iter().map(String::as_str))
+    }
+
+    /// Length of all segments over the length of the records in them that still count, `1.0` if
+    /// they are the same.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn amplification(&self) -> Result<f64, ThreadSafeFileStoreError> {
+        let state = self.state.lock()?;
+        Ok(match (state.total_len, state.live_len) {
+            (total, live) if total == live => 1.0,
+            (_, 0) => f64::INFINITY,
+            (total, live) => total as f64 / live as f64,
+        })
+    }
+
+    /// Time since a packed entry was last read or written.
+    pub(crate) fn idle_for(&self) -> Result<Duration, ThreadSafeFileStoreError> {
+        let state = self.state.lock()?;
+        Ok(state.clock.now().saturating_sub(state.last_used))
+    }
+
+    /// Replaces the clock the segments are used by, they count as used right now.
+    pub(crate) fn set_clock(&self, clock: SharedClock) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.last_used = clock.now();
+        state.clock = clock;
+    }
+
+    /// Rewrites the records that still count of every segment but the one being appended to into
+    /// it, see the [module docs][self]. Each segment is rewritten with the segments locked, and
+    /// `progress` is called after each one.
+    pub(crate) fn compact(
+        &self,
+        progress: &mut dyn FnMut(CompactProgress),
+    ) -> Result<CompactProgress, ThreadSafeFileStoreError> {
+        let sealed = {
+            let mut state = self.state.lock()?;
+            if state.total_len == state.live_len {
+                return Ok(CompactProgress::default());
+            }
+            if state.current_len > 0 {
+                state.current += 1;
+                state.current_len = 0;
+            }
+            let current = state.current;
+            let mut numbers = segment_numbers(&self.dir)?;
+            numbers.retain(|&segment| segment < current);
+            numbers
+        };
+
+        let mut done = CompactProgress {
+            segments_total: sealed.len(),
+            ..CompactProgress::default()
+        };
+        for segment in sealed {
+            let mut state = self.state.lock()?;
+            let path = segment_path(&self.dir, segment);
+            let mut old = Vec::new();
+            File::open(&path)?.read_to_end(&mut old)?;
+
+            let mut buf = Vec::new();
+            let mut moved = Vec::new();
+            for (name, location) in &state.index {
+                if location.segment == segment {
+                    let value = usize::try_from(location.offset)
+                        .ok()
+                        .and_then(|start| old.get(start..start + location.len as usize))
+                        .ok_or_else(|| std::io::Error::other("segment shorter than its index"))?;
+                    let offset = push_record(&mut buf, name, Some(value), location.written);
+                    moved.push((name.clone(), offset));
+                }
+            }
+            if !buf.is_empty() {
+                if state.current_len >= SEGMENT_SIZE {
+                    state.current += 1;
+                    state.current_len = 0;
+                }
+                let base = self.append(state.current, &buf)?;
+                state.current_len = base + buf.len() as u64;
+                let current = state.current;
+                for (name, offset) in moved {
+                    if let Some(location) = state.index.get_mut(&name) {
+                        location.segment = current;
+                        location.offset = base + offset;
+                    }
+                }
+            }
+            std::fs::remove_file(&path)?;
+
+            let freed = (old.len() - buf.len()) as u64;
+            state.total_len = state.total_len.saturating_sub(freed);
+            drop(state);
+            done.segments_done += 1;
+            done.bytes_freed += freed;
+            progress(done);
+        }
+        Ok(done)
+    }
+
+    /// Syncs every segment to disk.
+    pub(crate) fn sync(&self) -> Result<(), ThreadSafeFileStoreError> {
+        let state = self.state.lock()?;
+        for segment in 0..=state.current {
+            match File::open(segment_path(&self.dir, segment)) {
+                Ok(file) => file.sync_all()?,
+                Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends records to a segment, returns the offset they start at. It's told by the file
+    /// itself, so a failed append doesn't throw off the following ones.
+    fn append(&self, segment: u32, buf: &[u8]) -> Result<u64, ThreadSafeFileStoreError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(&self.dir, segment))?;
+        let start = file.metadata()?.len();
+        file.write_all(buf)?;
+        Ok(start)
+    }
+}
+
+fn segment_path(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(format!("{segment:08}.seg"))
+}
@@ -0,0 +1,206 @@
+//! Store that partitions its entries into fixed time windows.
+//!
+//! Useful for values computed per time period, like analytics rollups: each entry belongs to the
+//! window of a timestamp, and once a window falls out of the retention all its entries are dropped
+//! at once, without going through them one by one to check their expiry.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     CacheStore,
+//! #     clock::{Clock, SystemClock},
+//! #     stores::windowed::WindowedMemoryStore,
+//! # };
+//! #
+//! const HOUR: Duration = Duration::from_hours(1);
+//!
+//! // Hourly windows, keeping the current one and the one before
+//! let mut store: WindowedMemoryStore<&str, u32> = WindowedMemoryStore::new(HOUR, 2);
+//!
+//! let now = SystemClock.now();
+//! store.set_at("visits", 10, now - HOUR);
+//! store.set_at("signups", 3, now - 5 * HOUR);
+//! store.set("errors", 1);
+//!
+//! assert_eq!(store.get("visits"), Some(10));
+//! // Too old to be kept
+//! assert_eq!(store.get("signups"), None);
+//! assert_eq!(store.get("errors"), Some(1));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{hash::Hash, time::Duration};
+use std::collections::{HashMap, VecDeque};
+
+use crate::clock::{Clock, SystemClock};
+
+/// In memory store that groups entries by time windows and drops whole windows once they are older
+/// than the retention.
+///
+/// Plain [`set`][CacheStore::set]s go to the current window, [`set_at`][Self::set_at] allows
+/// setting entries in the window of any timestamp. Entries of windows out of the retention are
+/// never returned, even before [`expire`][Self::expire] frees them, which sets also do.
+///
+/// Time is told by a [`Clock`], the system time by default.
+pub struct WindowedMemoryStore<K, V, C: Clock = SystemClock> {
+    clock: C,
+    window: Duration,
+    retention: u64,
+    /// Buckets ordered by window, with the index of their window.
+    buckets: VecDeque<(u64, HashMap<K, V>)>,
+}
+
+impl<K, V> WindowedMemoryStore<K, V> {
+    /// Makes a new empty store with windows of the given length, keeping the entries of the current
+    /// window and the `retention - 1` ones before it.
+    ///
+    /// # Panics
+    /// If `window` is zero.
+    #[must_use]
+    pub fn new(window: Duration, retention: usize) -> Self {
+        assert!(!window.is_zero(), "windows can't have a zero length");
+        Self {
+            clock: SystemClock,
+            window,
+            retention: retention as u64,
+            buckets: VecDeque::new(),
+        }
+    }
+}
+
+impl<K, V, C: Clock> WindowedMemoryStore<K, V, C> {
+    /// Replaces the clock used to tell the current window.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> WindowedMemoryStore<K, V, C2> {
+        WindowedMemoryStore {
+            clock,
+            window: self.window,
+            retention: self.retention,
+            buckets: self.buckets,
+        }
+    }
+
+    /// Amount of windows with entries in the store, including the expired ones not freed yet.
+    #[must_use]
+    pub fn windows(&self) -> usize {
+        self.buckets.len()
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn window_of(&self, at: Duration) -> u64 {
+        (at.as_nanos() / self.window.as_nanos()) as u64
+    }
+
+    /// Index of the oldest window still in the retention, [`None`] if there's no retention at all.
+    fn oldest_window(&self) -> Option<u64> {
+        let current = self.window_of(self.clock.now());
+        (self.retention > 0).then(|| current.saturating_sub(self.retention - 1))
+    }
+
+    /// Drops all the windows out of the retention, returns how many were dropped.
+    pub fn expire(&mut self) -> usize {
+        let oldest = self.oldest_window();
+        let expired = self
+            .buckets
+            .iter()
+            .take_while(|(window, _)| oldest.is_none_or(|oldest| *window < oldest))
+            .count();
+        self.buckets.drain(..expired);
+        expired
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, C: Clock> WindowedMemoryStore<K, V, C> {
+    /// Sets an entry in the window that contains the timestamp, as a time of the store's clock.
+    /// It's silently dropped if that window is already out of the retention.
+    pub fn set_at(&mut self, key: impl Borrow<K>, value: impl Borrow<V>, at: Duration) {
+        self.expire();
+        let window = self.window_of(at);
+        if self.oldest_window().is_none_or(|oldest| window < oldest) {
+            return;
+        }
+
+        let key = key.borrow();
+        for (_, bucket) in &mut self.buckets {
+            bucket.remove(key);
+        }
+
+        let idx = match self.buckets.binary_search_by_key(&window, |(w, _)| *w) {
+            Ok(idx) => idx,
+            Err(idx) => {
+                self.buckets.insert(idx, (window, HashMap::new()));
+                idx
+            }
+        };
+        self.buckets[idx]
+            .1
+            .insert(key.clone(), value.borrow().clone());
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, C: Clock> CacheStore for WindowedMemoryStore<K, V, C> {
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let oldest = self.oldest_window()?;
+        self.buckets
+            .iter()
+            .rev()
+            .take_while(|(window, _)| *window >= oldest)
+            .find_map(|(_, bucket)| bucket.get(key.borrow()))
+            .cloned()
+    }
+
+    fn set(&mut self, key: impl Borrow<Self::Key>, value: impl Borrow<Self::Value>) {
+        self.set_at(key, value, self.clock.now());
+    }
+
+    fn remove(&mut self, key: impl Borrow<Self::Key>) -> Option<Self::Value> {
+        let key = key.borrow();
+        let value = self.get(key);
+        for (_, bucket) in &mut self.buckets {
+            bucket.remove(key);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    const HOUR: Duration = Duration::from_hours(1);
+
+    #[test]
+    fn expires_whole_windows() {
+        let clock = MockClock::new(HOUR * 100);
+        let mut store = WindowedMemoryStore::new(HOUR, 3).with_clock(&clock);
+        for i in 0..3 {
+            store.set_at(i, i, HOUR * (100 - i));
+        }
+        assert_eq!(store.windows(), 3);
+        assert_eq!(store.expire(), 0);
+
+        clock.advance(HOUR * 2);
+        assert_eq!(store.get(1), None);
+        assert_eq!(store.expire(), 2);
+        assert_eq!(store.windows(), 1);
+        assert_eq!(store.get(0), Some(0));
+    }
+
+    #[test]
+    fn entries_move_between_windows() {
+        let clock = MockClock::new(HOUR * 100);
+        let mut store = WindowedMemoryStore::new(HOUR, 2).with_clock(&clock);
+        store.set_at(0, 0, HOUR * 99);
+        store.set(0, 1);
+        assert_eq!(store.get(0), Some(1));
+
+        clock.advance(HOUR);
+        store.expire();
+        assert_eq!(store.get(0), Some(1));
+    }
+}
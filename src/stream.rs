@@ -0,0 +1,40 @@
+//! Async iteration over the entries of stores.
+//!
+//! Stores that can list their entries implement [`EntriesStream`], which yields them one by one
+//! as a [`Stream`], so tooling like backups or migrations can go over big caches without loading
+//! all of them at once. Stores backed by blocking iterators can bridge them with
+//! [`futures_util::stream::iter`].
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{stores::MemoryStore, stream::EntriesStream};
+//! # use futures::{executor::block_on, StreamExt};
+//! #
+//! let store: MemoryStore<&str, u32> = [("a", 1), ("b", 2)].into_iter().collect();
+//!
+//! let mut entries = block_on(store.entries_stream().collect::<Vec<_>>());
+//! entries.sort_unstable();
+//! assert_eq!(entries, [("a", 1), ("b", 2)]);
+//! ```
+
+use futures_util::stream::{self, Stream};
+
+use crate::stores::MemoryStore;
+
+/// Trait for a store whose entries can be streamed.
+pub trait EntriesStream {
+    type Key;
+    type Value;
+
+    /// Stream of owned copies of all entries, in no particular order.
+    fn entries_stream(&self) -> impl Stream<Item = (Self::Key, Self::Value)> + '_;
+}
+
+impl<K: Clone, V: Clone> EntriesStream for MemoryStore<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn entries_stream(&self) -> impl Stream<Item = (K, V)> + '_ {
+        stream::iter(self.iter().map(|(key, value)| (key.clone(), value.clone())))
+    }
+}
@@ -0,0 +1,17 @@
+//! Synchronization primitives of the thread safe stores.
+//!
+//! They are the std ones, unless built with `--cfg loom`, then they are [loom]'s so the stores can
+//! be model checked with it:
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+//! Other tests can't run under loom, only those named after it.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{
+    atomic::AtomicBool, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{
+    atomic::AtomicBool, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
@@ -0,0 +1,226 @@
+//! Property testing helpers for store implementations.
+//!
+//! Unbounded stores should behave just like a [`HashMap`]: what was set is what is got back. This
+//! module provides [`proptest`] strategies for random sequences of [`Op`]s and
+//! [`check_against_model`], which runs them on a store and on a [`HashMap`] and fails as soon as
+//! both disagree, so authors of stores can check their implementations with little effort.
+//!
+//! Stores that evict or expire entries on their own don't behave like a [`HashMap`] and will fail
+//! the check.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     stores::MemoryStore,
+//! #     testing::{check_against_model, ops},
+//! # };
+//! # use proptest::prelude::*;
+//! #
+//! proptest! {
+//!     fn memory_store_is_a_map(ops in ops(0..8u8, any::<u32>(), 0..64)) {
+//!         check_against_model(&mut MemoryStore::new(), &ops)?;
+//!     }
+//! }
+//! # memory_store_is_a_map();
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{fmt::Debug, hash::Hash};
+use std::{collections::HashMap, vec::Vec};
+
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    collection::{vec, SizeRange},
+    prop_assert_eq, prop_oneof,
+    strategy::{BoxedStrategy, Strategy},
+    test_runner::TestCaseError,
+};
+
+use crate::context::CacheOp;
+
+/// Single operation to run on a store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<K, V> {
+    Get(K),
+    Set(K, V),
+    Exists(K),
+    Replace(K, V),
+    SetIfAbsent(K, V),
+    ReplaceOnly(K, V),
+    Remove(K),
+}
+
+impl<K, V> Op<K, V> {
+    /// Returns what kind of operation this is.
+    pub fn kind(&self) -> CacheOp {
+        match self {
+            Self::Get(_) => CacheOp::Get,
+            Self::Set(..) => CacheOp::Set,
+            Self::Exists(_) => CacheOp::Exists,
+            Self::Replace(..) => CacheOp::Replace,
+            Self::SetIfAbsent(..) => CacheOp::SetIfAbsent,
+            Self::ReplaceOnly(..) => CacheOp::ReplaceOnly,
+            Self::Remove(_) => CacheOp::Remove,
+        }
+    }
+}
+
+impl<K: Arbitrary + Clone + 'static, V: Arbitrary + Clone + 'static> Arbitrary for Op<K, V> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        op(any::<K>().boxed(), any::<V>().boxed()).boxed()
+    }
+}
+
+/// Strategy for a single operation, with keys and values drawn from the given strategies.
+///
+/// Drawing keys from a small set makes operations hit the same entries more often.
+pub fn op<K, V>(
+    keys: impl Strategy<Value = K> + Clone + 'static,
+    values: impl Strategy<Value = V> + Clone + 'static,
+) -> impl Strategy<Value = Op<K, V>>
+where
+    K: Debug + Clone + 'static,
+    V: Debug + Clone + 'static,
+{
+    prop_oneof![
+        keys.clone().prop_map(Op::Get),
+        (keys.clone(), values.clone()).prop_map(|(k, v)| Op::Set(k, v)),
+        keys.clone().prop_map(Op::Exists),
+        (keys.clone(), values.clone()).prop_map(|(k, v)| Op::Replace(k, v)),
+        (keys.clone(), values.clone()).prop_map(|(k, v)| Op::SetIfAbsent(k, v)),
+        (keys.clone(), values).prop_map(|(k, v)| Op::ReplaceOnly(k, v)),
+        keys.prop_map(Op::Remove),
+    ]
+}
+
+/// Strategy for a sequence of operations, see [`op`].
+pub fn ops<K, V>(
+    keys: impl Strategy<Value = K> + Clone + 'static,
+    values: impl Strategy<Value = V> + Clone + 'static,
+    len: impl Into<SizeRange>,
+) -> impl Strategy<Value = Vec<Op<K, V>>>
+where
+    K: Debug + Clone + 'static,
+    V: Debug + Clone + 'static,
+{
+    vec(op(keys, values), len)
+}
+
+/// Runs the operations on the store and on a [`HashMap`], checking every result of the store
+/// against the one of the map.
+///
+/// # Errors
+/// Fails the test case at the first operation where the store and the map disagree, or if the
+/// store fails.
+pub fn check_against_model<S: TryCacheStore>(
+    store: &mut S,
+    ops: &[Op<S::Key, S::Value>],
+) -> Result<(), TestCaseError>
+where
+    S::Key: Hash + Eq + Clone + Debug,
+    S::Value: Clone + PartialEq + Debug,
+    S::Error: Debug,
+{
+    let mut model = HashMap::new();
+    let failed = |err: S::Error| TestCaseError::fail(std::format!("store failed: {err:?}"));
+
+    for (i, op) in ops.iter().enumerate() {
+        match op {
+            Op::Get(k) => {
+                prop_assert_eq!(
+                    store.try_get(k).map_err(failed)?,
+                    model.get(k).cloned(),
+                    "op {}: {:?}",
+                    i,
+                    op
+                );
+            }
+            Op::Set(k, v) => {
+                store.try_set(k, v).map_err(failed)?;
+                model.insert(k.clone(), v.clone());
+            }
+            Op::Exists(k) => {
+                prop_assert_eq!(
+                    store.try_exists(k).map_err(failed)?,
+                    model.contains_key(k),
+                    "op {}: {:?}",
+                    i,
+                    op
+                );
+            }
+            Op::Replace(k, v) => {
+                prop_assert_eq!(
+                    store.try_replace(k, v).map_err(failed)?,
+                    model.insert(k.clone(), v.clone()),
+                    "op {}: {:?}",
+                    i,
+                    op
+                );
+            }
+            Op::SetIfAbsent(k, v) => {
+                let absent = !model.contains_key(k);
+                if absent {
+                    model.insert(k.clone(), v.clone());
+                }
+                prop_assert_eq!(
+                    store.try_set_if_absent(k, v).map_err(failed)?,
+                    absent,
+                    "op {}: {:?}",
+                    i,
+                    op
+                );
+            }
+            Op::ReplaceOnly(k, v) => {
+                let present = model.contains_key(k);
+                if present {
+                    model.insert(k.clone(), v.clone());
+                }
+                prop_assert_eq!(
+                    store.try_replace_only(k, v).map_err(failed)?,
+                    present,
+                    "op {}: {:?}",
+                    i,
+                    op
+                );
+            }
+            Op::Remove(k) => {
+                prop_assert_eq!(
+                    store.try_remove(k).map_err(failed)?,
+                    model.remove(k),
+                    "op {}: {:?}",
+                    i,
+                    op
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::ContextStore, normalize::NormalizedStore, stores::MemoryStore};
+    use proptest::proptest;
+
+    proptest! {
+        #[test]
+        fn wrappers_are_maps(ops in ops(0..8i8, any::<u16>(), 0..64)) {
+            check_against_model(&mut ContextStore::new(MemoryStore::new()), &ops)?;
+            check_against_model(&mut NormalizedStore::new(MemoryStore::new(), |k: &i8| *k), &ops)?;
+        }
+    }
+
+    #[test]
+    fn catches_wrong_stores() {
+        let ops = [Op::Set(0, 0), Op::Get(1)];
+        let mut store = NormalizedStore::new(MemoryStore::new(), |_: &u8| 0);
+        assert!(check_against_model(&mut store, &ops).is_err());
+        assert_eq!(ops[1].kind(), CacheOp::Get);
+    }
+}
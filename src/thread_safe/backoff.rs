@@ -0,0 +1,186 @@
+//! Lock acquisition with backoff, for stores whose locks are contended.
+//!
+//! Instead of retrying [`ts_try_xlock_nblock`][ThreadSafeTryCacheStore::ts_try_xlock_nblock] in
+//! an ad-hoc loop, [`ThreadSafeTryCacheStoreBackoff`] retries it following a [`BackoffPolicy`]:
+//! first spinning, as locks are usually held for very short, then yielding the thread so the holder
+//! gets to run, and then parking it for exponentially longer, so a lock held for long doesn't burn
+//! a core. It only retries while the error [is transient][CacheError::is_transient], like a
+//! lock that would block, any other error is returned right away.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     prelude::*,
+//! #     stores::ThreadSafeMemoryStore,
+//! #     thread_safe::backoff::{BackoffPolicy, ThreadSafeTryCacheStoreBackoff},
+//! # };
+//! #
+//! let store = ThreadSafeMemoryStore::<&str, u32>::default();
+//! let policy = BackoffPolicy::default().with_timeout(Duration::from_millis(10));
+//!
+//! let handle = store.ts_try_xlock_with_backoff(&"key", &policy).unwrap();
+//! // Gives up once the timeout passes
+//! assert!(store.ts_try_xlock_with_backoff(&"key", &policy).is_err());
+//!
+//! drop(handle);
+//! assert!(store.ts_try_slock_with_backoff(&"key", &policy).is_ok());
+//! ```
+
+use crate::{error::CacheError, thread_safe::ThreadSafeTryCacheStore};
+
+use core::time::Duration;
+use std::time::Instant;
+
+/// How to wait between attempts of acquiring a lock, see the [module docs][self].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackoffPolicy {
+    /// Attempts made spinning right after the first one.
+    pub spins: u32,
+    /// Attempts made yielding the thread after spinning.
+    pub yields: u32,
+    /// Time parked after the first parked attempt, doubled after each one.
+    pub min_park: Duration,
+    /// Longest time parked between attempts.
+    pub max_park: Duration,
+    /// Time after which no more attempts are made, if any.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for BackoffPolicy {
+    /// Spins 64 times, yields 16 times and then parks from 50µs up to 10ms, without timeout.
+    fn default() -> Self {
+        Self {
+            spins: 64,
+            yields: 16,
+            min_park: Duration::from_micros(50),
+            max_park: Duration::from_millis(10),
+            timeout: None,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Sets the spinning attempts.
+    #[must_use]
+    pub fn with_spins(mut self, spins: u32) -> Self {
+        self.spins = spins;
+        self
+    }
+
+    /// Sets the yielding attempts.
+    #[must_use]
+    pub fn with_yields(mut self, yields: u32) -> Self {
+        self.yields = yields;
+        self
+    }
+
+    /// Sets the shortest and longest time parked between attempts.
+    #[must_use]
+    pub fn with_park(mut self, min_park: Duration, max_park: Duration) -> Self {
+        self.min_park = min_park;
+        self.max_park = max_park;
+        self
+    }
+
+    /// Sets the time after which no more attempts are made.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Calls `attempt` until it succeeds, fails with an error that's not transient or the timeout
+    /// passes, waiting between calls as the policy says.
+    ///
+    /// # Errors
+    /// With the last error of `attempt`.
+    pub fn retry<T, E: CacheError>(
+        &self,
+        mut attempt: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut park = self.min_park;
+        let mut tries = 0u32;
+        loop {
+            let err = match attempt() {
+                Err(err) if err.is_transient() => err,
+                res => return res,
+            };
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Err(err);
+            }
+
+            if tries < self.spins {
+                core::hint::spin_loop();
+            } else if tries < self.spins.saturating_add(self.yields) {
+                std::thread::yield_now();
+            } else {
+                let left = deadline.map_or(park, |deadline| deadline - now);
+                std::thread::park_timeout(park.min(left));
+                park = (park * 2).min(self.max_park);
+            }
+            tries = tries.saturating_add(1);
+        }
+    }
+}
+
+/// Lock acquisition with a [`BackoffPolicy`], implemented by every [`ThreadSafeTryCacheStore`]
+/// whose error is a [`CacheError`].
+#[allow(clippy::missing_errors_doc)]
+pub trait ThreadSafeTryCacheStoreBackoff<'lock>: ThreadSafeTryCacheStore<'lock>
+where
+    Self::Error: CacheError,
+{
+    /// Attempt to exclusively lock a key until the handle is dropped, retrying while it's
+    /// contended as the policy says.
+    fn ts_try_xlock_with_backoff(
+        &'lock self,
+        key: &'lock Self::Key,
+        policy: &BackoffPolicy,
+    ) -> Result<Self::XLock, Self::Error> {
+        policy.retry(|| self.ts_try_xlock_nblock(key))
+    }
+
+    /// Attempt to acquire a shared lock of a key until the handle is dropped, retrying while it's
+    /// contended as the policy says.
+    fn ts_try_slock_with_backoff(
+        &'lock self,
+        key: &'lock Self::Key,
+        policy: &BackoffPolicy,
+    ) -> Result<Self::SLock<'lock>, Self::Error> {
+        policy.retry(|| self.ts_try_slock_nblock(key))
+    }
+}
+
+impl<'lock, S: ThreadSafeTryCacheStore<'lock>> ThreadSafeTryCacheStoreBackoff<'lock> for S where
+    S::Error: CacheError
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::ThreadSafeMemoryStore;
+    use std::thread;
+
+    #[test]
+    fn acquires_once_released() {
+        let store = ThreadSafeMemoryStore::<u8, u8>::default();
+        let policy =
+            BackoffPolicy::default().with_park(Duration::from_micros(50), Duration::from_millis(1));
+
+        thread::scope(|scope| {
+            let handle = store.ts_try_xlock_nblock(&0).unwrap();
+            let waiter = scope.spawn(|| {
+                let mut handle = store.ts_try_xlock_with_backoff(&0, &policy).unwrap();
+                *handle = Some(1);
+            });
+            thread::sleep(Duration::from_millis(20));
+            drop(handle);
+            waiter.join().unwrap();
+        });
+        assert_eq!(store.ts_one_try_get(&0).unwrap(), Some(1));
+    }
+}
@@ -1,9 +1,16 @@
 //! Thread safe traits for generative cache stores.
+//!
+//! The wrappers can cap how many generators run at once across all keys with a [`Semaphore`],
+//! so a burst of misses doesn't saturate the network or CPU. Generations over the cap wait for a
+//! running one to finish.
 
 use core::marker::PhantomData;
+use std::sync::Arc;
 
 use crate::__internal_prelude::*;
 
+use super::semaphore::Semaphore;
+
 use super::ThreadSafeCacheStore;
 
 /// Infalible thread safe generative cache store. This trait is **HIGHLY** discouraged for the
@@ -127,6 +134,7 @@ pub struct ThreadSafeGenCacheStoreWrapper<
 > {
     pub store: S,
     pub generator: F,
+    limit: Option<Arc<Semaphore>>,
     phantom: PhantomData<&'lock (K, V, A)>,
 }
 
@@ -146,9 +154,57 @@ impl<
         Self {
             store,
             generator,
+            limit: None,
             phantom: PhantomData,
         }
     }
+
+    /// Caps how many generators run at once to `max`, across all keys.
+    #[must_use]
+    pub fn with_max_concurrent(self, max: usize) -> Self {
+        self.with_limit(Arc::new(Semaphore::new(max)))
+    }
+
+    /// Takes a permit of the given semaphore to run the generator, which can be shared with other
+    /// wrappers to cap them all together.
+    #[must_use]
+    pub fn with_limit(mut self, limit: Arc<Semaphore>) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl<
+        'lock,
+        K,
+        V,
+        A,
+        S: super::ThreadSafeCacheStore<'lock, Key = K, Value = V> + Clone,
+        F: Fn(&K, A) -> V + Clone,
+    > Clone for ThreadSafeGenCacheStoreWrapper<'lock, K, V, A, S, F>
+{
+    fn clone(&self) -> Self {
+        Self {
+            limit: self.limit.clone(),
+            ..Self::new(self.store.clone(), self.generator.clone())
+        }
+    }
+}
+
+impl<
+        'lock,
+        K,
+        V,
+        A,
+        S: super::ThreadSafeCacheStore<'lock, Key = K, Value = V> + core::fmt::Debug,
+        F: Fn(&K, A) -> V,
+    > core::fmt::Debug for ThreadSafeGenCacheStoreWrapper<'lock, K, V, A, S, F>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ThreadSafeGenCacheStoreWrapper")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Implement [`ThreadSafeCacheStore`]
@@ -170,6 +226,7 @@ impl<
         key: &'lock <Self as ThreadSafeGenCacheStore<'lock>>::Key,
         args: Self::Args,
     ) -> <Self as ThreadSafeGenCacheStore<'lock>>::Value {
+        let _permit = self.limit.as_deref().map(Semaphore::acquire);
         (self.generator)(key, args)
     }
 
@@ -239,6 +296,7 @@ pub struct ThreadSafeGenTryCacheStoreWrapper<
 > {
     pub store: S,
     pub generator: F,
+    limit: Option<Arc<Semaphore>>,
     phantom: PhantomData<&'lock (K, V, A, E)>,
 }
 
@@ -260,9 +318,64 @@ impl<
         Self {
             store,
             generator,
+            limit: None,
             phantom: PhantomData,
         }
     }
+
+    /// Caps how many generators run at once to `max`, across all keys.
+    #[must_use]
+    pub fn with_max_concurrent(self, max: usize) -> Self {
+        self.with_limit(Arc::new(Semaphore::new(max)))
+    }
+
+    /// Takes a permit of the given semaphore to run the generator, which can be shared with other
+    /// wrappers to cap them all together.
+    #[must_use]
+    pub fn with_limit(mut self, limit: Arc<Semaphore>) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl<
+        'lock,
+        K,
+        V,
+        E,
+        A,
+        StErr: Into<E> + 'lock,
+        FnErr: Into<E> + 'lock,
+        S: super::ThreadSafeTryCacheStore<'lock, Key = K, Value = V, Error = StErr> + Clone,
+        F: Fn(&K, A) -> Result<V, FnErr> + Clone,
+    > Clone for ThreadSafeGenTryCacheStoreWrapper<'lock, K, V, E, A, StErr, FnErr, S, F>
+{
+    fn clone(&self) -> Self {
+        Self {
+            limit: self.limit.clone(),
+            ..Self::new(self.store.clone(), self.generator.clone())
+        }
+    }
+}
+
+impl<
+        'lock,
+        K,
+        V,
+        E,
+        A,
+        StErr: Into<E> + 'lock,
+        FnErr: Into<E> + 'lock,
+        S: super::ThreadSafeTryCacheStore<'lock, Key = K, Value = V, Error = StErr> + core::fmt::Debug,
+        F: Fn(&K, A) -> Result<V, FnErr>,
+    > core::fmt::Debug
+    for ThreadSafeGenTryCacheStoreWrapper<'lock, K, V, E, A, StErr, FnErr, S, F>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ThreadSafeGenTryCacheStoreWrapper")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Implement [`ThreadSafeCacheStore`]
@@ -292,6 +405,7 @@ impl<
         <Self as ThreadSafeTryGenCacheStore<'lock>>::Value,
         <Self as ThreadSafeTryGenCacheStore<'lock>>::Error,
     > {
+        let _permit = self.limit.as_deref().map(Semaphore::acquire);
         (self.generator)(key, args).map_err(Into::into)
     }
 
@@ -21,9 +21,10 @@
 //! - `ThreadA` and `ThreadB` write to `A`: The smart store would block until `ThreadA` is done to
 //!   allow `ThreadB` to write to it.
 //!
-//! Due to this, a smart thread safe store can become a normal [`CacheStore`], and a [`CacheStore`]
-//! can become a dumb thread safe cache. But there's no way to go back, as they "lose" information
-//! on how to handle the store concurrently through these conversions.
+//! Due to this, a smart thread safe store can become a normal [`TryCacheStore`] through a
+//! [`TryThreadUnsafeWrapper`], and a [`CacheStore`] can become a dumb thread safe cache. But
+//! there's no way to go back, as they "lose" information on how to handle the store concurrently
+//! through these conversions.
 //!
 //! # Error Handling
 //!
@@ -44,14 +45,16 @@
 //! If you want to wrap a [`TryCacheStore`], make sure that the error type implements
 //! [`From<PoisonError<…>>`][From] for [`PoisonError`]s.
 
+pub mod backoff;
 pub mod generative;
+pub mod semaphore;
 
 use crate::__internal_prelude::*;
 
 use core::ops::Deref;
 use std::sync::PoisonError;
 
-/// Trait for a thread safe infallible cache store, analogous to [CacheStore]
+/// Trait for a thread safe infallible cache store, analogous to [`CacheStore`]
 #[delegatable_trait]
 pub trait ThreadSafeCacheStore<'lock>
 where
@@ -70,6 +73,8 @@ where
     fn ts_get(&'lock self, handle: &Self::SLock<'_>) -> Option<Self::Value>;
     /// Sets a value given its key.
     fn ts_set(&'lock self, handle: &mut Self::XLock, value: &Self::Value);
+    /// Removes the cache entry, returning its value if it had one.
+    fn ts_remove(&'lock self, handle: &mut Self::XLock) -> Option<Self::Value>;
     /// Checks if the cache entry exists.
     fn ts_exists(&'lock self, handle: &Self::SLock<'_>) -> bool {
         self.ts_get(handle).is_some()
@@ -85,6 +90,11 @@ where
         let mut handle = self.ts_xlock(key);
         self.ts_set(&mut handle, value);
     }
+    /// Same as `ts_remove` but it performs a one-time lock
+    fn ts_one_remove(&'lock self, key: &Self::Key) -> Option<Self::Value> {
+        let mut handle = self.ts_xlock(key);
+        self.ts_remove(&mut handle)
+    }
     /// Same as `ts_exists` but it performs a one-time lock
     fn ts_one_exists(&'lock self, key: &Self::Key) -> bool {
         let handle = self.ts_slock(key);
@@ -131,10 +141,51 @@ where
         handle: &mut Self::XLock,
         value: &Self::Value,
     ) -> Result<(), Self::Error>;
+    /// Attempts to remove the cache entry, returning its value if it had one.
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error>;
     /// Attempts to check if the cache key entry exists.
     fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
         self.ts_try_get(handle).map(|v| v.is_some())
     }
+    /// Attempts to set a value given its key, returning the one it had before if any.
+    fn ts_try_replace(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let old = self.ts_try_get(&Self::SLock::from(&*handle))?;
+        self.ts_try_set(handle, value)?;
+        Ok(old)
+    }
+    /// Attempts to set a value given its key only if it doesn't exist yet, returns whether it was
+    /// set.
+    fn ts_try_set_if_absent(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<bool, Self::Error> {
+        let absent = !self.ts_try_exists(&Self::SLock::from(&*handle))?;
+        if absent {
+            self.ts_try_set(handle, value)?;
+        }
+        Ok(absent)
+    }
+    /// Attempts to set a value given its key only if it already exists, returns whether it was
+    /// set.
+    fn ts_try_replace_only(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<bool, Self::Error> {
+        let present = self.ts_try_exists(&Self::SLock::from(&*handle))?;
+        if present {
+            self.ts_try_set(handle, value)?;
+        }
+        Ok(present)
+    }
 
     /// Same as `ts_get` but it performs a one-time lock
     fn ts_one_try_get(
@@ -153,11 +204,46 @@ where
         let mut handle = self.ts_try_xlock(key)?;
         self.ts_try_set(&mut handle, value)
     }
+    /// Same as `ts_try_remove` but it performs a one-time lock
+    fn ts_one_try_remove(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let mut handle = self.ts_try_xlock(key)?;
+        self.ts_try_remove(&mut handle)
+    }
     /// Same as `ts_exists` but it performs a one-time lock
     fn ts_one_try_exists(&'lock self, key: &'lock Self::Key) -> Result<bool, Self::Error> {
         let handle = self.ts_try_slock(key)?;
         self.ts_try_exists(&handle)
     }
+    /// Same as `ts_try_replace` but it performs a one-time lock
+    fn ts_one_try_replace(
+        &'lock self,
+        key: &'lock Self::Key,
+        value: &Self::Value,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let mut handle = self.ts_try_xlock(key)?;
+        self.ts_try_replace(&mut handle, value)
+    }
+    /// Same as `ts_try_set_if_absent` but it performs a one-time lock
+    fn ts_one_try_set_if_absent(
+        &'lock self,
+        key: &'lock Self::Key,
+        value: &Self::Value,
+    ) -> Result<bool, Self::Error> {
+        let mut handle = self.ts_try_xlock(key)?;
+        self.ts_try_set_if_absent(&mut handle, value)
+    }
+    /// Same as `ts_try_replace_only` but it performs a one-time lock
+    fn ts_one_try_replace_only(
+        &'lock self,
+        key: &'lock Self::Key,
+        value: &Self::Value,
+    ) -> Result<bool, Self::Error> {
+        let mut handle = self.ts_try_xlock(key)?;
+        self.ts_try_replace_only(&mut handle, value)
+    }
 
     /// Attempt to exclusively lock a key until the handle is dropped.
     fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error>;
@@ -173,6 +259,28 @@ where
     ) -> Result<Self::SLock<'lock>, Self::Error>;
 }
 
+/// Trait for a [`ThreadSafeTryCacheStore`] whose values can be modified in place while holding
+/// their exclusive lock, analogous to [`TryCacheStoreMut`]
+#[allow(clippy::missing_errors_doc)]
+pub trait ThreadSafeTryCacheStoreMut<'lock>: ThreadSafeTryCacheStore<'lock> {
+    /// Attempts to modify the cache element in place if present, returns whether it was.
+    fn ts_try_update(
+        &'lock self,
+        handle: &mut Self::XLock,
+        f: impl FnOnce(&mut Self::Value),
+    ) -> Result<bool, Self::Error>;
+
+    /// Same as `ts_try_update` but it performs a one-time lock
+    fn ts_one_try_update(
+        &'lock self,
+        key: &'lock Self::Key,
+        f: impl FnOnce(&mut Self::Value),
+    ) -> Result<bool, Self::Error> {
+        let mut handle = self.ts_try_xlock(key)?;
+        self.ts_try_update(&mut handle, f)
+    }
+}
+
 /// Blanket implementation to allow a [`ThreadSafeCacheStore`] to behave as a
 /// [`ThreadSafeTryCacheStore`]
 impl<
@@ -209,6 +317,13 @@ impl<
         Ok(self.ts_set(handle, value))
     }
 
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(self.ts_remove(handle))
+    }
+
     fn ts_try_exists(&'lock self, handle: &Self::SLock<'lock>) -> Result<bool, Self::Error> {
         Ok(self.ts_exists(handle))
     }
@@ -251,30 +366,6 @@ impl<
 //     }
 // }
 
-/// Macro to automatically implement [`CacheStore`] on a struct that implements [`ThreadSafeCacheStore`]
-#[macro_export]
-macro_rules! implThreadUnsafe {
-    ($for:expr) => {
-        impl<K, V> CacheStore for $for {
-            type Key = K;
-            type Value = V;
-
-            fn get(&self, key: &Self::Key) -> Option<Self::Value> {
-                self.ts_one_get(key)
-            }
-
-            fn set(&mut self, key: &Self::Key, value: &Self::Value) {
-                self.ts_one_set(key, value)
-            }
-
-            fn exists(&self, key: &Self::Key) -> bool {
-                self.ts_one_exists(key)
-            }
-        }
-    };
-}
-pub use implThreadUnsafe;
-
 // /// Blanket implementation to allow a [`ThreadSafeTryCacheStore`] to behave as a [`TryCacheStore`]
 // impl<
 //         K,
@@ -301,32 +392,54 @@ pub use implThreadUnsafe;
 //     }
 // }
 
-/// Macro to automatically implement [`TryCacheStore`] on a struct that implements
-/// [`ThreadSafeTryCacheStore`]
-#[macro_export]
-macro_rules! implTryThreadUnsafe {
-    ($for:ty, $( $t:tt $( : $tb:ident)? ),*) => {
-        impl<$($t $( : $tb)?),*> TryCacheStore for $for
-            {
-            type Key = K;
-            type Value = V;
-            type Error = E;
-
-            fn try_get(&self, key: &Self::Key) -> Result<Option<Self::Value>, Self::Error> {
-                self.ts_one_try_get(key)
-            }
+/// Wrapper that makes a [`ThreadSafeTryCacheStore`] a [`TryCacheStore`], taking a one-time lock
+/// for each call. It lets thread safe stores, like the file ones, be wrapped by anything that takes
+/// a [`TryCacheStore`].
+///
+/// Generics:
+/// - `S`: [`ThreadSafeTryCacheStore`] which this wraps around.
+pub struct TryThreadUnsafeWrapper<S> {
+    pub store: S,
+}
 
-            fn try_set(&mut self, key: &Self::Key, value: &Self::Value) -> Result<(), Self::Error> {
-                self.ts_one_try_set(key, value)
-            }
+impl<S> TryThreadUnsafeWrapper<S> {
+    /// Make a new [`TryThreadUnsafeWrapper`] around the given store.
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+}
 
-            fn try_exists(&self, key: &Self::Key) -> Result<bool, Self::Error> {
-                self.ts_one_try_exists(key)
-            }
-        }
-    };
+impl<K, V, E, S> TryCacheStore for TryThreadUnsafeWrapper<S>
+where
+    S: for<'lock> ThreadSafeTryCacheStore<'lock, Key = K, Value = V, Error = E>,
+{
+    type Key = K;
+    type Value = V;
+    type Error = E;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.ts_one_try_get(key.borrow())
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.store.ts_one_try_set(key.borrow(), value.borrow())
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.ts_one_try_remove(key.borrow())
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.ts_one_try_exists(key.borrow())
+    }
 }
-pub use implTryThreadUnsafe;
 
 // wtf tho 😭
 // pub fn lol<'b, L, E: for<'a> From<PoisonError<MutexGuard<'a, L>>>>(
@@ -339,8 +452,9 @@ pub use implTryThreadUnsafe;
 // }
 
 pub mod dumb_wrappers {
+    use crate::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
     use core::{convert::Infallible, marker::PhantomData};
-    use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+    use std::sync::TryLockError;
 
     #[allow(clippy::wildcard_imports)]
     use super::*;
@@ -365,6 +479,15 @@ pub mod dumb_wrappers {
             }
         }
     }
+    impl crate::error::CacheError for EmptyDumbError {
+        fn is_transient(&self) -> bool {
+            matches!(self, Self::WouldBlock)
+        }
+
+        fn is_poison(&self) -> bool {
+            matches!(self, Self::Poisoned)
+        }
+    }
     impl From<Infallible> for EmptyDumbError {
         fn from(_: Infallible) -> Self {
             unreachable!()
@@ -412,7 +535,6 @@ pub mod dumb_wrappers {
         pub store: RwLock<S>,
         __phantom: PhantomData<&'a ()>,
     }
-    // implTryThreadUnsafe!(DumbTryThreadSafeWrapper<K, V, E, S>, K, V, E, S: TryCacheStore<>);
     // impl<K, V, E, S: TryCacheStore<Key = K, Value = V, Error = E>> crate::TryCacheStore
     //     for DumbTryThreadSafeWrapper<K, V, E, S>
     // {
@@ -507,6 +629,13 @@ pub mod dumb_wrappers {
             handle.0.try_set(handle.1, value)
         }
 
+        fn ts_try_remove(
+            &self,
+            handle: &mut Self::XLock,
+        ) -> Result<Option<Self::Value>, Self::Error> {
+            handle.0.try_remove(handle.1)
+        }
+
         fn ts_try_exists(&self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
             handle.try_exists(handle.get_key())
         }
@@ -0,0 +1,133 @@
+//! Counting semaphore to limit how many threads do something at once.
+//!
+//! The thread safe generative wrappers take one to cap how many generators run at the same time,
+//! the same [`Semaphore`] can be shared between several of them to cap them all together.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::thread_safe::semaphore::Semaphore;
+//! #
+//! let semaphore = Semaphore::new(2);
+//!
+//! let first = semaphore.acquire();
+//! let second = semaphore.acquire();
+//! assert!(semaphore.try_acquire().is_none());
+//!
+//! drop(first);
+//! assert_eq!(semaphore.available(), 1);
+//! ```
+
+use std::sync::PoisonError;
+
+use crate::sync::{Condvar, Mutex};
+
+/// Counting semaphore, blocks threads acquiring it while all its permits are taken.
+#[derive(Debug)]
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permit of a [`Semaphore`], given back when dropped.
+#[derive(Debug)]
+#[must_use = "the permit is given back as soon as it's dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Makes a semaphore with the given amount of permits.
+    #[must_use]
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Takes a permit, waiting for one to be given back if there are none.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock().unwrap_or_else(PoisonError::into_inner);
+        while *permits == 0 {
+            permits = self
+                .released
+                .wait(permits)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *permits -= 1;
+        SemaphorePermit { semaphore: self }
+    }
+
+    /// Takes a permit if there's any available.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut permits = self.permits.lock().unwrap_or_else(PoisonError::into_inner);
+        if *permits == 0 {
+            return None;
+        }
+        *permits -= 1;
+        Some(SemaphorePermit { semaphore: self })
+    }
+
+    /// Amount of permits not taken right now.
+    pub fn available(&self) -> usize {
+        *self.permits.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        *self
+            .semaphore
+            .permits
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        prelude::*,
+        stores::MemoryStore,
+        thread_safe::{
+            dumb_wrappers::{DumbTryThreadSafeWrapper, EmptyDumbError},
+            generative::{ThreadSafeGenTryCacheStoreWrapper, ThreadSafeTryGenCacheStore},
+        },
+        TryCacheStoreErrorMap,
+    };
+    use core::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use std::{thread, time::Duration, vec::Vec};
+
+    #[test]
+    fn generators_are_capped() {
+        let (running, max_running) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let inner: TryCacheStoreErrorMap<_, _, _, EmptyDumbError, _> =
+            MemoryStore::<usize, usize>::default().into();
+        let store: ThreadSafeGenTryCacheStoreWrapper<_, _, EmptyDumbError, _, _, Infallible, _, _> =
+            ThreadSafeGenTryCacheStoreWrapper::new(
+                DumbTryThreadSafeWrapper::new(inner),
+                |&key: &usize, ()| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(key)
+                },
+            )
+            .with_max_concurrent(2);
+
+        let keys: Vec<usize> = (0..8).collect();
+        thread::scope(|scope| {
+            for key in &keys {
+                let store = &store;
+                scope.spawn(move || store.ts_try_gen_new(key, ()).unwrap());
+            }
+        });
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+        assert_eq!(store.ts_one_try_get(&7).unwrap(), Some(7));
+    }
+}
@@ -0,0 +1,273 @@
+//! Throttling of the operations and bytes that reach a store.
+//!
+//! Refilling a cold cache can flood its backend with writes, starving the rest of the
+//! application of disk or network I/O. [`ThrottledStore`] limits the operations and bytes per
+//! second that reach the inner store with [`TokenBucket`]s, making callers wait when they go over
+//! the limits.
+//!
+//! Each bucket holds up to a second worth of its rate, so short bursts under that go through right
+//! away. Bytes are weighed with [`MemSize`], only counting values.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{TryCacheStore, stores::MemoryStore, throttled::ThrottledStore};
+//! #
+//! let mut store = ThrottledStore::new(MemoryStore::new())
+//!     .with_ops_per_sec(1000.0)
+//!     .with_bytes_per_sec(1024.0 * 1024.0);
+//!
+//! // A burst under the limits doesn't wait
+//! for i in 0..100u32 {
+//!     store.try_set(i, i).unwrap();
+//! }
+//! assert!(store.waited().is_zero());
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    clock::{Clock, SystemClock},
+    health::{Health, HealthCheck},
+    size::{MemSize, SizedStore},
+};
+
+use core::time::Duration;
+use std::sync::{Mutex, PoisonError};
+
+/// Token bucket, refilled at a steady rate up to a burst capacity.
+///
+/// Tokens can be reserved past what the bucket has, leaving it in debt, so callers know how long
+/// to wait for their share instead of retrying.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    /// Time of the last refill, as told by the clock of the caller.
+    last: Option<Duration>,
+}
+
+impl TokenBucket {
+    /// Make a new full [`TokenBucket`] refilled with `rate` tokens per second, up to `burst`.
+    #[must_use]
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: None,
+        }
+    }
+
+    /// Takes `amount` tokens at time `now`, returns how long to wait until they would've been
+    /// available, zero if they were already.
+    pub fn reserve(&mut self, amount: f64, now: Duration) -> Duration {
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_sub(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        }
+        self.last = Some(now);
+
+        self.tokens -= amount;
+        if self.tokens >= 0.0 || self.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+}
+
+/// Buckets of a [`ThrottledStore`] and how long it made callers wait in total.
+#[derive(Debug, Default)]
+struct Limits {
+    ops: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    waited: Duration,
+}
+
+/// Wrapper around a [`TryCacheStore`] that limits the operations and bytes per second that reach
+/// it, see the [module docs][self].
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `C`: [`Clock`] used to refill the buckets, the system time by default.
+pub struct ThrottledStore<S, C: Clock = SystemClock> {
+    pub store: S,
+    clock: C,
+    limits: Mutex<Limits>,
+    sleep: fn(Duration),
+}
+
+impl<S: TryCacheStore> ThrottledStore<S>
+where
+    S::Value: MemSize,
+{
+    /// Make a new [`ThrottledStore`] around the given store, without limits.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            clock: SystemClock,
+            limits: Mutex::new(Limits::default()),
+            sleep: std::thread::sleep,
+        }
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> ThrottledStore<S, C>
+where
+    S::Value: MemSize,
+{
+    /// Replaces the clock used to refill the buckets.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> ThrottledStore<S, C2> {
+        ThrottledStore {
+            store: self.store,
+            clock,
+            limits: self.limits,
+            sleep: self.sleep,
+        }
+    }
+
+    /// Limits the operations per second, of any kind.
+    #[must_use]
+    pub fn with_ops_per_sec(mut self, rate: f64) -> Self {
+        self.limits_mut().ops = Some(TokenBucket::new(rate, rate));
+        self
+    }
+
+    /// Limits the bytes of values read or written per second.
+    #[must_use]
+    pub fn with_bytes_per_sec(mut self, rate: f64) -> Self {
+        self.limits_mut().bytes = Some(TokenBucket::new(rate, rate));
+        self
+    }
+
+    /// Total time callers were made to wait.
+    pub fn waited(&self) -> Duration {
+        self.limits
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .waited
+    }
+
+    fn limits_mut(&mut self) -> &mut Limits {
+        self.limits
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes an operation of `bytes` from the buckets, waiting if they're short.
+    #[allow(clippy::cast_precision_loss)]
+    fn throttle(&self, ops: u8, bytes: usize) {
+        let wait = {
+            let mut limits = self.limits.lock().unwrap_or_else(PoisonError::into_inner);
+            let now = self.clock.now();
+            let ops_wait = limits
+                .ops
+                .as_mut()
+                .map_or(Duration::ZERO, |bucket| bucket.reserve(f64::from(ops), now));
+            let bytes_wait = limits
+                .bytes
+                .as_mut()
+                .map_or(Duration::ZERO, |bucket| bucket.reserve(bytes as f64, now));
+            let wait = ops_wait.max(bytes_wait);
+            limits.waited += wait;
+            wait
+        };
+        if !wait.is_zero() {
+            (self.sleep)(wait);
+        }
+    }
+}
+
+impl<S: SizedStore, C: Clock> SizedStore for ThrottledStore<S, C> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+/// Pings aren't throttled, so readiness can still be told when the limit is reached.
+impl<S: HealthCheck, C: Clock> HealthCheck for ThrottledStore<S, C> {
+    type Error = S::Error;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        self.store.ping()
+    }
+}
+
+impl<S: TryCacheStore, C: Clock> TryCacheStore for ThrottledStore<S, C>
+where
+    S::Value: MemSize,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    /// The bytes of the value are taken after reading it, so they delay the next operations.
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.throttle(1, 0);
+        let value = self.store.try_get(key)?;
+        if let Some(value) = &value {
+            self.throttle(0, value.mem_size());
+        }
+        Ok(value)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let value = value.borrow();
+        self.throttle(1, value.mem_size());
+        self.store.try_set(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.throttle(1, 0);
+        self.store.try_remove(key)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.throttle(1, 0);
+        self.store.try_exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+
+    #[test]
+    fn waits_for_refill() {
+        let mut bucket = TokenBucket::new(10.0, 10.0);
+        let start = Duration::ZERO;
+        assert_eq!(bucket.reserve(10.0, start), Duration::ZERO);
+        assert_eq!(bucket.reserve(5.0, start), Duration::from_millis(500));
+        // Refilled up to the burst only
+        assert_eq!(
+            bucket.reserve(10.0, Duration::from_secs(30)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn throttles_bytes() {
+        let clock = MockClock::default();
+        let mut store = ThrottledStore::new(MemoryStore::<u8, u64>::new())
+            .with_bytes_per_sec(16.0)
+            .with_clock(&clock);
+        store.sleep = |_| {};
+
+        store.try_set(0, 0).unwrap();
+        store.try_set(1, 0).unwrap();
+        assert!(store.waited().is_zero());
+        store.try_get(0).unwrap();
+        assert_eq!(store.waited(), Duration::from_millis(500));
+
+        clock.advance(Duration::from_secs(1));
+        store.try_exists(0).unwrap();
+        assert_eq!(store.waited(), Duration::from_millis(500));
+    }
+}
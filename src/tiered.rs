@@ -0,0 +1,518 @@
+//! Two levels of stores, a small fast one in front of a big slow one.
+//!
+//! [`TieredStore`] reads from its first tier and falls back to the second one on a miss, promoting
+//! the values found there to the first tier. Writes go to both tiers.
+//!
+//! Promoting every value read pollutes the small first tier with entries read only once, so
+//! promotion can be limited to keys read [a few times][TieredStore::with_promotion_after] from the
+//! second tier or to [small values][TieredStore::with_max_promoted_size]. Promotions can also be
+//! [left][TieredStore::with_background_promotion] to a background task, so reads don't wait for the
+//! first tier to be written.
+//!
+//! [Removing][TryCacheStore::try_remove] a key removes it from both tiers and also leaves a
+//! tombstone: while it lasts the key is a miss on both tiers, so stale copies of the value, like
+//! the ones of replicas feeding the second tier, aren't served or promoted again.
+//! [`tombstone`][TieredStore::tombstone] only leaves the tombstone, for tiers whose entries
+//! shouldn't be touched. Setting the key again clears its tombstone. Tombstones expire after a TTL
+//! to bound their memory, which should outlive any stale copy, like the TTL of the entries of the
+//! tiers themselves.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{TryCacheStore, stores::MemoryStore, tiered::TieredStore};
+//! #
+//! let mut store: TieredStore<MemoryStore<&str, &str>, _> =
+//!     TieredStore::new(MemoryStore::new(), MemoryStore::new(), Duration::from_secs(60));
+//!
+//! store.l2.try_set("key", "from l2").unwrap();
+//! assert_eq!(store.try_get("key").unwrap(), Some("from l2"));
+//!
+//! // Both tiers still have it, but it's gone
+//! store.tombstone("key");
+//! assert_eq!(store.try_get("key").unwrap(), None);
+//! ```
+//!
+//! Promoting in the background, only keys read twice:
+//! ```rust
+//! # use std::{sync::Arc, time::Duration};
+//! # use ezcache::{TryCacheStore, spawn::ThreadSpawner, stores::MemoryStore, tiered::TieredStore};
+//! #
+//! let mut store: TieredStore<MemoryStore<u32, u32>, _> =
+//!     TieredStore::new(MemoryStore::new(), MemoryStore::new(), Duration::from_secs(60))
+//!         .with_promotion_after(2)
+//!         .with_background_promotion();
+//! store.l2.try_set(0, 0).unwrap();
+//!
+//! let store = Arc::new(store);
+//! TieredStore::spawn_promoter(&store, &ThreadSpawner, Duration::from_millis(10));
+//! store.try_get(0).unwrap();
+//! store.try_get(0).unwrap();
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    clock::{Clock, SystemClock},
+};
+
+use core::{hash::Hash, ops::ControlFlow, time::Duration};
+use std::{
+    boxed::Box,
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    health::{Health, HealthCheck},
+    size::MemSize,
+    spawn::Spawner,
+};
+
+/// Error of a [`TieredStore`].
+#[derive(Debug)]
+pub enum TieredError<E1, E2> {
+    /// The first tier failed.
+    L1(E1),
+    /// The second tier failed.
+    L2(E2),
+}
+impl<E1: std::error::Error + 'static, E2: std::error::Error + 'static> std::error::Error
+    for TieredError<E1, E2>
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::L1(err) => Some(err),
+            Self::L2(err) => Some(err),
+        }
+    }
+}
+impl<E1: core::fmt::Display, E2: core::fmt::Display> core::fmt::Display for TieredError<E1, E2> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::L1(err) => writeln!(f, "first tier error: {err}"),
+            Self::L2(err) => writeln!(f, "second tier error: {err}"),
+        }
+    }
+}
+
+/// Most keys whose hits on the second tier are counted at once. Once reached, all counts start
+/// over, so scans of keys read once can't make them grow forever.
+const MAX_COUNTED_KEYS: usize = 4096;
+
+/// Tells the size of values, for the promotion size threshold.
+type ValueSizer<V> = fn(&V) -> usize;
+
+/// Promotions that are still pending.
+struct Promotions<K, V> {
+    /// Hits on the second tier of keys not promoted yet.
+    hits: HashMap<K, u32>,
+    /// Values waiting to be promoted in the background.
+    queued: HashMap<K, V>,
+}
+
+/// Store of two tiers, see the [module docs][self].
+///
+/// The first tier is behind a lock, as reads promote values to it. Keys read from the second tier
+/// fewer times than needed to promote them are counted until they are, up to 4096 keys at once.
+///
+/// Generics:
+/// - `L1`: [`TryCacheStore`] of the first tier, the one read first.
+/// - `L2`: [`TryCacheStore`] of the second tier, with the same keys and values.
+/// - `C`: [`Clock`] used to expire tombstones, the system time by default.
+pub struct TieredStore<L1: TryCacheStore, L2, C: Clock = SystemClock> {
+    l1: Mutex<L1>,
+    pub l2: L2,
+    clock: C,
+    tombstone_ttl: Duration,
+    /// Removed keys, with the time their tombstone expires at.
+    tombstones: HashMap<L1::Key, Duration>,
+    promote_after: u32,
+    max_promoted_size: Option<(usize, ValueSizer<L1::Value>)>,
+    background: bool,
+    promotions: Mutex<Promotions<L1::Key, L1::Value>>,
+}
+
+impl<L1, L2> TieredStore<L1, L2>
+where
+    L1: TryCacheStore,
+    L1::Key: Hash + Eq + Clone,
+    L1::Value: Clone,
+    L2: TryCacheStore<Key = L1::Key, Value = L1::Value>,
+{
+    /// Make a new [`TieredStore`] from its tiers, whose removed keys are kept as misses for
+    /// `tombstone_ttl`.
+    pub fn new(l1: L1, l2: L2, tombstone_ttl: Duration) -> Self {
+        Self {
+            l1: Mutex::new(l1),
+            l2,
+            clock: SystemClock,
+            tombstone_ttl,
+            tombstones: HashMap::new(),
+            promote_after: 1,
+            max_promoted_size: None,
+            background: false,
+            promotions: Mutex::new(Promotions {
+                hits: HashMap::new(),
+                queued: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl<L1, L2, C: Clock> TieredStore<L1, L2, C>
+where
+    L1: TryCacheStore,
+    L1::Key: Hash + Eq + Clone,
+    L1::Value: Clone,
+    L2: TryCacheStore<Key = L1::Key, Value = L1::Value>,
+{
+    /// Replaces the clock used to expire tombstones, meant to be done right away.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> TieredStore<L1, L2, C2> {
+        TieredStore {
+            l1: self.l1,
+            l2: self.l2,
+            clock,
+            tombstone_ttl: self.tombstone_ttl,
+            tombstones: self.tombstones,
+            promote_after: self.promote_after,
+            max_promoted_size: self.max_promoted_size,
+            background: self.background,
+            promotions: self.promotions,
+        }
+    }
+
+    /// Only promotes keys once they were read this many times from the second tier.
+    ///
+    /// Hits are counted for up to 4096 keys, reading one more clears all counts. So keys read
+    /// often enough are still promoted under scans, but ones read rarely might never be.
+    #[must_use]
+    pub fn with_promotion_after(mut self, hits: u32) -> Self {
+        self.promote_after = hits;
+        self
+    }
+
+    /// Never promotes values taking more than `bytes`, as told by [`MemSize`].
+    #[must_use]
+    pub fn with_max_promoted_size(mut self, bytes: usize) -> Self
+    where
+        L1::Value: MemSize,
+    {
+        self.max_promoted_size = Some((bytes, MemSize::mem_size));
+        self
+    }
+
+    /// Queues promotions instead of writing them on the read that found them, they're written
+    /// by [`promote_pending`][TieredStore::promote_pending].
+    #[must_use]
+    pub fn with_background_promotion(mut self) -> Self {
+        self.background = true;
+        self
+    }
+
+    /// Writes the queued promotions to the first tier, returns how many were written.
+    ///
+    /// # Errors
+    /// Fails when setting a value on the first tier does, the ones not written yet are dropped.
+    pub fn promote_pending(&self) -> Result<usize, L1::Error> {
+        let queued = core::mem::take(&mut self.lock_promotions().queued);
+        let mut l1 = self.lock_l1();
+        let mut count = 0;
+        for (key, value) in queued {
+            // Removed after being queued
+            if !self.is_removed(&key) {
+                l1.try_set(&key, &value)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Spawns a task that writes the queued promotions every `interval`, it stops once the store
+    /// is dropped. Failed promotions are dropped, the key is promoted again on a later read.
+    pub fn spawn_promoter(this: &Arc<Self>, spawner: &impl Spawner, interval: Duration)
+    where
+        Self: Send + Sync + 'static,
+    {
+        let store = Arc::downgrade(this);
+        spawner.spawn_every(
+            interval,
+            Box::new(move || {
+                let Some(store) = store.upgrade() else {
+                    return ControlFlow::Break(());
+                };
+                _ = store.promote_pending();
+                ControlFlow::Continue(())
+            }),
+        );
+    }
+
+    /// Returns both tiers.
+    pub fn into_inner(self) -> (L1, L2) {
+        (
+            self.l1.into_inner().unwrap_or_else(PoisonError::into_inner),
+            self.l2,
+        )
+    }
+
+    /// Leaves a tombstone of a key, so it's a miss on both tiers until it's set again or the
+    /// tombstone expires. The entries of the tiers are left as they are.
+    pub fn tombstone(&mut self, key: impl Borrow<L1::Key>) {
+        let key = key.borrow();
+        self.prune_tombstones();
+        self.forget_promotion(key);
+        let expires = self.clock.now().saturating_add(self.tombstone_ttl);
+        self.tombstones.insert(key.clone(), expires);
+    }
+
+    /// Counts a hit of `key` on the second tier, returns whether its value should be promoted.
+    fn should_promote(&self, key: &L1::Key, value: &L1::Value) -> bool {
+        if let Some((max, size)) = self.max_promoted_size {
+            if size(value) > max {
+                return false;
+            }
+        }
+        if self.promote_after <= 1 {
+            return true;
+        }
+        let mut promotions = self.lock_promotions();
+        if promotions.hits.len() >= MAX_COUNTED_KEYS && !promotions.hits.contains_key(key) {
+            promotions.hits.clear();
+        }
+        let hits = promotions.hits.entry(key.clone()).or_default();
+        *hits += 1;
+        if *hits < self.promote_after {
+            return false;
+        }
+        promotions.hits.remove(key);
+        true
+    }
+
+    /// Drops the hits and queued promotion of a key, as its value is outdated.
+    fn forget_promotion(&mut self, key: &L1::Key) {
+        let promotions = self
+            .promotions
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        promotions.hits.remove(key);
+        promotions.queued.remove(key);
+    }
+
+    /// Whether the key has a tombstone that didn't expire yet.
+    fn is_removed(&self, key: &L1::Key) -> bool {
+        self.tombstones
+            .get(key)
+            .is_some_and(|expires| *expires > self.clock.now())
+    }
+
+    /// Drops the expired tombstones.
+    fn prune_tombstones(&mut self) {
+        let now = self.clock.now();
+        self.tombstones.retain(|_, expires| *expires > now);
+    }
+
+    fn lock_l1(&self) -> MutexGuard<'_, L1> {
+        self.l1.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_promotions(&self) -> MutexGuard<'_, Promotions<L1::Key, L1::Value>> {
+        self.promotions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Pings both tiers, as healthy as the worst of them.
+impl<L1, L2, C: Clock> HealthCheck for TieredStore<L1, L2, C>
+where
+    L1: TryCacheStore + HealthCheck,
+    L1::Key: Hash + Eq + Clone,
+    L1::Value: Clone,
+    L2: TryCacheStore<Key = L1::Key, Value = L1::Value> + HealthCheck,
+{
+    type Error = TieredError<<L1 as HealthCheck>::Error, <L2 as HealthCheck>::Error>;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        let l1 = self.lock_l1().ping().map_err(TieredError::L1)?;
+        let l2 = self.l2.ping().map_err(TieredError::L2)?;
+        Ok(l1.worst(l2))
+    }
+}
+
+impl<L1, L2, C: Clock> TryCacheStore for TieredStore<L1, L2, C>
+where
+    L1: TryCacheStore,
+    L1::Key: Hash + Eq + Clone,
+    L1::Value: Clone,
+    L2: TryCacheStore<Key = L1::Key, Value = L1::Value>,
+{
+    type Key = L1::Key;
+    type Value = L1::Value;
+    type Error = TieredError<L1::Error, L2::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        if self.is_removed(key) {
+            return Ok(None);
+        }
+        if let Some(value) = self.lock_l1().try_get(key).map_err(TieredError::L1)? {
+            return Ok(Some(value));
+        }
+
+        let Some(value) = self.l2.try_get(key).map_err(TieredError::L2)? else {
+            return Ok(None);
+        };
+        if self.should_promote(key, &value) {
+            if self.background {
+                self.lock_promotions()
+                    .queued
+                    .insert(key.clone(), value.clone());
+            } else {
+                self.lock_l1()
+                    .try_set(key, &value)
+                    .map_err(TieredError::L1)?;
+            }
+        }
+        Ok(Some(value))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let (key, value) = (key.borrow(), value.borrow());
+        self.l2.try_set(key, value).map_err(TieredError::L2)?;
+        self.l1
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_set(key, value)
+            .map_err(TieredError::L1)?;
+        self.tombstones.remove(key);
+        self.forget_promotion(key);
+        Ok(())
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let removed = self.is_removed(key);
+        let old_l2 = self.l2.try_remove(key).map_err(TieredError::L2)?;
+        let old_l1 = self
+            .l1
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_remove(key)
+            .map_err(TieredError::L1)?;
+        self.tombstone(key);
+        Ok(old_l1.or(old_l2).filter(|_| !removed))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        let key = key.borrow();
+        if self.is_removed(key) {
+            return Ok(false);
+        }
+        Ok(self.lock_l1().try_exists(key).map_err(TieredError::L1)?
+            || self.l2.try_exists(key).map_err(TieredError::L2)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+    use std::{vec, vec::Vec};
+
+    #[test]
+    fn tombstones_hide_both_tiers() {
+        let clock = MockClock::default();
+        let mut store = TieredStore::new(MemoryStore::new(), MemoryStore::new(), Duration::MAX)
+            .with_clock(&clock);
+        store.try_set(1, 1).unwrap();
+        store.l2.try_set(2, 2).unwrap();
+
+        store.tombstone(1);
+        store.tombstone(2);
+        // A stale replica writes the old value again
+        store.l2.try_set(2, 2).unwrap();
+        assert_eq!(store.try_get(1).unwrap(), None);
+        assert!(!store.try_exists(2).unwrap());
+
+        store.try_set(2, 3).unwrap();
+        assert_eq!(store.try_get(2).unwrap(), Some(3));
+        let (l1, _) = store.into_inner();
+        assert_eq!(l1.try_get(1).unwrap(), Some(1));
+        assert_eq!(l1.try_get(2).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn try_remove_clears_both_tiers() {
+        let mut store = TieredStore::new(MemoryStore::new(), MemoryStore::new(), Duration::MAX);
+        store.try_set(1, 1).unwrap();
+        assert_eq!(store.try_remove(1).unwrap(), Some(1));
+        assert_eq!(store.try_remove(1).unwrap(), None);
+
+        // A stale replica writes the old value again
+        store.l2.try_set(1, 1).unwrap();
+        assert!(!store.try_exists(1).unwrap());
+        let (l1, l2) = store.into_inner();
+        assert!(!l1.try_exists(1).unwrap());
+        assert_eq!(l2.try_get(1).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn promotes_misses_and_expires_tombstones() {
+        let clock = MockClock::default();
+        let mut store = TieredStore::new(
+            MemoryStore::new(),
+            MemoryStore::new(),
+            Duration::from_secs(1),
+        )
+        .with_clock(&clock);
+        store.l2.try_set(1, 1).unwrap();
+        assert_eq!(store.try_get(1).unwrap(), Some(1));
+
+        store.tombstone(1);
+        assert_eq!(store.try_get(1).unwrap(), None);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(store.try_get(1).unwrap(), Some(1));
+        assert_eq!(store.into_inner().0.try_get(1).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn promotion_policy() {
+        let mut store: TieredStore<MemoryStore<u32, Vec<u8>>, _> =
+            TieredStore::new(MemoryStore::new(), MemoryStore::new(), Duration::MAX)
+                .with_promotion_after(2)
+                .with_max_promoted_size(64)
+                .with_background_promotion();
+        store.l2.try_set(0, vec![0; 8]).unwrap();
+        store.l2.try_set(1, vec![0; 1024]).unwrap();
+        for _ in 0..2 {
+            store.try_get(0).unwrap();
+            store.try_get(1).unwrap();
+        }
+        // Only the small one, on its second hit, and not until queued promotions are written
+        assert!(!store.lock_l1().try_exists(0).unwrap());
+        assert_eq!(store.promote_pending().unwrap(), 1);
+        let l1 = store.into_inner().0;
+        assert!(l1.try_exists(0).unwrap());
+        assert!(!l1.try_exists(1).unwrap());
+    }
+
+    #[test]
+    fn scans_dont_grow_hit_counts() {
+        let mut store: TieredStore<MemoryStore<usize, usize>, _> =
+            TieredStore::new(MemoryStore::new(), MemoryStore::new(), Duration::MAX)
+                .with_promotion_after(2);
+        for key in 0..2 * MAX_COUNTED_KEYS {
+            store.l2.try_set(key, key).unwrap();
+        }
+        for key in 0..2 * MAX_COUNTED_KEYS {
+            assert_eq!(store.try_get(key).unwrap(), Some(key));
+        }
+        assert!(store.lock_promotions().hits.len() <= MAX_COUNTED_KEYS);
+    }
+}
@@ -0,0 +1,343 @@
+//! Stores whose values are kept in another format, like one per tier.
+//!
+//! The tiers of a [`TieredStore`][crate::tiered::TieredStore] must have the same values, but the
+//! best format for each of them isn't the same: a local first tier is best read in place, like
+//! with rkyv, and a remote second tier shared with other languages is best kept as JSON.
+//! [`TranscodingStore`] bridges them, it takes and returns values of any type and keeps them in the
+//! store it wraps as a [`Codec`] encodes them. With one around each tier, values are encoded in the
+//! format of the tier they're written to, including when they're promoted to the first tier.
+//!
+//! [`Json`] (feature "json") and [`Rkyv`] (feature "rkyv") encode values into bytes, and other
+//! formats just need to implement [`Codec`].
+//!
+//! # Examples
+//! ```rust
+//! # #[cfg(all(feature = "json", feature = "rkyv"))] {
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     stores::MemoryStore,
+//! #     tiered::TieredStore,
+//! #     transcoding::{Json, Rkyv, TranscodingStore},
+//! # };
+//! #
+//! let l1 = TranscodingStore::new(MemoryStore::<&str, Vec<u8>>::new(), Rkyv);
+//! let l2 = TranscodingStore::new(MemoryStore::<&str, Vec<u8>>::new(), Json);
+//! let mut store: TieredStore<TranscodingStore<(String, u32), _, _>, _> =
+//!     TieredStore::new(l1, l2, Duration::from_secs(60));
+//!
+//! // As another client would write it
+//! store.l2.store.try_set("ada", br#"["Ada",36]"#.to_vec()).unwrap();
+//! assert_eq!(store.try_get("ada").unwrap(), Some((String::from("Ada"), 36)));
+//! # }
+//! ```
+
+use crate::{
+    __internal_prelude::*,
+    error::CacheError,
+    health::{Health, HealthCheck},
+    size::SizedStore,
+};
+
+#[cfg(any(feature = "json", feature = "rkyv"))]
+use std::vec::Vec;
+
+/// Turns values into the format they're stored in and back.
+pub trait Codec<V> {
+    /// Values as stored.
+    type Encoded;
+    type Error;
+
+    /// Returns the value as stored.
+    ///
+    /// # Errors
+    /// When the value can't be represented in the format.
+    fn encode(&self, value: &V) -> Result<Self::Encoded, Self::Error>;
+
+    /// Returns the value of what's stored.
+    ///
+    /// # Errors
+    /// When what's stored isn't a valid value, like if it was written by something else.
+    fn decode(&self, encoded: &Self::Encoded) -> Result<V, Self::Error>;
+}
+
+/// Stores values as JSON.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl<V: serde::Serialize + serde::de::DeserializeOwned> Codec<V> for Json {
+    type Encoded = Vec<u8>;
+    type Error = serde_json::Error;
+
+    fn encode(&self, value: &V) -> Result<Vec<u8>, Self::Error> {
+        serde_json::to_vec(value)
+    }
+
+    fn decode(&self, encoded: &Vec<u8>) -> Result<V, Self::Error> {
+        serde_json::from_slice(encoded)
+    }
+}
+
+/// Stores values in the format of rkyv, which is validated before reading it back.
+#[cfg(feature = "rkyv")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rkyv;
+
+#[cfg(feature = "rkyv")]
+impl<V> Codec<V> for Rkyv
+where
+    V: rkyv::Archive
+        + for<'a> rkyv::Serialize<
+            rkyv::api::high::HighSerializer<
+                rkyv::util::AlignedVec,
+                rkyv::ser::allocator::ArenaHandle<'a>,
+                rkyv::rancor::Error,
+            >,
+        >,
+    V::Archived: for<'a> rkyv::bytecheck::CheckBytes<rkyv::api::high::HighValidator<'a, rkyv::rancor::Error>>
+        + rkyv::Deserialize<V, rkyv::rancor::Strategy<rkyv::de::Pool, rkyv::rancor::Error>>,
+{
+    type Encoded = Vec<u8>;
+    type Error = rkyv::rancor::Error;
+
+    fn encode(&self, value: &V) -> Result<Vec<u8>, Self::Error> {
+        Ok(rkyv::to_bytes(value)?.into_vec())
+    }
+
+    fn decode(&self, encoded: &Vec<u8>) -> Result<V, Self::Error> {
+        // Stores don't keep the alignment archives need
+        let mut aligned = rkyv::util::AlignedVec::<16>::with_capacity(encoded.len());
+        aligned.extend_from_slice(encoded);
+        rkyv::from_bytes(&aligned)
+    }
+}
+
+/// Error of a [`TranscodingStore`].
+#[derive(Debug)]
+pub enum TranscodingError<E, C> {
+    /// The inner store failed.
+    Store(E),
+    /// A value couldn't be encoded, or the stored one decoded.
+    Codec(C),
+}
+impl<E: std::error::Error + 'static, C: std::error::Error + 'static> std::error::Error
+    for TranscodingError<E, C>
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Store(err) => Some(err),
+            Self::Codec(err) => Some(err),
+        }
+    }
+}
+impl<E: core::fmt::Display, C: core::fmt::Display> core::fmt::Display for TranscodingError<E, C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+            Self::Codec(err) => writeln!(f, "codec error: {err}"),
+        }
+    }
+}
+
+impl<E: CacheError, C> CacheError for TranscodingError<E, C> {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_transient())
+    }
+
+    fn is_poison(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_poison())
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Store(err) if err.is_not_found())
+    }
+}
+
+/// Wrapper around a [`TryCacheStore`] that keeps values as a [`Codec`] encodes them, see the
+/// [module docs][self].
+///
+/// Generics:
+/// - `V`: Type of the values, before encoding.
+/// - `S`: [`TryCacheStore`] which this wraps around, with encoded values.
+/// - `C`: [`Codec`] for the values.
+pub struct TranscodingStore<V, S, C> {
+    pub store: S,
+    codec: C,
+    phantom: PhantomData<V>,
+}
+
+impl<V, S, C> TranscodingStore<V, S, C>
+where
+    S: TryCacheStore,
+    C: Codec<V, Encoded = S::Value>,
+{
+    /// Make a new [`TranscodingStore`] around the given store.
+    pub fn new(store: S, codec: C) -> Self {
+        Self {
+            store,
+            codec,
+            phantom: PhantomData,
+        }
+    }
+
+    fn encode(&self, value: &V) -> Result<S::Value, TranscodingError<S::Error, C::Error>> {
+        self.codec.encode(value).map_err(TranscodingError::Codec)
+    }
+
+    fn decode(
+        &self,
+        encoded: Option<S::Value>,
+    ) -> Result<Option<V>, TranscodingError<S::Error, C::Error>> {
+        encoded
+            .map(|encoded| self.codec.decode(&encoded))
+            .transpose()
+            .map_err(TranscodingError::Codec)
+    }
+}
+
+impl<V, S: SizedStore, C> SizedStore for TranscodingStore<V, S, C> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<V, S: HealthCheck, C> HealthCheck for TranscodingStore<V, S, C> {
+    type Error = S::Error;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        self.store.ping()
+    }
+}
+
+impl<V, S, C> TryCacheStore for TranscodingStore<V, S, C>
+where
+    S: TryCacheStore,
+    C: Codec<V, Encoded = S::Value>,
+{
+    type Key = S::Key;
+    type Value = V;
+    type Error = TranscodingError<S::Error, C::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        let encoded = self.store.try_get(key).map_err(TranscodingError::Store)?;
+        self.decode(encoded)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let encoded = self.encode(value.borrow())?;
+        self.store
+            .try_set(key, encoded)
+            .map_err(TranscodingError::Store)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let old = self
+            .store
+            .try_remove(key)
+            .map_err(TranscodingError::Store)?;
+        self.decode(old)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key).map_err(TranscodingError::Store)
+    }
+
+    fn try_replace(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let encoded = self.encode(value.borrow())?;
+        let old = self
+            .store
+            .try_replace(key, encoded)
+            .map_err(TranscodingError::Store)?;
+        self.decode(old)
+    }
+
+    fn try_set_if_absent(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let encoded = self.encode(value.borrow())?;
+        self.store
+            .try_set_if_absent(key, encoded)
+            .map_err(TranscodingError::Store)
+    }
+
+    fn try_replace_only(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<bool, Self::Error> {
+        let encoded = self.encode(value.borrow())?;
+        self.store
+            .try_replace_only(key, encoded)
+            .map_err(TranscodingError::Store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::MemoryStore;
+    use std::string::String;
+
+    /// Stores numbers as their decimal text.
+    struct Decimal;
+
+    impl Codec<u32> for Decimal {
+        type Encoded = String;
+        type Error = core::num::ParseIntError;
+
+        fn encode(&self, value: &u32) -> Result<String, Self::Error> {
+            Ok(std::format!("{value}"))
+        }
+
+        fn decode(&self, encoded: &String) -> Result<u32, Self::Error> {
+            encoded.parse()
+        }
+    }
+
+    #[test]
+    fn encodes_values_as_stored() {
+        let mut store = TranscodingStore::new(MemoryStore::<u8, String>::new(), Decimal);
+        assert_eq!(store.try_replace(0, 42).unwrap(), None);
+        assert_eq!(store.store.try_get(0).unwrap().as_deref(), Some("42"));
+        assert_eq!(store.try_replace(0, 7).unwrap(), Some(42));
+
+        store.store.try_set(1, String::from("nope")).unwrap();
+        assert!(matches!(store.try_get(1), Err(TranscodingError::Codec(_))));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "rkyv"))]
+    fn promotes_across_formats() {
+        use crate::tiered::TieredStore;
+        use core::time::Duration;
+        use std::vec::Vec;
+
+        let mut store: TieredStore<TranscodingStore<Vec<u16>, _, _>, _> = TieredStore::new(
+            TranscodingStore::new(MemoryStore::<u8, Vec<u8>>::new(), Rkyv),
+            TranscodingStore::new(MemoryStore::<u8, Vec<u8>>::new(), Json),
+            Duration::from_mins(1),
+        );
+        store.l2.store.try_set(0, b"[1,2,3]".to_vec()).unwrap();
+        assert_eq!(store.try_get(0).unwrap(), Some(std::vec![1, 2, 3]));
+
+        // Promoted as rkyv
+        let (l1, _) = store.into_inner();
+        let promoted = l1.store.try_get(0).unwrap().unwrap();
+        assert_eq!(Rkyv.decode(&promoted).ok(), Some(std::vec![1u16, 2, 3]));
+    }
+}
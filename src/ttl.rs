@@ -0,0 +1,582 @@
+//! Expiration of entries after some time.
+//!
+//! [`TtlStore`] wraps around any store and keeps track of when each entry was set. It supports two
+//! thresholds: once an entry is older than the soft TTL it's stale, it's still served but flagged
+//! as such so callers can refresh it, and once it's older than the hard TTL it's a miss.
+//!
+//! It can also adapt the TTL of each key with an [`AdaptiveTtl`] policy: keys whose values keep
+//! coming back the same when set again get longer TTLs, and keys that change often get shorter
+//! ones. Or [take it from the values][TtlStore::with_value_ttl] themselves, like the max-age of an
+//! HTTP response or the expiry of a token.
+//!
+//! # Examples
+//! ```rust
+//! # use std::time::Duration;
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     stores::MemoryStore,
+//! #     ttl::{Freshness, TtlStore},
+//! # };
+//! #
+//! let mut store: TtlStore<&str, &str, _> = TtlStore::with_grace(
+//!     MemoryStore::new(),
+//!     Duration::ZERO,
+//!     Duration::from_secs(60),
+//! );
+//!
+//! store.try_set("key", "value").unwrap();
+//!
+//! // Still served, but it should be refreshed
+//! assert_eq!(
+//!     store.get_with_freshness("key").unwrap(),
+//!     Some(("value", Freshness::Stale))
+//! );
+//! assert_eq!(store.try_get("key").unwrap(), Some("value"));
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{boxed::Box, collections::HashMap, hash::DefaultHasher, vec::Vec};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    meta::{AgedCacheStore, EntryMeta, TryMetaCacheStore},
+    size::SizedStore,
+};
+
+/// How fresh an entry of a [`TtlStore`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// Younger than the soft TTL.
+    Fresh,
+    /// Older than the soft TTL but still younger than the hard one.
+    Stale,
+}
+
+/// Policy to adapt the soft TTL of each key to how often its value changes.
+///
+/// Every time a key is set again, its soft TTL doubles if the new value is the same as the previous
+/// one (compared by hash) or halves if it's different, always within `min` and `max`. The grace
+/// period between the soft and hard TTLs stays the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveTtl {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+/// Hashes values to tell if they changed, for [`AdaptiveTtl`].
+type ValueHasher<V> = fn(&V) -> u64;
+
+/// Tells the soft TTL of a value, if it has one of its own.
+type ValueTtl<V> = Box<dyn Fn(&V) -> Option<Duration> + Send + Sync>;
+
+/// Bookkeeping of a single entry.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct TtlMeta {
+    /// When it was set, as told by the clock of the store.
+    written: Duration,
+    /// Soft TTL adapted to this entry, if there's an adaptive policy or it came from its value.
+    soft_ttl: Option<Duration>,
+    /// Hash of its value, if there's an adaptive policy.
+    hash: Option<u64>,
+    hits: AtomicU64,
+}
+
+/// Wrapper around a [`TryCacheStore`] that expires its entries.
+///
+/// Entries older than the hard TTL are treated as missing, they are not removed from the inner
+/// store but they are never returned again. They and their bookkeeping stay until they're set or
+/// removed again, or [purged][TtlStore::purge_expired]. Entries set directly on the inner store,
+/// without going through this wrapper, have no known age so they are always fresh.
+///
+/// Generics:
+/// - `K`: Type of the key used for cache indexing.
+/// - `V`: Type of the value stored in the cache store.
+/// - `S`: [`TryCacheStore`] which this wraps around.
+/// - `C`: [`Clock`] used to tell the age of entries, the system time by default.
+///
+/// With the "serde" feature it (de)serializes along with the inner store and the age of every
+/// entry, which is only meaningful with clocks that keep going across processes like
+/// [`SystemClock`]. The adaptive policy and value TTLs are not kept, they have to be set again
+/// after deserializing.
+pub struct TtlStore<K, V, S: TryCacheStore<Key = K, Value = V>, C: Clock = SystemClock> {
+    pub store: S,
+    clock: C,
+    soft_ttl: Duration,
+    hard_ttl: Duration,
+    adaptive: Option<(AdaptiveTtl, ValueHasher<V>)>,
+    value_ttl: Option<ValueTtl<V>>,
+    entries: HashMap<K, TtlMeta>,
+    __phantom: PhantomData<V>,
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = K, Value = V>> TtlStore<K, V, S> {
+    /// Make a new [`TtlStore`] where entries expire right after the TTL, without being stale first.
+    pub fn new(store: S, ttl: Duration) -> Self {
+        Self::with_grace(store, ttl, ttl)
+    }
+
+    /// Make a new [`TtlStore`] where entries become stale after `soft_ttl` and expire after
+    /// `hard_ttl`. A `hard_ttl` lower than `soft_ttl` is raised to it.
+    pub fn with_grace(store: S, soft_ttl: Duration, hard_ttl: Duration) -> Self {
+        Self {
+            store,
+            soft_ttl,
+            hard_ttl: hard_ttl.max(soft_ttl),
+            clock: SystemClock,
+            adaptive: None,
+            value_ttl: None,
+            entries: HashMap::new(),
+            __phantom: PhantomData,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = K, Value = V>, C: Clock> TtlStore<K, V, S, C> {
+    /// Replaces the clock used to tell the age of entries. Entries already set keep the time they
+    /// were set at according to the previous clock, so it's meant to be done right away.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> TtlStore<K, V, S, C2> {
+        TtlStore {
+            store: self.store,
+            clock,
+            soft_ttl: self.soft_ttl,
+            hard_ttl: self.hard_ttl,
+            adaptive: self.adaptive,
+            value_ttl: self.value_ttl,
+            entries: self.entries,
+            __phantom: PhantomData,
+        }
+    }
+
+    /// Takes the soft TTL of each entry from its value, the hard TTL keeps the same grace period
+    /// after it. Values it returns [`None`] for get the TTL of the store, or the adaptive one if
+    /// there's a policy. Only applies to entries set from now on.
+    #[must_use]
+    pub fn with_value_ttl(
+        mut self,
+        ttl: impl Fn(&V) -> Option<Duration> + Send + Sync + 'static,
+    ) -> Self {
+        self.value_ttl = Some(Box::new(ttl));
+        self
+    }
+
+    /// Soft TTL currently applied to a key, which only differs from the configured one if there's
+    /// an adaptive policy. [`None`] if the key wasn't set through this wrapper.
+    pub fn ttl_of(&self, key: impl Borrow<K>) -> Option<Duration> {
+        let meta = self.entries.get(key.borrow())?;
+        Some(meta.soft_ttl.unwrap_or(self.soft_ttl))
+    }
+
+    /// Soft and hard TTLs that apply to an entry.
+    fn ttls(&self, meta: &TtlMeta) -> (Duration, Duration) {
+        let soft_ttl = meta.soft_ttl.unwrap_or(self.soft_ttl);
+        let grace = self.hard_ttl.saturating_sub(self.soft_ttl);
+        (soft_ttl, soft_ttl.saturating_add(grace))
+    }
+
+    /// Freshness of an entry, [`None`] if it already expired.
+    fn freshness(&self, key: &K) -> Option<Freshness> {
+        let Some(meta) = self.entries.get(key) else {
+            return Some(Freshness::Fresh);
+        };
+
+        let (soft_ttl, hard_ttl) = self.ttls(meta);
+        let age = self.clock.now().saturating_sub(meta.written);
+        if age >= hard_ttl {
+            None
+        } else if age >= soft_ttl {
+            Some(Freshness::Stale)
+        } else {
+            Some(Freshness::Fresh)
+        }
+    }
+
+    /// Attempts to get an entry along with how fresh it is, expired entries are misses.
+    ///
+    /// # Errors
+    /// Fails when the inner store does.
+    pub fn get_with_freshness(
+        &self,
+        key: impl Borrow<K>,
+    ) -> Result<Option<(V, Freshness)>, S::Error> {
+        let key = key.borrow();
+        let Some(freshness) = self.freshness(key) else {
+            return Ok(None);
+        };
+
+        let value = self.store.try_get(key)?;
+        if let (Some(meta), Some(_)) = (self.entries.get(key), &value) {
+            meta.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(value.map(|value| (value, freshness)))
+    }
+
+    /// Removes the expired entries from the inner store along with their bookkeeping, returns how
+    /// many were removed. Meant to be called every now and then on stores with many keys that
+    /// aren't set again, which otherwise keep them forever.
+    ///
+    /// # Errors
+    /// Fails when the inner store does, the entries before the failing one are removed.
+    pub fn purge_expired(&mut self) -> Result<usize, S::Error> {
+        let expired: Vec<K> = self
+            .entries
+            .keys()
+            .filter(|key| self.freshness(key).is_none())
+            .cloned()
+            .collect();
+        for key in &expired {
+            self.store.try_remove(key)?;
+            self.entries.remove(key);
+        }
+        Ok(expired.len())
+    }
+
+    /// Bookkeeping for a new value of a key, adapting its TTL if there's a policy.
+    fn meta_for(&self, key: &K, value: &V) -> TtlMeta {
+        if let Some(soft_ttl) = self.value_ttl.as_ref().and_then(|ttl| ttl(value)) {
+            return TtlMeta {
+                written: self.clock.now(),
+                soft_ttl: Some(soft_ttl),
+                hash: None,
+                hits: AtomicU64::new(0),
+            };
+        }
+        let Some((policy, hasher)) = self.adaptive else {
+            return TtlMeta {
+                written: self.clock.now(),
+                soft_ttl: None,
+                hash: None,
+                hits: AtomicU64::new(0),
+            };
+        };
+
+        let hash = hasher(value);
+        let previous = self
+            .entries
+            .get(key)
+            .and_then(|meta| Some((meta.soft_ttl?, meta.hash?)));
+        let soft_ttl = match previous {
+            Some((soft_ttl, previous)) if previous == hash => soft_ttl.saturating_mul(2),
+            Some((soft_ttl, _)) => soft_ttl / 2,
+            None => self.soft_ttl,
+        };
+        TtlMeta {
+            written: self.clock.now(),
+            soft_ttl: Some(soft_ttl.clamp(policy.min, policy.max)),
+            hash: Some(hash),
+            hits: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Hash, S: TryCacheStore<Key = K, Value = V>, C: Clock>
+    TtlStore<K, V, S, C>
+{
+    /// Sets the policy to adapt TTLs per key, or disables it. A `max` lower than `min` is raised
+    /// to it. Only applies to entries set from now on.
+    pub fn set_adaptive_ttl(&mut self, policy: Option<AdaptiveTtl>) {
+        self.adaptive = policy.map(|policy| {
+            let policy = AdaptiveTtl {
+                min: policy.min,
+                max: policy.max.max(policy.min),
+            };
+            let hasher: ValueHasher<V> = |value| {
+                let mut hasher = DefaultHasher::new();
+                value.hash(&mut hasher);
+                hasher.finish()
+            };
+            (policy, hasher)
+        });
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = K, Value = V>, C: Clock> TryCacheStore
+    for TtlStore<K, V, S, C>
+{
+    type Key = K;
+    type Value = V;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        Ok(self.get_with_freshness(key)?.map(|(value, _)| value))
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let (key, value) = (key.borrow(), value.borrow());
+        self.store.try_set(key, value)?;
+        let meta = self.meta_for(key, value);
+        self.entries.insert(key.clone(), meta);
+        Ok(())
+    }
+
+    /// Expired entries are removed too, but their value isn't returned.
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        let key = key.borrow();
+        let expired = self.freshness(key).is_none();
+        let old = self.store.try_remove(key)?;
+        self.entries.remove(key);
+        Ok(old.filter(|_| !expired))
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        if self.freshness(key.borrow()).is_none() {
+            return Ok(false);
+        }
+        self.store.try_exists(key)
+    }
+}
+
+/// Expired entries still count, as they are still in the inner store.
+impl<K, V, S: TryCacheStore<Key = K, Value = V> + SizedStore, C: Clock> SizedStore
+    for TtlStore<K, V, S, C>
+{
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+/// Serialized form of a [`TtlStore`], generic so it can hold references or owned fields.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "TtlStore")]
+struct TtlState<S, E> {
+    store: S,
+    soft_ttl: Duration,
+    hard_ttl: Duration,
+    entries: E,
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S, C: Clock> serde::Serialize for TtlStore<K, V, S, C>
+where
+    K: Hash + Eq + Clone + serde::Serialize,
+    S: TryCacheStore<Key = K, Value = V> + serde::Serialize,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        TtlState {
+            store: &self.store,
+            soft_ttl: self.soft_ttl,
+            hard_ttl: self.hard_ttl,
+            entries: &self.entries,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S, C: Clock + Default> serde::Deserialize<'de> for TtlStore<K, V, S, C>
+where
+    K: Hash + Eq + Clone + serde::Deserialize<'de>,
+    S: TryCacheStore<Key = K, Value = V> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = TtlState::<S, HashMap<K, TtlMeta>>::deserialize(deserializer)?;
+        Ok(Self {
+            store: state.store,
+            clock: C::default(),
+            soft_ttl: state.soft_ttl,
+            hard_ttl: state.hard_ttl,
+            adaptive: None,
+            value_ttl: None,
+            entries: state.entries,
+            __phantom: PhantomData,
+        })
+    }
+}
+
+/// Entries not set through the wrapper have no metadata.
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = K, Value = V>, C: Clock> TryMetaCacheStore
+    for TtlStore<K, V, S, C>
+{
+    fn try_get_with_meta(
+        &self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<(Self::Value, EntryMeta)>, Self::Error> {
+        let key = key.borrow();
+        let Some(value) = self.try_get(key)? else {
+            return Ok(None);
+        };
+
+        let meta = self
+            .entries
+            .get(key)
+            .map_or_else(EntryMeta::default, |meta| {
+                let age = self.clock.now().saturating_sub(meta.written);
+                EntryMeta {
+                    age: Some(age),
+                    expires_in: Some(self.ttls(meta).1.saturating_sub(age)),
+                    size: None,
+                    hits: Some(meta.hits.load(Ordering::Relaxed)),
+                }
+            });
+        Ok(Some((value, meta)))
+    }
+}
+
+/// Scans the bookkeeping of every entry, only entries set through the wrapper are known.
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = K, Value = V>, C: Clock> AgedCacheStore
+    for TtlStore<K, V, S, C>
+{
+    fn oldest_entry(&self) -> Option<(Self::Key, Duration)> {
+        self.live_entries().max_by_key(|(_, age)| *age)
+    }
+
+    fn newest_entry(&self) -> Option<(Self::Key, Duration)> {
+        self.live_entries().min_by_key(|(_, age)| *age)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: TryCacheStore<Key = K, Value = V>, C: Clock> TtlStore<K, V, S, C> {
+    /// Keys and ages of the entries that didn't expire yet.
+    fn live_entries(&self) -> impl Iterator<Item = (K, Duration)> + '_ {
+        let now = self.clock.now();
+        self.entries.iter().filter_map(move |(key, meta)| {
+            let age = now.saturating_sub(meta.written);
+            (age < self.ttls(meta).1).then(|| (key.clone(), age))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, stores::MemoryStore};
+
+    #[test]
+    fn hard_ttl_expires() {
+        let mut store = TtlStore::new(MemoryStore::new(), Duration::ZERO);
+        store.try_set(0, 0).unwrap();
+        assert_eq!(store.try_get(0).unwrap(), None);
+        assert!(!store.try_exists(0).unwrap());
+        // Still in the inner store
+        assert!(store.store.try_exists(0).unwrap());
+    }
+
+    #[test]
+    fn freshness_thresholds() {
+        let clock = MockClock::default();
+        let mut store = TtlStore::with_grace(
+            MemoryStore::new(),
+            Duration::from_hours(1),
+            Duration::from_hours(2),
+        )
+        .with_clock(&clock);
+        store.try_set(0, 0).unwrap();
+        assert_eq!(
+            store.get_with_freshness(0).unwrap(),
+            Some((0, Freshness::Fresh))
+        );
+
+        clock.advance(Duration::from_hours(1));
+        assert_eq!(
+            store.get_with_freshness(0).unwrap(),
+            Some((0, Freshness::Stale))
+        );
+        clock.advance(Duration::from_hours(1));
+        assert_eq!(store.get_with_freshness(0).unwrap(), None);
+        assert_eq!(store.get_with_freshness(1).unwrap(), None);
+    }
+
+    #[test]
+    fn expired_entries_are_purged() {
+        let clock = MockClock::default();
+        let mut store =
+            TtlStore::new(MemoryStore::new(), Duration::from_hours(1)).with_clock(&clock);
+        store.try_set(0, 0).unwrap();
+        clock.advance(Duration::from_hours(1));
+        store.try_set(1, 1).unwrap();
+
+        assert_eq!(store.purge_expired().unwrap(), 1);
+        assert!(!store.store.try_exists(0).unwrap());
+        assert_eq!(store.ttl_of(0), None);
+        assert_eq!(store.try_get(1).unwrap(), Some(1));
+        assert_eq!(store.purge_expired().unwrap(), 0);
+    }
+
+    #[test]
+    fn adaptive_ttl_follows_changes() {
+        let mut store = TtlStore::new(MemoryStore::new(), Duration::from_secs(8));
+        store.set_adaptive_ttl(Some(AdaptiveTtl {
+            min: Duration::from_secs(2),
+            max: Duration::from_secs(16),
+        }));
+
+        store.try_set(0, 0).unwrap();
+        assert_eq!(store.ttl_of(0), Some(Duration::from_secs(8)));
+        store.try_set(0, 0).unwrap();
+        store.try_set(0, 0).unwrap();
+        assert_eq!(store.ttl_of(0), Some(Duration::from_secs(16)));
+
+        for i in 1..5 {
+            store.try_set(0, i).unwrap();
+        }
+        assert_eq!(store.ttl_of(0), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn oldest_and_newest_entries() {
+        let clock = MockClock::default();
+        let mut store =
+            TtlStore::new(MemoryStore::new(), Duration::from_secs(10)).with_clock(&clock);
+        assert_eq!(store.oldest_entry(), None);
+
+        store.try_set(0, 0).unwrap();
+        clock.advance(Duration::from_secs(6));
+        store.try_set(1, 1).unwrap();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(store.oldest_entry(), Some((0, Duration::from_secs(8))));
+        assert_eq!(store.newest_entry(), Some((1, Duration::from_secs(2))));
+
+        // Expired entries don't count
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(store.oldest_entry(), Some((1, Duration::from_secs(4))));
+    }
+
+    #[test]
+    fn value_ttl_overrides_store_ttl() {
+        let clock = MockClock::default();
+        let mut store = TtlStore::new(MemoryStore::new(), Duration::from_secs(10))
+            .with_clock(&clock)
+            .with_value_ttl(|max_age: &u64| (*max_age > 0).then(|| Duration::from_secs(*max_age)));
+        store.try_set(0, 1).unwrap();
+        store.try_set(1, 0).unwrap();
+        assert_eq!(store.ttl_of(0), Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(store.try_get(0).unwrap(), None);
+        assert_eq!(store.try_get(1).unwrap(), Some(0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_keeps_ages() {
+        let mut store = TtlStore::with_grace(
+            MemoryStore::new(),
+            Duration::from_hours(1),
+            Duration::from_hours(2),
+        );
+        store.try_set(0u8, 0u8).unwrap();
+
+        let json = serde_json::to_string(&store).unwrap();
+        assert_eq!(serde_json::to_string(&store.store).unwrap(), r#"{"0":0}"#);
+
+        let store: TtlStore<u8, u8, MemoryStore<u8, u8>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            store.get_with_freshness(0).unwrap(),
+            Some((0, Freshness::Fresh))
+        );
+        assert_eq!(store.ttl_of(0), Some(Duration::from_hours(1)));
+    }
+}
@@ -0,0 +1,452 @@
+//! Stores that buffer writes and set them on the inner store later.
+//!
+//! [`WriteBackStore`] keeps the values set through it in a buffer until they get
+//! [flushed][WriteBackStore::flush], so slow stores (files, network...) aren't hit by every write.
+//! The buffer keeps only the latest value of each key, so a key written many times is set only once.
+//!
+//! Its methods take `&self`, so it can be shared between threads. How reads see buffered writes is
+//! chosen with [`ReadConsistency`]. Flushes can be left to a background task with
+//! [`spawn_flusher`][WriteBackStore::spawn_flusher], and the buffer can be bounded with
+//! [`with_max_pending`][WriteBackStore::with_max_pending] so bursts of writes are slowed down to
+//! the pace of the inner store instead of piling up.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     stores::MemoryStore,
+//! #     write_back::{ReadConsistency, WriteBackStore},
+//! # };
+//! #
+//! let store = WriteBackStore::new(MemoryStore::<&str, u32>::new())
+//!     .with_consistency(ReadConsistency::ReadYourWrites);
+//!
+//! store.write("key", 1).unwrap();
+//! store.write("key", 2).unwrap();
+//! // Not in the inner store yet, but reads see it
+//! assert_eq!(store.read("key").unwrap(), Some(2));
+//!
+//! assert_eq!(store.flush().unwrap(), 1);
+//! assert_eq!(store.into_inner().unwrap().try_get("key").unwrap(), Some(2));
+//! ```
+//!
+//! Flushing in the background:
+//! ```rust
+//! # use std::{sync::Arc, time::Duration};
+//! # use ezcache::{spawn::ThreadSpawner, stores::MemoryStore, write_back::WriteBackStore};
+//! #
+//! let store = Arc::new(WriteBackStore::new(MemoryStore::<u32, u32>::new()).with_max_pending(64));
+//! WriteBackStore::spawn_flusher(&store, &ThreadSpawner, Duration::from_millis(10));
+//!
+//! for i in 0..1000 {
+//!     // Flushes itself if the background thread can't keep up
+//!     store.write(i % 100, i).unwrap();
+//! }
+//! assert!(store.pending() <= 64);
+//! ```
+
+use crate::__internal_prelude::*;
+
+use core::{
+    hash::Hash,
+    mem::{self, ManuallyDrop},
+    ops::ControlFlow,
+    ptr,
+    time::Duration,
+};
+use std::{
+    boxed::Box,
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    health::{Health, HealthCheck},
+    shutdown::Shutdown,
+    spawn::Spawner,
+};
+
+/// How reads of a [`WriteBackStore`] treat writes that weren't flushed yet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReadConsistency {
+    /// Reads go straight to the inner store, so they don't see writes until they get flushed.
+    #[default]
+    Eventual,
+    /// Reads look into the buffered writes first, so a write is seen as soon as it's done, even
+    /// before it's flushed.
+    ReadYourWrites,
+    /// Reads flush all buffered writes first, waiting for any flush already going on, so the inner
+    /// store is always up to date when read.
+    Strict,
+}
+
+/// Writes that weren't set on the inner store yet.
+struct Buffers<K, V> {
+    /// Writes waiting for the next flush.
+    pending: HashMap<K, V>,
+    /// Writes being set by the current flush.
+    flushing: HashMap<K, V>,
+}
+
+/// Wrapper around a [`TryCacheStore`] that buffers writes until they get flushed.
+///
+/// Writes still buffered when the wrapper is dropped are flushed, ignoring errors, use
+/// [`Shutdown`] to handle them.
+///
+/// Generics:
+/// - `S`: [`TryCacheStore`] which this wraps around.
+pub struct WriteBackStore<S: TryCacheStore>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    // Always locked before `buffers` when both are needed
+    store: Mutex<S>,
+    buffers: Mutex<Buffers<S::Key, S::Value>>,
+    consistency: ReadConsistency,
+    max_pending: usize,
+}
+
+/// Locks a mutex, the data behind the ones of a [`WriteBackStore`] is fine even if a thread
+/// panicked while holding them.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<S: TryCacheStore> WriteBackStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    /// Make a new [`WriteBackStore`] around the given store, with
+    /// [`ReadConsistency::Eventual`] reads.
+    pub fn new(store: S) -> Self {
+        Self {
+            store: Mutex::new(store),
+            buffers: Mutex::new(Buffers {
+                pending: HashMap::new(),
+                flushing: HashMap::new(),
+            }),
+            consistency: ReadConsistency::default(),
+            max_pending: usize::MAX,
+        }
+    }
+
+    /// Replaces how reads treat buffered writes.
+    #[must_use]
+    pub fn with_consistency(mut self, consistency: ReadConsistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Bounds the amount of buffered writes. A write that leaves more than `max_pending` keys
+    /// buffered flushes them itself before returning, waiting for any flush going on, so writers
+    /// can't get ahead of the inner store. With `0` every write is set right away.
+    #[must_use]
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// Returns how reads treat buffered writes.
+    pub fn consistency(&self) -> ReadConsistency {
+        self.consistency
+    }
+
+    /// Amount of buffered writes waiting to be flushed.
+    pub fn pending(&self) -> usize {
+        lock(&self.buffers).pending.len()
+    }
+
+    /// Buffers a write until the next flush, replacing any buffered value of the key.
+    ///
+    /// # Errors
+    /// Fails when the buffer is full and flushing it does, the write stays buffered then.
+    pub fn write(
+        &self,
+        key: impl Borrow<S::Key>,
+        value: impl Borrow<S::Value>,
+    ) -> Result<(), S::Error> {
+        let full = {
+            let mut buffers = lock(&self.buffers);
+            buffers
+                .pending
+                .insert(key.borrow().clone(), value.borrow().clone());
+            buffers.pending.len() > self.max_pending
+        };
+        if full {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the value of a key, as told by the [`ReadConsistency`] of the store.
+    ///
+    /// # Errors
+    /// Fails when the inner store does, with [`ReadConsistency::Strict`] also if flushing does.
+    pub fn read(&self, key: impl Borrow<S::Key>) -> Result<Option<S::Value>, S::Error> {
+        let key = key.borrow();
+        match self.consistency {
+            ReadConsistency::Eventual => lock(&self.store).try_get(key),
+            ReadConsistency::ReadYourWrites => {
+                {
+                    let buffers = lock(&self.buffers);
+                    let buffered = buffers.pending.get(key);
+                    if let Some(value) = buffered.or_else(|| buffers.flushing.get(key)) {
+                        return Ok(Some(value.clone()));
+                    }
+                }
+                // Flushes clear the writes they set only after setting them with the store locked,
+                // so if it wasn't buffered it's already in the store.
+                lock(&self.store).try_get(key)
+            }
+            ReadConsistency::Strict => {
+                let mut store = lock(&self.store);
+                self.flush_into(&mut store)?;
+                store.try_get(key)
+            }
+        }
+    }
+
+    /// Removes a key right away, from the inner store and the buffer, returns its latest value.
+    ///
+    /// # Errors
+    /// Fails when the inner store does, the buffered write is dropped anyway.
+    pub fn remove(&self, key: impl Borrow<S::Key>) -> Result<Option<S::Value>, S::Error> {
+        let key = key.borrow();
+        // Flushes set their writes with the store locked, so none of them is left behind
+        let mut store = lock(&self.store);
+        let buffered = lock(&self.buffers).pending.remove(key);
+        let stored = store.try_remove(key)?;
+        Ok(buffered.or(stored))
+    }
+
+    /// Sets all the buffered writes on the inner store, returns how many were set.
+    ///
+    /// # Errors
+    /// Fails when the inner store does. Writes that weren't set are buffered again, unless the key
+    /// was written meanwhile.
+    pub fn flush(&self) -> Result<usize, S::Error> {
+        self.flush_into(&mut lock(&self.store))
+    }
+
+    /// Flushes the buffered writes and returns the inner store.
+    ///
+    /// # Errors
+    /// Fails when flushing does, the store is lost then.
+    pub fn into_inner(self) -> Result<S, S::Error> {
+        self.flush()?;
+        let this = ManuallyDrop::new(self);
+        // Nothing is left to flush, so move the fields out without running drop, `this` is never
+        // touched again
+        let (store, buffers) = unsafe {
+            (
+                ptr::read(&raw const this.store),
+                ptr::read(&raw const this.buffers),
+            )
+        };
+        drop(buffers);
+        Ok(store.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Spawns a task that flushes the store every `interval`, it stops once the store is dropped.
+    /// Failed flushes are retried on the next one.
+    pub fn spawn_flusher(this: &Arc<Self>, spawner: &impl Spawner, interval: Duration)
+    where
+        S: Send + 'static,
+        S::Key: Send + 'static,
+        S::Value: Send + 'static,
+    {
+        let store = Arc::downgrade(this);
+        spawner.spawn_every(
+            interval,
+            Box::new(move || {
+                let Some(store) = store.upgrade() else {
+                    return ControlFlow::Break(());
+                };
+                _ = store.flush();
+                ControlFlow::Continue(())
+            }),
+        );
+    }
+
+    /// Flushes the buffered writes into the already locked inner store.
+    fn flush_into(&self, store: &mut S) -> Result<usize, S::Error> {
+        let batch = {
+            let mut buffers = lock(&self.buffers);
+            let batch = mem::take(&mut buffers.pending);
+            buffers.flushing.clone_from(&batch);
+            batch
+        };
+
+        let count = batch.len();
+        let mut batch = batch.into_iter();
+        let result = batch.try_for_each(|(key, value)| {
+            store.try_set(&key, &value).inspect_err(|_| {
+                // Put back the one that failed, the rest are still in the iterator
+                lock(&self.buffers).pending.entry(key).or_insert(value);
+            })
+        });
+
+        let mut buffers = lock(&self.buffers);
+        buffers.flushing.clear();
+        result.map(|()| count).inspect_err(|_| {
+            for (key, value) in batch {
+                buffers.pending.entry(key).or_insert(value);
+            }
+        })
+    }
+}
+
+/// Flushes and stops buffering writes, as if [`with_max_pending`][WriteBackStore::with_max_pending]
+/// was `0`. The background flusher stops once the store is dropped, like always.
+impl<S: TryCacheStore> Shutdown for WriteBackStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    type Error = S::Error;
+
+    fn shutdown(&mut self) -> Result<(), Self::Error> {
+        self.max_pending = 0;
+        self.flush().map(|_| ())
+    }
+}
+
+/// Pings the wrapped store, waiting for any flush in progress to finish.
+impl<S: TryCacheStore + HealthCheck> HealthCheck for WriteBackStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    type Error = <S as HealthCheck>::Error;
+
+    fn ping(&self) -> Result<Health, Self::Error> {
+        lock(&self.store).ping()
+    }
+}
+
+impl<S: TryCacheStore> Drop for WriteBackStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    fn drop(&mut self) {
+        _ = self.flush();
+    }
+}
+
+impl<S: TryCacheStore> TryCacheStore for WriteBackStore<S>
+where
+    S::Key: Hash + Eq + Clone,
+    S::Value: Clone,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = S::Error;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.read(key)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        self.write(key, value)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        WriteBackStore::remove(self, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spawn::ManualSpawner, stores::MemoryStore, write_once::WriteOnceStore};
+
+    #[test]
+    fn reads_follow_consistency() {
+        let store = WriteBackStore::new(MemoryStore::<u8, u8>::new());
+        store.write(0, 0).unwrap();
+        assert_eq!(store.read(0).unwrap(), None);
+
+        let store = store.with_consistency(ReadConsistency::ReadYourWrites);
+        assert_eq!(store.read(0).unwrap(), Some(0));
+        assert_eq!(store.pending(), 1);
+
+        let store = store.with_consistency(ReadConsistency::Strict);
+        store.write(1, 1).unwrap();
+        assert_eq!(store.read(0).unwrap(), Some(0));
+        assert_eq!(store.pending(), 0);
+    }
+
+    #[test]
+    fn failed_flush_keeps_writes() {
+        let mut inner = WriteOnceStore::new(MemoryStore::<u8, u8>::new());
+        inner.try_set(0, 0).unwrap();
+        let store = WriteBackStore::new(inner).with_consistency(ReadConsistency::ReadYourWrites);
+
+        store.write(0, 1).unwrap();
+        store.write(1, 1).unwrap();
+        assert!(store.flush().is_err());
+        assert_eq!(store.read(0).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn full_buffer_flushes_coalesced() {
+        let store = WriteBackStore::new(MemoryStore::<u8, u8>::new()).with_max_pending(2);
+        for i in 0..10 {
+            store.write(0, i).unwrap();
+        }
+        store.write(1, 0).unwrap();
+        assert_eq!(store.pending(), 2);
+
+        store.write(2, 0).unwrap();
+        assert_eq!(store.pending(), 0);
+        assert_eq!(store.into_inner().unwrap().try_get(0).unwrap(), Some(9));
+    }
+
+    #[test]
+    fn remove_drops_buffered_writes() {
+        let store = WriteBackStore::new(MemoryStore::<u8, u8>::new());
+        store.write(0, 0).unwrap();
+        store.flush().unwrap();
+        store.write(0, 1).unwrap();
+
+        assert_eq!(store.remove(0).unwrap(), Some(1));
+        assert_eq!(store.pending(), 0);
+        assert_eq!(store.into_inner().unwrap().try_get(0).unwrap(), None);
+    }
+
+    #[test]
+    fn flusher_stops_with_store() {
+        let spawner = ManualSpawner::new();
+        let store = Arc::new(WriteBackStore::new(MemoryStore::<u8, u8>::new()));
+        WriteBackStore::spawn_flusher(&store, &spawner, Duration::from_secs(1));
+
+        store.write(0, 0).unwrap();
+        assert_eq!(spawner.tick(), 1);
+        assert_eq!(store.pending(), 0);
+
+        drop(store);
+        assert_eq!(spawner.tick(), 0);
+    }
+
+    #[test]
+    fn shutdown_stops_buffering() {
+        let mut store = WriteBackStore::new(MemoryStore::<u8, u8>::new());
+        store.write(0, 0).unwrap();
+        store.shutdown().unwrap();
+        assert_eq!(store.pending(), 0);
+
+        store.write(1, 1).unwrap();
+        assert_eq!(store.pending(), 0);
+        assert_eq!(store.into_inner().unwrap().try_get(1).unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,247 @@
+//! Stores whose entries can't be overwritten.
+//!
+//! [`WriteOnceStore`] wraps around any store and refuses to set keys that already have a value, so
+//! once something is cached it stays the same. Useful for content addressed caches, where a key
+//! changing its value means something went wrong. Entries can still be removed, and set again
+//! after that.
+//!
+//! # Examples
+//! ```rust
+//! # use ezcache::{
+//! #     TryCacheStore,
+//! #     stores::MemoryStore,
+//! #     write_once::{WriteOnceError, WriteOnceStore},
+//! # };
+//! #
+//! let mut store = WriteOnceStore::new(MemoryStore::<&str, &str>::new());
+//!
+//! store.try_set("key", "value").unwrap();
+//! assert!(matches!(
+//!     store.try_set("key", "other value"),
+//!     Err(WriteOnceError::AlreadySet)
+//! ));
+//! // Or just skip it if it's there
+//! assert!(!store.try_set_if_absent("key", "other value").unwrap());
+//!
+//! assert_eq!(store.try_get("key").unwrap(), Some("value"));
+//! ```
+
+use crate::{__internal_prelude::*, error::CacheError, size::SizedStore};
+
+/// Error of a [`WriteOnceStore`].
+#[derive(Debug)]
+pub enum WriteOnceError<E> {
+    /// The key already had a value.
+    AlreadySet,
+    /// The inner store failed.
+    Store(E),
+}
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for WriteOnceError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AlreadySet => None,
+            Self::Store(err) => Some(err),
+        }
+    }
+}
+impl<E: core::fmt::Display> core::fmt::Display for WriteOnceError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::AlreadySet => writeln!(f, "key is already set"),
+            Self::Store(err) => writeln!(f, "store error: {err}"),
+        }
+    }
+}
+
+impl<E: CacheError> CacheError for WriteOnceError<E> {
+    fn is_transient(&self) -> bool {
+        self.store_error().is_some_and(CacheError::is_transient)
+    }
+
+    fn is_poison(&self) -> bool {
+        self.store_error().is_some_and(CacheError::is_poison)
+    }
+
+    fn is_not_found(&self) -> bool {
+        self.store_error().is_some_and(CacheError::is_not_found)
+    }
+}
+
+impl<E> WriteOnceError<E> {
+    fn store_error(&self) -> Option<&E> {
+        match self {
+            Self::AlreadySet => None,
+            Self::Store(err) => Some(err),
+        }
+    }
+}
+
+/// Wrapper around a store that fails to set keys that already have a value, with
+/// [`WriteOnceError::AlreadySet`].
+///
+/// Works over a [`TryCacheStore`] or, checking under the exclusive lock of the key so two threads
+/// can't both set it, over a [`ThreadSafeTryCacheStore`]. Entries set directly on the inner store
+/// are not protected until they exist.
+///
+/// Generics:
+/// - `S`: Store which this wraps around.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOnceStore<S> {
+    pub store: S,
+}
+
+impl<S> WriteOnceStore<S> {
+    /// Make a new [`WriteOnceStore`] around the given store.
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+}
+
+impl<S: SizedStore> SizedStore for WriteOnceStore<S> {
+    fn bytes_used(&self) -> usize {
+        self.store.bytes_used()
+    }
+}
+
+impl<S: TryCacheStore> TryCacheStore for WriteOnceStore<S> {
+    type Key = S::Key;
+    type Value = S::Value;
+    type Error = WriteOnceError<S::Error>;
+
+    fn try_get(&self, key: impl Borrow<Self::Key>) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_get(key).map_err(WriteOnceError::Store)
+    }
+
+    fn try_set(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+        value: impl Borrow<Self::Value>,
+    ) -> Result<(), Self::Error> {
+        let key = key.borrow();
+        if self.try_exists(key)? {
+            return Err(WriteOnceError::AlreadySet);
+        }
+        self.store
+            .try_set(key, value)
+            .map_err(WriteOnceError::Store)
+    }
+
+    fn try_remove(
+        &mut self,
+        key: impl Borrow<Self::Key>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.try_remove(key).map_err(WriteOnceError::Store)
+    }
+
+    fn try_exists(&self, key: impl Borrow<Self::Key>) -> Result<bool, Self::Error> {
+        self.store.try_exists(key).map_err(WriteOnceError::Store)
+    }
+}
+
+#[cfg(feature = "thread-safe")]
+impl<'lock, S: ThreadSafeTryCacheStore<'lock>> ThreadSafeTryCacheStore<'lock> for WriteOnceStore<S>
+where
+    Self: 'lock,
+{
+    type Key = S::Key;
+    type Value = S::Value;
+    type SLock<'guard>
+        = S::SLock<'guard>
+    where
+        'lock: 'guard;
+    type XLock = S::XLock;
+    type Error = WriteOnceError<S::Error>;
+
+    fn ts_try_get(
+        &'lock self,
+        handle: &Self::SLock<'_>,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store.ts_try_get(handle).map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_set(
+        &'lock self,
+        handle: &mut Self::XLock,
+        value: &Self::Value,
+    ) -> Result<(), Self::Error> {
+        if self.ts_try_exists(&S::SLock::from(&*handle))? {
+            return Err(WriteOnceError::AlreadySet);
+        }
+        self.store
+            .ts_try_set(handle, value)
+            .map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_remove(
+        &'lock self,
+        handle: &mut Self::XLock,
+    ) -> Result<Option<Self::Value>, Self::Error> {
+        self.store
+            .ts_try_remove(handle)
+            .map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_exists(&'lock self, handle: &Self::SLock<'_>) -> Result<bool, Self::Error> {
+        self.store
+            .ts_try_exists(handle)
+            .map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_xlock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        self.store.ts_try_xlock(key).map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_slock(&'lock self, key: &'lock Self::Key) -> Result<Self::SLock<'lock>, Self::Error> {
+        self.store.ts_try_slock(key).map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_xlock_nblock(&'lock self, key: &'lock Self::Key) -> Result<Self::XLock, Self::Error> {
+        self.store
+            .ts_try_xlock_nblock(key)
+            .map_err(WriteOnceError::Store)
+    }
+
+    fn ts_try_slock_nblock(
+        &'lock self,
+        key: &'lock Self::Key,
+    ) -> Result<Self::SLock<'lock>, Self::Error> {
+        self.store
+            .ts_try_slock_nblock(key)
+            .map_err(WriteOnceError::Store)
+    }
+}
+
+#[cfg(all(test, feature = "thread-safe"))]
+mod tests {
+    use super::*;
+    use crate::stores::{MemoryStore, ThreadSafeMemoryStore};
+
+    #[test]
+    fn replace_fails_on_set_keys() {
+        let mut store = WriteOnceStore::new(MemoryStore::<u8, u8>::new());
+        assert_eq!(store.try_replace(0, 1).unwrap(), None);
+        assert!(matches!(
+            store.try_replace(0, 2),
+            Err(WriteOnceError::AlreadySet)
+        ));
+        assert_eq!(store.try_get(0).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn one_thread_sets_it() {
+        let store = WriteOnceStore::new(ThreadSafeMemoryStore::<u8, usize>::default());
+        let set = std::thread::scope(|scope| {
+            let store = &store;
+            let threads: std::vec::Vec<_> = (0..4)
+                .map(|i| scope.spawn(move || store.ts_one_try_set(&0, &i).is_ok()))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .filter(|&set| set)
+                .count()
+        });
+        assert_eq!(set, 1);
+    }
+}
@@ -0,0 +1,137 @@
+//! Values stored in schema-based formats, readable in place and from other languages.
+//!
+//! [`FlatBuffer`] holds a finished [FlatBuffers](https://flatbuffers.dev) buffer. It can be the
+//! value of stores of raw bytes like
+//! [`ThreadSafeFileStore`][crate::stores::file_stores::ThreadSafeFileStore], which then write the
+//! buffer as is, so readers in any language with code generated from the same schema can use the
+//! cached files directly. Reading it back doesn't deserialize anything either, the
+//! [root][FlatBuffer::root] table is read in place after verifying the buffer.
+//!
+//! # Examples
+//! With `Monster` generated by `flatc` from a schema:
+//! ```rust,ignore
+//! # use ezcache::{stores::file_stores::ThreadSafeFileStore, zero_copy::FlatBuffer};
+//! #
+//! let store = ThreadSafeFileStore::<&str, FlatBuffer>::new_on("cache")?;
+//!
+//! let mut builder = flatbuffers::FlatBufferBuilder::new();
+//! let name = builder.create_string("orc");
+//! let monster = Monster::create(&mut builder, &MonsterArgs { name: Some(name), hp: 80 });
+//! builder.finish(monster, None);
+//! store.ts_one_try_set(&"orc", &FlatBuffer::from_builder(&builder))?;
+//!
+//! let value = store.ts_one_try_get(&"orc")?.unwrap();
+//! assert_eq!(value.root::<Monster>()?.hp(), 80);
+//! ```
+
+use std::vec::Vec;
+
+use flatbuffers::{FlatBufferBuilder, Follow, InvalidFlatbuffer, Verifiable};
+
+/// Finished flatbuffer, see the [module docs][self].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FlatBuffer(Vec<u8>);
+
+impl FlatBuffer {
+    /// Copies the data of a builder, which must have been finished.
+    #[must_use]
+    pub fn from_builder(builder: &FlatBufferBuilder<'_>) -> Self {
+        Self(builder.finished_data().to_vec())
+    }
+
+    /// Reads the root table, of type `T`, in place. The buffer is verified first, as it could be
+    /// anything read from the store.
+    ///
+    /// # Errors
+    /// Fails when the buffer isn't a valid flatbuffer with a `T` root.
+    pub fn root<'a, T: Follow<'a> + Verifiable + 'a>(
+        &'a self,
+    ) -> Result<T::Inner, InvalidFlatbuffer> {
+        flatbuffers::root::<T>(&self.0)
+    }
+
+    /// Returns the raw buffer.
+    #[must_use]
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for FlatBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for FlatBuffer {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+#[cfg(all(test, feature = "file-stores"))]
+mod tests {
+    use super::*;
+    use crate::{stores::file_stores::ThreadSafeFileStore, thread_safe::ThreadSafeTryCacheStore};
+    use flatbuffers::{Table, VOffsetT, Verifier};
+
+    /// What `flatc` generates for `table Point { x: int; }`.
+    struct Point<'a> {
+        table: Table<'a>,
+    }
+
+    impl Point<'_> {
+        const VT_X: VOffsetT = 4;
+
+        fn x(&self) -> i32 {
+            // SAFETY: The buffer was verified to have an int at this field
+            unsafe { self.table.get::<i32>(Self::VT_X, Some(0)).unwrap() }
+        }
+    }
+
+    impl<'a> Follow<'a> for Point<'a> {
+        type Inner = Point<'a>;
+
+        unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+            Point {
+                // SAFETY: Same preconditions as this function
+                table: unsafe { Table::new(buf, loc) },
+            }
+        }
+    }
+
+    impl Verifiable for Point<'_> {
+        fn run_verifier(verifier: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            verifier
+                .visit_table(pos)?
+                .visit_field::<i32>("x", Self::VT_X, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn file_holds_the_raw_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ThreadSafeFileStore::<&str, FlatBuffer>::new_on(dir.path()).unwrap();
+
+        let mut builder = FlatBufferBuilder::new();
+        let start = builder.start_table();
+        builder.push_slot::<i32>(Point::VT_X, 7, 0);
+        let point = builder.end_table(start);
+        builder.finish(point, None);
+        let buffer = FlatBuffer::from_builder(&builder);
+        store.ts_one_try_set(&"point", &buffer).unwrap();
+
+        // Other readers see the same bytes
+        let file = std::fs::read_dir(dir.path()).unwrap().next().unwrap();
+        assert_eq!(
+            std::fs::read(file.unwrap().path()).unwrap(),
+            buffer.as_ref()
+        );
+
+        let value = store.ts_one_try_get(&"point").unwrap().unwrap();
+        assert_eq!(value.root::<Point>().unwrap().x(), 7);
+        assert!(FlatBuffer::from(std::vec![1, 2]).root::<Point>().is_err());
+    }
+}